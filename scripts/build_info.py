@@ -56,7 +56,7 @@ def generate_build_info_c(configuration, cc=""):
 
     machine = f"{username()}@{platform.node()}"
 
-    maj, min, rev = 0, 0, 0
+    maj, min, rev = None, None, None
     with open("./src/emon32.h", "r") as f:
         for ln in f:
             if "VERSION_FW" in ln:
@@ -66,6 +66,9 @@ def generate_build_info_c(configuration, cc=""):
                     min = ln.split()[-1].rstrip("u")
                 elif "REV" in ln:
                     rev = ln.split()[-1].rstrip("u")
+    # Fail rather than build with a version that does not match the firmware
+    if None in (maj, min, rev):
+        raise SystemExit("build_info.py: VERSION_FW_* not found in emon32.h")
     version = f"v{maj}.{min}.{rev}"
 
     build_info_string = (
@@ -79,6 +82,7 @@ def generate_build_info_c(configuration, cc=""):
     #include "emon32_build_info.h"
 
     static const char version[] = "{version}";
+    static const char configuration[] = "{configuration}";
     static const char compiler[] = "{compiler}";
     static const char revision[] = "{revision}";
     static const char date[] = "{date}";
@@ -89,6 +93,7 @@ def generate_build_info_c(configuration, cc=""):
     struct Emon32BuildInfo emon32_build_info() {{
         return (struct Emon32BuildInfo){{
             .version = version,
+            .configuration = configuration,
             .revision = revision,
             .date = date,
             .compiler = compiler,
@@ -107,7 +112,7 @@ def generate_build_info_c(configuration, cc=""):
 
 def main():
     parser = argparse.ArgumentParser()
-    parser.add_argument("--config", "--configuration", default="Default")
+    parser.add_argument("--config", "--configuration", default="Release")
     parser.add_argument("--cc", "--compiler_path", default="")
     parser.add_argument("output", type=argparse.FileType("w", encoding="utf-8"))
 
@@ -26,6 +26,12 @@
 #define SAMPLE_BUF_DEPTH   2
 #define OVERSAMPLING_RATIO 2u
 
+/* The ADC is triggered by TIMER_ADC through the event system, at
+ * (SAMPLE_RATE * VCT_TOTAL). To check the rate on a scope, define a spare pin
+ * when building, e.g. -DGRP_ADC_DBG=GRP_PINA -DPIN_ADC_DBG=14u; it toggles
+ * after each sample set of (VCT_TOTAL * OVERSAMPLING_RATIO) conversions.
+ */
+
 #define ADC_VREF     1.024f
 #define ADC_RES_BITS 11
 #define CAL_V        8.16f
@@ -55,26 +61,47 @@
 
 /* Serial Communication Instances */
 
+/* The UART's SERCOM instance number. Its SERCOM, clock, interrupt, and DMA
+ * defines follow from this. A board wired differently defines it (and the UART
+ * pins and pads, below) when building, e.g. -DUART_SERCOM_NUM=0. SERCOM2-4 are
+ * in use.
+ */
+#ifndef UART_SERCOM_NUM
+#define UART_SERCOM_NUM 5
+#endif
+
+#define SERCOM_PASTE_(a, n, b) a##n##b
+#define SERCOM_PASTE(a, n, b)  SERCOM_PASTE_(a, n, b)
+
 #define SERCOM_SPI      SERCOM2
 #define SERCOM_I2CM_EXT SERCOM3
 #define SERCOM_I2CM     SERCOM4
-#define SERCOM_UART     SERCOM5
+#define SERCOM_UART     SERCOM_PASTE(SERCOM, UART_SERCOM_NUM, )
 
 #define SERCOM_SPI_APBCMASK      PM_APBCMASK_SERCOM2
 #define SERCOM_I2CM_EXT_APBCMASK PM_APBCMASK_SERCOM3
 #define SERCOM_I2CM_INT_APBCMASK PM_APBCMASK_SERCOM4
-#define SERCOM_UART_APBCMASK     PM_APBCMASK_SERCOM5
+#define SERCOM_UART_APBCMASK                                                   \
+  SERCOM_PASTE(PM_APBCMASK_SERCOM, UART_SERCOM_NUM, )
 
 #define SERCOM_SPI_GCLK_ID      SERCOM2_GCLK_ID_CORE
 #define SERCOM_I2CM_EXT_GCLK_ID SERCOM3_GCLK_ID_CORE
 #define SERCOM_I2CM_INT_GCLK_ID SERCOM4_GCLK_ID_CORE
-#define SERCOM_UART_GCLK_ID     SERCOM5_GCLK_ID_CORE
+#define SERCOM_UART_GCLK_ID                                                    \
+  SERCOM_PASTE(SERCOM, UART_SERCOM_NUM, _GCLK_ID_CORE)
+
+#define SERCOM_UART_INTERACTIVE_HANDLER                                        \
+  SERCOM_PASTE(irq_handler_sercom, UART_SERCOM_NUM, )()
+#define SERCOM_UART_INTERACTIVE SERCOM_UART
 
-#define SERCOM_UART_INTERACTIVE_HANDLER irq_handler_sercom5()
-#define SERCOM_UART_INTERACTIVE         SERCOM5
+#define SERCOM_UART_DMAC_ID_TX                                                 \
+  SERCOM_PASTE(SERCOM, UART_SERCOM_NUM, _DMAC_ID_TX)
+#define SERCOM_UART_DMAC_ID_RX                                                 \
+  SERCOM_PASTE(SERCOM, UART_SERCOM_NUM, _DMAC_ID_RX)
 
-#define SERCOM_UART_NVIC_IRQn        SERCOM5_IRQn
-#define SERCOM_UART_INTERACTIVE_IRQn SERCOM5_IRQn
+#define SERCOM_UART_NVIC_IRQn                                                  \
+  SERCOM_PASTE(SERCOM, UART_SERCOM_NUM, _IRQn)
+#define SERCOM_UART_INTERACTIVE_IRQn SERCOM_UART_NVIC_IRQn
 
 /* Timer Instances */
 
@@ -129,6 +156,14 @@
 #define PIN_OPA1_PU 18
 #define PIN_OPA2_PU 19
 
+/* External interrupt (EXTINT) line for each pulse input. OPA1 (PA16) shares
+ * EXTINT[0] with DISABLE_EXT_INTF (PB16), so it is polled from the 1 ms tick.
+ */
+#define EXTINT_NONE 0xFFu
+#define EXTINT_OPA1 EXTINT_NONE
+#define EXTINT_OPA2 1u
+#define EXTINT_OPA3 9u
+
 /* DISABLE_EXT_INTF */
 #define GRP_DISABLE_EXT GRP_PINB
 #define PIN_DISABLE_EXT 16u
@@ -184,14 +219,32 @@
 #define PIN_USB_DP 25
 #define PMUX_USB   PORT_PMUX_PMUXE_G
 
-/* UART related defines */
+/* UART related defines. The pins and pads go with UART_SERCOM_NUM; a board
+ * that sets that defines all of these too.
+ */
+#ifndef PIN_UART_TX
 #define PMUX_UART       PORT_PMUX_PMUXE_C /* SERCOM */
 #define GRP_SERCOM_UART GRP_PINA
 #define PIN_UART_TX     20u
 #define PIN_UART_RX     21u
 #define UART_PAD_RX     3u /* RXPO */
 #define UART_PAD_TX     1u /* TXPO value, Tx on pad 2 */
-#define UART_BAUD       115200u
+#endif
+#define UART_BAUD 115200u
+
+/* A board with an RS485 transceiver on the UART defines the pin for its driver
+ * enable (DE, with /RE tied to it) when building, e.g.
+ * -DGRP_RS485_DE=GRP_PINA -DPIN_RS485_DE=10u. Without, the UART is point to
+ * point. The pin is not driven until the configuration has been loaded, so
+ * the board should pull it low.
+ */
+
+/* A board with an output for surplus diversion (e.g. an SSR driving an
+ * immersion heater) defines its pin when building, e.g.
+ * -DGRP_DIVERT=GRP_PINA -DPIN_DIVERT=11u. The output follows the diversion
+ * state from the first processed mains cycle, so the board should pull it
+ * low.
+ */
 
 /* RFM related defines */
 #define RFM_PALEVEL_DEF 0x19 /* Safe level if no antenna installed. */
@@ -206,6 +259,7 @@
 #define GRP_RFM_INTF    GRP_PINB
 #define PIN_RFM_IRQ     14u
 #define PMUX_RFM_IRQ    PORT_PMUX_PMUXE_A
+#define EXTINT_RFM_IRQ  14u /* DIO0 */
 #define PIN_RFM_RST     15u
 #define RFM_RETRIES     4
 #define RFM_TIMEOUT     30
@@ -221,7 +275,9 @@
 #define PIN_I2C_EXT_SCL    23u
 #define PMUX_I2CM_EXT      PORT_PMUX_PMUXE_C
 
-/* DMA defines */
-#define NUM_CHAN_DMA  2u
-#define DMA_CHAN_ADC1 1u
-#define DMA_CHAN_ADC0 0u
+/* DMA defines. Without DMA_CHAN_UART_RX, UART Rx is interrupt driven. */
+#define NUM_CHAN_DMA     4u
+#define DMA_CHAN_UART_RX 3u
+#define DMA_CHAN_UART    2u
+#define DMA_CHAN_ADC1    1u
+#define DMA_CHAN_ADC0    0u
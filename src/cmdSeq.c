@@ -0,0 +1,77 @@
+#include <string.h>
+
+#include "cmdSeq.h"
+#include "util.h"
+
+static const char *errName[] = {"OK", "badvalue", "unknown", "failed",
+                                "badtag"};
+
+void cmdSeqInit(CmdSeq_t *pSeq) { memset(pSeq, 0, sizeof(*pSeq)); }
+
+CmdTag_t cmdSeqParse(const CmdSeq_t *pSeq, const char *pLine, uint32_t *pTag,
+                     size_t *pCmd, CmdErr_t *pErr) {
+  uint32_t tag = 0;
+  size_t   i   = 1u;
+
+  *pTag = 0;
+  if ('#' != pLine[0]) {
+    return CMD_TAG_NONE;
+  }
+
+  while ((pLine[i] >= '0') && (pLine[i] <= '9')) {
+    if (i > CMD_SEQ_DIGITS) {
+      return CMD_TAG_BAD;
+    }
+    tag = (tag * 10u) + (uint32_t)(pLine[i] - '0');
+    i++;
+  }
+
+  /* At least one digit, then the end of the line or a space */
+  if ((1u == i) || ((0 != pLine[i]) && (' ' != pLine[i]))) {
+    return CMD_TAG_BAD;
+  }
+
+  *pTag = tag;
+  for (size_t h = 0; h < pSeq->n; h++) {
+    if (tag == pSeq->hist[h].seq) {
+      *pErr = pSeq->hist[h].err;
+      return CMD_TAG_DUP;
+    }
+  }
+
+  while (' ' == pLine[i]) {
+    i++;
+  }
+  *pCmd = i;
+  return CMD_TAG_NEW;
+}
+
+void cmdSeqRecord(CmdSeq_t *pSeq, const uint32_t tag, const CmdErr_t err) {
+  pSeq->hist[pSeq->next].seq = tag;
+  pSeq->hist[pSeq->next].err = err;
+
+  pSeq->next = (pSeq->next + 1u) % CMD_SEQ_HISTORY;
+  if (pSeq->n < CMD_SEQ_HISTORY) {
+    pSeq->n++;
+  }
+}
+
+size_t cmdSeqReply(char *pDst, const uint32_t tag, const CmdErr_t err) {
+  size_t n = 0;
+
+  pDst[n++] = '#';
+  if (CMD_ERR_TAG != err) {
+    n += utilUtoa((pDst + n), tag, ITOA_BASE10) - 1u;
+  }
+  pDst[n++] = ' ';
+  if (CMD_OK != err) {
+    memcpy((pDst + n), "ERR ", 4u);
+    n += 4u;
+  }
+
+  const size_t w = strlen(errName[err]);
+  memcpy((pDst + n), errName[err], w);
+  n += w;
+  memcpy((pDst + n), "\r\n", 3u);
+  return n + 2u;
+}
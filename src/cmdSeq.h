@@ -0,0 +1,77 @@
+#pragma once
+
+#include <stddef.h>
+#include <stdint.h>
+
+/* Sequence tags on configuration commands. A command line may start with a
+ * tag, "#<seq> ", e.g. "#17 k1 1 100.0 4.2". After the command has been
+ * handled, a reply line echoes the tag with the result:
+ *   #17 OK
+ *   #17 ERR <reason>
+ * A tag that matches one of the last CMD_SEQ_HISTORY tagged commands is a
+ * retransmission: the command is not carried out again, and the recorded
+ * result is sent. Tags need not be in order. Lines without a tag are handled
+ * as before, with no reply line.
+ */
+
+#define CMD_SEQ_HISTORY 4u  /* Tagged commands remembered for duplicates */
+#define CMD_SEQ_DIGITS  9u  /* Most digits in a tag, so it fits a uint32 */
+#define CMD_SEQ_REPLY_W 32u /* Width of a reply line, including the NULL */
+
+typedef enum CmdErr_ {
+  CMD_OK,
+  CMD_ERR_VALUE,   /* "badvalue": an argument was rejected */
+  CMD_ERR_UNKNOWN, /* "unknown": no such command */
+  CMD_ERR_FAIL,    /* "failed": valid, but could not be done, e.g. NVM */
+  CMD_ERR_TAG      /* "badtag": the tag could not be read */
+} CmdErr_t;
+
+typedef enum CmdTag_ {
+  CMD_TAG_NONE, /* No tag; handle the line as before */
+  CMD_TAG_NEW,  /* New tag; handle the command and record the result */
+  CMD_TAG_DUP,  /* Retransmission; reply with the recorded result only */
+  CMD_TAG_BAD   /* Starts with '#', but the tag is malformed */
+} CmdTag_t;
+
+typedef struct CmdSeqEntry_ {
+  uint32_t seq;
+  CmdErr_t err;
+} CmdSeqEntry_t;
+
+typedef struct CmdSeq_ {
+  CmdSeqEntry_t hist[CMD_SEQ_HISTORY];
+  size_t        n;    /* Entries in use */
+  size_t        next; /* Entry to replace next */
+} CmdSeq_t;
+
+/*! @brief Forget all the recorded tags
+ *  @param [out] pSeq : pointer to the tag history
+ */
+void cmdSeqInit(CmdSeq_t *pSeq);
+
+/*! @brief Read the tag at the start of a command line
+ *  @param [in] pSeq : pointer to the tag history
+ *  @param [in] pLine : null terminated command line
+ *  @param [out] pTag : the tag if CMD_TAG_NEW or CMD_TAG_DUP, otherwise 0
+ *  @param [out] pCmd : offset of the command after the tag, if CMD_TAG_NEW
+ *  @param [out] pErr : the recorded result, if CMD_TAG_DUP
+ *  @return the kind of line
+ */
+CmdTag_t cmdSeqParse(const CmdSeq_t *pSeq, const char *pLine, uint32_t *pTag,
+                     size_t *pCmd, CmdErr_t *pErr);
+
+/*! @brief Record the result of a tagged command, replacing the oldest entry
+ *  @param [out] pSeq : pointer to the tag history
+ *  @param [in] tag : tag of the command
+ *  @param [in] err : result of the command
+ */
+void cmdSeqRecord(CmdSeq_t *pSeq, uint32_t tag, CmdErr_t err);
+
+/*! @brief Format the reply line, "#<tag> OK\r\n" or "#<tag> ERR <reason>\r\n".
+ *         With CMD_ERR_TAG, the tag is unknown: "# ERR badtag\r\n".
+ *  @param [out] pDst : destination, at least CMD_SEQ_REPLY_W wide
+ *  @param [in] tag : tag of the command
+ *  @param [in] err : result of the command
+ *  @return number of characters, not including the NULL
+ */
+size_t cmdSeqReply(char *pDst, uint32_t tag, CmdErr_t err);
@@ -0,0 +1,138 @@
+#include "cobs.h"
+
+static bool decoderPut(CobsDecoder_t *pDec, uint8_t c);
+
+/*! @brief Add a byte to the payload of an incremental decoder
+ *  @param [in,out] pDec : pointer to the decoder
+ *  @param [in] c : decoded byte
+ *  @return false if the payload buffer is full; the frame is discarded
+ */
+static bool decoderPut(CobsDecoder_t *pDec, const uint8_t c) {
+  if (pDec->n >= pDec->m) {
+    pDec->error = true;
+    return false;
+  }
+  pDec->pBuf[pDec->n++] = c;
+  return true;
+}
+
+size_t cobsEncode(const void *pSrc, size_t n, uint8_t *pDst, size_t m) {
+  const uint8_t *pIn     = (const uint8_t *)pSrc;
+  size_t         idxCode = 0;
+  size_t         w       = 1;
+  uint8_t        code    = 1;
+
+  if (0 == m) {
+    return 0;
+  }
+
+  /* Each zero, and each full block, closes the current block by writing its
+   * code byte. The position of the next code byte is reserved. */
+  for (size_t i = 0; i < n; i++) {
+    if (0 != pIn[i]) {
+      if (w >= m) {
+        return 0;
+      }
+      pDst[w++] = pIn[i];
+      code++;
+    }
+    if ((0 == pIn[i]) || ((COBS_BLOCK_MAX + 1u) == code)) {
+      if (w >= m) {
+        return 0;
+      }
+      pDst[idxCode] = code;
+      idxCode       = w++;
+      code          = 1;
+    }
+  }
+  pDst[idxCode] = code;
+  return w;
+}
+
+CobsStatus_t cobsDecode(const uint8_t *pSrc, size_t n, uint8_t *pDst, size_t m,
+                        size_t *pN) {
+  size_t r = 0;
+  size_t w = 0;
+
+  /* The payload is never longer than the frame, so w < r and decoding in
+   * place is safe. */
+  while (r < n) {
+    const uint8_t code = pSrc[r++];
+    if (0 == code) {
+      return COBS_ZERO;
+    }
+
+    for (uint8_t k = 1; k < code; k++) {
+      if (r >= n) {
+        return COBS_SHORT;
+      }
+      if (0 == pSrc[r]) {
+        return COBS_ZERO;
+      }
+      if (w >= m) {
+        return COBS_OVERFLOW;
+      }
+      pDst[w++] = pSrc[r++];
+    }
+
+    /* A block shorter than the maximum ends in a zero, except the last */
+    if (((COBS_BLOCK_MAX + 1u) != code) && (r < n)) {
+      if (w >= m) {
+        return COBS_OVERFLOW;
+      }
+      pDst[w++] = 0;
+    }
+  }
+
+  *pN = w;
+  return COBS_OK;
+}
+
+void cobsDecoderInit(CobsDecoder_t *pDec, uint8_t *pBuf, size_t m) {
+  pDec->pBuf  = pBuf;
+  pDec->m     = m;
+  pDec->n     = 0;
+  pDec->code  = 0;
+  pDec->left  = 0;
+  pDec->error = false;
+}
+
+CobsRx_t cobsDecodeByte(CobsDecoder_t *pDec, const uint8_t c) {
+  if (COBS_DELIM == c) {
+    /* Consecutive delimiters are empty frames, and are skipped */
+    const bool started  = (0 != pDec->code) || pDec->error;
+    const bool complete = !pDec->error && (0 != pDec->code) && !pDec->left;
+
+    pDec->code  = 0;
+    pDec->left  = 0;
+    pDec->error = false;
+    if (complete) {
+      return COBS_RX_FRAME;
+    }
+    pDec->n = 0;
+    return started ? COBS_RX_ERROR : COBS_RX_BUSY;
+  }
+
+  if (pDec->error) {
+    return COBS_RX_BUSY;
+  }
+
+  if (0 != pDec->left) {
+    (void)decoderPut(pDec, c);
+    pDec->left--;
+    return COBS_RX_BUSY;
+  }
+
+  /* A code byte. The first starts a new frame; later ones mark the end of a
+   * block, which is a zero unless the block was full. */
+  if (0 == pDec->code) {
+    pDec->n = 0;
+  } else if ((COBS_BLOCK_MAX + 1u) != pDec->code) {
+    if (!decoderPut(pDec, 0)) {
+      return COBS_RX_BUSY;
+    }
+  }
+  pDec->code = c;
+  pDec->left = (uint8_t)(c - 1u);
+  return COBS_RX_BUSY;
+}
@@ -0,0 +1,76 @@
+#pragma once
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+/* Consistent Overhead Byte Stuffing (COBS). An encoded frame contains no zero
+ * bytes, so frames on a stream are delimited by COBS_DELIM and a receiver
+ * resynchronises at the next delimiter after any corruption. Each block is a
+ * code byte, giving the distance to the next zero in the payload, followed by
+ * up to 254 non-zero bytes. The delimiter is not added by cobsEncode.
+ */
+#define COBS_DELIM     0x00u
+#define COBS_BLOCK_MAX 254u
+
+/* Worst case encoded width of an n byte payload, excluding the delimiter */
+#define COBS_ENC_MAX(n) ((n) + ((n) / COBS_BLOCK_MAX) + 1u)
+
+typedef enum CobsStatus_ {
+  COBS_OK,
+  COBS_ZERO,    /* A zero byte within the frame */
+  COBS_SHORT,   /* A code byte runs past the end of the frame */
+  COBS_OVERFLOW /* The payload does not fit in the destination */
+} CobsStatus_t;
+
+typedef enum CobsRx_ {
+  COBS_RX_BUSY,  /* Frame in progress, or an empty frame was skipped */
+  COBS_RX_FRAME, /* A frame is complete; the payload is in pBuf */
+  COBS_RX_ERROR  /* A frame was discarded, the next starts after a delimiter */
+} CobsRx_t;
+
+/* Incremental decoder for a received byte stream, see cobsDecodeByte */
+typedef struct CobsDecoder_ {
+  uint8_t *pBuf;  /* Payload buffer */
+  size_t   m;     /* Width of pBuf */
+  size_t   n;     /* Payload bytes received */
+  uint8_t  code;  /* Current code byte, 0 before the first */
+  uint8_t  left;  /* Bytes remaining in the current block */
+  bool     error; /* Frame is discarded until the next delimiter */
+} CobsDecoder_t;
+
+/*! @brief Encode a payload. The delimiter is not added.
+ *  @param [in] pSrc : pointer to the payload
+ *  @param [in] n : payload length
+ *  @param [out] pDst : destination buffer, must not overlap pSrc
+ *  @param [in] m : width of the destination buffer, COBS_ENC_MAX(n) always
+ *                  fits
+ *  @return encoded length, or 0 if it does not fit in m
+ */
+size_t cobsEncode(const void *pSrc, size_t n, uint8_t *pDst, size_t m);
+
+/*! @brief Decode a complete frame, without its delimiter
+ *  @param [in] pSrc : pointer to the encoded frame
+ *  @param [in] n : encoded length
+ *  @param [out] pDst : destination buffer, may be the same as pSrc
+ *  @param [in] m : width of the destination buffer
+ *  @param [out] pN : payload length, only valid if COBS_OK
+ *  @return COBS_OK if the frame is valid
+ */
+CobsStatus_t cobsDecode(const uint8_t *pSrc, size_t n, uint8_t *pDst, size_t m,
+                        size_t *pN);
+
+/*! @brief Initialise an incremental decoder
+ *  @param [out] pDec : pointer to the decoder
+ *  @param [in] pBuf : payload buffer
+ *  @param [in] m : width of the payload buffer
+ */
+void cobsDecoderInit(CobsDecoder_t *pDec, uint8_t *pBuf, size_t m);
+
+/*! @brief Add a received byte to the decoder. When a frame is complete, the
+ *         payload is in pDec->pBuf with length pDec->n, until the next byte.
+ *  @param [in,out] pDec : pointer to the decoder
+ *  @param [in] c : received byte
+ *  @return COBS_RX_FRAME at the delimiter of a valid frame
+ */
+CobsRx_t cobsDecodeByte(CobsDecoder_t *pDec, uint8_t c);
@@ -3,18 +3,26 @@
 
 #include "emon32_assert.h"
 
+#include "driver_ADC.h"
 #include "driver_SERCOM.h"
 #include "driver_TIME.h"
 
+#include "cmdSeq.h"
+#include "cobs.h"
 #include "configuration.h"
+#include "dataPack.h"
 #include "eeprom.h"
 #include "emon32.h"
 #include "emon32_build_info.h"
 #include "emon_CM.h"
+#include "logger.h"
+#include "modbusMap.h"
 #include "periph_rfm69.h"
+#include "pollMap.h"
 #include "pulse.h"
 #include "temperature.h"
 #include "util.h"
+#include "wallClock.h"
 
 #include "printf.h"
 #include "qfplib-m0-full.h"
@@ -34,6 +42,14 @@ typedef enum {
      confirmation */
 } ConfirmState_t;
 
+/* Result of reading the configuration from NVM */
+typedef enum {
+  NVM_RD_OK,        /* Key and CRC match */
+  NVM_RD_READ_FAIL, /* EEPROM did not respond */
+  NVM_RD_NO_KEY,    /* Nothing has been saved */
+  NVM_RD_CRC        /* Stored copy is corrupt */
+} NvmRd_t;
+
 typedef enum {
   RCAUSE_SYST  = 0x40,
   RCAUSE_WDT   = 0x20,
@@ -47,18 +63,28 @@ typedef enum {
  * Prototypes
  *************************************/
 
+static bool     calibrationValid(const size_t ch, const float cal);
+static void     cmdFrameByte(const uint8_t c);
+static void     cmdReply(uint32_t tag, CmdErr_t err);
 static void     configDefault(void);
 static void     configEchoQueueChar(const uint8_t c);
 static void     configEchoQueueStr(const char *s);
 static void     configInitialiseNVM(void);
+static NvmRd_t  configNVMRead(Emon32Config_t *pDst);
+static bool     configNVMSave(void);
+static void     configRxByte(const uint8_t c);
 static uint16_t configTimeToCycles(const float time, const uint32_t mainsFreq);
 static bool     configureAnalog(void);
 static bool     configureAssumed(void);
 static void     configureBackup(void);
+static void     configureCalibrate(void);
 static bool     configureDatalog(void);
+static bool     configureDivert(void);
 static bool     configureGroupID(void);
 static bool     configureJSON(void);
 static bool     configureLineFrequency(void);
+static bool     configureLogLevel(void);
+static bool     configureModbus(void);
 static bool     configure1WAddr(void);
 static void     configure1WFind(void);
 static bool     configure1WFreeze(void);
@@ -66,22 +92,36 @@ static void     configure1WList(void);
 static bool     configure1WSave(void);
 static bool     configureOPA(void);
 static bool     configureNodeID(void);
+static bool     configurePoll(void);
 static bool     configureRFEnable(void);
 static bool     configureRF433(void);
 static bool     configureRFPower(void);
 static bool     configureSerialLog(void);
+static void     configureStore(void);
+static bool     configureTextDp(void);
+static bool     configureTextSel(void);
+static bool     configureTime(void);
+static bool     configureUART(void);
 static void     enterBootloader(void);
 static uint32_t getBoardRevision(void);
 static char    *getLastReset(void);
 static void     handleConfirmation(char c);
 static void     inBufferClear(const size_t n);
 static size_t   inBufferTok(void);
+static char    *lineCheckName(void);
+static void     printDiagnostics(void);
 static void     printSettingCT(const size_t ch);
 static void     printSettingDatalog(void);
+static void     printSettingDivert(void);
 static void     printSettingJSON(void);
+static void     printSettingModbus(void);
 static void     printSettingOPA(const size_t ch);
+static void     printSettingPoll(void);
+static void     printSettingTextDp(void);
+static void     printSettingTextSel(void);
 static void     printSettingRF(void);
 static void     printSettingRFFreq(void);
+static void     printSettingUART(void);
 static void     printSettingV(const size_t ch);
 static void     printSettings(void);
 static void     printSettingsHR(void);
@@ -97,10 +137,20 @@ static void     zeroAccumulators(void);
  * Local variables
  *************************************/
 
-#define IN_BUFFER_W  64u
-#define ERROR_PREFIX "> Error: "
+#define IN_BUFFER_W     64u
+#define ERROR_PREFIX    "> Error: "
+#define CAL_REPORTS_DEF 5u  /* Reports averaged by a calibration */
+#define CAL_REPORTS_MAX 60u /* Most reports a calibration may average */
+
+/* Result of the command being handled, for the reply to a tagged command.
+ * Any error reported while handling it is a rejected value unless set. */
+static CmdErr_t cmdErr = CMD_OK;
+static CmdSeq_t cmdSeq = {0};
 
 static void serialPutsError(const char *msg) {
+  if (CMD_OK == cmdErr) {
+    cmdErr = CMD_ERR_VALUE;
+  }
   serialPuts(ERROR_PREFIX);
   serialPuts(msg);
   serialPuts("\r\n");
@@ -108,6 +158,9 @@ static void serialPutsError(const char *msg) {
 
 static void printfError(const char *fmt, ...) {
   va_list args;
+  if (CMD_OK == cmdErr) {
+    cmdErr = CMD_ERR_VALUE;
+  }
   serialPuts(ERROR_PREFIX);
   va_start(args, fmt);
   vprintf_(fmt, args);
@@ -127,6 +180,15 @@ static size_t inBufferIdx   = 0;
 static bool   cmdPending    = false;
 static bool   unsavedChange = false;
 
+/* With COBS output, commands on the UART are COBS frames. Leave room for the
+ * NULL when copied to inBuffer. */
+static uint8_t       cmdFrameBuf[IN_BUFFER_W];
+static CobsDecoder_t cmdFrame = {.pBuf = cmdFrameBuf, .m = (IN_BUFFER_W - 1)};
+
+/* Text output decimal places when not set, as sent before they could be:
+ * voltage, power, energy, power factor, frequency, and temperature. */
+static const uint8_t textDpDef[DATA_FIELD_NUM] = {2u, 0, 0, 2u, 2u, 2u};
+
 /*! @brief Set all configuration values to defaults */
 static void configDefault(void) {
   (void)memset(&config, 0, sizeof(config));
@@ -143,8 +205,12 @@ static void configDefault(void) {
   config.baseCfg.epDeltaStore = DELTA_EP_STORE_DEF;
   config.baseCfg.dataGrp      = GROUP_ID_DEF;
   config.baseCfg.logToSerial  = true;
-  config.baseCfg.useJson      = false;
-  config.baseCfg.debugSerial  = false;
+  config.baseCfg.dataFmt      = DATA_FMT_KV;
+  config.baseCfg.logLevel     = 0;
+  config.baseCfg.uartBaud     = UART_BAUD;
+  config.baseCfg.uartParity   = 0;
+  config.baseCfg.uartStop     = 1u;
+  config.baseCfg.modbusAddr   = 0;
   config.dataTxCfg.useRFM     = true;
   config.dataTxCfg.rfmPwr     = RFM_PALEVEL_DEF;
   config.dataTxCfg.rfmFreq    = RFM_FREQ_DEF;
@@ -209,6 +275,46 @@ static void configInitialiseNVM(void) {
   serialPuts("Done!\r\n");
 }
 
+/*! @brief Read the configuration from NVM and check the key and CRC
+ *  @param [out] pDst : destination, only valid if NVM_RD_OK is returned
+ *  @return status of the read
+ */
+static NvmRd_t configNVMRead(Emon32Config_t *pDst) {
+  if (!eepromRead(0, pDst, sizeof(*pDst))) {
+    return NVM_RD_READ_FAIL;
+  }
+  if (CONFIG_NVM_KEY != pDst->key) {
+    return NVM_RD_NO_KEY;
+  }
+  /* -2 from the size to account for the stored 16 bit CRC */
+  if (calcCRC16_ccitt(pDst, sizeof(*pDst) - 2u) != pDst->crc16_ccitt) {
+    return NVM_RD_CRC;
+  }
+  return NVM_RD_OK;
+}
+
+/*! @brief Save the configuration to NVM and read it back to check the write
+ *  @return true if the stored copy matches
+ */
+static bool configNVMSave(void) {
+  /* Compare in small pieces to keep the copy off the stack */
+  const uint8_t *pCfg = (const uint8_t *)&config;
+  uint8_t        chunk[32];
+
+  config.crc16_ccitt = calcCRC16_ccitt(&config, (sizeof(config) - 2u));
+  eepromInitConfig(&config, sizeof(config));
+
+  for (size_t i = 0; i < sizeof(config); i += sizeof(chunk)) {
+    const size_t n = ((sizeof(config) - i) < sizeof(chunk))
+                         ? (sizeof(config) - i)
+                         : sizeof(chunk);
+    if (!eepromRead(i, chunk, n) || (0 != memcmp(chunk, pCfg + i, n))) {
+      return false;
+    }
+  }
+  return true;
+}
+
 static bool configureAnalog(void) {
   /* String format: k<x> <a> <y.y> <z.z> v1 v2
    * Find space delimiters, then convert to null and a->i/f
@@ -308,9 +414,16 @@ static bool configureAnalog(void) {
   }
   calPhase = convF.val;
 
+  /* The phase is corrected by interpolating between samples held in a short
+   * buffer, so the lead must be bounded. */
+  if ((calPhase < -45.0f) || (calPhase > 45.0f)) {
+    serialPutsError("Phase out of range (valid: -45-45).");
+    return false;
+  }
+
   if (NUM_V > ch) {
 
-    if ((calAmpl <= 25.0f) || (calAmpl >= 150.0f)) {
+    if (!calibrationValid(ch, calAmpl)) {
       serialPutsError("vCal out of range (valid: 25-150).");
       return false;
     }
@@ -365,8 +478,8 @@ static bool configureAnalog(void) {
     vCh2 = vCh1;
   }
 
-  /* CT configuration - assume 10/200 A min/max CTs */
-  if ((calAmpl < 10.0f) || (calAmpl) > 200.0f) {
+  /* CT configuration */
+  if (!calibrationValid(ch, calAmpl)) {
     serialPutsError("iCal out of range (valid: 10-200).");
     return false;
   }
@@ -393,19 +506,37 @@ static bool configureAnalog(void) {
 }
 
 static bool configureAssumed(void) {
+  /* a<v> [<pf>]
+   *   - v  : assumed RMS voltage (V)
+   *   - pf : optional assumed power factor (%), 1-100
+   */
+  const size_t tokens = inBufferTok();
+
   ConvUint_t convU = utilAtoui(inBuffer + 1, ITOA_BASE10);
-  if (convU.valid) {
-    ECMCfg_t *pEcmCfg          = ecmConfigGet();
-    pEcmCfg->assumedVrms       = qfp_uint2float(convU.val.u32);
-    config.baseCfg.assumedVrms = convU.val.u16;
-    return true;
+  if (!convU.valid) {
+    return false;
   }
-  return false;
+
+  ECMCfg_t *pEcmCfg          = ecmConfigGet();
+  pEcmCfg->assumedVrms       = qfp_uint2float(convU.val.u32);
+  config.baseCfg.assumedVrms = convU.val.u16;
+
+  if (tokens > 0) {
+    const size_t posPF = strlen(inBuffer) + 1u;
+    ConvUint_t   convPF = utilAtoui(inBuffer + posPF, ITOA_BASE10);
+    if (!convPF.valid || !convPF.val.u32 || (convPF.val.u32 > 100u)) {
+      serialPutsError("Assumed PF out of range (valid: 1-100).");
+      return false;
+    }
+    config.baseCfg.assumedPF = convPF.val.u8;
+    pEcmCfg->assumedPF       = configAssumedPF();
+  }
+  return true;
 }
 
 static void configureBackup(void) {
   /* Send all configuration values as JSON over the serial link. */
-  char strBuf[8] = {0};
+  char strBuf[16] = {0};
 
   /* Open JSON block */
   serialPuts("{");
@@ -423,6 +554,8 @@ static void configureBackup(void) {
           config.dataTxCfg.rfmFreq, config.baseCfg.mainsFreq, strBuf);
 
   printf_("\"assumedV\":%d,", config.baseCfg.assumedVrms);
+  printf_("\"assumedPF\":%d,",
+          config.baseCfg.assumedPF ? config.baseCfg.assumedPF : 100);
   /* {v_config} list of dicts */
   serialPuts("\"v_config\":[");
   for (size_t i = 0; i < NUM_V; i++) {
@@ -454,6 +587,99 @@ static void configureBackup(void) {
   serialPuts("}\r\n");
 }
 
+static void configureCalibrate(void) {
+  /* kc[<x> <v> <p> [<n>]] | kca
+   *   - kc : show the state of the calibration and any proposed values
+   *   - kc<x> <v> <p> [<n>] : calibrate CT x and its voltage channel against a
+   *     resistive load of p (W) at v (V RMS), averaged over n reports
+   *   - kca : apply the proposed values. Not saved until the s command
+   */
+  const char *statusNames[] = {"idle",      "running",     "done",
+                               "no signal", "PF too low", "unstable"};
+  ECMCalResult_t res;
+
+  if ('a' == inBuffer[2]) {
+    if (!ecmCalTake(&res)) {
+      serialPutsError("No calibration values to apply.");
+      cmdErr = CMD_ERR_FAIL;
+      return;
+    }
+
+    const size_t idxV = config.ctCfg[res.idxCt].vChan1;
+    if (!calibrationValid(idxV, res.voltageCalRaw) ||
+        !calibrationValid((res.idxCt + NUM_V), res.ctCalRaw)) {
+      serialPutsError("Proposed values out of range, not applied.");
+      cmdErr = CMD_ERR_FAIL;
+      return;
+    }
+
+    (void)configSetCalibration(idxV, res.voltageCalRaw);
+    (void)configSetCalibration((res.idxCt + NUM_V), res.ctCalRaw);
+    printSettingV(idxV);
+    printSettingCT(res.idxCt);
+    return;
+  }
+
+  if (0 == inBuffer[2]) {
+    ecmCalResult(&res);
+    printf_("cal = %s", statusNames[res.status]);
+    if (ECM_CAL_IDLE != res.status) {
+      printf_(", CT%u", (res.idxCt + 1u));
+    }
+    if (ECM_CAL_DONE == res.status) {
+      serialPuts(", vCal = ");
+      putFloat(res.voltageCalRaw, 0);
+      serialPuts(", iCal = ");
+      putFloat(res.ctCalRaw, 0);
+    }
+    serialPuts("\r\n");
+    return;
+  }
+
+  const size_t tokens = inBufferTok();
+  if (tokens < 2) {
+    serialPutsError("Missing required parameters.");
+    return;
+  }
+
+  ConvUint_t convU = utilAtoui(inBuffer + 2, ITOA_BASE10);
+  if (!convU.valid || (0 == convU.val.u32) || (convU.val.u32 > NUM_CT)) {
+    printfError("CT out of range (valid: 1-%d).", NUM_CT);
+    return;
+  }
+  const size_t ch = convU.val.u32 - 1u;
+
+  size_t            pos   = strlen(inBuffer) + 1u;
+  const ConvFloat_t convV = utilAtof(inBuffer + pos);
+  pos += strlen(inBuffer + pos) + 1u;
+  const ConvFloat_t convP = utilAtof(inBuffer + pos);
+  if (!convV.valid || !convP.valid || !(convV.val > 0.0f) ||
+      !(convP.val > 0.0f)) {
+    serialPutsError("Reference voltage and power must be above 0.");
+    return;
+  }
+
+  uint32_t reports = CAL_REPORTS_DEF;
+  if (tokens > 2) {
+    pos += strlen(inBuffer + pos) + 1u;
+    convU = utilAtoui(inBuffer + pos, ITOA_BASE10);
+    if (!convU.valid || (0 == convU.val.u32) ||
+        (convU.val.u32 > CAL_REPORTS_MAX)) {
+      printfError("Reports must be 1 to %u.", CAL_REPORTS_MAX);
+      return;
+    }
+    reports = convU.val.u32;
+  }
+
+  if (!ecmCalStart(ch, convV.val, convP.val, reports)) {
+    serialPutsError("CT must be on a single voltage channel.");
+    cmdErr = CMD_ERR_FAIL;
+    return;
+  }
+  printf_("> Calibrating CT%u over %u reports. Check with kc.\r\n",
+          (ch + 1u), reports);
+}
+
 static bool configureDatalog(void) {
   ConvFloat_t convF = utilAtof(inBuffer + 1);
   /* Set the datalog period (s) in range 0.5 <= t <= 600 */
@@ -469,13 +695,72 @@ static bool configureDatalog(void) {
 
   config.baseCfg.reportTime = convF.val;
   config.baseCfg.reportCycles =
-      configTimeToCycles(convF.val, config.baseCfg.mainsFreq);
+      configTimeToCycles(convF.val, ecmConfigGet()->mainsFreq);
   ecmConfigReportCycles(config.baseCfg.reportCycles);
 
   printSettingDatalog();
   return true;
 }
 
+static bool configureDivert(void) {
+  /* kd<m> [<c> <l> <h>]
+   *   - m : 0 off, 1 threshold, 2 energy bucket
+   *   - c : CT on the grid connection [1..NUM_CT]
+   *   - l : threshold, export (W) to switch on; bucket, capacity (J)
+   *   - h : minimum half-cycles between changes
+   * In threshold mode the output switches off when importing. With m = 0,
+   * the other values are kept.
+   */
+  const size_t tokens = inBufferTok();
+
+  ConvUint_t convU = utilAtoui(inBuffer + 2, ITOA_BASE10);
+  if (!convU.valid || (convU.val.u32 > ECM_DIVERT_BUCKET)) {
+    serialPutsError("Mode must be 0 (off), 1 (threshold), or 2 (bucket).");
+    return false;
+  }
+  const uint8_t mode = convU.val.u8;
+
+  if (ECM_DIVERT_DISABLED != mode) {
+    if (tokens < 3) {
+      serialPutsError("Missing required parameters.");
+      return false;
+    }
+
+    size_t pos = strlen(inBuffer) + 1u;
+    convU      = utilAtoui(inBuffer + pos, ITOA_BASE10);
+    if (!convU.valid || (0 == convU.val.u32) || (convU.val.u32 > NUM_CT)) {
+      printfError("CT out of range (valid: 1-%d).", NUM_CT);
+      return false;
+    }
+    const uint8_t ch = convU.val.u8 - 1u;
+
+    pos += strlen(inBuffer + pos) + 1u;
+    convU = utilAtoui(inBuffer + pos, ITOA_BASE10);
+    if (!convU.valid || (0 == convU.val.u32) ||
+        (convU.val.u32 > UINT16_MAX)) {
+      printfError("Level must be 1 to %u.", UINT16_MAX);
+      return false;
+    }
+    const uint16_t level = convU.val.u16;
+
+    pos += strlen(inBuffer + pos) + 1u;
+    convU = utilAtoui(inBuffer + pos, ITOA_BASE10);
+    if (!convU.valid || (convU.val.u32 > UINT8_MAX)) {
+      printfError("Hold must be 0 to %u half-cycles.", UINT8_MAX);
+      return false;
+    }
+
+    config.divertCh    = ch;
+    config.divertLevel = level;
+    config.divertHold  = convU.val.u8;
+  }
+
+  config.divertMode = mode;
+  configDivert(&ecmConfigGet()->divertCfg);
+  printSettingDivert();
+  return true;
+}
+
 static bool configureGroupID(void) {
   ConvUint_t convU = utilAtoui(inBuffer + 1, ITOA_BASE10);
 
@@ -495,26 +780,58 @@ static bool configureGroupID(void) {
 }
 
 static bool configureJSON(void) {
-  ConvUint_t convU = utilAtoui(inBuffer + 1, ITOA_BASE10);
+  /* j<n> [<k> [<x>]]
+   *   - n : serial format
+   *   - k : text line checksum. 0: none, 1: XOR, 2: CRC8; unchanged if absent
+   *   - x : 1 to add frequency and power factor fields, 0 to leave them out;
+   *         unchanged if absent. See js to select the fields one by one.
+   */
+  const size_t tokens = inBufferTok();
+  ConvUint_t   convU  = utilAtoui(inBuffer + 1, ITOA_BASE10);
+  ConvUint_t   convK  = {true, {config.lineCheck}};
+  uint8_t      sel    = configTextSel();
 
   if (!convU.valid) {
-    serialPutsError("Invalid JSON value.");
+    serialPutsError("Invalid format value.");
     return false;
   }
 
-  if (convU.val.u32 > 1) {
-    serialPutsError("JSON value must be 0 or 1.");
+  if (convU.val.u32 > DATA_FMT_COBS) {
+    serialPutsError("Format value must be 0, 1, 2, 3, or 4.");
     return false;
   }
 
-  config.baseCfg.useJson = (bool)convU.val.u8;
+  if (tokens > 0) {
+    const size_t posK = strlen(inBuffer) + 1u;
+    convK             = utilAtoui(inBuffer + posK, ITOA_BASE10);
+    if (!convK.valid || (convK.val.u32 > LINE_CHECK_CRC8)) {
+      serialPutsError("Checksum must be 0 (none), 1 (XOR), or 2 (CRC8).");
+      return false;
+    }
+
+    if (tokens > 1) {
+      const size_t     posX  = posK + strlen(inBuffer + posK) + 1u;
+      const ConvUint_t convX = utilAtoui(inBuffer + posX, ITOA_BASE10);
+      if (!convX.valid || (convX.val.u32 > 1u)) {
+        serialPutsError("Frequency and PF must be 0 (off) or 1 (on).");
+        return false;
+      }
+      sel = convX.val.u32 ? (sel | DATA_SEL_FREQ | DATA_SEL_PF)
+                          : (sel & ~(DATA_SEL_FREQ | DATA_SEL_PF));
+    }
+  }
+
+  config.baseCfg.dataFmt = convU.val.u8;
+  config.lineCheck       = convK.val.u8;
+  config.textSel         = sel ^ DATA_SEL_DEF;
   printSettingJSON();
+  printSettingTextSel();
   return true;
 }
 
 static bool configureLineFrequency(void) {
   /* f<n>
-   * n must be 50 or 60
+   * n must be 50 or 60, or 0 to detect automatically
    */
   ConvUint_t convU = utilAtoui(inBuffer + 1, ITOA_BASE10);
   if (!convU.valid) {
@@ -522,22 +839,63 @@ static bool configureLineFrequency(void) {
     return false;
   }
 
-  if (!((50 == convU.val.u32) || (60 == convU.val.u32))) {
-    serialPutsError("Frequency must be 50 or 60.");
+  if (!((0 == convU.val.u32) || (50 == convU.val.u32) ||
+        (60 == convU.val.u32))) {
+    serialPutsError("Frequency must be 0 (auto), 50 or 60.");
     return false;
   }
 
   config.baseCfg.mainsFreq = convU.val.u8;
 
-  /* Recalculate all CT interpolation values */
-  ECMCfg_t *ecmCfg  = ecmConfigGet();
-  ecmCfg->mainsFreq = convU.val.u8;
-  for (size_t i = 0; i < NUM_CT; i++) {
-    ecmConfigChannel(i + NUM_V);
+  /* Recalculate all CT interpolation values and the report cycles */
+  ecmConfigMainsFreq(config.baseCfg.mainsFreq);
+  config.baseCfg.reportCycles = ecmConfigGet()->reportCycles;
+
+  if (0 == config.baseCfg.mainsFreq) {
+    serialPuts("> Mains frequency set to: auto\r\n");
+  } else {
+    printf_("> Mains frequency set to: %d\r\n", config.baseCfg.mainsFreq);
   }
-  ecmFlush();
+  return true;
+}
 
-  printf_("> Mains frequency set to: %d\r\n", config.baseCfg.mainsFreq);
+static bool configureLogLevel(void) {
+  /* v<n>
+   * n is the log threshold: 0 (error), 1 (warn), 2 (info), or 3 (debug), or
+   * the name of the level. Applies immediately.
+   */
+  LogLevel_t level;
+
+  if (!logLevelParse(inBuffer + 1, &level)) {
+    serialPutsError("Log level must be 0-3, or error, warn, info, or debug.");
+    return false;
+  }
+
+  logLevelSet(level);
+  config.baseCfg.logLevel = (uint8_t)(level + 1u);
+  printf_("log = %s\r\n", logLevelName(level));
+  return true;
+}
+
+static bool configureModbus(void) {
+  /* h<n>
+   * n is the slave address, 1..247, or 0 to use the UART for serial output.
+   * Applied at the next start, once saved.
+   */
+  ConvUint_t convU = utilAtoui(inBuffer + 1, ITOA_BASE10);
+  if (!convU.valid) {
+    serialPutsError("Invalid Modbus address.");
+    return false;
+  }
+
+  if (convU.val.u32 > 247u) {
+    serialPutsError("Modbus address out of range (valid: 0-247).");
+    return false;
+  }
+
+  config.baseCfg.modbusAddr = convU.val.u8;
+  printSettingModbus();
+  serialPuts("Modbus settings apply after save (s) and restart.\r\n");
   return true;
 }
 
@@ -772,6 +1130,27 @@ static bool configureNodeID(void) {
   return true;
 }
 
+static bool configurePoll(void) {
+  /* y<n>
+   * n is the node ID for the polled protocol, 1..254, 255 to derive it from
+   * the serial number, or 0 to use the UART for serial output. Applied at the
+   * next start, once saved.
+   */
+  ConvUint_t convU = utilAtoui(inBuffer + 1, ITOA_BASE10);
+  if (!convU.valid || (convU.val.u32 > POLL_NODE_SERIAL)) {
+    serialPutsError("Node ID must be 0-254, or 255 for the serial number.");
+    return false;
+  }
+
+  config.pollNode = convU.val.u8;
+  printSettingPoll();
+  if (config.pollNode && config.baseCfg.modbusAddr) {
+    serialPuts("Modbus is enabled, and takes priority (h0 to disable).\r\n");
+  }
+  serialPuts("Polled protocol settings apply after save (s) and restart.\r\n");
+  return true;
+}
+
 static bool configureRFEnable(void) {
   int32_t val = inBuffer[1] - '0';
 
@@ -849,6 +1228,228 @@ static bool configureSerialLog(void) {
   return true;
 }
 
+static void configureStore(void) {
+  /* s[x]
+   *   - s  : save the configuration to NVM
+   *   - sl : load the saved configuration, discarding unsaved changes
+   *   - sd : restore the defaults and save them
+   * Commands are handled in the main loop between reports, so a report is
+   * never formatted from a partly loaded configuration.
+   */
+  Emon32Config_t stored;
+  NvmRd_t        status;
+
+  switch (inBuffer[1]) {
+  case 0:
+    serialPuts("> Saving configuration to NVM... ");
+    if (!configNVMSave()) {
+      serialPuts("\r\n");
+      serialPutsError("Save failed, stored copy does not match.");
+      cmdErr = CMD_ERR_FAIL;
+      return;
+    }
+    serialPuts("Done!\r\n");
+    break;
+  case 'l':
+    status = configNVMRead(&stored);
+    if (NVM_RD_READ_FAIL == status) {
+      serialPutsError("Load failed, could not read NVM.");
+      cmdErr = CMD_ERR_FAIL;
+      return;
+    } else if (NVM_RD_NO_KEY == status) {
+      serialPutsError("Load failed, no saved configuration.");
+      cmdErr = CMD_ERR_FAIL;
+      return;
+    } else if (NVM_RD_CRC == status) {
+      serialPutsError("Load failed, CRC mismatch. Configuration unchanged.");
+      cmdErr = CMD_ERR_FAIL;
+      return;
+    }
+    config = stored;
+    serialPuts("> Loaded configuration from NVM.\r\n");
+    break;
+  case 'd':
+    configDefault();
+    serialPuts("> Restoring and saving default values... ");
+    if (!configNVMSave()) {
+      serialPuts("\r\n");
+      serialPutsError("Save failed, stored copy does not match.");
+      cmdErr = CMD_ERR_FAIL;
+      unsavedChange = true;
+      emon32EventSet(EVT_CONFIG_LOADED);
+      return;
+    }
+    serialPuts("Done!\r\n");
+    break;
+  default:
+    serialPutsError("Unknown store command (valid: s, sl, sd).");
+    return;
+  }
+
+  unsavedChange = false;
+  emon32EventSet(EVT_CONFIG_SAVED);
+  if (0 != inBuffer[1]) {
+    emon32EventSet(EVT_CONFIG_LOADED);
+  }
+}
+
+static bool configureTextDp(void) {
+  /* jp [<v> [<p> [<e> [<pf> [<f> [<t>]]]]]]
+   * Decimal places for voltage, power, energy, power factor, frequency, and
+   * temperature, each 0..FTOA_DP_MAX. Classes that are not given are
+   * unchanged. With no values, all return to the defaults.
+   */
+  uint8_t  dp[DATA_FIELD_NUM];
+  uint32_t vals[DATA_FIELD_NUM];
+  size_t   num;
+
+  if (!utilAtouiList(inBuffer + 2, vals, DATA_FIELD_NUM, &num)) {
+    printfError("Give up to %u decimal places, each 0 to %u.", DATA_FIELD_NUM,
+                FTOA_DP_MAX);
+    return false;
+  }
+
+  if (0 == num) {
+    (void)memset(config.textDp, 0, sizeof(config.textDp));
+    printSettingTextDp();
+    return true;
+  }
+
+  for (size_t i = 0; i < DATA_FIELD_NUM; i++) {
+    dp[i] = config.textDp[i];
+    if (i >= num) {
+      continue;
+    }
+
+    if (vals[i] > FTOA_DP_MAX) {
+      printfError("Decimal places must be 0 to %u.", FTOA_DP_MAX);
+      return false;
+    }
+    dp[i] = (uint8_t)vals[i] + 1u;
+  }
+
+  (void)memcpy(config.textDp, dp, sizeof(config.textDp));
+  printSettingTextDp();
+  return true;
+}
+
+static bool configureTextSel(void) {
+  /* js [<e> [<pf> [<f>]]]
+   * Include (1) or leave out (0) the energy, power factor, and frequency
+   * fields in the text formats, whichever channels are active. Fields that are
+   * not given are unchanged. With no values, only energy is included.
+   */
+  static const uint8_t selBit[3] = {DATA_SEL_ENERGY, DATA_SEL_PF,
+                                    DATA_SEL_FREQ};
+
+  uint32_t vals[3];
+  size_t   num;
+  uint8_t  sel = configTextSel();
+
+  if (!utilAtouiList(inBuffer + 2, vals, 3u, &num)) {
+    serialPutsError("Give up to 3 fields, each 0 (leave out) or 1 (include).");
+    return false;
+  }
+
+  if (0 == num) {
+    config.textSel = 0;
+    printSettingTextSel();
+    return true;
+  }
+
+  for (size_t i = 0; i < num; i++) {
+    if (vals[i] > 1u) {
+      serialPutsError("Fields must be 0 (leave out) or 1 (include).");
+      return false;
+    }
+    sel = vals[i] ? (sel | selBit[i]) : (sel & ~selBit[i]);
+  }
+
+  config.textSel = sel ^ DATA_SEL_DEF;
+  printSettingTextSel();
+  return true;
+}
+
+static bool configureTime(void) {
+  /* t<n>
+   * n is the Unix time (s) now. Reports after this carry the time; those
+   * already sent are not changed.
+   */
+  char       isoBuf[WALL_CLOCK_ISO_W];
+  ConvUint_t convU = utilAtoui(inBuffer + 1, ITOA_BASE10);
+
+  if (!convU.valid || (0 == convU.val.u32)) {
+    serialPutsError("Invalid time (Unix time in s).");
+    return false;
+  }
+
+  wallClockSet(convU.val.u32);
+  (void)wallClockIso(isoBuf, convU.val.u32);
+  printf_("time = %s\r\n", isoBuf);
+  return true;
+}
+
+static bool configureUART(void) {
+  /* q<baud> [<parity> [<stop> [<guard>]]]
+   *   - baud   : baud rate, must be reachable within 1 %
+   *   - parity : n (none), e (even), or o (odd); default n
+   *   - stop   : 1 or 2 stop bits; default 1
+   *   - guard  : RS485 turnaround guard (us); default 0
+   * Applied at the next start, once saved.
+   */
+  const char   parities[] = "neo";
+  const size_t tokens     = inBufferTok();
+  uint16_t     reg;
+  uint8_t      parity = 0;
+  uint8_t      stop   = 1u;
+  uint16_t     guard  = 0;
+
+  ConvUint_t convU = utilAtoui(inBuffer + 1, ITOA_BASE10);
+  if (!convU.valid || !utilBaudReg(convU.val.u32, F_PERIPH, &reg)) {
+    serialPutsError("Baud rate can not be reached.");
+    return false;
+  }
+
+  if (tokens > 0) {
+    const size_t posParity = strlen(inBuffer) + 1u;
+    const char  *pParity   = strchr(parities, inBuffer[posParity]);
+    if ((0 == inBuffer[posParity]) || !pParity ||
+        (0 != inBuffer[posParity + 1u])) {
+      serialPutsError("Parity must be n, e, or o.");
+      return false;
+    }
+    parity = (uint8_t)(pParity - parities);
+
+    if (tokens > 1) {
+      const size_t posStop = posParity + 2u;
+      ConvUint_t   convS   = utilAtoui(inBuffer + posStop, ITOA_BASE10);
+      if (!convS.valid || (convS.val.u32 < 1u) || (convS.val.u32 > 2u)) {
+        serialPutsError("Stop bits must be 1 or 2.");
+        return false;
+      }
+      stop = convS.val.u8;
+
+      if (tokens > 2) {
+        const size_t posGuard = posStop + strlen(inBuffer + posStop) + 1u;
+        ConvUint_t   convG    = utilAtoui(inBuffer + posGuard, ITOA_BASE10);
+        if (!convG.valid || (convG.val.u32 > RS485_GUARD_MAX)) {
+          printfError("Guard must be 0 to %u us.", RS485_GUARD_MAX);
+          return false;
+        }
+        guard = convG.val.u16;
+      }
+    }
+  }
+
+  config.baseCfg.uartBaud   = convU.val.u32;
+  config.baseCfg.uartParity = parity;
+  config.baseCfg.uartStop   = stop;
+  config.rs485Guard         = guard;
+  printSettingUART();
+  serialPuts("UART settings apply after save (s) and restart.\r\n");
+  return true;
+}
+
 static void enterBootloader(void) {
   /* Set confirmation state and prompt user
    * Response will be handled asynchronously by handleConfirmation() */
@@ -926,6 +1527,12 @@ static size_t inBufferTok(void) {
   return tokCount;
 }
 
+static char *lineCheckName(void) {
+  char *names[] = {"off", "xor", "crc8"};
+  return names[(config.lineCheck <= LINE_CHECK_CRC8) ? config.lineCheck
+                                                     : LINE_CHECK_NONE];
+}
+
 static void printSettingCT(const size_t ch) {
   printf_("iCal%u = ", (ch + 1));
   putFloat(config.ctCfg[ch].ctCal, 0);
@@ -944,8 +1551,47 @@ static void printSettingDatalog(void) {
   serialPuts("\r\n");
 }
 
+static void printSettingDivert(void) {
+  const char *modes[] = {"off", "threshold", "bucket"};
+  printf_("divert = %s, divertCT = %u, divertLevel = %u, divertHold = %u\r\n",
+          modes[(config.divertMode <= ECM_DIVERT_BUCKET) ? config.divertMode
+                                                          : 0],
+          (config.divertCh + 1), config.divertLevel, config.divertHold);
+}
+
 static void printSettingJSON(void) {
-  printf_("json = %s\r\n", config.baseCfg.useJson ? "on" : "off");
+  printf_("json = %s\r\n",
+          (DATA_FMT_JSON == config.baseCfg.dataFmt) ? "on" : "off");
+  printf_("csv = %s\r\n",
+          (DATA_FMT_CSV == config.baseCfg.dataFmt) ? "on" : "off");
+  printf_("binary = %s\r\n",
+          (DATA_FMT_BINARY == config.baseCfg.dataFmt) ? "on" : "off");
+  printf_("cobs = %s\r\n",
+          (DATA_FMT_COBS == config.baseCfg.dataFmt) ? "on" : "off");
+  printf_("checksum = %s\r\n", lineCheckName());
+}
+
+static void printSettingModbus(void) {
+  printf_("modbus = %u\r\n", config.baseCfg.modbusAddr);
+}
+
+static void printSettingPoll(void) {
+  printf_("poll = %u\r\n", config.pollNode);
+}
+
+static void printSettingTextSel(void) {
+  const uint8_t sel = configTextSel();
+
+  printf_("energy = %s\r\n", (sel & DATA_SEL_ENERGY) ? "on" : "off");
+  printf_("pf = %s\r\n", (sel & DATA_SEL_PF) ? "on" : "off");
+  printf_("freq = %s\r\n", (sel & DATA_SEL_FREQ) ? "on" : "off");
+}
+
+static void printSettingTextDp(void) {
+  printf_("dp = %u %u %u %u %u %u\r\n", configTextDp(DATA_FIELD_V),
+          configTextDp(DATA_FIELD_P), configTextDp(DATA_FIELD_E),
+          configTextDp(DATA_FIELD_PF), configTextDp(DATA_FIELD_F),
+          configTextDp(DATA_FIELD_TEMP));
 }
 
 static void printSettingOPA(const size_t ch) {
@@ -996,6 +1642,23 @@ static void printSettingRFFreq(void) {
   }
 }
 
+static void printSettingUART(void) {
+  const char parities[] = "NEO";
+  uint32_t   baud       = config.baseCfg.uartBaud;
+  uint8_t    parity     = config.baseCfg.uartParity;
+
+  /* Configurations from before these fields were added hold 0 */
+  if (0 == baud) {
+    baud = UART_BAUD;
+  }
+  if (parity > 2u) {
+    parity = 0;
+  }
+
+  printf_("uart = %lu 8%c%u, rs485Guard = %u\r\n", baud, parities[parity],
+          (2u == config.baseCfg.uartStop) ? 2u : 1u, config.rs485Guard);
+}
+
 static void printSettingV(const size_t ch) {
   printf_("vCal%u = ", (ch + 1));
   putFloat(config.voltageCfg[ch].voltageCal, 0);
@@ -1033,6 +1696,51 @@ static void printAccumulators(void) {
   serialPuts("\r\n");
 }
 
+static void printDiagnostics(void) {
+  if ('z' == inBuffer[1]) {
+    ecmDiagnosticsReset();
+    serialPuts("> Diagnostic counters cleared.\r\n");
+    return;
+  }
+
+  const ECMDiagnostics_t *pDiag = ecmDiagnostics();
+  const PingPongStats_t  *pSets = adcSetStats();
+  UARTStats_t             uartStat;
+
+  uartStats(&uartStat);
+  serialPuts("> Diagnostics:\r\n");
+  printf_("  - ADC lost samples : %lu\r\n", adcMissed());
+  printf_("  - ADC set overruns : %lu\r\n", pSets->overruns);
+  printf_("  - ADC resyncs      : %lu\r\n", pSets->resyncs);
+  printf_("  - Cycle overruns   : %lu\r\n", pDiag->cycleOverrun);
+  printf_("  - Long cycles      : %lu\r\n", pDiag->cycleLong);
+  printf_("  - Rejected crossing: %lu\r\n", pDiag->zcRejected);
+  printf_("  - Dropped events   : %lu\r\n", pDiag->eventDropped);
+  printf_("  - Non-finite values: %lu\r\n", pDiag->nonFinite);
+  printf_("  - Reports truncated: %lu\r\n", dataPackTruncated());
+  printf_("  - UART Tx queued   : %lu\r\n", uartStat.queued);
+  printf_("  - UART Tx sent     : %lu\r\n", uartStat.sent);
+  printf_("  - UART Tx dropped  : %lu (%lu writes cut short)\r\n",
+          uartStat.dropped, uartStat.writesCut);
+  printf_("  - UART Tx peak     : %lu\r\n", uartStat.txPeak);
+  printf_("  - UART Rx errors   : %lu frame, %lu parity, %lu overrun\r\n",
+          uartStat.rxFrame, uartStat.rxParity, uartStat.rxOverrun);
+  if (modbusMapEnabled()) {
+    const ModbusStats_t *pStats = modbusMapStats();
+    printf_("  - Modbus frames    : %lu\r\n", pStats->frames);
+    printf_("  - Modbus CRC errors: %lu\r\n", pStats->crcErrors);
+    printf_("  - Modbus overruns  : %lu\r\n", pStats->overruns);
+  }
+  if (pollMapEnabled()) {
+    const PollStats_t *pStats = pollMapStats();
+    printf_("  - Poll frames      : %lu\r\n", pStats->frames);
+    printf_("  - Poll bad frames  : %lu\r\n", pStats->badFrames);
+    printf_("  - Poll overruns    : %lu\r\n", pStats->overruns);
+    printf_("  - Poll retries     : %lu\r\n", pStats->repeats);
+  }
+  serialPuts("\r\n");
+}
+
 static void printSettings(void) {
   if ('h' == inBuffer[1]) {
     printSettingsHR();
@@ -1051,7 +1759,12 @@ static void printSettings(void) {
 
 static void printSettingsHR(void) {
   serialPuts("\r\n\r\n==== Settings ====\r\n\r\n");
-  printf_("Mains frequency (Hz):      %d\r\n", config.baseCfg.mainsFreq);
+  if (0 == config.baseCfg.mainsFreq) {
+    printf_("Mains frequency (Hz):      auto (%d)\r\n",
+            (int)ecmConfigGet()->mainsFreq);
+  } else {
+    printf_("Mains frequency (Hz):      %d\r\n", config.baseCfg.mainsFreq);
+  }
   serialPuts("Data log time (s):         ");
   putFloat(config.baseCfg.reportTime, 0);
   serialPuts("\r\nData transmission:         ");
@@ -1064,8 +1777,52 @@ static void printSettingsHR(void) {
   } else {
     serialPuts("Serial only\r\n");
   }
+  const char *fmtNames[] = {"Key:Value", "JSON", "CSV", "Binary",
+                            "Binary, COBS framed"};
   printf_("Data format:               %s\r\n",
-          config.baseCfg.useJson ? "JSON" : "Key:Value");
+          fmtNames[(config.baseCfg.dataFmt <= DATA_FMT_COBS)
+                       ? config.baseCfg.dataFmt
+                       : DATA_FMT_KV]);
+  printf_("Line checksum:             %s\r\n", lineCheckName());
+  printf_("Optional fields:           E %s, PF %s, F %s\r\n",
+          (configTextSel() & DATA_SEL_ENERGY) ? "on" : "off",
+          (configTextSel() & DATA_SEL_PF) ? "on" : "off",
+          (configTextSel() & DATA_SEL_FREQ) ? "on" : "off");
+  printf_("Decimal places:            V %u, P %u, E %u, PF %u, F %u, t %u\r\n",
+          configTextDp(DATA_FIELD_V), configTextDp(DATA_FIELD_P),
+          configTextDp(DATA_FIELD_E), configTextDp(DATA_FIELD_PF),
+          configTextDp(DATA_FIELD_F), configTextDp(DATA_FIELD_TEMP));
+  if (config.baseCfg.modbusAddr) {
+    printf_("Modbus RTU slave address:  %u\r\n", config.baseCfg.modbusAddr);
+  } else {
+    serialPuts("Modbus RTU:                off\r\n");
+  }
+  if (pollMapEnabled()) {
+    printf_("Polled protocol node ID:   %u\r\n", pollMapNode());
+  } else if (config.pollNode) {
+    printf_("Polled protocol node ID:   %u (after restart)\r\n",
+            config.pollNode);
+  } else {
+    serialPuts("Polled protocol:           off\r\n");
+  }
+  if (ECM_DIVERT_THRESHOLD == config.divertMode) {
+    printf_("Surplus diversion:         CT %u, on above %u W export, hold "
+            "%u\r\n",
+            (config.divertCh + 1), config.divertLevel, config.divertHold);
+  } else if (ECM_DIVERT_BUCKET == config.divertMode) {
+    printf_("Surplus diversion:         CT %u, %u J bucket, hold %u\r\n",
+            (config.divertCh + 1), config.divertLevel, config.divertHold);
+  } else {
+    serialPuts("Surplus diversion:         off\r\n");
+  }
+  printf_("Log level:                 %s\r\n", logLevelName(logLevelGet()));
+  if (wallClockValid()) {
+    char isoBuf[WALL_CLOCK_ISO_W];
+    (void)wallClockIso(isoBuf, wallClockNow());
+    printf_("Wall clock:                %s\r\n", isoBuf);
+  } else {
+    serialPuts("Wall clock:                not set\r\n");
+  }
   serialPuts("\r\n");
 
   for (size_t i = 0; i < NUM_OPA; i++) {
@@ -1097,7 +1854,10 @@ static void printSettingsHR(void) {
     serialPuts("\r\n");
   }
 
-  printf_("Assumed RMS voltage: %d V\r\n\r\n", config.baseCfg.assumedVrms);
+  printf_("Assumed RMS voltage: %d V\r\n", config.baseCfg.assumedVrms);
+  serialPuts("Assumed PF:          ");
+  putFloat(configAssumedPF(), 0);
+  serialPuts("\r\n\r\n");
 
   serialPuts(
       "| Ref | Channel | Active | Calibration |  Phase  | In 1 | In 2 |\r\n");
@@ -1129,7 +1889,10 @@ static void printSettingsKV(void) {
   printf_("version = %d.%d.%d\r\n", VERSION_FW_MAJ, VERSION_FW_MIN,
           VERSION_FW_REV);
   printf_("commit = %s\r\n", emon32_build_info().revision);
+  printf_("profile = %s\r\n", emon32_build_info().configuration);
   printf_("assumedV = %d\r\n", config.baseCfg.assumedVrms);
+  printf_("assumedPF = %d\r\n",
+          config.baseCfg.assumedPF ? config.baseCfg.assumedPF : 100);
   for (size_t i = 0; i < NUM_V; i++) {
     printSettingV(i);
   }
@@ -1142,6 +1905,13 @@ static void printSettingsKV(void) {
   printSettingRF();
   printSettingDatalog();
   printSettingJSON();
+  printSettingTextSel();
+  printSettingTextDp();
+  printSettingUART();
+  printSettingModbus();
+  printSettingPoll();
+  printSettingDivert();
+  printf_("log = %s\r\n", logLevelName(logLevelGet()));
 }
 
 static void putFloat(float val, const size_t flt_len) {
@@ -1407,6 +2177,64 @@ static void parseAndZeroAccumulator(void) {
   serialPutsError("Invalid command. Use z, ze1-12, or zp1-3.");
 }
 
+/*! @brief Send the reply line for a tagged command
+ *  @param [in] tag : tag of the command
+ *  @param [in] err : result of the command
+ */
+static void cmdReply(const uint32_t tag, const CmdErr_t err) {
+  char reply[CMD_SEQ_REPLY_W];
+
+  (void)cmdSeqReply(reply, tag, err);
+  serialPuts(reply);
+}
+
+/*! @brief Check an amplitude calibration is within the limits of the k command
+ *  @param [in] ch : channel, 0-2 for voltage, 3... for CTs
+ *  @param [in] cal : calibration value
+ *  @return true if the channel and value are in range
+ */
+static bool calibrationValid(const size_t ch, const float cal) {
+  if (ch >= VCT_TOTAL) {
+    return false;
+  }
+  if (ch < NUM_V) {
+    return (cal > 25.0f) && (cal < 150.0f);
+  }
+  /* Assume 10/200 A min/max CTs */
+  return (cal >= 10.0f) && (cal <= 200.0f);
+}
+
+/*! @brief Add a byte from a COBS framed command stream. A complete frame is
+ *         handled as a command line, without echo; it is dropped if a command
+ *         is still pending or it has a character that can not be typed.
+ *  @param [in] c : received byte
+ */
+static void cmdFrameByte(const uint8_t c) {
+  if (COBS_RX_FRAME != cobsDecodeByte(&cmdFrame, c)) {
+    return;
+  }
+
+  /* A one character frame answers a confirmation prompt */
+  if ((1u == cmdFrame.n) && configHandleConfirmation(cmdFrameBuf[0])) {
+    return;
+  }
+
+  if (cmdPending || (0 == cmdFrame.n)) {
+    return;
+  }
+  for (size_t i = 0; i < cmdFrame.n; i++) {
+    if (!utilCharPrintable(cmdFrameBuf[i])) {
+      return;
+    }
+  }
+
+  inBufferClear(IN_BUFFER_W);
+  memcpy(inBuffer, cmdFrameBuf, cmdFrame.n);
+  inBufferIdx = cmdFrame.n;
+  cmdPending  = true;
+  emon32EventSet(EVT_PROCESS_CMD);
+}
+
 void configCmdChar(const uint8_t c) {
   if (('\r' == c) || ('\n' == c)) {
     if (!cmdPending) {
@@ -1446,9 +2274,16 @@ void configFirmwareBoardInfo(void) {
   printUptime();
   serialPuts("\r\n");
 
+  const struct Emon32BuildInfo info = emon32_build_info();
+
   serialPuts("> Firmware:\r\n");
   printf_("  - Version:    %d.%d.%d\r\n", VERSION_FW_MAJ, VERSION_FW_MIN,
           VERSION_FW_REV);
+  printf_("  - Commit:     %s\r\n", info.revision);
+  printf_("  - Profile:    %s\r\n", info.configuration);
+  printf_("  - Features:   %u V, %u CT, %u OPA, %u OneWire\r\n", NUM_V, NUM_CT,
+          NUM_OPA, TEMP_MAX_ONEWIRE);
+  printf_("  - Perf trace: %s\r\n", PERF_ENABLED ? "on" : "off");
   serialPuts("  - Build:      ");
   serialPuts(emon32_build_info_string());
   serialPuts("\r\n\r\n");
@@ -1460,30 +2295,22 @@ void configFirmwareBoardInfo(void) {
 
 Emon32Config_t *configLoadFromNVM(void) {
 
-  const uint32_t cfgSize     = sizeof(config);
-  uint16_t       crc16_ccitt = 0;
-
   /* Load from "static" part of EEPROM. If the key does not match
    * CONFIG_NVM_KEY as this is the first time it has been run, run the built
    * in self test, write the default configuration to the EEPROM and zero wear
-   * levelled portion before resetting.
+   * levelled portion before resetting. Raise a warning if the CRC does not
+   * match.
    */
-  eepromRead(0, &config, cfgSize);
+  const NvmRd_t status = configNVMRead(&config);
 
-  if (CONFIG_NVM_KEY != config.key) {
+  if (NVM_RD_NO_KEY == status) {
     configInitialiseNVM();
     NVIC_SystemReset();
-  } else {
-    /* Check the CRC and raise a warning if not matched. -2 from the base
-     * size to account for the stored 16 bit CRC.
-     */
-    crc16_ccitt = calcCRC16_ccitt(&config, cfgSize - 2u);
-    if (crc16_ccitt != config.crc16_ccitt) {
-      serialPuts(
-          "  - NVM corrupt. Loading defaults (save with 's' to fix).\r\n");
-      configDefault();
-      unsavedChange = true;
-    }
+  } else if (NVM_RD_OK != status) {
+    serialPuts(
+        "  - NVM corrupt. Loading defaults (save with 's' to fix).\r\n");
+    configDefault();
+    unsavedChange = true;
   }
 
   return &config;
@@ -1498,14 +2325,29 @@ void configProcessCmd(void) {
       "\r\n"
       "emon32 information and configuration commands\r\n\r\n"
       " - ?           : show this text again\r\n"
-      " - a<n>        : set the assumed RMS voltage as integer\r\n"
+      " - #<n> <cmd>  : tagged command, answered with #<n> OK or\r\n"
+      "                 #<n> ERR <reason>. A repeated tag is not re-applied\r\n"
+      " - a<n> [<pf>] : set the assumed RMS voltage as integer, and optionally\r\n"
+      "                 the assumed power factor (%)\r\n"
       " - b           : backup to serial\r\n"
       " - c<n>        : log to serial output. n = 0: OFF, n = 1: ON\r\n"
       " - d<x.x>      : data log period (s)\r\n"
       " - e           : enter bootloader\r\n"
-      " - f<n>        : line frequency (Hz)\r\n"
+      " - f<n>        : line frequency (Hz), 0 for auto\r\n"
       " - g<n>        : set network group (default = 210)\r\n"
-      " - j<n>        : JSON serial format. n = 0: OFF, n = 1: ON\r\n"
+      " - h<n>        : Modbus RTU slave address on the UART [1..247], 0 to\r\n"
+      "                 disable. Applied after save and restart\r\n"
+      " - i           : show diagnostic counters\r\n"
+      " - iz          : clear diagnostic counters\r\n"
+      " - j<n> [<k> [<x>]] : serial format. n = 0: K:V, 1: JSON, 2: CSV, "
+      "3: binary,\r\n"
+      "                 4: COBS framed binary (UART commands also framed)\r\n"
+      "                 k: text line checksum. 0: none, 1: XOR, 2: CRC8\r\n"
+      "                 x: 1 to add frequency (F) and power factor (PF)\r\n"
+      " - js [<e> <pf> <f>] : text fields E, PF, F. 1: include, 0: omit\r\n"
+      " - jp [<v> <p> <e> <pf> <f> <t>] : text decimal places [0..3] for\r\n"
+      "                 voltage, power, energy, PF, frequency, temperature.\r\n"
+      "                 Trailing classes unchanged; none for defaults\r\n"
       " - k<x> <a> <y.y> <z.z> v1 v2 : Configure an analog input\r\n"
       "   - x:        : channel (1-3 -> V; 4... -> CT)\r\n"
       "   - a:        : channel active. a = 0: DISABLED, a = 1: ENABLED\r\n"
@@ -1513,6 +2355,13 @@ void configProcessCmd(void) {
       "   - z.z       : V/CT phase calibration value\r\n"
       "   - v1        : voltage 1 (for CT only)\r\n"
       "   - v2        : voltage 2 (for CT only, optional)\r\n"
+      " - kc<x> <v> <p> [<n>] : calibrate CT x and its voltage against a\r\n"
+      "                 resistive load of p (W) at v (V), over n reports\r\n"
+      " - kc          : show the calibration state and proposed values\r\n"
+      " - kca         : apply the proposed calibration values\r\n"
+      " - kd<m> <c> <l> <h> : surplus diversion. m = 0: OFF, 1: threshold,\r\n"
+      "                 2: energy bucket. c: grid CT, l: export to switch\r\n"
+      "                 on (W) or bucket size (J), h: hold (half-cycles)\r\n"
       " - l           : list settings\r\n"
       " - lh          : list settings and accumulators (human readable)\r\n"
       " - m<v> <w> <x> <y> <z> : Configure OPA1-3 for OneWire or Pulse\r\n"
@@ -1528,14 +2377,24 @@ void configProcessCmd(void) {
       "   - x = s   : save current addresses\r\n"
       "   - x = <n> : save address to index n\r\n"
       " - p<n>        : set the RF power level\r\n"
+      " - q<n> [<p> [<s> [<g>]]] : UART baud rate, parity (n, e, o), stop\r\n"
+      "                 bits (1, 2), and RS485 turnaround guard (us).\r\n"
+      "                 Applied after save and restart\r\n"
       " - r           : restore defaults\r\n"
       " - s           : save settings to NVM\r\n"
+      " - sl          : load saved settings, discarding unsaved changes\r\n"
+      " - sd          : restore defaults and save them to NVM\r\n"
       " - t           : trigger report on next cycle\r\n"
+      " - t<n>        : set the wall clock, n = Unix time (s)\r\n"
       " - u           : store current accumulator values to NVM\r\n"
       " - v           : firmware and board information\r\n"
+      " - v<n>        : log level. n = 0: error, 1: warn, 2: info, 3: debug\r\n"
       " - w<n>        : RF active. n = 0: OFF, n = 1: ON\r\n"
       " - x<n>        : 433 MHz compatibility. n = 0: 433.92 MHz, n = 1: "
       "433.00 MHz\r\n"
+      " - y<n>        : polled protocol node ID on the UART [1..254], 255 from\r\n"
+      "                 the serial number, 0 to disable. Applied after save\r\n"
+      "                 and restart\r\n"
       " - z           : zero all accumulators (E1-E12, pulse1-3)\r\n"
       " - ze<n>       : zero individual energy accumulator (n=1-12)\r\n"
       " - zp<n>       : zero individual pulse accumulator (n=1-3)\r\n\r\n";
@@ -1553,6 +2412,25 @@ void configProcessCmd(void) {
     return;
   }
 
+  /* A tagged command has the tag removed, and is answered with the result
+   * once handled. A retransmitted tag is answered without handling the
+   * command again. */
+  uint32_t       tag;
+  size_t         cmdStart;
+  CmdErr_t       prevErr;
+  const CmdTag_t tagType =
+      cmdSeqParse(&cmdSeq, inBuffer, &tag, &cmdStart, &prevErr);
+
+  if (CMD_TAG_NEW == tagType) {
+    (void)memmove(inBuffer, (inBuffer + cmdStart), (arglen + 1 - cmdStart));
+  } else if (CMD_TAG_NONE != tagType) {
+    cmdReply(tag, (CMD_TAG_DUP == tagType) ? prevErr : CMD_ERR_TAG);
+    cmdPending = false;
+    inBufferClear(arglen + 1);
+    return;
+  }
+  cmdErr = CMD_OK;
+
   /* Decode on first character in the buffer */
   switch (inBuffer[0]) {
   case '?':
@@ -1600,14 +2478,26 @@ void configProcessCmd(void) {
       emon32EventSet(EVT_CONFIG_CHANGED);
     }
     break;
+  case 'h':
+    if (configureModbus()) {
+      unsavedChange = true;
+    }
+    break;
+  case 'i':
+    printDiagnostics();
+    break;
   case 'j':
-    if (configureJSON()) {
+    if (('p' == inBuffer[1])   ? configureTextDp()
+        : ('s' == inBuffer[1]) ? configureTextSel()
+                               : configureJSON()) {
       unsavedChange = true;
       emon32EventSet(EVT_CONFIG_CHANGED);
     }
     break;
   case 'k':
-    if (configureAnalog()) {
+    if ('c' == inBuffer[1]) {
+      configureCalibrate();
+    } else if (('d' == inBuffer[1]) ? configureDivert() : configureAnalog()) {
       unsavedChange = true;
       emon32EventSet(EVT_CONFIG_CHANGED);
     }
@@ -1644,6 +2534,11 @@ void configProcessCmd(void) {
       emon32EventSet(EVT_CONFIG_CHANGED);
     }
     break;
+  case 'q':
+    if (configureUART()) {
+      unsavedChange = true;
+    }
+    break;
   case 'r':
     configDefault();
 
@@ -1653,26 +2548,25 @@ void configProcessCmd(void) {
     emon32EventSet(EVT_CONFIG_CHANGED);
     break;
   case 's':
-    /* Save to EEPROM config space after recalculating CRC and indicate if a
-     * reset is required.
-     */
-    config.crc16_ccitt = calcCRC16_ccitt(&config, (sizeof(config) - 2));
-
-    serialPuts("> Saving configuration to NVM... ");
-    eepromInitConfig(&config, sizeof(config));
-    serialPuts("Done!\r\n");
-
-    unsavedChange = false;
-    emon32EventSet(EVT_CONFIG_SAVED);
+    configureStore();
     break;
   case 't':
-    emon32EventSet(EVT_ECM_TRIG);
+    if (0 == inBuffer[1]) {
+      emon32EventSet(EVT_ECM_TRIG);
+    } else if (configureTime()) {
+      /* Adds the TIME column to the CSV header */
+      emon32EventSet(EVT_CONFIG_CHANGED);
+    }
     break;
   case 'u':
     emon32EventSet(EVT_STORE_ACCUM);
     break;
   case 'v':
-    configFirmwareBoardInfo();
+    if (0 == inBuffer[1]) {
+      configFirmwareBoardInfo();
+    } else if (configureLogLevel()) {
+      unsavedChange = true;
+    }
     break;
   case 'w':
     if (configureRFEnable()) {
@@ -1688,15 +2582,81 @@ void configProcessCmd(void) {
       emon32EventSet(EVT_CONFIG_CHANGED);
     }
     break;
+  case 'y':
+    if (configurePoll()) {
+      unsavedChange = true;
+    }
+    break;
   case 'z':
     parseAndZeroAccumulator();
     break;
+  default:
+    cmdErr = CMD_ERR_UNKNOWN;
+    break;
+  }
+
+  if (CMD_TAG_NEW == tagType) {
+    cmdSeqRecord(&cmdSeq, tag, cmdErr);
+    cmdReply(tag, cmdErr);
   }
 
   cmdPending = false;
   inBufferClear(arglen + 1);
 }
 
+void configDivert(DivertCfg_t *pDst) {
+  const uint8_t mode = config.divertMode;
+
+  pDst->mode       = (mode <= ECM_DIVERT_BUCKET) ? (ECM_DIVERT_MODE_t)mode
+                                                 : ECM_DIVERT_DISABLED;
+  pDst->ch         = config.divertCh;
+  pDst->onExport   = qfp_uint2float(config.divertLevel);
+  pDst->offExport  = 0.0f;
+  pDst->holdHalves = config.divertHold;
+  pDst->bucketJ    = qfp_uint2float(config.divertLevel);
+}
+
+uint8_t configTextDp(const DataField_t field) {
+  const uint8_t dp = config.textDp[field];
+
+  if ((0 == dp) || (dp > (FTOA_DP_MAX + 1u))) {
+    return textDpDef[field];
+  }
+  return dp - 1u;
+}
+
+uint8_t configTextSel(void) {
+  return (config.textSel ^ DATA_SEL_DEF) & DATA_SEL_ALL;
+}
+
+float configAssumedPF(void) {
+  if (0 == config.baseCfg.assumedPF) {
+    return 1.0f;
+  }
+  return qfp_fdiv(qfp_uint2float(config.baseCfg.assumedPF), 100.0f);
+}
+
+bool configSetCalibration(const size_t ch, const float cal) {
+  ECMCfg_t *ecmCfg = ecmConfigGet();
+
+  if (!calibrationValid(ch, cal)) {
+    return false;
+  }
+
+  if (ch < NUM_V) {
+    config.voltageCfg[ch].voltageCal = cal;
+    ecmCfg->vCfg[ch].voltageCalRaw   = cal;
+  } else {
+    config.ctCfg[ch - NUM_V].ctCal     = cal;
+    ecmCfg->ctCfg[ch - NUM_V].ctCalRaw = cal;
+  }
+
+  ecmConfigChannel(ch);
+  unsavedChange = true;
+  emon32EventSet(EVT_CONFIG_CHANGED);
+  return true;
+}
+
 bool configUnsavedChanges(void) { return unsavedChange; }
 
 uint16_t configTimeToCycles(const float time, const uint32_t mainsFreq) {
@@ -1740,17 +2700,45 @@ uint8_t configEchoChar(void) {
   return c;
 }
 
+static void configRxByte(const uint8_t c) {
+  /* With Modbus or the polled protocol enabled the UART carries only their
+   * frames, and with COBS output only COBS frames. */
+  if (modbusMapEnabled()) {
+    modbusMapRxByte(c);
+  } else if (pollMapEnabled()) {
+    pollMapRxByte(c);
+  } else if (DATA_FMT_COBS == config.baseCfg.dataFmt) {
+    cmdFrameByte(c);
+  } else if (!configHandleConfirmation(c)) {
+    configCmdChar(c);
+  }
+}
+
+void configRxProcess(void) {
+  uint8_t rxChunk[16];
+  size_t  n;
+
+  if (uartRxDMAReady()) {
+    while (0 != (n = uartRxDMARead(rxChunk, sizeof(rxChunk)))) {
+      for (size_t i = 0; i < n; i++) {
+        configRxByte(rxChunk[i]);
+      }
+    }
+  }
+}
+
 void SERCOM_UART_INTERACTIVE_HANDLER {
   /* Echo the received character to the TX channel, and send to the command
-   * stream.
+   * stream. Also feed the Tx ring to the UART. With Rx by DMA, RXC is not
+   * enabled and the bytes arrive through configRxProcess.
    */
-  if (uartGetcReady(SERCOM_UART_INTERACTIVE)) {
-    uint8_t rx_char = uartGetc(SERCOM_UART_INTERACTIVE);
-
-    if (!configHandleConfirmation(rx_char)) {
-      configCmdChar(rx_char);
-    }
+  if (uartGetcReady(SERCOM_UART_INTERACTIVE) &&
+      (SERCOM_UART_INTERACTIVE->USART.INTENSET.reg &
+       SERCOM_USART_INTENSET_RXC)) {
+    configRxByte(uartGetc(SERCOM_UART_INTERACTIVE));
   }
 
+  uartTxHandler(SERCOM_UART_INTERACTIVE);
+
   /* Revisit : need to handle the Error interrupt? */
 }
@@ -5,6 +5,8 @@
 #include <stdint.h>
 
 #include "board_def.h"
+#include "dataPack.h"
+#include "emon_CM.h"
 
 /* Configurable options. All the structs are packed to allow simple write to
  * EEPROM as a contiguous set.
@@ -16,10 +18,14 @@ typedef struct __attribute__((__packed__)) BaseCfg_ {
   uint16_t epDeltaStore; /* Minimum energy/pulse delta to store */
   uint8_t  dataGrp;      /* Transmission group - default 210 */
   bool     logToSerial;  /* Log data to serial output */
-  bool     useJson;      /* JSON format for serial output */
+  uint8_t  dataFmt;      /* Serial out: 0 K:V, 1 JSON, 2 CSV, 3 bin, 4 COBS */
   uint16_t assumedVrms;  /* Assumed RMS voltage if not present */
-  bool     debugSerial;  /* Verbose debug logged to serial */
-  uint8_t  res0[8];
+  uint8_t  logLevel;     /* Log threshold + 1, 0 for LOG_LEVEL_DEF */
+  uint8_t  assumedPF;    /* Assumed PF (%) if no voltage, 0 for unity */
+  uint32_t uartBaud;     /* UART baud rate, 0 for UART_BAUD */
+  uint8_t  uartParity;   /* 0: none, 1: even, 2: odd */
+  uint8_t  uartStop;     /* UART stop bits, 0 for 1 */
+  uint8_t  modbusAddr;   /* Modbus RTU slave address, 0 if disabled */
   float    reportTime; /* Time between reports */
 } BaseCfg_t;
 
@@ -65,7 +71,16 @@ typedef struct __attribute__((__packed__)) Emon32Config_ {
   CTCfgPacked_t      ctCfg[NUM_CT + CT_RES];
   OpaCfgPacked_t     opaCfg[NUM_OPA];
   OneWireAddr_t      oneWireAddr;
-  uint8_t            res0[16];
+  uint8_t            lineCheck; /* Text line checksum: 0 none, 1 XOR, 2 CRC8 */
+  uint8_t            textSel;   /* Text fields: DataSel_t ^ DATA_SEL_DEF */
+  uint8_t            pollNode;  /* Polled protocol node ID, 0 if disabled */
+  /* Text output decimal places + 1 for each DataField_t, 0 for the default */
+  uint8_t            textDp[DATA_FIELD_NUM];
+  uint16_t           rs485Guard; /* RS485 turnaround guard (us) */
+  uint8_t            divertMode;  /* Diversion: 0 off, 1 threshold, 2 bucket */
+  uint8_t            divertCh;    /* Diversion grid CT, 0 indexed */
+  uint16_t           divertLevel; /* Export (W) to switch on, or bucket (J) */
+  uint8_t            divertHold;  /* Half-cycles between diversion changes */
   uint16_t           crc16_ccitt;
 } Emon32Config_t;
 
@@ -103,6 +118,11 @@ bool configHandleConfirmation(const uint8_t c);
  */
 void configCheckConfirmationTimeout(void);
 
+/*! @brief Pass bytes received on the UART by DMA to the command stream, once
+ *         there is a gap in reception. Call every 1 ms from the main loop.
+ */
+void configRxProcess(void);
+
 /*! @brief If available, get a character from the echo queue
  *  @return 0 -> no characters, otherwise a character
  */
@@ -119,6 +139,35 @@ Emon32Config_t *configLoadFromNVM(void);
 /*! @brief Process a pending command from the UART */
 void configProcessCmd(void);
 
+/*! @brief Get the assumed power factor, used if there is no voltage
+ *  @return assumed power factor, 1.0 if not configured
+ */
+float configAssumedPF(void);
+
+/*! @brief Get the surplus diversion settings
+ *  @param [out] pDst : diversion configuration for the energy monitor
+ */
+void configDivert(DivertCfg_t *pDst);
+
+/*! @brief Get the decimal places for a class of text output field
+ *  @param [in] field : field class
+ *  @return decimal places, the default if not configured
+ */
+uint8_t configTextDp(DataField_t field);
+
+/*! @brief Get the optional fields in the text output
+ *  @return DataSel_t bits, the default if not configured
+ */
+uint8_t configTextSel(void);
+
+/*! @brief Set the amplitude calibration of an analog input, as the k command.
+ *         The change is not saved until the s command.
+ *  @param [in] ch : channel, 0-2 for voltage, 3... for CTs
+ *  @param [in] cal : calibration value
+ *  @return false if the channel or value is out of range
+ */
+bool configSetCalibration(const size_t ch, const float cal);
+
 /*! @brief Indicate if there are unsaved changes
  *  @return true if there are unsaved changes, false otherwise
  */
@@ -2,8 +2,10 @@
 #include <stdbool.h>
 #include <string.h>
 
+#include "cobs.h"
 #include "dataPack.h"
 #include "emon32_assert.h"
+#include "frame.h"
 #include "temperature.h"
 #include "util.h"
 
@@ -22,6 +24,20 @@
 #define STR_LCURL  9
 #define STR_RCURL  10
 #define STR_COMMA  11
+#define STR_TIME   12
+#define STR_FREQ   13
+#define STR_PF     14
+#define STR_TRUNC  15
+
+/* Below this apparent power (VA), PF is not sent. It is left out rather than
+ * sent as a placeholder, as receivers such as emonHub take only numbers. */
+#define PF_VA_MIN 5
+
+/* Any of the packed structures sent in a binary frame */
+typedef union PackedAny_ {
+  PackedDataCT_t        ct;
+  PackedDataTempPulse_t tempPulse;
+} PackedAny_t;
 
 /* "Fat" string with current length and buffer size. */
 typedef struct StrN {
@@ -30,34 +46,44 @@ typedef struct StrN {
   size_t m;   /* Buffer length */
 } StrN_t;
 
-static void   catId(StrN_t *strD, uint32_t id, int32_t field, bool json);
-static void   catMsg(StrN_t *strD, uint32_t msg, bool json);
+static void   catId(StrN_t *strD, uint32_t id, int32_t field, DataFmt_t fmt);
+static void   catMsg(StrN_t *strD, uint32_t msg, DataFmt_t fmt);
+static void   catName(StrN_t *strD, int32_t field, DataFmt_t fmt);
 static void   initFields(StrN_t *pD, char *pS, const size_t m);
+static void   markField(const StrN_t *strD, size_t *pKeep, size_t limit);
 static size_t strnCat(StrN_t *strD, const StrN_t *strS);
-static size_t strnCatFloat(StrN_t *strD, float v);
-static size_t strnCatInt(StrN_t *strD, int32_t v);
+static size_t strnCatFixed(StrN_t *strD, int32_t v, uint32_t dp);
+static size_t strnCatFloat(StrN_t *strD, float v, uint32_t dp);
 static size_t strnCatUint(StrN_t *strD, uint32_t v);
 
-static char tmpStr[CONV_STR_W] = {0};
+static char     tmpStr[CONV_STR_W] = {0};
+static uint32_t truncCount         = 0;
 
 /* Strings that are inserted in the transmitted message */
-const StrN_t baseStr[12] = {
+const StrN_t baseStr[16] = {
     {.str = "MSG", .n = 3, .m = 4},   {.str = "V", .n = 1, .m = 2},
     {.str = "P", .n = 1, .m = 2},     {.str = "E", .n = 1, .m = 2},
     {.str = "pulse", .n = 5, .m = 6}, {.str = "t", .n = 1, .m = 2},
     {.str = ":", .n = 1, .m = 2},     {.str = "\r\n", .n = 2, .m = 3},
     {.str = "\"", .n = 1, .m = 2},    {.str = "{", .n = 1, .m = 2},
-    {.str = "}", .n = 1, .m = 2},     {.str = ",", .n = 1, .m = 2}};
+    {.str = "}", .n = 1, .m = 2},     {.str = ",", .n = 1, .m = 2},
+    {.str = "TIME", .n = 4, .m = 5},  {.str = "F", .n = 1, .m = 2},
+    {.str = "PF", .n = 2, .m = 3},    {.str = "!TRUNC", .n = 6, .m = 7}};
 
-/*! @brief Append "<field><id>:" to the string
+/*! @brief Append ",<field><id>:" to the string, or only "," for CSV
  *  @param [out] strD : pointer to the fat string
  *  @param [in] id : numeric index
  *  @param [in] field : field name index, e.g. "STR_V"
- *  @param [in] json : select format
+ *  @param [in] fmt : select format
  */
 static void catId(StrN_t *strD, const uint32_t id, const int32_t field,
-                  const bool json) {
+                  const DataFmt_t fmt) {
+  const bool json = (DATA_FMT_JSON == fmt);
+
   strD->n += strnCat(strD, &baseStr[STR_COMMA]);
+  if (DATA_FMT_CSV == fmt) {
+    return;
+  }
   if (json) {
     strD->n += strnCat(strD, &baseStr[STR_DQUOTE]);
   }
@@ -72,11 +98,16 @@ static void catId(StrN_t *strD, const uint32_t id, const int32_t field,
 /*! @brief Append the MSG field to the fat string
  *  @param [out] strD : pointer to the destination fat string
  *  @param [in] msg : message number
- *  @param [in] json : select format
+ *  @param [in] fmt : select format
  */
-static void catMsg(StrN_t *strD, const uint32_t msg, const bool json) {
+static void catMsg(StrN_t *strD, const uint32_t msg, const DataFmt_t fmt) {
   /* <{">MSG<">:<"><#><"> */
+  const bool json = (DATA_FMT_JSON == fmt);
 
+  if (DATA_FMT_CSV == fmt) {
+    strD->n += strnCatUint(strD, msg);
+    return;
+  }
   if (json) {
     strD->n += strnCat(strD, &baseStr[STR_LCURL]);
     strD->n += strnCat(strD, &baseStr[STR_DQUOTE]);
@@ -89,6 +120,29 @@ static void catMsg(StrN_t *strD, const uint32_t msg, const bool json) {
   strD->n += strnCatUint(strD, msg);
 }
 
+/*! @brief Append ",<field>:" to the string for a field without an index, or
+ *         only "," for CSV
+ *  @param [out] strD : pointer to the fat string
+ *  @param [in] field : field name index, e.g. "STR_TIME"
+ *  @param [in] fmt : select format
+ */
+static void catName(StrN_t *strD, const int32_t field, const DataFmt_t fmt) {
+  const bool json = (DATA_FMT_JSON == fmt);
+
+  strD->n += strnCat(strD, &baseStr[STR_COMMA]);
+  if (DATA_FMT_CSV == fmt) {
+    return;
+  }
+  if (json) {
+    strD->n += strnCat(strD, &baseStr[STR_DQUOTE]);
+  }
+  strD->n += strnCat(strD, &baseStr[field]);
+  if (json) {
+    strD->n += strnCat(strD, &baseStr[STR_DQUOTE]);
+  }
+  strD->n += strnCat(strD, &baseStr[STR_COLON]);
+}
+
 /*! @brief Initialise a fat string
  *  @param [out] pD : pointer to fat string
  *  @param [in] pS : pointer to string buffer
@@ -101,6 +155,18 @@ static void initFields(StrN_t *pD, char *pS, const size_t m) {
   memset(pD->str, 0, m);
 }
 
+/*! @brief Record the end of a complete field if there is still room after it
+ *         for the truncation marker
+ *  @param [in] strD : pointer to the fat string
+ *  @param [out] pKeep : end of the last field that leaves room for the marker
+ *  @param [in] limit : longest line that leaves room for the marker
+ */
+static void markField(const StrN_t *strD, size_t *pKeep, const size_t limit) {
+  if (strD->n <= limit) {
+    *pKeep = strD->n;
+  }
+}
+
 /*! @brief Copy from tmpStr to fat string with bounds checking
  *  @param [out] strD : pointer to destination fat string
  *  @param [in] len : length of string in tmpStr (excluding null)
@@ -114,12 +180,31 @@ static size_t strnCatFromTmp(StrN_t *strD, const size_t len) {
   return toCopy;
 }
 
-static size_t strnCatFloat(StrN_t *strD, const float v) {
-  return strnCatFromTmp(strD, utilFtoa(tmpStr, v) - 1u);
+/*! @brief Append a whole number with dp decimal places, which are all 0. The
+ *         integer is not converted to float, which would lose the low digits
+ *         of a large energy.
+ *  @param [out] strD : pointer to destination fat string
+ *  @param [in] v : value
+ *  @param [in] dp : decimal places, limited to FTOA_DP_MAX
+ *  @return number of characters copied
+ */
+static size_t strnCatFixed(StrN_t *strD, const int32_t v, uint32_t dp) {
+  size_t len = utilItoa(tmpStr, v, ITOA_BASE10) - 1u;
+
+  if (dp > FTOA_DP_MAX) {
+    dp = FTOA_DP_MAX;
+  }
+  if (dp) {
+    tmpStr[len++] = '.';
+    while (dp--) {
+      tmpStr[len++] = '0';
+    }
+  }
+  return strnCatFromTmp(strD, len);
 }
 
-static size_t strnCatInt(StrN_t *strD, const int32_t v) {
-  return strnCatFromTmp(strD, utilItoa(tmpStr, v, ITOA_BASE10) - 1u);
+static size_t strnCatFloat(StrN_t *strD, const float v, const uint32_t dp) {
+  return strnCatFromTmp(strD, utilFtoaDp(tmpStr, v, dp) - 1u);
 }
 
 static size_t strnCatUint(StrN_t *strD, const uint32_t v) {
@@ -149,63 +234,150 @@ static size_t strnCat(StrN_t *strD, const StrN_t *strS) {
 }
 
 size_t dataPackSerial(const Emon32Dataset_t *pData, char *pDst, const size_t m,
-                      const bool json, const CHActive_t *pChsActive) {
+                      const DataFmt_t fmt, const CHActive_t *pChsActive) {
   EMON32_ASSERT(pData);
   EMON32_ASSERT(pDst);
 
   StrN_t strn;
   initFields(&strn, pDst, m);
 
-  catMsg(&strn, pData->msgNum, json);
+  /* JSON and CSV only include active channels. CSV includes all of them so
+   * that the columns match the header. */
+  const bool     activeOnly = (DATA_FMT_KV != fmt);
+  const bool     csv        = (DATA_FMT_CSV == fmt);
+  const bool     json       = (DATA_FMT_JSON == fmt);
+  const uint8_t *dp         = pChsActive->dp;
+
+  /* The fields are packed into the space left for "}\r\n" and the NULL; a
+   * field that goes over is detected as the line reaching strn.m. Fields are
+   * only kept up to limit, which leaves room for the ",!TRUNC" marker.
+   */
+  const size_t termW = json ? 3u : 2u;
+  const size_t markW = csv ? 7u : (json ? 11u : 9u);
+  EMON32_ASSERT(m > (termW + markW + 1u));
+
+  const size_t bodyW = m - termW - 1u;
+  const size_t limit = bodyW - markW;
+  size_t       keep  = 0;
+
+  strn.m = bodyW + 1u;
+
+  catMsg(&strn, pData->msgNum, fmt);
+  markField(&strn, &keep, limit);
+
+  /* CSV follows the header, so that the columns line up */
+  if (csv ? pChsActive->time : (0 != pData->epoch)) {
+    catName(&strn, STR_TIME, fmt);
+    strn.n += strnCatUint(&strn, pData->epoch);
+    markField(&strn, &keep, limit);
+  }
 
   /* V channels; only print V2/V3 if either active */
-  const uint32_t numV = (pData->pECM->activeCh & 0x6) ? NUM_V : 1;
+  const uint32_t numV = (csv || (pData->pECM->activeCh & 0x6)) ? NUM_V : 1;
 
   for (size_t i = 0; i < numV; i++) {
-    if (json && !pChsActive->V[i]) {
+    if (activeOnly && !pChsActive->V[i]) {
       continue;
     }
-    catId(&strn, (i + 1), STR_V, json);
-    strn.n += strnCatFloat(&strn, pData->pECM->rmsV[i]);
+    catId(&strn, (i + 1), STR_V, fmt);
+    strn.n += strnCatFloat(&strn, pData->pECM->rmsV[i], dp[DATA_FIELD_V]);
+    markField(&strn, &keep, limit);
+  }
+
+  /* Frequency and power factor are left out (CSV: empty) when not valid */
+  if ((pChsActive->sel & DATA_SEL_FREQ) && (csv || pData->pECM->freqValid)) {
+    catName(&strn, STR_FREQ, fmt);
+    if (pData->pECM->freqValid) {
+      strn.n +=
+          strnCatFloat(&strn, pData->pECM->frequency, dp[DATA_FIELD_F]);
+    }
+    markField(&strn, &keep, limit);
   }
 
   /* CT channels (power and energy)
    * Only print onboard CTs 7-12 if any are present
    */
   const uint32_t numCT =
-      (pData->pECM->activeCh & (0x3f << (NUM_V + (NUM_CT / 2)))) ? NUM_CT
-                                                                 : (NUM_CT / 2);
+      (csv || (pData->pECM->activeCh & (0x3f << (NUM_V + (NUM_CT / 2)))))
+          ? NUM_CT
+          : (NUM_CT / 2);
 
   for (size_t i = 0; i < numCT; i++) {
-    if (json && !pChsActive->CT[i]) {
+    if (activeOnly && !pChsActive->CT[i]) {
       continue;
     }
-    catId(&strn, (i + 1), STR_P, json);
-    strn.n += strnCatInt(&strn, pData->pECM->CT[i].realPower);
+    catId(&strn, (i + 1), STR_P, fmt);
+    strn.n +=
+        strnCatFixed(&strn, pData->pECM->CT[i].realPower, dp[DATA_FIELD_P]);
+    markField(&strn, &keep, limit);
   }
-  for (size_t i = 0; i < numCT; i++) {
-    if (json && !pChsActive->CT[i]) {
-      continue;
+  if (pChsActive->sel & DATA_SEL_ENERGY) {
+    for (size_t i = 0; i < numCT; i++) {
+      if (activeOnly && !pChsActive->CT[i]) {
+        continue;
+      }
+      catId(&strn, (i + 1), STR_E, fmt);
+      strn.n +=
+          strnCatFixed(&strn, pData->pECM->CT[i].wattHour, dp[DATA_FIELD_E]);
+      markField(&strn, &keep, limit);
+    }
+  }
+  if (pChsActive->sel & DATA_SEL_PF) {
+    for (size_t i = 0; i < numCT; i++) {
+      const bool valid = (pData->pECM->CT[i].apparentPower >= PF_VA_MIN);
+      if ((activeOnly && !pChsActive->CT[i]) || !(valid || csv)) {
+        continue;
+      }
+      catId(&strn, (i + 1), STR_PF, fmt);
+      if (valid) {
+        strn.n +=
+            strnCatFloat(&strn, pData->pECM->CT[i].pf, dp[DATA_FIELD_PF]);
+      }
+      markField(&strn, &keep, limit);
     }
-    catId(&strn, (i + 1), STR_E, json);
-    strn.n += strnCatInt(&strn, pData->pECM->CT[i].wattHour);
   }
 
   for (size_t i = 0; i < NUM_OPA; i++) {
-    if (json && !pChsActive->pulse[i]) {
+    if (activeOnly && !pChsActive->pulse[i]) {
       continue;
     }
-    catId(&strn, (i + 1), STR_PULSE, json);
+    catId(&strn, (i + 1), STR_PULSE, fmt);
     strn.n += strnCatUint(&strn, pData->pulseCnt[i]);
+    markField(&strn, &keep, limit);
   }
 
+  /* Only print temperature slots with a sensor; failed sensors are still
+   * reported with their out of range code. CSV leaves the column empty. */
   for (size_t i = 0; i < TEMP_MAX_ONEWIRE; i++) {
-    if (json && (pData->temp[i] == 4800)) {
+    const bool absent = (TEMP_Q4_ABSENT == pData->temp[i]);
+    if (absent && !csv) {
       continue;
     }
-    catId(&strn, (i + 1), STR_TEMP, json);
-    strn.n +=
-        strnCatFloat(&strn, tempAsFloat(TEMP_INTF_ONEWIRE, pData->temp[i]));
+    catId(&strn, (i + 1), STR_TEMP, fmt);
+    if (!absent) {
+      strn.n += strnCatFloat(&strn,
+                             tempAsFloat(TEMP_INTF_ONEWIRE, pData->temp[i]),
+                             dp[DATA_FIELD_TEMP]);
+    }
+    markField(&strn, &keep, limit);
+  }
+
+  /* If a field did not fit, drop it and any partial field before the marker
+   * rather than send a cut off, but plausible, value. The marker is a field
+   * of its own so the line still parses: ",!TRUNC:1" or ",!TRUNC" for CSV.
+   */
+  strn.m = m;
+  if (strn.n > bodyW) {
+    memset((strn.str + keep), 0, (strn.n - keep));
+    strn.n = keep;
+    if (csv) {
+      strn.n += strnCat(&strn, &baseStr[STR_COMMA]);
+      strn.n += strnCat(&strn, &baseStr[STR_TRUNC]);
+    } else {
+      catName(&strn, STR_TRUNC, fmt);
+      strn.n += strnCatUint(&strn, 1u);
+    }
+    truncCount++;
   }
 
   /* Terminate with } for JSON and \r\n */
@@ -216,6 +388,67 @@ size_t dataPackSerial(const Emon32Dataset_t *pData, char *pDst, const size_t m,
   return strn.n;
 }
 
+size_t dataPackSerialHeader(char *pDst, const size_t m,
+                            const CHActive_t *pChsActive) {
+  EMON32_ASSERT(pDst);
+
+  StrN_t strn;
+  initFields(&strn, pDst, m);
+
+  strn.n += strnCat(&strn, &baseStr[STR_MSG]);
+
+  if (pChsActive->time) {
+    strn.n += strnCat(&strn, &baseStr[STR_COMMA]);
+    strn.n += strnCat(&strn, &baseStr[STR_TIME]);
+  }
+
+  for (size_t i = 0; i < NUM_V; i++) {
+    if (pChsActive->V[i]) {
+      strn.n += strnCat(&strn, &baseStr[STR_COMMA]);
+      strn.n += strnCat(&strn, &baseStr[STR_V]);
+      strn.n += strnCatUint(&strn, (i + 1));
+    }
+  }
+
+  if (pChsActive->sel & DATA_SEL_FREQ) {
+    strn.n += strnCat(&strn, &baseStr[STR_COMMA]);
+    strn.n += strnCat(&strn, &baseStr[STR_FREQ]);
+  }
+
+  /* Power is always sent; energy and PF only when selected */
+  const int32_t ctFields[3] = {STR_P, STR_E, STR_PF};
+  const uint8_t ctSel[3]    = {0, DATA_SEL_ENERGY, DATA_SEL_PF};
+  for (size_t f = 0; f < 3u; f++) {
+    if ((0 != ctSel[f]) && (0 == (pChsActive->sel & ctSel[f]))) {
+      continue;
+    }
+    for (size_t i = 0; i < NUM_CT; i++) {
+      if (pChsActive->CT[i]) {
+        strn.n += strnCat(&strn, &baseStr[STR_COMMA]);
+        strn.n += strnCat(&strn, &baseStr[ctFields[f]]);
+        strn.n += strnCatUint(&strn, (i + 1));
+      }
+    }
+  }
+
+  for (size_t i = 0; i < NUM_OPA; i++) {
+    if (pChsActive->pulse[i]) {
+      strn.n += strnCat(&strn, &baseStr[STR_COMMA]);
+      strn.n += strnCat(&strn, &baseStr[STR_PULSE]);
+      strn.n += strnCatUint(&strn, (i + 1));
+    }
+  }
+
+  for (size_t i = 0; i < TEMP_MAX_ONEWIRE; i++) {
+    strn.n += strnCat(&strn, &baseStr[STR_COMMA]);
+    strn.n += strnCat(&strn, &baseStr[STR_TEMP]);
+    strn.n += strnCatUint(&strn, (i + 1));
+  }
+
+  strn.n += strnCat(&strn, &baseStr[STR_CRLF]);
+  return strn.n;
+}
+
 uint8_t dataPackPacked(const Emon32Dataset_t *pData, void *pPacked,
                        const PackedRange_t range) {
 
@@ -248,3 +481,32 @@ uint8_t dataPackPacked(const Emon32Dataset_t *pData, void *pPacked,
 
   return 0u;
 }
+
+size_t dataPackFrame(const Emon32Dataset_t *pData, const PackedRange_t range,
+                     const uint8_t node, void *pDst, const size_t m) {
+  PackedAny_t packed;
+
+  const uint8_t n = dataPackPacked(pData, &packed, range);
+  return frameEncode(&packed, n, (uint8_t)(node + range), pDst, m);
+}
+
+size_t dataPackFrameCobs(const Emon32Dataset_t *pData,
+                         const PackedRange_t range, const uint8_t node,
+                         void *pDst, const size_t m) {
+  uint8_t frame[FRAME_OVERHEAD + sizeof(PackedAny_t)];
+
+  const size_t n = dataPackFrame(pData, range, node, frame, sizeof(frame));
+  if ((0 == n) || (0 == m)) {
+    return 0;
+  }
+
+  /* Leave room for the delimiter */
+  const size_t w = cobsEncode(frame, n, pDst, (m - 1u));
+  if (0 == w) {
+    return 0;
+  }
+  ((uint8_t *)pDst)[w] = COBS_DELIM;
+  return w + 1u;
+}
+
+uint32_t dataPackTruncated(void) { return truncCount; }
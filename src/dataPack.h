@@ -5,32 +5,87 @@
 
 #include "emon32.h"
 
+/* Classes of text field with their own number of decimal places */
+typedef enum DataField_ {
+  DATA_FIELD_V,    /* Voltage */
+  DATA_FIELD_P,    /* Real power */
+  DATA_FIELD_E,    /* Energy */
+  DATA_FIELD_PF,   /* Power factor */
+  DATA_FIELD_F,    /* Mains frequency */
+  DATA_FIELD_TEMP, /* Temperature */
+  DATA_FIELD_NUM
+} DataField_t;
+
+/* Optional text fields, selected apart from the active channels */
+typedef enum DataSel_ {
+  DATA_SEL_FREQ   = (1u << 0), /* Mains frequency, F */
+  DATA_SEL_PF     = (1u << 1), /* Power factor, PF1-12 */
+  DATA_SEL_ENERGY = (1u << 2)  /* Energy, E1-12 */
+} DataSel_t;
+
+#define DATA_SEL_DEF DATA_SEL_ENERGY
+#define DATA_SEL_ALL (DATA_SEL_ENERGY | DATA_SEL_PF | DATA_SEL_FREQ)
+
 typedef struct CHActive_ {
   bool V[NUM_V];
   bool CT[NUM_CT];
   bool pulse[NUM_OPA];
+  bool time; /* CSV has a TIME column (wall clock set) */
+
+  uint8_t sel;                /* Optional fields, DataSel_t bits */
+  uint8_t dp[DATA_FIELD_NUM]; /* Decimal places, 0 to FTOA_DP_MAX */
 } CHActive_t;
 
+/* Serial output format. The values are stored in the configuration. When the
+ * wall clock is set, TIME (Unix time) follows MSG. Selected by DataSel_t, F
+ * follows the voltages, E1-12 the powers, and PF1-12 the energies.
+ */
+typedef enum DataFmt_ {
+  DATA_FMT_KV,    /* MSG:1,V1:240.0,... */
+  DATA_FMT_JSON,  /* {"MSG":1,"V1":240.0,...} */
+  DATA_FMT_CSV,   /* 1,240.0,... with a header from dataPackSerialHeader */
+  DATA_FMT_BINARY, /* Packed data in frames with CRC16, see dataPackFrame */
+  DATA_FMT_COBS    /* Binary frames, COBS encoded, see dataPackFrameCobs */
+} DataFmt_t;
+
 typedef enum PackedRange_ {
   PACKED_CT1_6,
   PACKED_TEMP_PULSE,
   PACKED_CT7_12,
 } PackedRange_t;
 
-/*! @brief Packs the emon_CM packet into serial format.
- *         Returns the number of characters that would have been packed,
- *         regardless of the value of m. If the return value != m, then the
- *         buffer would have overflowed (similar to snprintf). Does not append
- *         a NULL. Clears data buffer in advance.
+/*! @brief Packs the emon_CM packet into serial format. The line always fits
+ *         in m, including a NULL. If the selected fields do not fit, whole
+ *         trailing fields are dropped and a "!TRUNC" field is added before
+ *         the line ending: ",!TRUNC:1" (JSON: ",\"!TRUNC\":1", CSV:
+ *         ",!TRUNC"). Clears data buffer in advance.
  *  @param [in] pData : pointer to the raw data
  *  @param [out] pDst : pointer to the destination buffer
  *  @param [in] m : width of the destination buffer
- *  @param [in] json : false -> K:V; true -> JSON
- *  @param [in] chsActive : indicates presence or absence of sensors
- *  @return the number of the characters that would be packed
+ *  @param [in] fmt : K:V, JSON, or CSV
+ *  @param [in] chsActive : indicates presence or absence of sensors, and the
+ *                          decimal places for each class of field
+ *  @return the number of the characters packed
  */
 size_t dataPackSerial(const Emon32Dataset_t *pData, char *pDst, const size_t m,
-                      const bool json, const CHActive_t *pChsActive);
+                      const DataFmt_t fmt, const CHActive_t *pChsActive);
+
+/*! @brief Number of lines from dataPackSerial that were truncated
+ *  @return count of truncated lines since reset
+ */
+uint32_t dataPackTruncated(void);
+
+/*! @brief Packs the CSV header row naming the columns of dataPackSerial. The
+ *         columns follow the active channels, and every temperature slot is
+ *         included, empty when there is no sensor, so the rows stay aligned.
+ *         Does not append a NULL. Clears data buffer in advance.
+ *  @param [out] pDst : pointer to the destination buffer
+ *  @param [in] m : width of the destination buffer
+ *  @param [in] chsActive : indicates presence or absence of sensors
+ *  @return the number of the characters packed
+ */
+size_t dataPackSerialHeader(char *pDst, const size_t m,
+                            const CHActive_t *pChsActive);
 
 /*! @brief Pack the voltage, power, energy, temperature, and pulse data into a
  *         packed structure for transmission over RFM link.
@@ -41,3 +96,29 @@ size_t dataPackSerial(const Emon32Dataset_t *pData, char *pDst, const size_t m,
  */
 uint8_t dataPackPacked(const Emon32Dataset_t *restrict pData,
                        void *restrict pPacked, const PackedRange_t range);
+
+/*! @brief Pack a range as for dataPackPacked, and wrap it in a binary frame
+ *         with a CRC16 (see frame.h). As for the RFM link, the node ID is
+ *         offset by the range: node, node + 1, and node + 2.
+ *  @param [in] pData : pointer to the raw data
+ *  @param [in] range : select the packing range
+ *  @param [in] node : base node ID
+ *  @param [out] pDst : destination buffer
+ *  @param [in] m : width of the destination buffer
+ *  @return number of bytes in the frame, 0 if it does not fit in m
+ */
+size_t dataPackFrame(const Emon32Dataset_t *pData, const PackedRange_t range,
+                     const uint8_t node, void *pDst, const size_t m);
+
+/*! @brief As dataPackFrame, with the frame COBS encoded (see cobs.h) and
+ *         followed by the 0x00 delimiter
+ *  @param [in] pData : pointer to the raw data
+ *  @param [in] range : select the packing range
+ *  @param [in] node : base node ID
+ *  @param [out] pDst : destination buffer
+ *  @param [in] m : width of the destination buffer
+ *  @return number of bytes including the delimiter, 0 if it does not fit in m
+ */
+size_t dataPackFrameCobs(const Emon32Dataset_t *pData,
+                         const PackedRange_t range, const uint8_t node,
+                         void *pDst, const size_t m);
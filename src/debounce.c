@@ -0,0 +1,42 @@
+#include "debounce.h"
+
+void debounceInit(Debounce_t *pDb, const uint32_t window, const bool level,
+                  const uint32_t t) {
+  pDb->window = window;
+  pDb->tLast  = t;
+  pDb->tEdge  = t;
+  pDb->level  = level;
+  pDb->raw    = level;
+}
+
+bool debounceEdge(Debounce_t *pDb, const bool level, const uint32_t t) {
+  const uint32_t steady = t - pDb->tEdge;
+
+  pDb->raw   = level;
+  pDb->tEdge = t;
+
+  if ((level == pDb->level) || ((t - pDb->tLast) < pDb->window) ||
+      (steady < pDb->window)) {
+    return false;
+  }
+  pDb->level = level;
+  pDb->tLast = t;
+  return true;
+}
+
+void debounceSample(Debounce_t *pDb, const bool level, const uint32_t t) {
+  if (level != pDb->raw) {
+    pDb->raw   = level;
+    pDb->tEdge = t;
+  }
+}
+
+bool debounceUpdate(Debounce_t *pDb, const uint32_t t) {
+  if ((pDb->raw == pDb->level) || ((t - pDb->tLast) < pDb->window) ||
+      ((t - pDb->tEdge) < pDb->window)) {
+    return false;
+  }
+  pDb->level = pDb->raw;
+  pDb->tLast = t;
+  return true;
+}
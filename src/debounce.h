@@ -0,0 +1,56 @@
+#pragma once
+
+#include <stdbool.h>
+#include <stdint.h>
+
+/* Debounce of a dry contact input from its edges:
+ *   - An edge that changes the level is taken at once if the input had been
+ *     steady for the window before it, and the window has passed since the
+ *     last transition taken. Any other edge is contact bounce.
+ *   - If the input settles on the other level inside the window, the change
+ *     is taken by debounceUpdate once the window has passed and the input has
+ *     been steady for the window.
+ *   - A polled input is sampled with debounceSample. A change is never taken
+ *     at once, as a single sample may be a spike; debounceUpdate takes it
+ *     when the input has been steady for the window.
+ * Times are in any unit, as long as the window is in the same one. The state
+ * is kept here, away from the pins, so it can be tested.
+ */
+
+typedef struct Debounce_ {
+  uint32_t window; /* Minimum time between transitions */
+  uint32_t tLast;  /* Time of the last transition taken */
+  uint32_t tEdge;  /* Time of the most recent edge */
+  bool     level;  /* Debounced level */
+  bool     raw;    /* Level after the most recent edge */
+} Debounce_t;
+
+/*! @brief Initialise the state
+ *  @param [out] pDb : pointer to the state
+ *  @param [in] window : minimum time between transitions
+ *  @param [in] level : the input's current level
+ *  @param [in] t : time now
+ */
+void debounceInit(Debounce_t *pDb, uint32_t window, bool level, uint32_t t);
+
+/*! @brief The input has changed
+ *  @param [out] pDb : pointer to the state
+ *  @param [in] level : the input's level after the edge
+ *  @param [in] t : time of the edge
+ *  @return true if this is a transition of the debounced level
+ */
+bool debounceEdge(Debounce_t *pDb, bool level, uint32_t t);
+
+/*! @brief Sample a polled input
+ *  @param [out] pDb : pointer to the state
+ *  @param [in] level : the input's sampled level
+ *  @param [in] t : time of the sample
+ */
+void debounceSample(Debounce_t *pDb, bool level, uint32_t t);
+
+/*! @brief Take a change that settled inside the window. Call periodically.
+ *  @param [out] pDb : pointer to the state
+ *  @param [in] t : time now
+ *  @return true if this is a transition of the debounced level
+ */
+bool debounceUpdate(Debounce_t *pDb, uint32_t t);
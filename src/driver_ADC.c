@@ -6,15 +6,28 @@
 
 #include "emon32.h"
 #include "emon_CM.h"
+#include "pingPong.h"
 #include "qfplib-m0-full.h"
 
+/* Differential scan of all channels, from the first. INPUTOFFSET is 0, so
+ * writing this restarts the scan. */
+#define ADC_INPUTCTRL_SCAN                                                     \
+  (ADC_INPUTCTRL_MUXPOS_PIN2 | ADC_INPUTCTRL_MUXNEG_PIN0 |                     \
+   ADC_INPUTCTRL_INPUTSCAN(VCT_TOTAL - 1u))
+
 static int16_t correctionGain;
 static int16_t correctionOffset;
 static bool    correctionValid;
 
+static volatile uint32_t adcMissedCnt;
+
+static volatile RawSampleSetPacked_t *adcBuffer[2];
+static PingPong_t                     adcPingPong;
+
 static void    adcCalibrate(void);
 static int16_t adcCalibrateSmp(const uint32_t pin);
 static void    adcConfigureDMAC(void);
+static void    adcResync(void);
 static void    adcSync(void);
 
 /*! @brief Calculate coarse gain and offset corrections. Only available when
@@ -101,12 +114,6 @@ static void adcConfigureDMAC(void) {
   volatile DmacDescriptor *dmacDesc[2];
   uint8_t                  dmaChan[2] = {DMA_CHAN_ADC0, DMA_CHAN_ADC1};
 
-  volatile RawSampleSetPacked_t *adcBuffer[2];
-
-  /* Get the contiguous data buffers */
-  adcBuffer[0] = ecmDataBuffer();
-  adcBuffer[1] = adcBuffer[0] + 1;
-
   dmacConfig.ctrlb = DMAC_CHCTRLB_LVL(3u) |
                      DMAC_CHCTRLB_TRIGSRC(ADC_DMAC_ID_RESRDY) |
                      DMAC_CHCTRLB_TRIGACT_BEAT;
@@ -129,6 +136,7 @@ static void adcConfigureDMAC(void) {
     dmacChannelConfigure(dmaChan[i], &dmacConfig);
     dmacEnableChannelInterrupt(dmaChan[i]);
   }
+  dmacEnableChannelErrorInterrupt(DMA_CHAN_ADC0);
 
   /* Link the descriptors so sampling is continuous */
   dmacDesc[0]->DESCADDR.reg = (uint32_t)dmacDesc[1];
@@ -140,6 +148,8 @@ int16_t adcCorrectionOffset(void) { return correctionOffset; }
 bool    adcCorrectionValid(void) { return correctionValid; }
 
 void adcDMACStart(void) {
+  /* Results are not read while stopped, so discard any overrun from then */
+  ADC->INTFLAG.reg = ADC_INTFLAG_OVERRUN;
   dmacChannelEnable(DMA_CHAN_ADC0);
 
   /* Enable ADC; requires synchronisation (30.6.13) */
@@ -151,6 +161,57 @@ void adcDMACStart(void) {
 
 void adcDMACStop(void) { dmacChannelDisable(DMA_CHAN_ADC0); }
 
+uint32_t adcMissed(void) { return adcMissedCnt; }
+
+/*! @brief Restart the DMA at buffer 0 and the ADC scan at the first channel,
+ *         so that each sample is again attributed to the right channel.
+ */
+static void adcResync(void) {
+  dmacChannelDisable(DMA_CHAN_ADC0);
+
+  /* Abort the conversion in progress, and discard any result */
+  ADC->SWTRIG.reg = ADC_SWTRIG_FLUSH;
+  adcSync();
+  ADC->INPUTCTRL.reg = ADC_INPUTCTRL_SCAN;
+  adcSync();
+  (void)ADC->RESULT.reg;
+  ADC->INTFLAG.reg = ADC_INTFLAG_OVERRUN;
+
+  adcConfigureDMAC();
+  pingPongResync(&adcPingPong);
+  dmacChannelEnable(DMA_CHAN_ADC0);
+}
+
+void adcSetComplete(void) {
+#if defined(PIN_ADC_DBG)
+  portPinDrv(GRP_ADC_DBG, PIN_ADC_DBG, PIN_DRV_TGL);
+#endif
+
+  /* A result overwritten before the DMAC read it is a lost conversion, and
+   * every later sample would be attributed to the wrong channel.
+   */
+  if (ADC->INTFLAG.reg & ADC_INTFLAG_OVERRUN) {
+    adcMissedCnt++;
+    adcResync();
+    return;
+  }
+  pingPongComplete(&adcPingPong);
+}
+
+void adcSetRelease(void) {
+  /* The DMA has completed another set while this one was processed, and is
+   * now writing into it */
+  if (dmacChannelComplete(DMA_CHAN_ADC0)) {
+    dmacClearChannelInterrupt(DMA_CHAN_ADC0);
+    adcSetComplete();
+  }
+  pingPongRelease(&adcPingPong);
+}
+
+const PingPongStats_t *adcSetStats(void) { return &adcPingPong.stats; }
+
+bool adcSetTake(uint8_t *pIdx) { return pingPongTake(&adcPingPong, pIdx); }
+
 void adcSetup(void) {
   extern uint8_t pinsADC[][2];
 
@@ -158,6 +219,10 @@ void adcSetup(void) {
     portPinMux(pinsADC[i][0], pinsADC[i][1], PORT_PMUX_PMUXE_B_Val);
   }
 
+#if defined(PIN_ADC_DBG)
+  portPinDir(GRP_ADC_DBG, PIN_ADC_DBG, PIN_DIR_OUT);
+#endif
+
   /* APB bus clock is enabled by default (Table 15-1). Connect GCLK 3 */
   GCLK->CLKCTRL.reg =
       GCLK_CLKCTRL_ID(ADC_GCLK_ID) | GCLK_CLKCTRL_GEN(3u) | GCLK_CLKCTRL_CLKEN;
@@ -190,16 +255,19 @@ void adcSetup(void) {
   ADC->SAMPCTRL.reg = 0x21u;
 
   /* Input control - requires synchronisation (33.6.15) */
-  ADC->INPUTCTRL.reg = ADC_INPUTCTRL_MUXPOS_PIN2 |
-                       ADC_INPUTCTRL_MUXNEG_PIN0
-                       /* INPUTSCAN is number of channels - 1 */
-                       | ADC_INPUTCTRL_INPUTSCAN(VCT_TOTAL - 1u);
+  ADC->INPUTCTRL.reg = ADC_INPUTCTRL_SCAN;
   adcSync();
 
   /* ADC is triggered by an event from TIMER_ADC with no CPU intervention */
   ADC->EVCTRL.reg = ADC_EVCTRL_STARTEI;
 
+  /* Get the contiguous data buffers */
+  adcBuffer[0] = ecmDataBuffer();
+  adcBuffer[1] = adcBuffer[0] + 1;
+
+  pingPongInit(&adcPingPong);
   adcConfigureDMAC();
+  dmacCallbackBufferError(&adcResync);
 }
 
 static void adcSync(void) {
@@ -3,6 +3,8 @@
 #include <stdbool.h>
 #include <stdint.h>
 
+#include "pingPong.h"
+
 /*! @brief Get the gain correction value
  *  @return gain correction value
  */
@@ -18,11 +20,39 @@ int16_t adcCorrectionOffset(void);
  */
 bool adcCorrectionValid(void);
 
+/*! @brief Number of conversions lost since reset. A conversion is lost when
+ *         its result is overwritten before the DMAC has read it.
+ *  @return count of lost conversions
+ */
+uint32_t adcMissed(void);
+
 /*! @brief Starts the DMAC transfer from the ADC */
 void adcDMACStart(void);
 
 /*! @brief Stop the DMAC transfer from the ADC */
 void adcDMACStop(void);
 
+/*! @brief A full sample set has been captured. Toggles the debug pin, if
+ *         there is one. After a lost conversion the DMA and ADC scan are
+ *         restarted, and the set is discarded. Call from the DMAC completion.
+ */
+void adcSetComplete(void);
+
+/*! @brief Processing of the set from adcSetTake has finished. If the DMA
+ *         completed another set meanwhile, it is taken as complete here.
+ */
+void adcSetRelease(void);
+
+/*! @brief Get the sample set hand-off counters
+ *  @return pointer to the counters
+ */
+const PingPongStats_t *adcSetStats(void);
+
+/*! @brief Take the oldest completed sample set for processing
+ *  @param [out] pIdx : index of the buffer to process
+ *  @return true if there was a completed set, false otherwise
+ */
+bool adcSetTake(uint8_t *pIdx);
+
 /*! @brief Configure the ADC for the board */
 void adcSetup(void);
@@ -7,6 +7,8 @@ static volatile DmacDescriptor dmacs[NUM_CHAN_DMA];
 static DmacDescriptor          dmacs_wb[NUM_CHAN_DMA];
 
 static void (*cbBufferFill)(void);
+static void (*cbBufferError)(void);
+static void (*cbUartCmpl)(void);
 
 /* Useful ref: https://aykevl.nl/2019/09/samd21-dma */
 
@@ -26,8 +28,29 @@ void dmacSetup(void) {
 
 volatile DmacDescriptor *dmacGetDescriptor(uint8_t ch) { return &dmacs[ch]; }
 
+void dmacCallbackBufferError(void (*cb)(void)) { cbBufferError = cb; }
+
 void dmacCallbackBufferFill(void (*cb)(void)) { cbBufferFill = cb; }
 
+void dmacCallbackUartCmpl(void (*cb)(void)) { cbUartCmpl = cb; }
+
+bool dmacChannelComplete(uint8_t ch) {
+  DMAC->CHID.reg = ch;
+  return (bool)(DMAC->CHINTFLAG.reg & DMAC_CHINTFLAG_TCMPL);
+}
+
+uint16_t dmacChannelRemaining(uint8_t ch) {
+  /* The active channel's count is only in the ACTIVE register; any other
+   * channel's count is written back when it leaves the active state. */
+  const uint32_t active = DMAC->ACTIVE.reg;
+  if ((active & DMAC_ACTIVE_ABUSY) &&
+      (ch == ((active & DMAC_ACTIVE_ID_Msk) >> DMAC_ACTIVE_ID_Pos))) {
+    return (uint16_t)((active & DMAC_ACTIVE_BTCNT_Msk) >>
+                      DMAC_ACTIVE_BTCNT_Pos);
+  }
+  return dmacs_wb[ch].BTCNT.reg;
+}
+
 void dmacChannelDisable(uint8_t ch) {
   DMAC->CHID.reg           = ch;
   DMAC->CHCTRLA.bit.ENABLE = 0;
@@ -43,6 +66,11 @@ void dmacEnableChannelInterrupt(uint8_t ch) {
   DMAC->CHINTENSET.reg = DMAC_CHINTENSET_TCMPL;
 }
 
+void dmacEnableChannelErrorInterrupt(uint8_t ch) {
+  DMAC->CHID.reg       = ch;
+  DMAC->CHINTENSET.reg = DMAC_CHINTENSET_TERR;
+}
+
 void dmacDisableChannelInterrupt(uint8_t ch) {
   DMAC->CHID.reg       = ch;
   DMAC->CHINTENCLR.reg = DMAC_CHINTENCLR_TCMPL;
@@ -63,11 +91,26 @@ void irq_handler_dmac(void) {
    * clear the interrupt source
    */
   DMAC->CHID.reg = DMA_CHAN_ADC0;
-  if (DMAC->CHINTFLAG.reg & DMAC_CHINTFLAG_TCMPL) {
+  if (DMAC->CHINTFLAG.reg & DMAC_CHINTFLAG_TERR) {
+    /* The channel has stopped part way through a set; anything completed is
+     * discarded when it is restarted */
+    DMAC->CHINTFLAG.reg = DMAC_CHINTFLAG_TERR | DMAC_CHINTFLAG_TCMPL;
+    if (cbBufferError) {
+      (*cbBufferError)();
+    }
+  } else if (DMAC->CHINTFLAG.reg & DMAC_CHINTFLAG_TCMPL) {
     DMAC->CHINTFLAG.reg = DMAC_CHINTFLAG_TCMPL;
     dmacChannelEnable(DMA_CHAN_ADC0);
     (*cbBufferFill)();
   }
+
+  DMAC->CHID.reg = DMA_CHAN_UART;
+  if (DMAC->CHINTFLAG.reg & DMAC_CHINTFLAG_TCMPL) {
+    DMAC->CHINTFLAG.reg = DMAC_CHINTFLAG_TCMPL;
+    if (cbUartCmpl) {
+      (*cbUartCmpl)();
+    }
+  }
 }
 
 uint16_t calcCRC16_ccitt(const void *pSrc, size_t n) {
@@ -1,5 +1,6 @@
 #pragma once
 
+#include <stdbool.h>
 #include <stddef.h>
 
 #include "emon32_samd.h"
@@ -17,6 +18,12 @@ void dmacSetup(void);
  */
 volatile DmacDescriptor *dmacGetDescriptor(uint8_t ch);
 
+/*! @brief Set the callback when the sample buffer transfer has stopped on an
+ *         error
+ *  @param [in] cb : pointer to the callback function
+ */
+void dmacCallbackBufferError(void (*cb)(void));
+
 /*! @brief Set the callback when the DMA has filled the sample buffer
  *  @param [in] cb : pointer to the callback function
  */
@@ -27,6 +34,20 @@ void dmacCallbackBufferFill(void (*cb)(void));
  */
 void dmacCallbackUartCmpl(void (*cb)(void));
 
+/*! @brief Indicate if a channel has completed its transfer. The flag is left
+ *         set; clear it with dmacClearChannelInterrupt.
+ *  @param [in] ch : channel number
+ *  @return true if the transfer complete flag is set
+ */
+bool dmacChannelComplete(uint8_t ch);
+
+/*! @brief Beats left in a channel's current block. For a circular descriptor
+ *         this gives the write position into the destination.
+ *  @param [in] ch : channel number
+ *  @return remaining beats; 0 if the channel has not yet started
+ */
+uint16_t dmacChannelRemaining(uint8_t ch);
+
 /*! @brief Disable a DMAC channel
  *  @param [in] ch : channel number
  */
@@ -48,6 +69,11 @@ void dmacChannelConfigure(uint8_t ch, const DMACCfgCh_t *pCfg);
  */
 void dmacEnableChannelInterrupt(uint8_t ch);
 
+/*! @brief Enable DMAC channel transfer error interrupt
+ *  @param [in] ch : channel to enable interrupt for
+ */
+void dmacEnableChannelErrorInterrupt(uint8_t ch);
+
 /*! @brief Disable DMAC channel interrupt
  *  @param [in] ch : channel to disable interrupt for
  */
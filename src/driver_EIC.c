@@ -7,14 +7,52 @@
 #include "driver_PORT.h"
 #include "emon32.h"
 
+static void (*cbLine[EIC_EXTINT_NUM])(uint8_t line);
+
+static void eicSync(void);
+
+void eicCallbackLine(const uint8_t line, void (*cb)(uint8_t line)) {
+  cbLine[line] = cb;
+}
+
 void eicEnable(void) {
   EIC->CTRL.reg = EIC_CTRL_ENABLE;
-  while (EIC->STATUS.reg & EIC_STATUS_SYNCBUSY)
-    ;
+  eicSync();
 
   NVIC_EnableIRQ(EIC_IRQn);
 }
 
+/*! @brief Write a line's sense and filter. CONFIG is enable protected, so the
+ *         EIC is stopped around the write.
+ */
+static void eicLineConfig(const uint8_t line, const uint32_t cfg) {
+  const uint32_t shift   = (line & 7u) * 4u;
+  const bool     enabled = EIC->CTRL.reg & EIC_CTRL_ENABLE;
+
+  EIC->CTRL.reg = 0;
+  eicSync();
+  EIC->CONFIG[line >> 3].reg =
+      (EIC->CONFIG[line >> 3].reg & ~(0xFu << shift)) | (cfg << shift);
+  if (enabled) {
+    EIC->CTRL.reg = EIC_CTRL_ENABLE;
+    eicSync();
+  }
+}
+
+void eicLineDisable(const uint8_t line) {
+  EIC->INTENCLR.reg = (1u << line);
+  eicLineConfig(line, EIC_CONFIG_SENSE0_NONE);
+  EIC->INTFLAG.reg = (1u << line);
+}
+
+void eicLineEnable(const uint8_t line, const EICSense_t sense) {
+  const uint32_t cfg = (EIC_SENSE_BOTH == sense) ? EIC_CONFIG_SENSE0_BOTH
+                                                 : EIC_CONFIG_SENSE0_RISE;
+  eicLineConfig(line, (cfg | EIC_CONFIG_FILTEN0));
+  EIC->INTFLAG.reg  = (1u << line);
+  EIC->INTENSET.reg = (1u << line);
+}
+
 void eicSetup(void) {
   /* EIC APB clock is unmasked on reset (16.8.8)
    * GCLK required for edge detection */
@@ -35,4 +73,18 @@ void irq_handler_eic(void) {
     }
     EIC->INTFLAG.reg = EIC_INTFLAG_EXTINT0;
   }
+
+  const uint32_t lines =
+      EIC->INTFLAG.reg & EIC->INTENSET.reg & ~EIC_INTFLAG_EXTINT0;
+  EIC->INTFLAG.reg = lines;
+  for (uint8_t line = 1u; line < EIC_EXTINT_NUM; line++) {
+    if ((lines & (1u << line)) && cbLine[line]) {
+      (*cbLine[line])(line);
+    }
+  }
+}
+
+static void eicSync(void) {
+  while (EIC->STATUS.reg & EIC_STATUS_SYNCBUSY)
+    ;
 }
@@ -1,7 +1,30 @@
 #pragma once
 
+#include <stdint.h>
+
+typedef enum EICSense_ { EIC_SENSE_RISE, EIC_SENSE_BOTH } EICSense_t;
+
+/*! @brief Set the callback for an edge on a line enabled with eicLineEnable.
+ *         It is called from the interrupt.
+ *  @param [in] line : EXTINT line number, 1 or higher
+ *  @param [in] cb : pointer to the callback function, with the line number
+ */
+void eicCallbackLine(uint8_t line, void (*cb)(uint8_t line));
+
 /*! @brief Enable the External Interrupt Controller */
 void eicEnable(void);
 
+/*! @brief Stop interrupts from an external interrupt line
+ *  @param [in] line : EXTINT line number
+ */
+void eicLineDisable(uint8_t line);
+
+/*! @brief Interrupt on edges of an external interrupt line, with the majority
+ *         filter (3 samples) on. The pin must be muxed to the EIC.
+ *  @param [in] line : EXTINT line number, 1 or higher; 0 is DISABLE_EXT
+ *  @param [in] sense : rising edges only, or both edges
+ */
+void eicLineEnable(uint8_t line, EICSense_t sense);
+
 /*! @brief Setup the External Interrupt Controller */
 void eicSetup(void);
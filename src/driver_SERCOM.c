@@ -1,3 +1,5 @@
+#include <string.h>
+
 #include "emon32_samd.h"
 
 #include "configuration.h"
@@ -6,20 +8,63 @@
 #include "driver_SERCOM.h"
 #include "driver_TIME.h"
 #include "emon32.h"
+#include "ringbuf.h"
+#include "rs485.h"
+#include "util.h"
 
 #define I2CM_ACTIVATE_TIMEOUT_US 200u /* Time to wait for I2C address phase */
 #define I2CM_DATA_TIMEOUT_US     200u /* Time to wait for I2C data byte */
+#define UART_TX_DEPTH            256u /* UART Tx ring, must be power of 2 */
+#define UART_RX_DEPTH            256u /* UART Rx DMA buffer, power of 2 */
+
+/* Check the board's UART wiring (see board_def.h). Only pads 0 and 2 can be
+ * used for Tx, and Rx must be on another pad (26.8.1).
+ */
+_Static_assert((UART_SERCOM_NUM <= 5) && (UART_SERCOM_NUM != 2) &&
+                   (UART_SERCOM_NUM != 3) && (UART_SERCOM_NUM != 4),
+               "UART_SERCOM_NUM must be 0, 1, or 5");
+_Static_assert(UART_PAD_TX <= 1u, "UART_PAD_TX must be 0 (pad 0) or 1 (pad 2)");
+_Static_assert((UART_PAD_RX <= 3u) && (UART_PAD_RX != (UART_PAD_TX * 2u)),
+               "UART_PAD_RX must be a pad other than the Tx pad");
 
 static void i2cmCommon(Sercom *pSercom);
 static void i2cmExtPinsSetup(void);
 static void sercomSetupSPI(void);
 static void spiExtPinsSetup(bool enable);
 
-static void uartInterruptEnable(Sercom *sercom, uint8_t interrupt);
-static void uartSetup(void);
+static void   uartInterruptEnable(Sercom *sercom, uint8_t interrupt);
+static void   uartReset(Sercom *sercom);
+static void   uartRs485DE(bool on);
+static void   uartRs485Empty(Sercom *sercom);
+static bool   uartRs485Ready(uint32_t *pWait_us);
+static void   uartRs485Resume(void);
+static size_t uartRxDMAPos(void);
+static void   uartRxStatus(Sercom *sercom);
+static void   uartSetup(void);
+static void   uartTxDMACmpl(void);
+static void   uartTxDMAStart(void);
+static void   uartTxKick(Sercom *sercom);
+static void   uartTxPoll(Sercom *sercom);
+static void   uartTxService(Sercom *sercom);
 
 static volatile bool extIntfEnabled = true;
 
+static uint8_t           uartTxBuf[UART_TX_DEPTH];
+static RingBuf_t         uartTxRing    = {uartTxBuf, UART_TX_DEPTH, 0, 0, 0};
+static volatile bool     uartTxDMA     = false; /* Tx ring drained by DMA */
+static volatile size_t   uartTxDMALen  = 0;     /* Bytes in flight, 0: idle */
+static UARTCfg_t         uartLine      = {UART_BAUD, UART_PARITY_NONE, 1u};
+
+static volatile uint8_t uartRxBuf[UART_RX_DEPTH];
+static bool             uartRxDMA    = false; /* Rx by DMA, not RXC */
+static size_t           uartRxRd     = 0;     /* Next byte to read */
+static size_t           uartRxLastWr = 0;     /* Write position at last check */
+
+static Rs485_t uartRs485;
+static bool    uartRs485En = false; /* DE/RE driven around each transmission */
+
+static volatile UARTStats_t uartStat = {0};
+
 static void i2cmCommon(Sercom *pSercom) {
   /* For 400 kHz I2C (fast mode) with asymmetric timing:
    * At 8 MHz (125 ns/tick):
@@ -113,37 +158,67 @@ void sercomSetup(void) {
   sercomSetupSPI();
 }
 
-static void uartSetup(void) {
+static void uartReset(Sercom *sercom) {
+  /* uartSetup resets the SERCOM, so restore the interrupts and enable */
+  const uint8_t intenset = sercom->USART.INTENSET.reg;
+  const bool    enabled  = sercom->USART.CTRLA.reg & SERCOM_USART_CTRLA_ENABLE;
 
-  uint16_t baud;
-  // const uint64_t br_dbg = (uint64_t)65536 * (F_PERIPH - 16 * pCfg->baud) /
-  // F_PERIPH;
-  switch (UART_BAUD) {
-  case (UART_BAUD_9600):
-    baud = 64279;
-    break;
-  case (UART_BAUD_19200):
-    baud = 63020;
-    break;
-  case (UART_BAUD_28800):
-    baud = 61762;
-    break;
-  case (UART_BAUD_38400):
-    baud = 60504u;
-    break;
-  case (UART_BAUD_57600):
-    baud = 57987;
-    break;
-  case (UART_BAUD_76800):
-    baud = 55471;
-    break;
-  case (UART_BAUD_115200):
-    baud = 50438u;
-    break;
-  default:
-    /* Default to 9600 if a non-standard baud is entered */
-    baud = 64279;
+  uartSetup();
+
+  sercom->USART.INTENSET.reg = intenset;
+  if (enabled) {
+    sercom->USART.CTRLA.bit.ENABLE = 1;
+    while (sercom->USART.STATUS.reg & SERCOM_USART_SYNCBUSY_ENABLE)
+      ;
+  }
+}
+
+bool uartRs485Enable(const uint32_t guard_us) {
+#if defined(PIN_RS485_DE)
+  portPinDir(GRP_RS485_DE, PIN_RS485_DE, PIN_DIR_OUT);
+
+  __disable_irq();
+  rs485Init(&uartRs485, guard_us, &uartRs485DE);
+  uartRs485En = true;
+  __enable_irq();
+  return true;
+#else
+  (void)guard_us;
+  return false;
+#endif
+}
+
+static void uartRs485DE(bool on) {
+#if defined(PIN_RS485_DE)
+  portPinDrv(GRP_RS485_DE, PIN_RS485_DE, on ? PIN_DRV_SET : PIN_DRV_CLR);
+#else
+  (void)on;
+#endif
+}
+
+static void uartRs485Empty(Sercom *sercom) {
+  /* DRE and the DMA completion come when the last byte is loaded, not sent */
+  if (uartRs485En && rs485TxEmpty(&uartRs485)) {
+    uartInterruptEnable(sercom, SERCOM_USART_INTENSET_TXC);
   }
+}
+
+static bool uartRs485Ready(uint32_t *pWait_us) {
+  *pWait_us = 0;
+  return !uartRs485En || rs485TxStart(&uartRs485, timerMicros(), pWait_us);
+}
+
+static void uartRs485Resume(void) {
+  if (ringbufCount(&uartTxRing)) {
+    uartTxKick(SERCOM_UART);
+  }
+}
+
+static void uartSetup(void) {
+
+  /* uartLine is only changed to settings that have been checked */
+  uint16_t baud = 0;
+  (void)utilBaudReg(uartLine.baud, F_PERIPH, &baud);
 
   portPinMux(GRP_SERCOM_UART, PIN_UART_TX, PMUX_UART);
   portPinMux(GRP_SERCOM_UART, PIN_UART_RX, PMUX_UART);
@@ -159,15 +234,19 @@ static void uartSetup(void) {
     ;
 
   /* Configure the USART */
-  SERCOM_UART->USART.CTRLA.reg = SERCOM_USART_CTRLA_DORD |
-                                 SERCOM_USART_CTRLA_MODE_USART_INT_CLK |
-                                 SERCOM_USART_CTRLA_RXPO(UART_PAD_RX) |
-                                 SERCOM_USART_CTRLA_TXPO(UART_PAD_TX);
+  SERCOM_UART->USART.CTRLA.reg =
+      SERCOM_USART_CTRLA_DORD | SERCOM_USART_CTRLA_MODE_USART_INT_CLK |
+      SERCOM_USART_CTRLA_RXPO(UART_PAD_RX) |
+      SERCOM_USART_CTRLA_TXPO(UART_PAD_TX) |
+      /* FORM 1: frame with parity */
+      SERCOM_USART_CTRLA_FORM((UART_PARITY_NONE == uartLine.parity) ? 0 : 1);
 
   /* TX/RX enable requires synchronisation */
-  SERCOM_UART->USART.CTRLB.reg = SERCOM_USART_CTRLB_RXEN |
-                                 SERCOM_USART_CTRLB_TXEN |
-                                 SERCOM_USART_CTRLB_CHSIZE(0);
+  SERCOM_UART->USART.CTRLB.reg =
+      SERCOM_USART_CTRLB_RXEN | SERCOM_USART_CTRLB_TXEN |
+      SERCOM_USART_CTRLB_CHSIZE(0) |
+      ((UART_PARITY_ODD == uartLine.parity) ? SERCOM_USART_CTRLB_PMODE : 0) |
+      ((2u == uartLine.stopBits) ? SERCOM_USART_CTRLB_SBMODE : 0);
   while (SERCOM_UART->USART.STATUS.reg & SERCOM_USART_SYNCBUSY_CTRLB)
     ;
 
@@ -214,21 +293,31 @@ static void sercomSetupSPI(void) {
 
 void uartPutcBlocking(Sercom *sercom, char c) {
 
-  uint32_t t_start         = timerMillis();
-  bool     uartNotTimedOut = true;
-
-  while (!(sercom->USART.INTFLAG.reg & SERCOM_USART_INTFLAG_DRE) &&
-         uartNotTimedOut) {
-    uartNotTimedOut = (timerMillisDelta(t_start) < 10);
-  };
-
-  /* Assume at this point the UART is in error state, so reset and proceed. */
-  if (!uartNotTimedOut) {
-    uartSetup();
+  uint32_t t_start = timerMillis();
+
+  while (!ringbufPut(&uartTxRing, (uint8_t)c)) {
+    /* The Tx interrupts can not run if this is called from a handler or with
+     * interrupts masked, so move the ring along from here as well. */
+    __disable_irq();
+    uartTxPoll(sercom);
+    __enable_irq();
+
+    /* Assume at this point the UART is in error state, so reset, discard
+     * anything waiting, and proceed. */
+    if (timerMillisDelta(t_start) >= 10) {
+      if (uartTxDMA) {
+        dmacChannelDisable(DMA_CHAN_UART);
+        uartTxDMALen = 0;
+      }
+      uartReset(sercom);
+      const size_t discard = ringbufCount(&uartTxRing);
+      ringbufSkip(&uartTxRing, discard);
+      uartStat.dropped += discard;
+    }
   }
 
-  sercom->USART.DATA.reg    = c;
-  sercom->USART.INTFLAG.reg = SERCOM_USART_INTFLAG_DRE;
+  uartStat.queued++;
+  uartTxKick(sercom);
 }
 
 void uartPutsBlocking(Sercom *sercom, const char *s) {
@@ -237,6 +326,181 @@ void uartPutsBlocking(Sercom *sercom, const char *s) {
   }
 }
 
+size_t uartPuts(Sercom *sercom, const char *s) {
+  return uartWrite(sercom, s, strlen(s));
+}
+
+size_t uartWrite(Sercom *sercom, const void *pSrc, size_t n) {
+  const size_t added = ringbufWrite(&uartTxRing, pSrc, n);
+
+  /* Whatever did not fit is dropped and counted, rather than waited for */
+  uartStat.queued += added;
+  if (added < n) {
+    uartStat.dropped += (n - added);
+    uartStat.writesCut++;
+  }
+
+  if (added) {
+    uartTxKick(sercom);
+  }
+  return added;
+}
+
+uint32_t uartTxDropped(void) { return uartStat.dropped; }
+
+void uartStats(UARTStats_t *pStats) {
+  /* Sent and the receive errors are updated from the UART interrupt */
+  __disable_irq();
+  *pStats = *(const UARTStats_t *)&uartStat;
+  __enable_irq();
+  pStats->txPeak = ringbufPeak(&uartTxRing);
+}
+
+void uartTxHandler(Sercom *sercom) {
+  if (sercom->USART.INTENSET.reg & SERCOM_USART_INTENSET_DRE) {
+    uartTxService(sercom);
+  }
+
+  /* The last stop bit has gone; release the RS485 driver unless more has
+   * been queued since */
+  if ((sercom->USART.INTENSET.reg & SERCOM_USART_INTENSET_TXC) &&
+      (sercom->USART.INTFLAG.reg & SERCOM_USART_INTFLAG_TXC)) {
+    sercom->USART.INTFLAG.reg = SERCOM_USART_INTFLAG_TXC;
+    if (rs485TxComplete(&uartRs485)) {
+      sercom->USART.INTENCLR.reg = SERCOM_USART_INTENCLR_TXC;
+    }
+  }
+}
+
+void uartTxDMAEnable(void) {
+  DMACCfgCh_t              dmacConfig;
+  volatile DmacDescriptor *pDesc = dmacGetDescriptor(DMA_CHAN_UART);
+
+  /* Lowest priority; the ADC must never wait on the UART */
+  dmacConfig.ctrlb = DMAC_CHCTRLB_LVL(0u) |
+                     DMAC_CHCTRLB_TRIGSRC(SERCOM_UART_DMAC_ID_TX) |
+                     DMAC_CHCTRLB_TRIGACT_BEAT;
+  dmacChannelConfigure(DMA_CHAN_UART, &dmacConfig);
+
+  pDesc->DSTADDR.reg  = (uint32_t)&SERCOM_UART->USART.DATA.reg;
+  pDesc->DESCADDR.reg = 0;
+
+  dmacCallbackUartCmpl(&uartTxDMACmpl);
+  dmacEnableChannelInterrupt(DMA_CHAN_UART);
+
+  /* Let the interrupt path finish anything already queued */
+  __disable_irq();
+  SERCOM_UART->USART.INTENCLR.reg = SERCOM_USART_INTENCLR_DRE;
+  uartTxDMA                       = true;
+  uartTxDMAStart();
+  __enable_irq();
+}
+
+static void uartTxDMACmpl(void) {
+  ringbufSkip(&uartTxRing, uartTxDMALen);
+  uartStat.sent += uartTxDMALen;
+  uartTxDMALen = 0;
+  uartTxDMAStart();
+}
+
+static void uartTxDMAStart(void) {
+  const uint8_t *pData;
+  const size_t   n = ringbufPeek(&uartTxRing, &pData);
+
+  if (0 == n) {
+    uartRs485Empty(SERCOM_UART);
+    return;
+  }
+
+  /* A wrapped ring is sent as two transfers; a report line is normally a
+   * single transfer with one completion interrupt. */
+  volatile DmacDescriptor *pDesc = dmacGetDescriptor(DMA_CHAN_UART);
+
+  /* SRCADDR is the last address, rather than first! */
+  pDesc->SRCADDR.reg = (uint32_t)(pData + n);
+  pDesc->BTCNT.reg   = (uint16_t)n;
+  pDesc->BTCTRL.reg  = DMAC_BTCTRL_VALID | DMAC_BTCTRL_BLOCKACT_INT |
+                       DMAC_BTCTRL_BEATSIZE_BYTE | DMAC_BTCTRL_SRCINC;
+  uartTxDMALen       = n;
+  dmacChannelEnable(DMA_CHAN_UART);
+}
+
+static void uartTxKick(Sercom *sercom) {
+  uint32_t wait_us;
+
+  /* DMAC channel registers and the RS485 state are shared with the
+   * handlers */
+  __disable_irq();
+  if (uartRs485Ready(&wait_us)) {
+    if (!uartTxDMA) {
+      uartInterruptEnable(sercom, SERCOM_USART_INTENSET_DRE);
+    } else if (0 == uartTxDMALen) {
+      uartTxDMAStart();
+    }
+  }
+  __enable_irq();
+
+  /* Still inside the RS485 turnaround guard; try again once it has passed */
+  if (wait_us) {
+    (void)timerCancelCallback(&uartRs485Resume);
+    (void)timerScheduleCallback(&uartRs485Resume, wait_us);
+  }
+}
+
+static void uartTxPoll(Sercom *sercom) {
+  uint32_t wait_us;
+
+  if (!uartRs485Ready(&wait_us)) {
+    return;
+  }
+
+  if (uartTxDMA) {
+    if (dmacChannelComplete(DMA_CHAN_UART)) {
+      dmacClearChannelInterrupt(DMA_CHAN_UART);
+      uartTxDMACmpl();
+    }
+  } else {
+    uartTxService(sercom);
+  }
+}
+
+static void uartTxService(Sercom *sercom) {
+  uint8_t c;
+
+  /* Writing DATA clears DRE; stop the interrupt once the ring is empty */
+  if (sercom->USART.INTFLAG.reg & SERCOM_USART_INTFLAG_DRE) {
+    if (ringbufGet(&uartTxRing, &c)) {
+      sercom->USART.DATA.reg = c;
+      uartStat.sent++;
+    } else {
+      sercom->USART.INTENCLR.reg = SERCOM_USART_INTENCLR_DRE;
+      uartRs485Empty(sercom);
+    }
+  }
+}
+
+bool uartConfigure(Sercom *sercom, const UARTCfg_t *pCfg) {
+  uint16_t baud;
+
+  if (!utilBaudReg(pCfg->baud, F_PERIPH, &baud) ||
+      (pCfg->parity > UART_PARITY_ODD) || (pCfg->stopBits < 1u) ||
+      (pCfg->stopBits > 2u)) {
+    return false;
+  }
+
+  /* Let anything queued finish with the old settings. At 9600 baud the full
+   * ring takes ~270 ms. */
+  const uint32_t t_start = timerMillis();
+  while ((ringbufCount(&uartTxRing) || uartTxDMALen ||
+          !(sercom->USART.INTFLAG.reg & SERCOM_USART_INTFLAG_TXC)) &&
+         (timerMillisDelta(t_start) < 500))
+    ;
+
+  uartLine = *pCfg;
+  uartReset(sercom);
+  return true;
+}
+
 void uartEnableRx(Sercom *sercom, const uint32_t irqn) {
   uartInterruptEnable(sercom, SERCOM_USART_INTENSET_RXC);
   NVIC_EnableIRQ(irqn);
@@ -251,7 +515,9 @@ void uartEnableRx(Sercom *sercom, const uint32_t irqn) {
   }
 }
 
-void uartEnableTx(Sercom *sercom) {
+void uartEnableTx(Sercom *sercom, const uint32_t irqn) {
+  NVIC_EnableIRQ(irqn);
+
   sercom->USART.CTRLB.bit.TXEN = 1;
   /* Enable requires synchronisation (26.6.6) */
   if (!(sercom->USART.CTRLA.reg & SERCOM_USART_CTRLA_ENABLE)) {
@@ -261,12 +527,116 @@ void uartEnableTx(Sercom *sercom) {
   }
 }
 
-char uartGetc(Sercom *sercom) { return (char)sercom->USART.DATA.reg; }
+char uartGetc(Sercom *sercom) {
+  /* STATUS applies to the byte in DATA, so check it before reading */
+  uartRxStatus(sercom);
+  if (uartRs485En) {
+    rs485RxByte(&uartRs485, timerMicros());
+  }
+  return (char)sercom->USART.DATA.reg;
+}
 
 bool uartGetcReady(const Sercom *sercom) {
   return (bool)(sercom->USART.INTFLAG.reg & SERCOM_USART_INTFLAG_RXC);
 }
 
+bool uartRxDMAEnable(void) {
+#if defined(DMA_CHAN_UART_RX)
+  DMACCfgCh_t              dmacConfig;
+  volatile DmacDescriptor *pDesc = dmacGetDescriptor(DMA_CHAN_UART_RX);
+
+  /* The descriptor links to itself, so the buffer is refilled from the start
+   * without the CPU. DSTADDR is the last address, rather than first! */
+  pDesc->SRCADDR.reg  = (uint32_t)&SERCOM_UART->USART.DATA.reg;
+  pDesc->DSTADDR.reg  = (uint32_t)(uartRxBuf + UART_RX_DEPTH);
+  pDesc->BTCNT.reg    = UART_RX_DEPTH;
+  pDesc->BTCTRL.reg   = DMAC_BTCTRL_VALID | DMAC_BTCTRL_BLOCKACT_NOACT |
+                        DMAC_BTCTRL_BEATSIZE_BYTE | DMAC_BTCTRL_DSTINC;
+  pDesc->DESCADDR.reg = (uint32_t)pDesc;
+
+  /* Lowest priority, as Tx. A byte waiting in DATA is a pending trigger, so
+   * nothing is lost in the change from the RXC interrupt. CHID is shared
+   * with the DMAC handler. */
+  dmacConfig.ctrlb = DMAC_CHCTRLB_LVL(0u) |
+                     DMAC_CHCTRLB_TRIGSRC(SERCOM_UART_DMAC_ID_RX) |
+                     DMAC_CHCTRLB_TRIGACT_BEAT;
+
+  __disable_irq();
+  dmacChannelConfigure(DMA_CHAN_UART_RX, &dmacConfig);
+  SERCOM_UART->USART.INTENCLR.reg = SERCOM_USART_INTENCLR_RXC;
+  uartRxRd                        = 0;
+  uartRxLastWr                    = 0;
+  uartRxDMA                       = true;
+  dmacChannelEnable(DMA_CHAN_UART_RX);
+  __enable_irq();
+  return true;
+#else
+  return false;
+#endif
+}
+
+static size_t uartRxDMAPos(void) {
+#if defined(DMA_CHAN_UART_RX)
+  return (UART_RX_DEPTH - dmacChannelRemaining(DMA_CHAN_UART_RX)) &
+         (UART_RX_DEPTH - 1u);
+#else
+  return 0;
+#endif
+}
+
+size_t uartRxDMARead(void *pDst, const size_t m) {
+  uint8_t *pData = pDst;
+  size_t   n     = 0;
+
+  if (!uartRxDMA) {
+    return 0;
+  }
+
+  /* Errors can only be seen after the bytes have gone, so are counted at
+   * most once for each read. */
+  uartRxStatus(SERCOM_UART);
+
+  const size_t wr = uartRxDMAPos();
+  while ((uartRxRd != wr) && (n < m)) {
+    pData[n++] = uartRxBuf[uartRxRd];
+    uartRxRd   = (uartRxRd + 1u) & (UART_RX_DEPTH - 1u);
+  }
+  return n;
+}
+
+bool uartRxDMAReady(void) {
+  if (!uartRxDMA) {
+    return false;
+  }
+
+  /* The SERCOM has no idle line detection, so a gap is when nothing has
+   * arrived since the last check. Don't wait for a gap if the buffer is
+   * half full. */
+  const size_t wr    = uartRxDMAPos();
+  const size_t n     = (wr - uartRxRd) & (UART_RX_DEPTH - 1u);
+  const bool   quiet = (wr == uartRxLastWr);
+
+  uartRxLastWr = wr;
+  return (0 != n) && (quiet || (n >= (UART_RX_DEPTH / 2u)));
+}
+
+static void uartRxStatus(Sercom *sercom) {
+  const uint16_t status = sercom->USART.STATUS.reg;
+  if (status & (SERCOM_USART_STATUS_FERR | SERCOM_USART_STATUS_PERR |
+                SERCOM_USART_STATUS_BUFOVF)) {
+    if (status & SERCOM_USART_STATUS_FERR) {
+      uartStat.rxFrame++;
+    }
+    if (status & SERCOM_USART_STATUS_PERR) {
+      uartStat.rxParity++;
+    }
+    if (status & SERCOM_USART_STATUS_BUFOVF) {
+      uartStat.rxOverrun++;
+    }
+    sercom->USART.STATUS.reg = status;
+  }
+}
+
 static void uartInterruptEnable(Sercom *sercom, uint8_t interrupt) {
   sercom->USART.INTENSET.reg = interrupt;
 }
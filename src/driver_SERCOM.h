@@ -1,6 +1,7 @@
 #pragma once
 
 #include <stdbool.h>
+#include <stddef.h>
 #include <stdint.h>
 
 #include "driver_DMAC.h"
@@ -24,15 +25,28 @@ typedef enum I2CM_Status_ {
   I2CM_DISABLED
 } I2CM_Status_t;
 
-typedef enum UART_BAUD_ {
-  UART_BAUD_9600   = 9600,
-  UART_BAUD_19200  = 19200,
-  UART_BAUD_28800  = 28800,
-  UART_BAUD_38400  = 38400,
-  UART_BAUD_57600  = 57600,
-  UART_BAUD_76800  = 76800,
-  UART_BAUD_115200 = 115200
-} UART_BAUD_t;
+typedef enum UARTParity_ {
+  UART_PARITY_NONE = 0u,
+  UART_PARITY_EVEN = 1u,
+  UART_PARITY_ODD  = 2u
+} UARTParity_t;
+
+typedef struct UARTCfg_ {
+  uint32_t     baud;     /* Baud rate */
+  UARTParity_t parity;   /* Parity bit */
+  uint8_t      stopBits; /* 1 or 2 stop bits */
+} UARTCfg_t;
+
+typedef struct UARTStats_ {
+  uint32_t queued;    /* Bytes added to the Tx ring */
+  uint32_t sent;      /* Bytes moved from the Tx ring to the UART */
+  uint32_t dropped;   /* Bytes that did not fit in the Tx ring */
+  uint32_t writesCut; /* uartPuts/uartWrite calls that dropped any bytes */
+  uint32_t txPeak;    /* Most bytes waiting in the Tx ring at once */
+  uint32_t rxFrame;   /* Received bytes with a framing error */
+  uint32_t rxParity;  /* Received bytes with a parity error */
+  uint32_t rxOverrun; /* Received bytes lost to a full receive buffer */
+} UARTStats_t;
 
 /*! @brief Disable the external SPI and I2C interfaces */
 void sercomExtIntfDisable(void);
@@ -108,18 +122,30 @@ void spiSendBuffer(Sercom *sercom, const void *pSrc, size_t n);
  */
 uint8_t spiSendByte(Sercom *sercom, const uint8_t b);
 
+/*! @brief Change the UART line settings. Anything already queued is sent
+ *         with the old settings first. Data is always 8 bits.
+ *  @param [in] sercom : pointer to the SERCOM instance
+ *  @param [in] pCfg : baud rate, parity, and stop bits
+ *  @return false if the settings are not valid or the baud rate can not be
+ *          reached within 1 % from the SERCOM clock; the UART is unchanged.
+ */
+bool uartConfigure(Sercom *sercom, const UARTCfg_t *pCfg);
+
 /*! @brief Enable the UART instance for Rx
  *  @param [in] irqn : interrupt number
  */
 void uartEnableRx(Sercom *sercom, const uint32_t irqn);
 
-/*! @brief Enable the UART instance for Tx
+/*! @brief Enable the UART instance for Tx. Transmission is interrupt driven
+ *         from a ring buffer, so the interrupt is enabled here.
  *  @param [in] sercom : pointer to SERCOM instance
+ *  @param [in] irqn : interrupt number
  */
-void uartEnableTx(Sercom *sercom);
+void uartEnableTx(Sercom *sercom, const uint32_t irqn);
 
 /*! @brief Get a character from the USART data buffer. Only valid when the
- *         INTFLAG.RXC bit it set.
+ *         INTFLAG.RXC bit it set. Any receive error is counted in the UART
+ *         statistics and cleared.
  *  @param [in] sercom : SERCOM instance
  */
 char uartGetc(Sercom *sercom);
@@ -135,14 +161,86 @@ bool uartGetcReady(const Sercom *sercom);
  */
 uint32_t uartInterruptStatus(const Sercom *sercom);
 
-/*! @brief Send a single character (blocking) on UART
+/*! @brief Send a single character on UART. Waits only if the Tx ring is
+ *         full, so output is never dropped.
  *  @param [in] sercom : pointer to the SERCOM instance
  *  @param [in] c : Single character
  */
 void uartPutcBlocking(Sercom *sercom, char c);
 
-/*! @brief Send a string (blocking) on UART
+/*! @brief Send a string on UART, waiting for space in the Tx ring as needed
  *  @param [in] sercom : pointer to the SERCOM instance
  *  @param [in] s : Pointer to null terminated string
  */
 void uartPutsBlocking(Sercom *sercom, const char *s);
+
+/*! @brief Queue a string for UART without waiting. If the Tx ring does not
+ *         have room for all of it, the characters that do not fit are dropped
+ *         and added to the count returned by uartTxDropped.
+ *  @param [in] sercom : pointer to the SERCOM instance
+ *  @param [in] s : Pointer to null terminated string
+ *  @return number of characters queued
+ */
+size_t uartPuts(Sercom *sercom, const char *s);
+
+/*! @brief Queue bytes for UART without waiting, as uartPuts. For binary data
+ *         that may contain nulls.
+ *  @param [in] sercom : pointer to the SERCOM instance
+ *  @param [in] pSrc : bytes to send
+ *  @param [in] n : number of bytes
+ *  @return number of bytes queued
+ */
+size_t uartWrite(Sercom *sercom, const void *pSrc, size_t n);
+
+/*! @brief Characters dropped by uartPuts/uartWrite since reset */
+uint32_t uartTxDropped(void);
+
+/*! @brief Copy the UART statistics. The counters run from reset.
+ *  @param [out] pStats : destination
+ */
+void uartStats(UARTStats_t *pStats);
+
+/*! @brief Drive an RS485 transceiver's DE/RE pin around each transmission.
+ *         DE is asserted once the bus has been quiet for the guard time after
+ *         the last received byte, and released on transmit complete.
+ *  @param [in] guard_us : turnaround guard in microseconds
+ *  @return true if enabled, false if the board has no DE pin (see
+ *          PIN_RS485_DE)
+ */
+bool uartRs485Enable(uint32_t guard_us);
+
+/*! @brief Receive on the UART by DMA into a circular buffer instead of the
+ *         RXC interrupt. Call after uartEnableRx. Requires dmacSetup to have
+ *         been called.
+ *  @return true if enabled, false if no DMA channel is assigned for UART Rx
+ *          (see DMA_CHAN_UART_RX); Rx stays interrupt driven.
+ */
+bool uartRxDMAEnable(void);
+
+/*! @brief Copy received bytes from the Rx DMA buffer. Any receive error is
+ *         counted in the UART statistics.
+ *  @param [out] pDst : destination
+ *  @param [in] m : most bytes to copy
+ *  @return number of bytes copied, 0 if none or Rx is not by DMA
+ */
+size_t uartRxDMARead(void *pDst, size_t m);
+
+/*! @brief Indicate if received bytes should be read: there has been a quiet
+ *         gap since the last call, or the Rx buffer is half full. Call
+ *         every 1 ms.
+ *  @return true if there are bytes to read, false otherwise
+ */
+bool uartRxDMAReady(void);
+
+/*! @brief Drain the UART Tx ring by DMA instead of the DRE interrupt. Each
+ *         contiguous run in the ring is sent with a single completion
+ *         interrupt. If this is not called (or the DMAC is not set up), the
+ *         interrupt path is used. Requires dmacSetup to have been called.
+ */
+void uartTxDMAEnable(void);
+
+/*! @brief Move the next queued character to the UART when it is ready. Must
+ *         be called from the UART interrupt handler.
+ *  @param [in] sercom : pointer to the SERCOM instance
+ */
+void uartTxHandler(Sercom *sercom);
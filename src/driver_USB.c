@@ -82,6 +82,11 @@ void usbCDCTxFlush(void) { tud_cdc_write_flush(); }
 
 bool usbCDCTxFull(void) { return !tud_cdc_write_available(); }
 
+size_t usbCDCWrite(const void *pSrc, size_t n) {
+  const size_t available = tud_cdc_write_available();
+  return tud_cdc_write(pSrc, (n > available) ? available : n);
+}
+
 void usbSetup(void) {
   /* Clocking:
    *  - AHB is enabled by default (16.8.7)
@@ -35,5 +35,12 @@ void usbCDCTxFlush(void);
  */
 bool usbCDCTxFull(void);
 
+/*! @brief Add bytes to the Tx buffer without waiting
+ *  @param [in] pSrc : bytes to add
+ *  @param [in] n : number of bytes
+ *  @return number of bytes added; fewer than n if the buffer is full
+ */
+size_t usbCDCWrite(const void *pSrc, size_t n);
+
 /*! @brief Set up USB hardware and tinyUSB stack */
 void usbSetup(void);
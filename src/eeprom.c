@@ -19,20 +19,13 @@
 #include "driver_TIME.h"
 #include "eeprom.h"
 #include "emon32.h"
+#include "wlRing.h"
 
 /* Use WL_PKT_SIZE bytes for the wear limiting packet. This wastes some EEPROM
  * cells, but keeps everything page aligned for simplicity.
  */
 #define WL_PKT_SIZE 64u
 
-typedef struct __attribute__((__packed__)) WLHeader_ {
-  uint8_t  valid;
-  uint8_t  res0;
-  uint16_t crc16_ccitt;
-} WLHeader_t;
-
-_Static_assert(sizeof(WLHeader_t) == 4,
-               "EEPROM wear limit header was not 4 bytes.");
 _Static_assert((sizeof(Emon32Cumulative_t) + sizeof(WLHeader_t)) <= WL_PKT_SIZE,
                "EEPROM wear level packet >WL_PKT_SIZE bytes.");
 _Static_assert((WL_PKT_SIZE % 16 == 0),
@@ -384,11 +377,14 @@ eepromWLStatus_t eepromReadWL(void *pPktRd, uint32_t *pIdx) {
   /* Check for correct indexing, find it not yet set. Read into struct from
    * correct location.
    */
+  uint32_t         idxNewest;
   uint32_t         idxRd;
-  uint32_t         addrRd;
-  uint16_t         crcData;
-  WLHeader_t       header;
   eepromWLStatus_t status = EEPROM_WL_OK;
+  const WLRing_t   ring   = {.base    = EEPROM_WL_OFFSET,
+                             .count   = wlBlkCnt,
+                             .size    = wlBlkSize,
+                             .dataLen = wlData_n,
+                             .read    = &eepromRead};
 
   /* If an async write is in progress, the data may be inconsistent */
   if (eepromWriteWLBusy()) {
@@ -402,33 +398,22 @@ eepromWLStatus_t eepromReadWL(void *pPktRd, uint32_t *pIdx) {
     status = wlFindLast();
   }
 
-  idxRd = wlIdxNxtWr - 1u;
+  idxNewest = wlIdxNxtWr - 1u;
 
-  if (UINT32_MAX == idxRd) {
-    idxRd = wlBlkCnt - 1u;
-  }
-  if (pIdx) {
-    *pIdx = idxRd;
+  if (UINT32_MAX == idxNewest) {
+    idxNewest = wlBlkCnt - 1u;
   }
-  addrRd = EEPROM_WL_OFFSET + (idxRd * wlBlkSize);
-  eepromRead(addrRd, &header, sizeof(header));
 
-  addrRd += 4;
-  eepromRead(addrRd, pPktRd, wlData_n);
-  crcData = calcCRC16_ccitt(pPktRd, sizeof(Emon32Cumulative_t));
-
-  /* Retry once on CRC mismatch - I2C reads can occasionally be corrupted */
-  if (crcData != header.crc16_ccitt) {
-    printf_("EEPROM CRC retry: hdr=0x%04X data=0x%04X\r\n", header.crc16_ccitt,
-            crcData);
-    eepromRead(addrRd, pPktRd, wlData_n);
-    crcData = calcCRC16_ccitt(pPktRd, sizeof(Emon32Cumulative_t));
+  /* The newest record with a good CRC wins. An older record is only read if
+   * the newest was damaged, e.g. by a write cut short. */
+  if (!wlRingReadNewest(&ring, idxNewest, pPktRd, &idxRd)) {
+    printf_("EEPROM CRC FAIL: no good record\r\n");
+    status = EEPROM_WL_CRC_ALL_BAD;
+  } else if (idxRd != idxNewest) {
+    printf_("EEPROM CRC FAIL: record %lu, using %lu\r\n", idxNewest, idxRd);
   }
-
-  if (crcData != header.crc16_ccitt) {
-    printf_("EEPROM CRC FAIL: hdr=0x%04X data=0x%04X valid=0x%02X\r\n",
-            header.crc16_ccitt, crcData, header.valid);
-    status = EEPROM_WL_CRC_BAD;
+  if (pIdx) {
+    *pIdx = idxRd;
   }
 
   return status;
@@ -21,13 +21,18 @@
 #include "emon32.h"
 #include "emon32_assert.h"
 #include "emon_CM.h"
+#include "logger.h"
+#include "modbusMap.h"
+#include "outQueue.h"
 #include "periph_DS18B20.h"
 #include "periph_SSD1306.h"
 #include "periph_rfm69.h"
+#include "pollMap.h"
 #include "pulse.h"
 #include "temperature.h"
 #include "ui.h"
 #include "util.h"
+#include "wallClock.h"
 
 #include "printf.h"
 #include "qfplib-m0-full.h"
@@ -38,10 +43,11 @@ typedef struct EPAccum_ {
 } EPAccum_t;
 
 typedef struct TransmitOpt_ {
-  bool    json;      /* Use JSON format */
-  bool    useRFM;    /* Use wireless */
-  bool    logSerial; /* Log to serial */
-  uint8_t node;      /*  Node ID */
+  DataFmt_t   fmt;       /* Serial data format */
+  LineCheck_t check;     /* Checksum on text lines */
+  bool        useRFM;    /* Use wireless */
+  bool        logSerial; /* Log to serial */
+  uint8_t     node;      /*  Node ID */
 } TransmitOpt_t;
 
 typedef struct TxBlink_ {
@@ -53,11 +59,14 @@ typedef struct TxBlink_ {
  * Persistent state variables
  *************************************/
 
-static volatile uint32_t evtPend       = 0;
-AssertInfo_t             g_assert_info = {0};
-static EPAccum_t         lastStoredEP  = {0};
-static TxBlink_t         txBlink       = {0};
-Emon32Config_t          *pConfig       = 0;
+static volatile uint32_t evtPend          = 0;
+AssertInfo_t             g_assert_info    = {0};
+static EPAccum_t         lastStoredEP     = {0};
+static TxBlink_t         txBlink          = {0};
+static bool              csvHeaderPending = true;
+static uint8_t           usbOutBuf[OUT_DEPTH];
+static OutQueue_t        usbOut;
+Emon32Config_t          *pConfig          = 0;
 
 /*************************************
  * Static function prototypes
@@ -70,12 +79,21 @@ static void cumulativeProcess(Emon32Cumulative_t    *pPkt,
                               const Emon32Dataset_t *pData,
                               const uint32_t         epDeltaStore);
 static void datasetAddPulse(Emon32Dataset_t *pDst);
+static void divertOutput(void);
 static void ecmConfigure(void);
+static void ecmConfigureChannels(ECMCfg_t *ecmCfg);
+static void ecmReconfigure(void);
 static void ecmDmaCallback(void);
+static void ecmEventsLog(void);
 static void evtKiloHertz(void);
 static bool evtPending(EVTSRC_t evt);
+static void logLevelConfigure(void);
 static void pulseConfigure(void);
 void        putchar_(char c);
+static void reportPuts(const char *s);
+static void reportSerial(const Emon32Dataset_t *pSrc, const TransmitOpt_t *pOpt,
+                         char *txBuffer);
+static void reportWrite(const void *pSrc, const size_t n);
 static void rfmConfigure(void);
 static void ssd1306Setup(void);
 static void tempReadEvt(Emon32Dataset_t *pData, const uint32_t numT);
@@ -83,7 +101,12 @@ static uint32_t tempSetup(Emon32Dataset_t *pData);
 static void     totalEnergy(const Emon32Dataset_t *pData, EPAccum_t *pAcc);
 static void transmitData(const Emon32Dataset_t *pSrc, const TransmitOpt_t *pOpt,
                          char *txBuffer);
+static bool uartIsBus(void);
+static void uartLineConfigure(void);
 static void ucSetup(void);
+static void usbOutFlush(void);
+static void usbOutPut(const void *pSrc, size_t n);
+static void usbOutService(void);
 static void waitWithUSB(uint32_t t_ms);
 
 /*************************************
@@ -118,6 +141,9 @@ static void cumulativeNVMLoad(Emon32Cumulative_t *pPkt,
   for (size_t idxPulse = 0; idxPulse < NUM_OPA; idxPulse++) {
     uint32_t pulse = eepromOK ? pPkt->pulseCnt[idxPulse] : 0;
 
+    /* Restore the counter as well as the dataset, otherwise the count
+     * restarts from 0 on the first report after a reset. */
+    pulseSetCount(idxPulse, pulse);
     pData->pulseCnt[idxPulse] = pulse;
     totalP += pulse;
   }
@@ -152,10 +178,10 @@ static void cumulativeNVMStore(Emon32Cumulative_t    *pPkt,
     if (status == EEPROM_WR_BUSY) {
       /* Previous async write still in progress - this is expected occasionally
        */
-      debugPuts("EEPROM async write skipped (busy)\r\n");
+      LOG_DBG("EEPROM async write skipped (busy)\r\n");
     } else if (status == EEPROM_WR_FAIL) {
       /* Failed to start async write - callback queue full or other error */
-      debugPuts("EEPROM async write failed!\r\n");
+      LOG_ERR("EEPROM async write failed!\r\n");
     }
     /* EEPROM_WR_PEND is success - write has been queued */
   }
@@ -208,17 +234,48 @@ static void datasetAddPulse(Emon32Dataset_t *pDst) {
   }
 }
 
-void debugPuts(const char *s) {
-  if (pConfig->baseCfg.debugSerial) {
-    char tBuf[12];
-    serialPuts("DBG:");
-    utilUtoa(tBuf, timerMillis(), ITOA_BASE10);
-    serialPuts(tBuf);
-    serialPuts(":");
-    serialPuts(s);
+/*! @brief Drive the diversion output, if the board has one, to follow the
+ *         surplus diversion state. It is only driven once set up.
+ */
+static void divertOutput(void) {
+#if defined(PIN_DIVERT)
+  static bool init = false;
+  if (!init) {
+    portPinDir(GRP_DIVERT, PIN_DIVERT, PIN_DIR_OUT);
+    init = true;
+  }
+  portPinDrv(GRP_DIVERT, PIN_DIVERT,
+             ecmDivertActive() ? PIN_DRV_SET : PIN_DRV_CLR);
+#endif
+}
+
+/*! @brief Copy the voltage and CT channel settings to the energy monitor
+ *  @param [out] ecmCfg : energy monitor configuration
+ */
+static void ecmConfigureChannels(ECMCfg_t *ecmCfg) {
+  for (size_t i = 0; i < NUM_V; i++) {
+    ecmCfg->vCfg[i].voltageCalRaw = pConfig->voltageCfg[i].voltageCal;
+    ecmCfg->vCfg[i].vActive       = pConfig->voltageCfg[i].vActive;
+  }
+
+  for (size_t i = 0; i < NUM_CT; i++) {
+    ecmCfg->ctCfg[i].phCal    = pConfig->ctCfg[i].phase;
+    ecmCfg->ctCfg[i].ctCalRaw = pConfig->ctCfg[i].ctCal;
+    ecmCfg->ctCfg[i].active   = pConfig->ctCfg[i].ctActive;
+    ecmCfg->ctCfg[i].vChan1   = pConfig->ctCfg[i].vChan1;
+    ecmCfg->ctCfg[i].vChan2   = pConfig->ctCfg[i].vChan2;
   }
 }
 
+/*! @brief Apply a loaded configuration to the running energy monitor. Only
+ *         the channel, calibration, and mains frequency settings are pushed;
+ *         the accumulated energy is kept.
+ */
+static void ecmReconfigure(void) {
+  ecmConfigureChannels(ecmConfigGet());
+  ecmConfigReload(pConfig->baseCfg.mainsFreq);
+}
+
 /*! @brief Configure the continuous energy monitoring system
  *  @param [in] pCfg : pointer to the configuration struct
  */
@@ -232,13 +289,22 @@ void ecmConfigure(void) {
   ECMCfg_t *ecmCfg = ecmConfigGet();
 
   ecmCfg->reportCycles  = pConfig->baseCfg.reportCycles;
-  ecmCfg->mainsFreq     = pConfig->baseCfg.mainsFreq;
+  ecmCfg->freqAuto      = (0 == pConfig->baseCfg.mainsFreq);
+  ecmCfg->mainsFreq =
+      ecmCfg->freqAuto ? MAINS_FREQ_DEF : pConfig->baseCfg.mainsFreq;
   ecmCfg->samplePeriod  = timerADCPeriod();
   ecmCfg->reportTime_us = (1000000u / ecmCfg->mainsFreq) * ecmCfg->reportCycles;
   ecmCfg->assumedVrms   = qfp_uint2float(pConfig->baseCfg.assumedVrms);
+  ecmCfg->assumedPF     = configAssumedPF();
+  ecmCfg->phaseDeadband = qfp_uint2float(PHASE_DEADBAND_DEF);
   ecmCfg->timeMicros    = &timerMicros;
   ecmCfg->timeMicrosDelta = &timerMicrosDelta;
 
+  ecmCfg->evtCfg.vNominal  = qfp_uint2float(VNOMINAL_DEF);
+  ecmCfg->evtCfg.sagPct    = qfp_uint2float(SAG_PCT_DEF);
+  ecmCfg->evtCfg.swellPct  = qfp_uint2float(SWELL_PCT_DEF);
+  ecmCfg->evtCfg.minCycles = EVT_CYCLES_DEF;
+
   if (adcCorrectionValid()) {
     ecmCfg->correction.valid  = true;
     ecmCfg->correction.gain   = adcCorrectionGain();
@@ -247,39 +313,94 @@ void ecmConfigure(void) {
     ecmCfg->correction.valid = false;
   }
 
-  for (size_t i = 0; i < NUM_V; i++) {
-    ecmCfg->vCfg[i].voltageCalRaw = pConfig->voltageCfg[i].voltageCal;
-    ecmCfg->vCfg[i].vActive       = pConfig->voltageCfg[i].vActive;
-  }
-
-  for (size_t i = 0; i < NUM_CT; i++) {
-    ecmCfg->ctCfg[i].phCal    = pConfig->ctCfg[i].phase;
-    ecmCfg->ctCfg[i].ctCalRaw = pConfig->ctCfg[i].ctCal;
-    ecmCfg->ctCfg[i].active   = pConfig->ctCfg[i].ctActive;
-    ecmCfg->ctCfg[i].vChan1   = pConfig->ctCfg[i].vChan1;
-    ecmCfg->ctCfg[i].vChan2   = pConfig->ctCfg[i].vChan2;
-  }
+  ecmConfigureChannels(ecmCfg);
 
   for (size_t i = 0; i < NUM_CT; i++) {
     ecmCfg->mapCTLog[i] = ainRemap[i];
   }
 
+  configDivert(&ecmCfg->divertCfg);
+
   ecmConfigInit();
 }
 
 void ecmDmaCallback(void) {
-  ECM_STATUS_t injectStatus;
-  ecmDataBufferSwap();
-  injectStatus = ecmInjectSample();
-  switch (injectStatus) {
-  case ECM_REPORT_COMPLETE:
-    emon32EventSet(EVT_ECM_SET_CMPL);
-    break;
-  case ECM_PEND_1S:
-    emon32EventSet(EVT_ECM_PEND_1S);
-    break;
-  default:
-    break;
+  uint8_t idx;
+
+  adcSetComplete();
+
+  /* If processing falls behind, the set completed meanwhile follows */
+  while (adcSetTake(&idx)) {
+    ECM_STATUS_t injectStatus;
+    ecmDataBufferSelect(idx);
+    injectStatus = ecmInjectSample();
+    adcSetRelease();
+    switch (injectStatus) {
+    case ECM_REPORT_COMPLETE:
+      emon32EventSet(EVT_ECM_SET_CMPL);
+      break;
+    case ECM_PEND_1S:
+      emon32EventSet(EVT_ECM_PEND_1S);
+      break;
+    case ECM_CYCLE_COMPLETE:
+      emon32EventSet(EVT_ECM_CYCLE_CMPL);
+      break;
+    default:
+      break;
+    }
+  }
+}
+
+/*! @brief Apply the stored log threshold, or keep the default if unset */
+static void logLevelConfigure(void) {
+  if (pConfig->baseCfg.logLevel) {
+    logLevelSet((LogLevel_t)(pConfig->baseCfg.logLevel - 1u));
+  }
+}
+
+/*! @brief Log any completed power quality events. Faults are warnings, and
+ *         the rest are information.
+ */
+static void ecmEventsLog(void) {
+  const char      *evtNames[] = {"sag",          "swell",        "overcurrent",
+                                 "divert on",    "divert off",   "load step",
+                                 "sensor low",   "sensor stuck", "sensor ok",
+                                 "voltage lost", "voltage restored"};
+  const LogLevel_t evtLevels[] = {LOG_INFO, LOG_INFO, LOG_WARN, LOG_INFO,
+                                  LOG_INFO, LOG_INFO, LOG_WARN, LOG_WARN,
+                                  LOG_INFO, LOG_WARN, LOG_INFO};
+  ECMEvent_t       evt;
+  char             strBuf[48];
+  char             valBuf[16];
+
+  while (ecmEventGet(&evt)) {
+    const LogLevel_t level = evtLevels[evt.type];
+    if (!logEnabled(level)) {
+      continue;
+    }
+
+    utilFtoa(valBuf, evt.value);
+    if ((ECM_EVT_OVERCURRENT == evt.type) ||
+        (ECM_EVT_SENSOR_LOW == evt.type) ||
+        (ECM_EVT_SENSOR_STUCK == evt.type) ||
+        (ECM_EVT_SENSOR_OK == evt.type)) {
+      (void)snprintf_(strBuf, sizeof(strBuf), "%s CT%u %s A\r\n",
+                      evtNames[evt.type], (unsigned int)(evt.ch + 1u), valBuf);
+    } else if ((ECM_EVT_DIVERT_ON == evt.type) ||
+               (ECM_EVT_DIVERT_OFF == evt.type) ||
+               (ECM_EVT_LOAD_STEP == evt.type)) {
+      (void)snprintf_(strBuf, sizeof(strBuf), "%s CT%u %s W\r\n",
+                      evtNames[evt.type], (unsigned int)(evt.ch + 1u), valBuf);
+    } else if ((ECM_EVT_V_LOST == evt.type) ||
+               (ECM_EVT_V_RESTORED == evt.type)) {
+      (void)snprintf_(strBuf, sizeof(strBuf), "%s V%u\r\n",
+                      evtNames[evt.type], (unsigned int)(evt.ch + 1u));
+    } else {
+      (void)snprintf_(strBuf, sizeof(strBuf), "%s V%u %u cycles %s V\r\n",
+                      evtNames[evt.type], (unsigned int)(evt.ch + 1u),
+                      (unsigned int)evt.cycles, valBuf);
+    }
+    logPuts(level, strBuf);
   }
 }
 
@@ -347,24 +468,29 @@ static bool evtPending(EVTSRC_t evt) { return (evtPend & (1u << evt)) != 0; }
  */
 static void pulseConfigure(void) {
 
-  uint8_t pinsPulse[][NUM_OPA] = {
-      {GRP_OPA, PIN_OPA1}, {GRP_OPA, PIN_OPA2}, {GRP_OPA, PIN_OPA3}};
+  uint8_t pinsPulse[][NUM_OPA] = {{GRP_OPA, PIN_OPA1, EXTINT_OPA1},
+                                  {GRP_OPA, PIN_OPA2, EXTINT_OPA2},
+                                  {GRP_OPA, PIN_OPA3, EXTINT_OPA3}};
 
   for (size_t i = 0; i < NUM_OPA; i++) {
     PulseCfg_t *pulseCfg = pulseGetCfg(i);
 
     EMON32_ASSERT(pulseCfg);
 
+    pulseCfg->grp    = pinsPulse[i][0];
+    pulseCfg->pin    = pinsPulse[i][1];
+    pulseCfg->extint = pinsPulse[i][2];
+    pulseCfg->active = false;
+
     if (('o' != pConfig->opaCfg[i].func) && (pConfig->opaCfg[i].opaActive)) {
       pulseCfg->edge    = (PulseEdge_t)pConfig->opaCfg[i].func;
-      pulseCfg->grp     = pinsPulse[i][0];
-      pulseCfg->pin     = pinsPulse[i][1];
       pulseCfg->periods = pConfig->opaCfg[i].period;
       pulseCfg->puEn    = pConfig->opaCfg[i].puEn;
       pulseCfg->active  = true;
-
-      pulseInit(i);
     }
+
+    /* An inactive channel releases its interrupt line */
+    pulseInit(i);
   }
 }
 
@@ -373,9 +499,58 @@ static void pulseConfigure(void) {
  */
 void putchar_(char c) {
   if (usbCDCIsConnected()) {
+    usbOutFlush();
     usbCDCTxChar(c);
   }
-  uartPutcBlocking(SERCOM_UART, c);
+  if (!uartIsBus()) {
+    uartPutcBlocking(SERCOM_UART, c);
+  }
+}
+
+/*! @brief Output a report. Unlike serialPuts, the UART is not waited on; if
+ *         its Tx buffer is full the remainder is dropped (see uartTxDropped).
+ *         Nothing is sent to the UART when it is used as a bus.
+ */
+static void reportPuts(const char *s) {
+  if (usbCDCIsConnected()) {
+    usbOutPut(s, strlen(s));
+  }
+  if (!uartIsBus()) {
+    (void)uartPuts(SERCOM_UART, s);
+  }
+}
+
+/*! @brief Send a report to serial, either the text already packed in txBuffer
+ *         or, in binary formats, one frame for each packed range.
+ */
+static void reportSerial(const Emon32Dataset_t *pSrc, const TransmitOpt_t *pOpt,
+                         char *txBuffer) {
+  const PackedRange_t ranges[] = {PACKED_CT1_6, PACKED_TEMP_PULSE,
+                                  PACKED_CT7_12};
+
+  if (pOpt->fmt < DATA_FMT_BINARY) {
+    reportPuts(txBuffer);
+    return;
+  }
+
+  for (size_t i = 0; i < (sizeof(ranges) / sizeof(ranges[0])); i++) {
+    const size_t n =
+        (DATA_FMT_COBS == pOpt->fmt)
+            ? dataPackFrameCobs(pSrc, ranges[i], pOpt->node, txBuffer,
+                                TX_BUFFER_W)
+            : dataPackFrame(pSrc, ranges[i], pOpt->node, txBuffer, TX_BUFFER_W);
+    reportWrite(txBuffer, n);
+  }
+}
+
+/*! @brief Output binary report data, as reportPuts */
+static void reportWrite(const void *pSrc, const size_t n) {
+  if (usbCDCIsConnected()) {
+    usbOutPut(pSrc, n);
+  }
+  if (!uartIsBus()) {
+    (void)uartWrite(SERCOM_UART, pSrc, n);
+  }
 }
 
 static void rfmConfigure(void) {
@@ -394,9 +569,11 @@ void serialPuts(const char *s) {
   EMON32_ASSERT(s);
 
   if (usbCDCIsConnected()) {
-    usbCDCPutsBlocking(s);
+    usbOutPut(s, strlen(s));
+  }
+  if (!uartIsBus()) {
+    uartPutsBlocking(SERCOM_UART, s);
   }
-  uartPutsBlocking(SERCOM_UART, s);
 }
 
 /*! @brief Setup the SSD1306 display, if present. Display a basic message */
@@ -445,9 +622,9 @@ static void tempReadEvt(Emon32Dataset_t *pData, const uint32_t numT) {
         cntSinceLastValid[mapLogical]++;
       } else {
         if (TEMP_OUT_OF_RANGE == tempValue.status) {
-          tempData = 4832; /* 302°C */
+          tempData = TEMP_Q4_OUT_OF_RANGE;
         } else {
-          tempData = 4864; /* 304°C */
+          tempData = TEMP_Q4_FAILED;
         }
       }
     }
@@ -504,9 +681,9 @@ static uint32_t tempSetup(Emon32Dataset_t *pData) {
 
   tempMapDevices(TEMP_INTF_ONEWIRE, addrAlign8);
 
-  /* Set all unused temperature slots to 300°C (4800 as Q4 fixed point) */
+  /* Set all unused temperature slots to 300°C */
   for (size_t i = 0; i < TEMP_MAX_ONEWIRE; i++) {
-    pData->temp[i] = 4800;
+    pData->temp[i] = TEMP_Q4_ABSENT;
   }
 
   return numTempSensors;
@@ -549,13 +726,42 @@ static void transmitData(const Emon32Dataset_t *pSrc, const TransmitOpt_t *pOpt,
     bool    isPulse    = ('r' == func) || ('f' == func) || ('b' == func);
     chsActive.pulse[i] = pConfig->opaCfg[i].opaActive && isPulse;
   }
+  chsActive.time = wallClockValid();
+  chsActive.sel  = configTextSel();
+  for (size_t i = 0; i < DATA_FIELD_NUM; i++) {
+    chsActive.dp[i] = configTextDp((DataField_t)i);
+  }
 
-  (void)dataPackSerial(pSrc, txBuffer, TX_BUFFER_W, pOpt->json, &chsActive);
+  /* The CSV header is sent before the first row, and again after any
+   * configuration change as the columns may have changed. */
+  const bool toSerial = !pOpt->useRFM || pOpt->logSerial;
+  if ((DATA_FMT_CSV == pOpt->fmt) && csvHeaderPending && toSerial) {
+    const size_t n = dataPackSerialHeader(txBuffer, TX_BUFFER_W, &chsActive);
+    (void)utilLineCheck(txBuffer, n, TX_BUFFER_W, pOpt->check);
+    reportPuts(txBuffer);
+    csvHeaderPending = false;
+  }
+
+  /* The checksum is added to the line as packed, so it covers exactly what
+   * is sent. Room is left for it so that the line is not cut afterwards. */
+  if (pOpt->fmt < DATA_FMT_BINARY) {
+    const uint32_t truncated = dataPackTruncated();
+    size_t         w         = TX_BUFFER_W;
+
+    if (LINE_CHECK_NONE != pOpt->check) {
+      w -= LINE_CHECK_W;
+    }
+    const size_t n = dataPackSerial(pSrc, txBuffer, w, pOpt->fmt, &chsActive);
+    (void)utilLineCheck(txBuffer, n, TX_BUFFER_W, pOpt->check);
+    if (truncated != dataPackTruncated()) {
+      LOG_WRN("Report truncated to fit the output buffer.\r\n");
+    }
+  }
 
   if (pOpt->useRFM) {
 
     if (pOpt->logSerial) {
-      serialPuts(txBuffer);
+      reportSerial(pSrc, pOpt, txBuffer);
     }
 
     if (sercomExtIntfEnabled()) {
@@ -566,7 +772,7 @@ static void transmitData(const Emon32Dataset_t *pSrc, const TransmitOpt_t *pOpt,
 
       /* Only send temperature + pulse if found or active */
       for (size_t t = 0; t < TEMP_MAX_ONEWIRE; t++) {
-        if (4800 != pSrc->temp[t]) {
+        if (TEMP_Q4_ABSENT != pSrc->temp[t]) {
           sendTempPulse = true;
           break;
         }
@@ -617,7 +823,31 @@ static void transmitData(const Emon32Dataset_t *pSrc, const TransmitOpt_t *pOpt,
     }
 
   } else {
-    serialPuts(txBuffer);
+    reportSerial(pSrc, pOpt, txBuffer);
+  }
+}
+
+/*! @brief Indicate if the UART is a bus for Modbus or the polled protocol,
+ *         carrying only their frames
+ */
+static bool uartIsBus(void) { return modbusMapEnabled() || pollMapEnabled(); }
+
+/*! @brief Apply the stored UART line settings. Configurations from before
+ *         these were stored hold 0, and keep the default.
+ */
+static void uartLineConfigure(void) {
+  UARTCfg_t uartCfg;
+
+  if (0 == pConfig->baseCfg.uartBaud) {
+    return;
+  }
+
+  uartCfg.baud     = pConfig->baseCfg.uartBaud;
+  uartCfg.parity   = (UARTParity_t)pConfig->baseCfg.uartParity;
+  uartCfg.stopBits = pConfig->baseCfg.uartStop ? pConfig->baseCfg.uartStop : 1u;
+
+  if (!uartConfigure(SERCOM_UART, &uartCfg)) {
+    LOG_WRN("UART settings not valid, using the default.\r\n");
   }
 }
 
@@ -638,12 +868,57 @@ static void ucSetup(void) {
   wdtSetup();
 }
 
+/*! @brief Send everything queued for USB, waiting as needed. Anything written
+ *         straight to the CDC buffer must come after this to keep order.
+ */
+static void usbOutFlush(void) {
+  while (outQueueCount(&usbOut) && usbCDCIsConnected()) {
+    if (0 == outQueueService(&usbOut, OUT_DEPTH)) {
+      tud_task();
+    }
+  }
+  outQueueClear(&usbOut);
+}
+
+/*! @brief Queue a whole message for USB; it is sent OUT_BUDGET bytes at a
+ *         time by usbOutService. If it does not fit, the queue is sent first
+ *         and then the message, waiting as needed.
+ */
+static void usbOutPut(const void *pSrc, size_t n) {
+  if (!outQueuePut(&usbOut, pSrc, n)) {
+    const uint8_t *pData = (const uint8_t *)pSrc;
+
+    usbOutFlush();
+    for (size_t i = 0; i < n; i++) {
+      usbCDCTxChar(pData[i]);
+    }
+    return;
+  }
+  emon32EventSet(EVT_OUTPUT);
+}
+
+/*! @brief Send up to OUT_BUDGET queued bytes to USB. If more are waiting and
+ *         the CDC buffer took all it was given, run again once other pending
+ *         events have been handled; otherwise wait for the next tick.
+ */
+static void usbOutService(void) {
+  if (!usbCDCIsConnected()) {
+    outQueueClear(&usbOut);
+    return;
+  }
+  if ((OUT_BUDGET == outQueueService(&usbOut, OUT_BUDGET)) &&
+      outQueueCount(&usbOut)) {
+    emon32EventSet(EVT_OUTPUT);
+  }
+}
+
 static void waitWithUSB(uint32_t t_ms) {
   uint32_t t_start    = timerMillis();
   uint32_t t_last_usb = t_start;
   while (timerMillisDelta(t_start) < t_ms) {
     if (1 == timerMillisDelta(t_last_usb)) {
       tud_task();
+      usbOutService();
       usbCDCTask();
       t_last_usb = timerMillis();
     }
@@ -656,8 +931,11 @@ int main(void) {
   uint32_t           numTempSensors        = 0;
   Emon32Cumulative_t nvmCumulative         = {0};
   char               txBuffer[TX_BUFFER_W] = {0};
+  const LogOpt_t     logOpt                = {&serialPuts, &timerMillis,
+                                                  &wallClockNow};
 
   ucSetup();
+  outQueueInit(&usbOut, usbOutBuf, OUT_DEPTH, &usbCDCWrite);
   uiLedColour(LED_RED);
 
   /* Pause to allow any external pins to settle */
@@ -671,7 +949,8 @@ int main(void) {
   }
 
   eicEnable();
-  uartEnableTx(SERCOM_UART);
+  uartEnableTx(SERCOM_UART, SERCOM_UART_INTERACTIVE_IRQn);
+  uartTxDMAEnable();
 
   /* Load stored values (configuration and accumulated energy) from
    * non-volatile memory (NVM). If the NVM has not been used before then
@@ -679,6 +958,11 @@ int main(void) {
    */
   serialPuts("> Reading configuration and accumulators from NVM...\r\n");
   pConfig = configLoadFromNVM();
+  wallClockInit(&timerMillis);
+  logInit(&logOpt);
+  logLevelConfigure();
+  uartLineConfigure();
+  (void)uartRs485Enable(pConfig->rs485Guard);
 
   /* Load the accumulated energy and pulse values from NVM. */
   cumulativeNVMLoad(&nvmCumulative, &dataset);
@@ -702,7 +986,22 @@ int main(void) {
   dmacCallbackBufferFill(&ecmDmaCallback);
   ecmFlush();
   adcDMACStart();
+  modbusMapUpdate(&dataset);
+  modbusMapInit(pConfig->baseCfg.modbusAddr,
+                pConfig->baseCfg.uartBaud ? pConfig->baseCfg.uartBaud
+                                          : UART_BAUD);
+  pollMapUpdate(&dataset);
+  if (!modbusMapEnabled()) {
+    pollMapInit(pConfig->pollNode);
+  }
   uartEnableRx(SERCOM_UART, SERCOM_UART_INTERACTIVE_IRQn);
+
+  /* Commands are received by DMA and handled in chunks, so typing does not
+   * interrupt sampling. Modbus times each byte to find the end of a frame,
+   * so keep the Rx interrupt while the UART is a bus. */
+  if (!uartIsBus()) {
+    (void)uartRxDMAEnable();
+  }
   wdtEnable();
 
   if (configUnsavedChanges()) {
@@ -730,16 +1029,35 @@ int main(void) {
         tud_task();
         usbCDCTask();
 
+        /* The CDC buffer has been flushed, so continue any queued output */
+        if (outQueueCount(&usbOut)) {
+          emon32EventSet(EVT_OUTPUT);
+        }
+
         /* Process any timer callbacks that are ready */
         timerProcessPendingCallbacks();
 
         /* Check for confirmation timeout (30s) */
         configCheckConfirmationTimeout();
 
+        /* Answer a Modbus request once the inter-frame gap has passed, or a
+         * polled request once received */
+        modbusMapProcess();
+        pollMapProcess();
+
+        /* Handle commands received by DMA once the line is quiet */
+        configRxProcess();
+
         evtKiloHertz();
         emon32EventClr(EVT_TICK_1kHz);
       }
 
+      /* Queued USB output, sent a budget at a time */
+      if (evtPending(EVT_OUTPUT)) {
+        emon32EventClr(EVT_OUTPUT);
+        usbOutService();
+      }
+
       /* Pending character(s) in echo queue */
       if (evtPending(EVT_ECHO)) {
         uint8_t c = configEchoChar();
@@ -808,6 +1126,15 @@ int main(void) {
         emon32EventClr(EVT_ECM_PEND_1S);
       }
 
+      /* One or more mains cycles have completed; update the per cycle
+       * minimum and maximum values and log any events. */
+      if (evtPending(EVT_ECM_CYCLE_CMPL)) {
+        (void)ecmProcessCycle();
+        divertOutput();
+        ecmEventsLog();
+        emon32EventClr(EVT_ECM_CYCLE_CMPL);
+      }
+
       /* Readout has been requested, trigger a temperature read. */
       if (evtPending(EVT_ECM_SET_CMPL)) {
         emon32EventSet(EVT_TEMP_READ);
@@ -831,10 +1158,12 @@ int main(void) {
         opt.useRFM    = pConfig->dataTxCfg.useRFM;
         opt.logSerial = pConfig->baseCfg.logToSerial;
         opt.node      = pConfig->baseCfg.nodeID;
-        opt.json      = pConfig->baseCfg.useJson;
+        opt.fmt       = (DataFmt_t)pConfig->baseCfg.dataFmt;
+        opt.check     = (LineCheck_t)pConfig->lineCheck;
 
         dataset.msgNum++;
-        dataset.pECM = ecmProcessSet();
+        dataset.pECM  = ecmProcessSet();
+        dataset.epoch = wallClockNow();
         datasetAddPulse(&dataset);
         transmitData(&dataset, &opt, txBuffer);
 
@@ -866,11 +1195,23 @@ int main(void) {
         emon32EventClr(EVT_OPA_INIT);
       }
       if (evtPending(EVT_CONFIG_CHANGED)) {
+        csvHeaderPending = true;
         emon32EventClr(EVT_CONFIG_CHANGED);
       }
       if (evtPending(EVT_CONFIG_SAVED)) {
         emon32EventClr(EVT_CONFIG_SAVED);
       }
+      /* A whole configuration was loaded or restored; apply the parts that
+       * do not need a restart. UART and Modbus settings apply after reset.
+       */
+      if (evtPending(EVT_CONFIG_LOADED)) {
+        ecmReconfigure();
+        logLevelConfigure();
+        rfmSetPowerLevel(pConfig->dataTxCfg.rfmPwr);
+        csvHeaderPending = true;
+        emon32EventSet(EVT_OPA_INIT);
+        emon32EventClr(EVT_CONFIG_LOADED);
+      }
     }
 
     samdSleepIdle();
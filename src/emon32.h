@@ -19,10 +19,18 @@ _Static_assert((sizeof(bool) == 1), "bool must be 1 byte");
 #define REPORT_TIME_DEF    9.8f /* Report time, in seconds */
 #define ASSUMED_VRMS_DEF   0    /* Assumed voltage, used if no AC sense */
 #define CT_LEAD_DEF        1.5f /* CT lead angle */
+#define VNOMINAL_DEF       230u /* Nominal voltage for sag/swell events */
+#define SAG_PCT_DEF        90u  /* Sag threshold, % of nominal voltage */
+#define SWELL_PCT_DEF      110u /* Swell threshold, % of nominal voltage */
+#define EVT_CYCLES_DEF     1u   /* Minimum cycles for a sag/swell event */
+#define PHASE_DEADBAND_DEF 5u   /* Angle (°) reported as in phase */
+#define RS485_GUARD_MAX    5000u /* Longest RS485 turnaround guard (us) */
 
 #define PERF_ENABLED       0u     /* Performance tracing */
 #define TX_INDICATE_T      250u   /* Transmission indication time (ms) */
 #define CONFIRM_TIMEOUT_MS 30000u /* Confirmation timeout (ms) */
+#define OUT_BUDGET         64u    /* Most queued USB bytes sent per event */
+#define OUT_DEPTH          1024u  /* USB output queue, must be power of 2 */
 
 /*********************************
  * Firmware version
@@ -45,7 +53,8 @@ typedef struct Emon32Dataset_ {
   uint32_t      msgNum;
   ECMDataset_t *pECM;
   uint32_t      pulseCnt[NUM_OPA];
-  int16_t       temp[TEMP_MAX_ONEWIRE];
+  int16_t       temp[TEMP_MAX_ONEWIRE]; /* Q4, TEMP_Q4_ABSENT if no sensor */
+  uint32_t      epoch; /* Unix time of the report, 0 if the clock is not set */
 } Emon32Dataset_t;
 
 typedef struct __attribute__((__packed__)) Emon32Cumulative_ {
@@ -89,11 +98,14 @@ typedef enum EVTSRC_ {
   EVT_EXT_DISABLE     = 0u,
   EVT_TICK_1kHz       = 1u,
   EVT_ECHO            = 2u,
+  EVT_OUTPUT          = 3u,
   EVT_ECM_SET_CMPL    = 8u,
+  EVT_ECM_CYCLE_CMPL  = 9u,
   EVT_OPA_INIT        = 14u,
   EVT_TEMP_READ       = 15u,
   EVT_CONFIG_CHANGED  = 16u,
   EVT_CONFIG_SAVED    = 17u,
+  EVT_CONFIG_LOADED   = 18u,
   EVT_PROCESS_CMD     = 19u,
   EVT_PROCESS_DATASET = 20u,
   EVT_STORE_ACCUM     = 21u,
@@ -102,12 +114,6 @@ typedef enum EVTSRC_ {
   EVT_ECM_TRIG        = 24u
 } EVTSRC_t;
 
-/*! @brief When enabled, output debug message to serial (USB if available, and
- *         hardware UART) including a millisecond timestamp.
- *  @param [in] s: pointer to null terminated string
- */
-void debugPuts(const char *s);
-
 /*! @brief Clear a pending event/interrupt flag after the task has been handled
  *  @param [in] Event source in enum
  */
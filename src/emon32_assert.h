@@ -15,6 +15,14 @@ extern AssertInfo_t g_assert_info;
 
 extern void emon32_assert(const uint32_t *pc, const uint32_t *lr);
 
+#ifdef HOSTED
+
+#include <assert.h>
+
+#define EMON32_ASSERT(exp) assert(exp)
+
+#else
+
 #define EMON32_ASSERT_RECORD()                                                 \
   do {                                                                         \
     void *pc;                                                                  \
@@ -29,3 +37,5 @@ extern void emon32_assert(const uint32_t *pc, const uint32_t *lr);
       EMON32_ASSERT_RECORD();                                                  \
     }                                                                          \
   } while (0)
+
+#endif /* HOSTED */
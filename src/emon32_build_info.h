@@ -69,6 +69,11 @@ struct Emon32BuildInfo {
       Version as found in emon32.h. For example: "v1.0.0"
   */
   const char *version;
+
+  /*
+      Build profile, from BUILD_PROFILE in the Makefile. For example: "Release"
+  */
+  const char *configuration;
 };
 
 struct Emon32BuildInfo emon32_build_info(void);
@@ -21,9 +21,23 @@
 #include "emon_CM_coeffs.h"
 
 #define PROC_DEPTH   16u /* Voltage sample buffer depth. Must be power of 2. */
+#define CYCLE_DEPTH  4u  /* Completed cycle queue depth. Must be power of 2. */
+#define CYCLE_FMASK  ((CYCLE_DEPTH << 1) - 1u)
+#define CYCLE_SMP_MAX                                                          \
+  256u /* Maximum samples in a single cycle before it is discarded. Well beyond \
+          a 40 Hz cycle, and bounds the 32-bit cycle accumulators */
+#define THD_CYCLES   25u /* Cycles between waveform captures for THD */
+#define THD_SMP_MAX  80u /* Maximum samples in a captured cycle */
+#define THD_RESAMPLE 64u /* Points in the resampled cycle */
+#define THD_HARM_MAX 9u  /* Highest harmonic included in THD */
+#define EVENT_DEPTH  8u  /* Pending event queue depth. Must be power of 2. */
+#define OC_HYST_PCT  5u  /* Overcurrent release hysteresis, % of the limit */
+#define STUCK_OFFSET 64  /* Minimum mean (ADC counts) for a stuck channel */
+#define CREST_RMS_MIN                                                          \
+  4 /* Minimum RMS (ADC counts) to calculate a crest factor */
 #define ZC_HYST      2   /* Zero crossing hysteresis */
 #define ZC_HYST_AV   8 /* Zero crossing hysteresis when using assumd voltage */
-#define EQUIL_CYCLES 8 /* Number of cycles to discard at startup */
+#define EQUIL_CYCLES 8 /* Default number of cycles to discard at startup */
 #define ZC_MIN_VPEAK                                                           \
   40 /* Minimum peak voltage to accept zero-crossings (40 counts = ~14V mains) \
       */
@@ -32,15 +46,31 @@
            tolerance) */
 #define ZC_PERIOD_MAX_US                                                       \
   25000 /* Maximum period between crossings (25ms = 40Hz) */
+#define FREQ_AUTO_INIT 50u /* Provisional frequency while detecting */
+#define FREQ_DET_TIME_US                                                       \
+  1000000u /* Time to measure the mains period over when detecting */
+#define FREQ_DET_SPLIT_US                                                      \
+  18182u /* Mean period dividing 50 Hz and 60 Hz (55 Hz) */
+#define FREQ_AVG_DEF   50u   /* Default cycles in the frequency average */
+#define FREQ_AVG_MAX   128u  /* Maximum cycles in the frequency average */
+#define CAL_PF_MIN     0.98f /* Minimum PF of a resistive reference */
+#define CAL_STABLE_PCT 2.0f  /* Maximum power spread between reports (%) */
+#define V_MEAN_CYCLES  16u   /* Cycles in the offset averages */
 
 _Static_assert(!(PROC_DEPTH & (PROC_DEPTH - 1)),
                "PROC_DEPTH is not a power of 2.");
+_Static_assert(!(CYCLE_DEPTH & (CYCLE_DEPTH - 1)),
+               "CYCLE_DEPTH is not a power of 2.");
+_Static_assert(!(EVENT_DEPTH & (EVENT_DEPTH - 1)),
+               "EVENT_DEPTH is not a power of 2.");
 
 static const float TWO_PI = (6.2831853072f);
 
 static bool channelActive[VCT_TOTAL] = {0};
-static bool threePhase               = false;
-static bool useAssumedV              = false;
+static bool apparentOnly[NUM_CT]      = {0};
+static bool llActive[3]               = {0}; /* V1-V2, V2-V3, V3-V1 */
+static bool useAssumedV               = false;
+static bool vLostReported             = false;
 
 /*************************************
  * Local typedefs
@@ -48,9 +78,15 @@ static bool useAssumedV              = false;
 
 typedef enum Polarity_ { POL_POS, POL_NEG } Polarity_t;
 
+typedef enum ZeroCross_ { ZC_NONE, ZC_POS, ZC_NEG } ZeroCross_t;
+
+typedef enum ThdState_ { THD_IDLE, THD_CAPTURING, THD_READY } ThdState_t;
+
 typedef struct VAccumulator_ {
   uint64_t sumV_sqr;
   int32_t  sumV_deltas;
+  q15_t    smpMax;
+  q15_t    smpMin;
 } VAccumulator_t;
 
 typedef struct CTAccumulator_ {
@@ -58,6 +94,8 @@ typedef struct CTAccumulator_ {
   int64_t  sumPB[2];
   uint64_t sumI_sqr;
   int32_t  sumI_deltas;
+  q15_t    smpMax;
+  q15_t    smpMin;
 } CTAccumulator_t;
 
 typedef struct Accumulator_ {
@@ -67,8 +105,87 @@ typedef struct Accumulator_ {
   uint32_t        cycles;
   uint32_t        tStart_us;
   uint32_t        tDelta_us;
+  uint32_t        window;
 } Accumulator_t;
 
+/* Accumulators for a single mains cycle. As a cycle is short, 32-bit sums are
+ * sufficient. The power sums use the V1 phase correction for L-L loads. The
+ * voltage sums, and the diversion CT's sums, are also taken at the
+ * negative-going crossing, so that each half-cycle's RMS voltage and
+ * diversion power can be found.
+ */
+typedef struct CycleAccumulator_ {
+  uint32_t sumV_sqr[NUM_V];
+  int32_t  sumV_deltas[NUM_V];
+  uint32_t sumV_sqrH[NUM_V];    /* First half-cycle */
+  int32_t  sumV_deltasH[NUM_V]; /* First half-cycle */
+  uint32_t numSamplesH;         /* Samples in the first half, 0 if not seen */
+  int32_t  sumPA[NUM_CT];
+  int32_t  sumPB[NUM_CT];
+  uint32_t sumI_sqr[NUM_CT];
+  int32_t  sumI_deltas[NUM_CT];
+  int32_t  sumPA_H;      /* Diversion CT, first half-cycle */
+  int32_t  sumPB_H;      /* Diversion CT, first half-cycle */
+  int32_t  sumI_deltasH; /* Diversion CT, first half-cycle */
+  uint32_t numSamples;
+  uint32_t window;
+  uint32_t tStart_us;
+  uint32_t tHalf_us; /* Time of the negative-going crossing */
+} CycleAccumulator_t;
+
+/* Running calibration against a reference, accumulated over reports */
+typedef struct CalSession_ {
+  ECMCalResult_t result;
+  float          refVrms;
+  float          refPower;
+  uint32_t       reports;
+  uint32_t       count;
+  float          sumV;
+  float          sumP;
+  float          sumPF;
+  float          powerMin;
+  float          powerMax;
+} CalSession_t;
+
+/* Minimum and maximum values within a report window; voltage for each
+ * half-cycle, power for each cycle */
+typedef struct CycleTracker_ {
+  float    rmsVMin[NUM_V];
+  float    rmsVMax[NUM_V];
+  float    powerMin[NUM_CT];
+  float    powerMax[NUM_CT];
+  float    thdFund[NUM_V]; /* Sum of fundamental power in THD captures */
+  float    thdHarm[NUM_V]; /* Sum of harmonic power in THD captures */
+  uint32_t numCycles;
+} CycleTracker_t;
+
+/* An event in progress on a single channel */
+typedef struct EventState_ {
+  bool        active;
+  ECM_EVENT_t type;
+  uint32_t    tStart_us;
+  uint32_t    cycles;
+  float       extreme;
+  float       power;
+} EventState_t;
+
+/* Load step detection: the mean power of each window of cycles is compared
+ * with the previous window. When two windows agree, a change from the steady
+ * level beyond the threshold is a step. Otherwise the steady level follows
+ * the older window, so a ramp gives no event and a window only partly after
+ * a step cannot become the steady level. */
+typedef struct StepState_ {
+  float    sum;        /* Power summed over the open window */
+  uint32_t n;          /* Cycles in the open window */
+  float    last;       /* Mean of the previous window */
+  float    base;       /* Last steady level */
+  bool     haveBase;   /* A steady level has been found */
+  bool     changing;   /* Windows have differed since the steady level */
+  uint32_t tStart_us;  /* Start of the first window of the change */
+  uint32_t tWindow_us; /* Start of the open window */
+  uint32_t cycles;     /* Cycles since the start of the change */
+} StepState_t;
+
 typedef struct CalcRMS_ {
   float    cal;
   uint64_t sSqr;
@@ -87,15 +204,49 @@ typedef struct RawSampleSetUnpacked {
 static inline q15_t __STRUNCATE(int32_t val) RAMFUNC;
 static q15_t        applyCorrection(q15_t smp) RAMFUNC;
 static float        calcRMS(const CalcRMS_t *pSrc) RAMFUNC;
-static bool         zeroCrossingSW(q15_t smpV, uint32_t timeNow_us) RAMFUNC;
+static float halfCycleRMS(float cal, uint32_t sSqr, int32_t sDelta, uint32_t n,
+                          uint32_t nCycle, float mean);
+static float crestFactor(const CalcRMS_t *pSrc, float rms, q15_t smpMax,
+                         q15_t smpMin);
+static float reactivePower(float sumReactive, int32_t viOffset,
+                           uint32_t numSamples, float coeffSum, float cal);
+static ZeroCross_t  zeroCrossingSW(q15_t smpV, uint32_t timeNow_us) RAMFUNC;
 
 static void    accumSwapClear(void);
+static void    cycleClear(uint32_t tStart_us) RAMFUNC;
+static bool    cyclePush(uint32_t tStart_us) RAMFUNC;
+static bool    freqDetect(uint32_t tNow_us) RAMFUNC;
+static void    freqLock(void);
+static void    freqSet(uint32_t mainsFreq);
+static void    freqTrack(uint32_t tStart_us);
+static void    freqTrackReset(void);
+static void    divertCheck(float power, uint32_t tStart_us);
+static void    divertBucket(float exportNow, uint32_t tStart_us);
+static void    divertCycle(const CycleAccumulator_t *pCycle, size_t idxCT,
+                           float power);
+static void    divertSwitch(float exportNow, uint32_t tStart_us);
+static void    eventCheckI(size_t idxCT, float irms, uint32_t tStart_us);
+static void    eventCheckV(size_t idxV, float vrms, uint32_t tStart_us);
+static void    eventCheckStep(size_t idxCT, float power, uint32_t tStart_us);
+static void    sensorCheck(size_t idxCT, float irms, bool stuck,
+                           uint32_t tStart_us);
+static void    eventPush(const EventState_t *pState, uint32_t ch);
 static int32_t floorf_(const float f);
+static bool    isFinite_(const float f);
+static float   goertzelPower(const float *pSmp, uint32_t n, float w);
+static void    thdProcess(void);
+static void    phaseTotals(void);
+static void    netEnergy(float power, float time);
+static void    demandUpdate(float totalPower, float time);
+static void    calUpdate(void);
 static float   calibrationAmplitude(float cal, bool isV);
 static void calibrationPhase(CTCfg_t *pCfgCT, const VCfg_t *pCfgV, size_t idxCT,
                              bool vChan2);
 static void configChannelV(size_t ch);
+static void configLineLine(void);
 static void configChannelCT(size_t ch);
+static uint32_t settleCycles(void);
+static uint32_t freqAvgWindow(void);
 static void swapPtr(void **pIn1, void **pIn2);
 
 /******************************************************************************
@@ -112,14 +263,68 @@ static Accumulator_t  accumBuffer[2];
 static Accumulator_t *accumCollecting = accumBuffer;
 static Accumulator_t *accumProcessing = accumBuffer + 1;
 
+static CycleAccumulator_t cycleRing[CYCLE_DEPTH];
+static volatile uint32_t  cycleIdxWr = 0;
+static volatile uint32_t  cycleIdxRd = 0;
+static CycleTracker_t     cycleTracker[2];
+static uint32_t           reportWindow = 0;
+
+/* Single cycle voltage capture for harmonic analysis */
+static q15_t               thdCapture[NUM_V][THD_SMP_MAX];
+static uint32_t            thdLen    = 0;
+static uint32_t            thdTarget = 0;
+static uint32_t            thdWindow = 0;
+static volatile ThdState_t thdState  = THD_IDLE;
+
+static ECMEvent_t   eventQueue[EVENT_DEPTH];
+static uint32_t     eventIdxWr = 0;
+static uint32_t     eventIdxRd = 0;
+static EventState_t eventStateV[NUM_V];
+static bool         ocTripped[NUM_CT];
+static StepState_t  stepState[NUM_CT];
+static uint32_t     sensorCycles[NUM_CT]; /* Consecutive fault cycles */
+static bool         sensorFault[NUM_CT];
+
+static bool     divertOn     = false;
+static uint32_t divertHalves = 0;    /* Half-cycles since the last change */
+static float    divertLevel  = 0.0f; /* Energy bucket level (J) */
+
 static ECMPerformance_t  perfCounter[2];
 static ECMPerformance_t *perfActive = perfCounter;
 static ECMPerformance_t *perfIdle   = perfCounter + 1;
+static ECMDiagnostics_t  diag       = {0};
+
+static ECMDemand_t demand              = {0};
+static float       demandJ[NUM_CT + 1] = {0}; /* Energy in the open window */
 
 static ECMDataset_t datasetProc = {0};
 
 static uint32_t t_ZClast = 0;
 
+/* Voltage offset, averaged over cycles, for the half-cycle RMS */
+static float vMean[NUM_V];
+static bool  vMeanSet[NUM_V];
+static float divertIMean    = 0.0f; /* Diversion CT current offset */
+static bool  divertIMeanSet = false;
+
+static CalSession_t calSession = {0};
+
+/* Mains frequency measurement, rolling over the last cycle periods */
+static uint16_t freqPeriods[FREQ_AVG_MAX];
+static uint32_t freqSum_us   = 0;
+static uint32_t freqCount    = 0;
+static uint32_t freqIdx      = 0;
+static uint32_t freqLast_us  = 0;
+static uint32_t freqWindow   = 0;
+static bool     freqHaveLast = false;
+
+/* Nominal mains frequency detection. The interrupt finds the frequency, and
+ * the main loop applies it. */
+static uint32_t          freqDetCycles   = 0;
+static uint32_t          freqDetStart_us = 0;
+static volatile uint32_t freqDetected    = 0; /* Found, not yet applied */
+static volatile bool     freqLocked      = false;
+
 /******** FIXED POINT MATHS FUNCTIONS ********
  *
  * Adapted from Arm CMSIS-DSP: https://github.com/ARM-software/CMSIS-DSP
@@ -158,6 +363,73 @@ static RAMFUNC float calcRMS(const CalcRMS_t *pSrc) {
   return rms;
 }
 
+/*! @brief Calculate the RMS value of a half-cycle. The offset is averaged
+ *         over many cycles; the mean of a half-cycle is mostly signal, and a
+ *         dip in one half moves the mean of its cycle. The sum is divided
+ *         by half the cycle's samples rather than the samples in the half;
+ *         the crossing falls between samples, and the sample either side of
+ *         it adds almost nothing to the sum.
+ *  @param [in] cal : calibration factor
+ *  @param [in] sSqr : sum of the squared samples in the half-cycle
+ *  @param [in] sDelta : sum of the samples in the half-cycle
+ *  @param [in] n : number of samples in the half-cycle
+ *  @param [in] nCycle : number of samples in the whole cycle
+ *  @param [in] mean : offset of the samples
+ *  @return calibrated RMS value
+ */
+static float halfCycleRMS(float cal, uint32_t sSqr, int32_t sDelta, uint32_t n,
+                          uint32_t nCycle, float mean) {
+  /* sum((x - m)^2) = sum(x^2) - 2m sum(x) + n m^2 */
+  float ss = qfp_uint2float(sSqr);
+  ss = qfp_fsub(ss, qfp_fmul(qfp_fmul(2.0f, mean), qfp_int2float(sDelta)));
+  ss = qfp_fadd(ss, qfp_fmul(qfp_uint2float(n), qfp_fmul(mean, mean)));
+
+  const float ms = qfp_fdiv(ss, qfp_fmul(qfp_uint2float(nCycle), 0.5f));
+  if (!(ms > 0.0f)) {
+    return 0.0f;
+  }
+  return qfp_fmul(cal, qfp_fsqrt(ms));
+}
+
+/*! @brief Calculate the crest factor of a channel, the ratio of the peak to
+ *         RMS value after the offset is removed.
+ *  @param [in] pSrc : pointer to the RMS values
+ *  @param [in] rms : calibrated RMS value
+ *  @param [in] smpMax : maximum sample
+ *  @param [in] smpMin : minimum sample
+ *  @return crest factor, or 0 if the RMS value is too small
+ */
+static float crestFactor(const CalcRMS_t *pSrc, float rms, q15_t smpMax,
+                         q15_t smpMin) {
+  if (!(rms > qfp_fmul(pSrc->cal, (float)CREST_RMS_MIN))) {
+    return 0.0f;
+  }
+
+  const float mean = qfp_fdiv(qfp_int2float(pSrc->sDelta),
+                              qfp_uint2float(pSrc->numSamples));
+  float       peakPos = qfp_fsub(qfp_int2float(smpMax), mean);
+  float       peakNeg = qfp_fsub(mean, qfp_int2float(smpMin));
+  float       peak    = (peakPos > peakNeg) ? peakPos : peakNeg;
+
+  return qfp_fdiv(qfp_fmul(peak, pSrc->cal), rms);
+}
+
+/*! @brief Calculate reactive power from the quadrature accumulators
+ *  @param [in] sumReactive : interpolated sum of I x V shifted by 90°
+ *  @param [in] viOffset : product of the I and V sample sums
+ *  @param [in] numSamples : number of samples
+ *  @param [in] coeffSum : sum of the quadrature interpolation coefficients
+ *  @param [in] cal : combined I and V calibration
+ *  @return reactive power (VAr)
+ */
+static float reactivePower(float sumReactive, int32_t viOffset,
+                           uint32_t numSamples, float coeffSum, float cal) {
+  float q = qfp_fdiv(sumReactive, qfp_uint2float(numSamples));
+  q       = qfp_fsub(q, qfp_fdiv(qfp_fmul(coeffSum, qfp_int2float(viOffset)),
+                                 qfp_uint642float(usqr64(numSamples))));
+  return qfp_fmul(q, cal);
+}
+
 /*! @brief Swap pointers to buffers */
 static void swapPtr(void **pIn1, void **pIn2) {
   void *tmp = *pIn1;
@@ -172,7 +444,7 @@ static void swapPtr(void **pIn1, void **pIn2) {
 static ECMCfg_t ecmCfg           = {0};
 static bool     processTrigger   = false;
 static uint8_t  mapLogCT[NUM_CT] = {0};
-static uint8_t  discardCycles    = EQUIL_CYCLES;
+static uint32_t discardCycles    = EQUIL_CYCLES;
 static bool     initDone         = false;
 
 ECMCfg_t *ecmConfigGet(void) { return &ecmCfg; }
@@ -180,11 +452,7 @@ ECMCfg_t *ecmConfigGet(void) { return &ecmCfg; }
 void ecmConfigChannel(const size_t ch) {
   if (ch < NUM_V) {
     configChannelV(ch);
-    if ((NUM_V == 3u) && channelActive[1] && channelActive[2]) {
-      threePhase = true;
-    } else {
-      threePhase = false;
-    }
+    configLineLine();
   } else {
     configChannelCT(ch - NUM_V);
   }
@@ -192,6 +460,7 @@ void ecmConfigChannel(const size_t ch) {
 
 void configChannelCT(size_t ch) {
   channelActive[ch + NUM_V] = ecmCfg.ctCfg[ch].active;
+  apparentOnly[ch]          = ecmCfg.ctCfg[ch].assumedPF > 0.0f;
 
   if (ecmCfg.ctCfg[ch].active) {
     datasetProc.activeCh |= 1u << (ch + NUM_V);
@@ -208,6 +477,16 @@ void configChannelCT(size_t ch) {
   }
 }
 
+/*! @brief Enable the L-L voltage of each pair of active voltage channels. This
+ *         covers 3-phase, and split-phase where V1 and V2 are 180° apart.
+ */
+static void configLineLine(void) {
+  for (size_t i = 0; i < 3u; i++) {
+    llActive[i] = (NUM_V == 3u) && channelActive[i] &&
+                  channelActive[(i + 1u) % 3u];
+  }
+}
+
 void configChannelV(size_t ch) {
   channelActive[ch] = ecmCfg.vCfg[ch].vActive;
 
@@ -232,10 +511,7 @@ void ecmConfigInit(void) {
     configChannelV(i);
   }
 
-  /* 3-phase if all present and active */
-  if ((NUM_V == 3) && channelActive[1] && channelActive[2]) {
-    threePhase = true;
-  }
+  configLineLine();
 
   /* Configure each CT channel and load the initial Wh value from NVM. */
   for (size_t i = 0; i < NUM_CT; i++) {
@@ -246,8 +522,150 @@ void ecmConfigInit(void) {
   initDone = true;
 }
 
+void ecmConfigReload(const uint32_t mainsFreq) {
+  for (size_t i = 0; i < (NUM_V + NUM_CT); i++) {
+    ecmConfigChannel(i);
+  }
+  ecmConfigMainsFreq(mainsFreq);
+}
+
+bool ecmConfigApparentOnly(const size_t ch, const float pf) {
+  if ((ch >= NUM_CT) || (pf < 0.0f) || (pf > 1.0f) || (pf != pf)) {
+    return false;
+  }
+  ecmCfg.ctCfg[ch].assumedPF = pf;
+  configChannelCT(ch);
+  return true;
+}
+
+void ecmConfigTotalMask(const uint32_t mask) { ecmCfg.totalMask = mask; }
+
+void ecmConfigCurrentLimit(const size_t ch, const float limit) {
+  if (ch < NUM_CT) {
+    ecmCfg.evtCfg.iLimit[ch] = limit;
+    ocTripped[ch]            = false;
+  }
+}
+
+void ecmConfigSensorCheck(const size_t ch, const float iMin,
+                          const uint32_t cycles) {
+  if (ch < NUM_CT) {
+    ecmCfg.evtCfg.iMin[ch]       = iMin;
+    ecmCfg.evtCfg.iMinCycles[ch] = cycles;
+    sensorCycles[ch]             = 0;
+    sensorFault[ch]              = false;
+  }
+}
+
+void ecmConfigMainsFreq(const uint32_t mainsFreq) {
+  ecmCfg.freqAuto = (0 == mainsFreq);
+  freqSet(ecmCfg.freqAuto ? FREQ_AUTO_INIT : mainsFreq);
+  freqLocked   = false;
+  freqDetected = 0;
+  ecmFlush();
+}
+
 void ecmConfigReportCycles(uint32_t reportCycles) {
-  ecmCfg.reportCycles = reportCycles;
+  ecmCfg.reportCycles  = reportCycles;
+  ecmCfg.reportTime_us = (1000000u / ecmCfg.mainsFreq) * reportCycles;
+}
+
+/*! @brief Set the nominal mains frequency, keeping the report time constant
+ *         and recalculating the CT phase interpolation.
+ *  @param [in] mainsFreq : nominal mains frequency
+ */
+static void freqSet(uint32_t mainsFreq) {
+  ecmCfg.mainsFreq = mainsFreq;
+  ecmCfg.reportCycles =
+      (uint32_t)(((uint64_t)ecmCfg.reportTime_us * mainsFreq) / 1000000u);
+
+  for (size_t i = 0; i < NUM_CT; i++) {
+    configChannelCT(i);
+  }
+}
+
+/*! @brief Measure the mean period of the zero-crossings over the detection
+ *         time, and choose the nearest of 50 Hz or 60 Hz. Called from the
+ *         interrupt; the recalibration is left to freqLock in the main loop.
+ *  @param [in] tNow_us : time of the zero-crossing
+ *  @return true when the frequency has been found
+ */
+static RAMFUNC bool freqDetect(uint32_t tNow_us) {
+  if (0 != freqDetected) {
+    return false;
+  }
+
+  if (0 == freqDetCycles) {
+    freqDetStart_us = tNow_us;
+  } else {
+    const uint32_t elapsed_us = tNow_us - freqDetStart_us;
+    if (elapsed_us >= FREQ_DET_TIME_US) {
+      const bool is60Hz = (elapsed_us / freqDetCycles) < FREQ_DET_SPLIT_US;
+      freqDetected      = is60Hz ? 60u : 50u;
+      return true;
+    }
+  }
+  freqDetCycles++;
+  return false;
+}
+
+/*! @brief Apply a detected mains frequency, recalculating the CT phase
+ *         calibration. The equilibration cycles are repeated with the new
+ *         configuration before the interrupt accepts cycles.
+ */
+static void freqLock(void) {
+  freqSet(freqDetected);
+  discardCycles = settleCycles();
+  freqDetected  = 0;
+  freqLocked    = true;
+}
+
+/*! @brief Restart the frequency average, for example after a dropout */
+static void freqTrackReset(void) {
+  freqSum_us   = 0;
+  freqCount    = 0;
+  freqIdx      = 0;
+  freqHaveLast = false;
+}
+
+/*! @brief Number of cycles in the frequency average
+ *  @return configured cycles, or the default if not set
+ */
+static uint32_t freqAvgWindow(void) {
+  const uint32_t window =
+      (0 != ecmCfg.freqAvgCycles) ? ecmCfg.freqAvgCycles : FREQ_AVG_DEF;
+  return (window > FREQ_AVG_MAX) ? FREQ_AVG_MAX : window;
+}
+
+/*! @brief Add a cycle's period to the rolling frequency average. A period
+ *         outside the plausible range, from a dropped or restarted cycle,
+ *         restarts the average.
+ *  @param [in] tStart_us : start time of the cycle
+ */
+static void freqTrack(uint32_t tStart_us) {
+  const uint32_t window = freqAvgWindow();
+  if (window != freqWindow) {
+    freqTrackReset();
+    freqWindow = window;
+  }
+
+  if (freqHaveLast) {
+    const uint32_t period_us = tStart_us - freqLast_us;
+    if ((period_us < ZC_PERIOD_MIN_US) || (period_us > ZC_PERIOD_MAX_US)) {
+      freqTrackReset();
+    } else {
+      if (freqCount >= window) {
+        freqSum_us -= freqPeriods[freqIdx];
+      } else {
+        freqCount++;
+      }
+      freqPeriods[freqIdx] = (uint16_t)period_us;
+      freqSum_us += period_us;
+      freqIdx = (freqIdx + 1u) % window;
+    }
+  }
+  freqLast_us  = tStart_us;
+  freqHaveLast = true;
 }
 
 /******************************************************************************
@@ -258,7 +676,11 @@ static volatile RawSampleSetPacked_t adcSamples[SAMPLE_BUF_DEPTH];
 static volatile RawSampleSetPacked_t *volatile adcActive = adcSamples;
 static volatile RawSampleSetPacked_t *volatile adcProc   = adcSamples + 1;
 
-static float residualEnergy[NUM_CT] = {0};
+static float    residualEnergy[NUM_CT]         = {0};
+static float    residualImport                 = 0.0f;
+static float    residualExport                 = 0.0f;
+static float    residualApparentEnergy[NUM_CT] = {0};
+static uint32_t whPulses[NUM_CT]               = {0}; /* Imported Wh to pulse */
 
 void ecmDataBufferSwap(void) {
   swapPtr((void **)&adcActive, (void **)&adcProc);
@@ -266,6 +688,11 @@ void ecmDataBufferSwap(void) {
 
 volatile RawSampleSetPacked_t *ecmDataBuffer(void) { return adcActive; }
 
+void ecmDataBufferSelect(const size_t idx) {
+  adcProc   = adcSamples + idx;
+  adcActive = adcSamples + (idx ^ 1u);
+}
+
 /******************************************************************************
  * Functions
  *****************************************************************************/
@@ -284,18 +711,438 @@ static RAMFUNC q15_t applyCorrection(q15_t smp) {
 static void accumSwapClear(void) {
   swapPtr((void **)&accumCollecting, (void **)&accumProcessing);
   (void)memset((void *)accumCollecting, 0, sizeof(*accumCollecting));
+  accumCollecting->window = ++reportWindow;
+}
+
+/*! @brief Clear the cycle currently being accumulated
+ *  @param [in] tStart_us : start time of the new cycle
+ */
+static RAMFUNC void cycleClear(uint32_t tStart_us) {
+  CycleAccumulator_t *pCycle = &cycleRing[cycleIdxWr & (CYCLE_DEPTH - 1u)];
+  (void)memset(pCycle, 0, sizeof(*pCycle));
+  pCycle->tStart_us = tStart_us;
+}
+
+/*! @brief Queue the current cycle for processing and start a new one. If the
+ *         queue is full, the cycle is dropped.
+ *  @param [in] tStart_us : start time of the new cycle
+ *  @return true if the cycle was queued, false otherwise
+ */
+static RAMFUNC bool cyclePush(uint32_t tStart_us) {
+  if (CYCLE_DEPTH == ((cycleIdxWr - cycleIdxRd) & CYCLE_FMASK)) {
+    diag.cycleOverrun++;
+    cycleClear(tStart_us);
+    return false;
+  }
+
+  cycleRing[cycleIdxWr & (CYCLE_DEPTH - 1u)].window = accumCollecting->window;
+  cycleIdxWr = (cycleIdxWr + 1u) & CYCLE_FMASK;
+  cycleClear(tStart_us);
+  return true;
+}
+
+/*! @brief Update the surplus diversion output from a half-cycle's power on
+ *         the grid connection. The output only changes once it has been held
+ *         for the configured number of half-cycles, and each change is queued.
+ *  @param [in] power : real power of the half-cycle, positive when importing
+ *  @param [in] tStart_us : start time of the half-cycle
+ */
+static void divertCheck(float power, uint32_t tStart_us) {
+  const DivertCfg_t *pCfg      = &ecmCfg.divertCfg;
+  const float        exportNow = qfp_fsub(0.0f, power);
+
+  if (ECM_DIVERT_BUCKET == pCfg->mode) {
+    divertBucket(exportNow, tStart_us);
+    return;
+  }
+
+  if (divertHalves < pCfg->holdHalves) {
+    divertHalves++;
+    return;
+  }
+
+  const bool change = divertOn ? (exportNow < pCfg->offExport)
+                               : (exportNow > pCfg->onExport);
+  if (change) {
+    divertSwitch(exportNow, tStart_us);
+  } else {
+    divertHalves++;
+  }
+}
+
+/*! @brief Add a half-cycle's exported energy to the diversion bucket and
+ *         switch the output when the bucket fills or empties. The bucket
+ *         saturates at its limits, so the on/off bursts settle to the surplus
+ *         ratio.
+ *  @param [in] exportNow : exported power of the half-cycle
+ *  @param [in] tStart_us : start time of the half-cycle
+ */
+static void divertBucket(float exportNow, uint32_t tStart_us) {
+  const DivertCfg_t *pCfg = &ecmCfg.divertCfg;
+
+  divertLevel = qfp_fadd(
+      divertLevel,
+      qfp_fdiv(exportNow, qfp_uint2float(2u * ecmCfg.mainsFreq)));
+  if (divertLevel > pCfg->bucketJ) {
+    divertLevel = pCfg->bucketJ;
+  } else if (divertLevel < 0.0f) {
+    divertLevel = 0.0f;
+  }
+
+  if (divertHalves < pCfg->holdHalves) {
+    divertHalves++;
+    return;
+  }
+
+  const bool change = divertOn ? (divertLevel <= 0.0f)
+                               : (divertLevel >= pCfg->bucketJ);
+  if (change) {
+    divertSwitch(exportNow, tStart_us);
+  } else {
+    divertHalves++;
+  }
+}
+
+/*! @brief Toggle the diversion output and queue the change as an event
+ *  @param [in] exportNow : exported power of the half-cycle
+ *  @param [in] tStart_us : start time of the half-cycle
+ */
+static void divertSwitch(float exportNow, uint32_t tStart_us) {
+  const EventState_t evt = {.active    = true,
+                            .type      = divertOn ? ECM_EVT_DIVERT_OFF
+                                                  : ECM_EVT_DIVERT_ON,
+                            .tStart_us = tStart_us,
+                            .cycles    = divertHalves,
+                            .extreme   = exportNow};
+  eventPush(&evt, ecmCfg.divertCfg.ch);
+  divertOn     = !divertOn;
+  divertHalves = 0;
+}
+
+/*! @brief Evaluate the diversion on each half of a cycle. The offsets are
+ *         averaged over cycles, as a load drawing more current in one half
+ *         moves the mean of a single cycle. If the negative-going crossing was
+ *         not seen, or the channel has no voltage, both halves take the
+ *         cycle's power.
+ *  @param [in] pCycle : pointer to the cycle's accumulators
+ *  @param [in] idxCT : CT on the grid connection
+ *  @param [in] power : real power of the cycle
+ */
+static void divertCycle(const CycleAccumulator_t *pCycle, size_t idxCT,
+                        float power) {
+  const uint32_t numSamples = pCycle->numSamples;
+  const uint32_t nH         = pCycle->numSamplesH;
+  const float    nCycle     = qfp_uint2float(numSamples);
+  float          pHalf[2]   = {power, power};
+  uint32_t       tHalf_us   = pCycle->tStart_us;
+
+  const float meanCycle =
+      qfp_fdiv(qfp_int2float(pCycle->sumI_deltas[idxCT]), nCycle);
+  if (divertIMeanSet) {
+    divertIMean = qfp_fadd(
+        divertIMean, qfp_fdiv(qfp_fsub(meanCycle, divertIMean),
+                              qfp_uint2float(V_MEAN_CYCLES)));
+  } else {
+    divertIMean    = meanCycle;
+    divertIMeanSet = true;
+  }
+
+  if (!apparentOnly[idxCT] && (nH > 0) && (nH < numSamples)) {
+    const size_t idxV1 = ecmCfg.ctCfg[idxCT].vChan1;
+    const size_t idxV2 = ecmCfg.ctCfg[idxCT].vChan2;
+
+    int32_t sumV[2] = {pCycle->sumV_deltasH[idxV1],
+                       pCycle->sumV_deltas[idxV1]};
+    float   meanV   = vMean[idxV1];
+    if (idxV1 != idxV2) {
+      sumV[0] -= pCycle->sumV_deltasH[idxV2];
+      sumV[1] -= pCycle->sumV_deltas[idxV2];
+      meanV = qfp_fsub(meanV, vMean[idxV2]);
+    }
+    sumV[1] -= sumV[0];
+
+    const int32_t sumPA[2] = {pCycle->sumPA_H,
+                              pCycle->sumPA[idxCT] - pCycle->sumPA_H};
+    const int32_t sumPB[2] = {pCycle->sumPB_H,
+                              pCycle->sumPB[idxCT] - pCycle->sumPB_H};
+    const int32_t sumI[2]  = {pCycle->sumI_deltasH,
+                              pCycle->sumI_deltas[idxCT] -
+                                  pCycle->sumI_deltasH};
+    const uint32_t n[2]    = {nH, numSamples - nH};
+    const float    cal     = qfp_fmul(ecmCfg.ctCfg[idxCT].ctCal,
+                                      ecmCfg.vCfg[idxV1].voltageCal);
+
+    for (size_t h = 0; h < 2u; h++) {
+      float p = qfp_fadd(qfp_fmul(qfp_int2float(sumPA[h]),
+                                  ecmCfg.ctCfg[idxCT].phaseX[0]),
+                         qfp_fmul(qfp_int2float(sumPB[h]),
+                                  ecmCfg.ctCfg[idxCT].phaseY[0]));
+
+      /* Remove the offsets: sum((i - mI)(v - mV)) */
+      const float offset = qfp_fsub(
+          qfp_fadd(qfp_fmul(meanV, qfp_int2float(sumI[h])),
+                   qfp_fmul(divertIMean, qfp_int2float(sumV[h]))),
+          qfp_fmul(qfp_uint2float(n[h]), qfp_fmul(divertIMean, meanV)));
+      p = qfp_fdiv(qfp_fsub(p, offset), qfp_fmul(nCycle, 0.5f));
+      p = qfp_fmul(p, cal);
+
+      if (isFinite_(p)) {
+        pHalf[h] = p;
+      }
+    }
+    tHalf_us = pCycle->tHalf_us;
+  }
+
+  divertCheck(pHalf[0], pCycle->tStart_us);
+  divertCheck(pHalf[1], tHalf_us);
+}
+
+bool ecmDivertActive(void) {
+  return divertOn && (ECM_DIVERT_DISABLED != ecmCfg.divertCfg.mode);
+}
+
+/*! @brief Number of cycles to discard after a flush or frequency lock
+ *  @return configured settling cycles, or the default if not set
+ */
+static uint32_t settleCycles(void) {
+  return (0 != ecmCfg.settleCycles) ? ecmCfg.settleCycles : EQUIL_CYCLES;
+}
+
+bool ecmSettled(void) {
+  return (0 == discardCycles) && (!ecmCfg.freqAuto || freqLocked);
+}
+
+/*! @brief Check a CT's cycle RMS current against its overcurrent limit. The
+ *         event is queued as soon as the limit is exceeded, and the channel
+ *         is held until the current falls below the release threshold.
+ *  @param [in] idxCT : CT channel index
+ *  @param [in] irms : RMS current of the cycle
+ *  @param [in] tStart_us : start time of the cycle
+ */
+static void eventCheckI(size_t idxCT, float irms, uint32_t tStart_us) {
+  const float limit = ecmCfg.evtCfg.iLimit[idxCT];
+
+  if (!(limit > 0.0f)) {
+    ocTripped[idxCT] = false;
+    return;
+  }
+
+  if (ocTripped[idxCT]) {
+    const float release =
+        qfp_fmul(limit, qfp_fdiv(qfp_uint2float(100u - OC_HYST_PCT), 100.0f));
+    if (irms < release) {
+      ocTripped[idxCT] = false;
+    }
+  } else if (irms > limit) {
+    const EventState_t trip = {.active    = true,
+                               .type      = ECM_EVT_OVERCURRENT,
+                               .tStart_us = tStart_us,
+                               .cycles    = 1u,
+                               .extreme   = irms};
+    eventPush(&trip, idxCT);
+    ocTripped[idxCT] = true;
+  }
+}
+
+/*! @brief Update the sensor check of a CT with a cycle's RMS current. A
+ *         fault event is queued after the configured number of consecutive
+ *         fault cycles, and a recovery event on the first good cycle after.
+ *  @param [in] idxCT : CT channel index
+ *  @param [in] irms : RMS current of the cycle
+ *  @param [in] stuck : samples were constant away from the midpoint
+ *  @param [in] tStart_us : start time of the cycle
+ */
+static void sensorCheck(size_t idxCT, float irms, bool stuck,
+                        uint32_t tStart_us) {
+  const float    iMin   = ecmCfg.evtCfg.iMin[idxCT];
+  const uint32_t cycles = ecmCfg.evtCfg.iMinCycles[idxCT];
+
+  if (0 == cycles) {
+    sensorCycles[idxCT] = 0;
+    sensorFault[idxCT]  = false;
+    return;
+  }
+
+  /* Recovery needs the current a few percent over the minimum */
+  const float iGood = qfp_fmul(
+      iMin, qfp_fdiv(qfp_uint2float(100u + OC_HYST_PCT), 100.0f));
+  const bool low  = (iMin > 0.0f) && (irms < iMin);
+  const bool good = !stuck && !(irms < iGood);
+
+  EventState_t evt = {.active    = true,
+                      .tStart_us = tStart_us,
+                      .cycles    = 1u,
+                      .extreme   = irms};
+
+  if (sensorFault[idxCT]) {
+    if (good) {
+      evt.type           = ECM_EVT_SENSOR_OK;
+      sensorFault[idxCT] = false;
+      eventPush(&evt, idxCT);
+    }
+  } else if (stuck || low) {
+    sensorCycles[idxCT]++;
+    if (sensorCycles[idxCT] >= cycles) {
+      evt.type            = stuck ? ECM_EVT_SENSOR_STUCK : ECM_EVT_SENSOR_LOW;
+      evt.cycles          = sensorCycles[idxCT];
+      sensorCycles[idxCT] = 0;
+      sensorFault[idxCT]  = true;
+      eventPush(&evt, idxCT);
+    }
+  } else {
+    sensorCycles[idxCT] = 0;
+  }
+}
+
+/*! @brief Update the load step state of a CT with a cycle's real power. An
+ *         ECM_EVT_LOAD_STEP event is queued when the power settles at a level
+ *         that differs from the last steady level by more than the threshold.
+ *  @param [in] idxCT : CT channel index
+ *  @param [in] power : real power of the cycle
+ *  @param [in] tStart_us : start time of the cycle
+ */
+static void eventCheckStep(size_t idxCT, float power, uint32_t tStart_us) {
+  const EventCfg_t *pCfg   = &ecmCfg.evtCfg;
+  StepState_t      *pState = &stepState[idxCT];
+
+  if (!(pCfg->stepPower > 0.0f) || (0 == pCfg->stepCycles)) {
+    return;
+  }
+
+  if (0 == pState->n) {
+    pState->tWindow_us = tStart_us;
+  }
+  pState->sum = qfp_fadd(pState->sum, power);
+  pState->n++;
+  if (pState->n < pCfg->stepCycles) {
+    return;
+  }
+
+  const float mean = qfp_fdiv(pState->sum, qfp_uint2float(pState->n));
+  const bool  steady =
+      pState->haveBase &&
+      !(qfp_fsub(mean, pState->last) > pCfg->stepPower) &&
+      !(qfp_fsub(pState->last, mean) > pCfg->stepPower);
+
+  if (!pState->haveBase) {
+    pState->base     = mean;
+    pState->haveBase = true;
+  } else if (steady) {
+    const float change = qfp_fsub(mean, pState->base);
+    if ((change > pCfg->stepPower) ||
+        (qfp_fsub(0.0f, change) > pCfg->stepPower)) {
+      const EventState_t step = {.active    = true,
+                                 .type      = ECM_EVT_LOAD_STEP,
+                                 .tStart_us = pState->changing
+                                                  ? pState->tStart_us
+                                                  : pState->tWindow_us,
+                                 .cycles    = pState->cycles + pState->n,
+                                 .extreme   = change,
+                                 .power     = mean};
+      eventPush(&step, idxCT);
+      pState->base = mean;
+    } else {
+      pState->base = pState->last;
+    }
+    pState->changing = false;
+    pState->cycles   = 0;
+  } else {
+    if (!pState->changing) {
+      pState->changing  = true;
+      pState->tStart_us = pState->tWindow_us;
+      pState->cycles    = 0;
+    }
+    pState->cycles += pState->n;
+  }
+
+  pState->last = mean;
+  pState->sum  = 0.0f;
+  pState->n    = 0;
+}
+
+/*! @brief Update the sag/swell state of a voltage channel with a cycle's RMS
+ *         voltage. An event is queued when it ends, if it lasted for at least
+ *         the configured number of cycles.
+ *  @param [in] idxV : voltage channel index
+ *  @param [in] vrms : RMS voltage of the cycle
+ *  @param [in] tStart_us : start time of the cycle
+ */
+static void eventCheckV(size_t idxV, float vrms, uint32_t tStart_us) {
+  const EventCfg_t *pCfg   = &ecmCfg.evtCfg;
+  EventState_t     *pState = &eventStateV[idxV];
+
+  if (!(pCfg->vNominal > 0.0f)) {
+    return;
+  }
+
+  const float vNom100 = qfp_fdiv(pCfg->vNominal, 100.0f);
+  const bool  isSag   = vrms < qfp_fmul(vNom100, pCfg->sagPct);
+  const bool  isSwell = vrms > qfp_fmul(vNom100, pCfg->swellPct);
+  ECM_EVENT_t type    = isSag ? ECM_EVT_SAG : ECM_EVT_SWELL;
+
+  /* End any event in progress if the voltage has recovered or changed */
+  if (pState->active && ((!isSag && !isSwell) || (type != pState->type))) {
+    if (pState->cycles >= pCfg->minCycles) {
+      eventPush(pState, idxV);
+    }
+    pState->active = false;
+  }
+
+  if (isSag || isSwell) {
+    if (!pState->active) {
+      pState->active    = true;
+      pState->type      = type;
+      pState->tStart_us = tStart_us;
+      pState->cycles    = 0;
+      pState->extreme   = vrms;
+    }
+    pState->cycles++;
+    if (isSag ? (vrms < pState->extreme) : (vrms > pState->extreme)) {
+      pState->extreme = vrms;
+    }
+  }
+}
+
+/*! @brief Add an event to the pending queue, discarding it if full
+ *  @param [in] pState : pointer to the completed event's state
+ *  @param [in] ch : logical channel index
+ */
+static void eventPush(const EventState_t *pState, uint32_t ch) {
+  if ((eventIdxWr - eventIdxRd) >= EVENT_DEPTH) {
+    diag.eventDropped++;
+    return;
+  }
+
+  ECMEvent_t *pEvt = &eventQueue[eventIdxWr & (EVENT_DEPTH - 1u)];
+  pEvt->type       = pState->type;
+  pEvt->ch         = ch;
+  pEvt->tStart_us  = pState->tStart_us;
+  pEvt->cycles     = pState->cycles;
+  pEvt->value      = pState->extreme;
+  pEvt->power      = pState->power;
+  eventIdxWr++;
+}
+
+bool ecmEventGet(ECMEvent_t *pDst) {
+  if (eventIdxRd == eventIdxWr) {
+    return false;
+  }
+
+  *pDst = eventQueue[eventIdxRd & (EVENT_DEPTH - 1u)];
+  eventIdxRd++;
+  return true;
 }
 
 /*! @brief Zero crossing detection, software
  *  @param [in] smpV : current voltage sample
  *  @param [in] timeNow_us : current time in microseconds
- *  @return true for positive crossing, false otherwise
+ *  @return ZC_POS for a valid positive crossing, ZC_NEG for a negative
+ *          crossing, ZC_NONE otherwise
  */
-RAMFUNC bool zeroCrossingSW(q15_t smpV, uint32_t timeNow_us) {
+RAMFUNC ZeroCross_t zeroCrossingSW(q15_t smpV, uint32_t timeNow_us) {
   static Polarity_t polarityLast     = POL_POS;
   static int8_t     hystCnt          = ZC_HYST;
   static q15_t      vPeakSinceLastZC = 0;
-  static uint32_t   lastZC_us        = 0;
   Polarity_t        polarityNow      = (smpV < 0) ? POL_NEG : POL_POS;
 
   /* Track peak voltage magnitude since last zero-crossing */
@@ -322,8 +1169,8 @@ RAMFUNC bool zeroCrossingSW(q15_t smpV, uint32_t timeNow_us) {
         bool validAmplitude = (vPeakSinceLastZC >= ZC_MIN_VPEAK) || useAssumedV;
         bool validPeriod    = true;
 
-        if (lastZC_us != 0 && timeNow_us != 0) {
-          uint32_t period_us = timeNow_us - lastZC_us;
+        if (t_ZClast != 0 && timeNow_us != 0) {
+          uint32_t period_us = timeNow_us - t_ZClast;
           /* Accept period if within reasonable bounds for 40-71 Hz */
           validPeriod        = (period_us >= ZC_PERIOD_MIN_US &&
                          period_us <= ZC_PERIOD_MAX_US) ||
@@ -331,18 +1178,22 @@ RAMFUNC bool zeroCrossingSW(q15_t smpV, uint32_t timeNow_us) {
         }
 
         if (validAmplitude && validPeriod) {
-          lastZC_us        = timeNow_us;
           vPeakSinceLastZC = 0; /* Reset for next cycle */
-          return true;
+          return ZC_POS;
+        }
+        if (!validPeriod) {
+          diag.zcRejected++;
         }
         /* Invalid crossing - reset peak tracker anyway */
         vPeakSinceLastZC = 0;
+      } else {
+        return ZC_NEG;
       }
     }
   } else {
     hystCnt = useAssumedV ? ZC_HYST_AV : ZC_HYST;
   }
-  return false;
+  return ZC_NONE;
 }
 
 /*! @brief Return the floor of a float
@@ -357,6 +1208,17 @@ static int32_t floorf_(const float f) {
   return i;
 }
 
+/*! @brief Check if a float is finite from its exponent bits, without any
+ *         floating point operations
+ *  @param [in] f : float in
+ *  @return false if f is infinite or NaN, true otherwise
+ */
+static bool isFinite_(const float f) {
+  uint32_t u;
+  (void)memcpy(&u, &f, sizeof(u));
+  return (u & 0x7F800000u) != 0x7F800000u;
+}
+
 /*! @brief Turn an amplitude calibration value into a factor to change the
  *         abstract value into the real value, accounting for ADC width.
  *  @param [in] cal : the calibration value
@@ -435,29 +1297,85 @@ static void calibrationPhase(CTCfg_t *pCfgCT, const VCfg_t *pCfgV, size_t idxCT,
       qfp_fsub(1.0f, qfp_fdiv(qfp_fmul(sampleRate_rad, sampleRate_rad), 2.0f));
   pCfgCT->phaseX[idxPh] =
       qfp_fsub(shiftXRate, qfp_fmul(pCfgCT->phaseY[idxPh], rateSqr));
+
+  /* The interpolated voltage is sin(θ + ε), where θ is the phase of the
+   * earlier sample, ε the shift, and δ the angle between samples. The same
+   * samples give the voltage shifted by a further 90°:
+   *   cos(θ + ε) = (v[n] cos(ε) - v[n-1] cos(δ - ε)) / sin(δ)
+   * This is negated so that a lagging current has positive reactive power.
+   */
+  const float sinRate = qfp_fsin(sampleRate_rad);
+  pCfgCT->quadX[idxPh] =
+      qfp_fdiv(qfp_fcos(qfp_fsub(sampleRate_rad, phaseShift_rad)), sinRate);
+  pCfgCT->quadY[idxPh] =
+      qfp_fdiv(qfp_fsub(0.0f, qfp_fcos(phaseShift_rad)), sinRate);
 }
 
 void ecmClearEnergy(void) {
   for (size_t i = 0; i < NUM_CT; i++) {
     datasetProc.CT[i].wattHour = 0;
+    datasetProc.CT[i].vaHour   = 0;
     residualEnergy[i]          = 0.0f;
+    residualApparentEnergy[i]  = 0.0f;
+  }
+  datasetProc.importWh = 0;
+  datasetProc.exportWh = 0;
+  residualImport       = 0.0f;
+  residualExport       = 0.0f;
+}
+
+void ecmSetEnergyChannel(const size_t idx, const int32_t wattHour) {
+  if (idx < NUM_CT) {
+    datasetProc.CT[idx].wattHour = wattHour;
+    residualEnergy[idx]          = 0.0f;
+  }
+}
+
+uint32_t ecmWhPulsesTake(const size_t idx) {
+  uint32_t pulses = 0;
+  if (idx < NUM_CT) {
+    pulses        = whPulses[idx];
+    whPulses[idx] = 0;
   }
+  return pulses;
 }
 
 void ecmClearEnergyChannel(const size_t idx) {
   if (idx < NUM_CT) {
     datasetProc.CT[idx].wattHour = 0;
+    datasetProc.CT[idx].vaHour   = 0;
     residualEnergy[idx]          = 0.0f;
+    residualApparentEnergy[idx]  = 0.0f;
   }
 }
 
 void ecmFlush(void) {
-  discardCycles = EQUIL_CYCLES;
+  discardCycles = settleCycles();
+  freqTrackReset();
 
   (void)memset(accumBuffer, 0, (2 * sizeof(*accumBuffer)));
   (void)memset(dspBuffer, 0, (DOWNSAMPLE_TAPS * sizeof(*dspBuffer)));
   (void)memset(&residualEnergy, 0, (sizeof(*residualEnergy) * NUM_CT));
-  t_ZClast = 0;
+  (void)memset(&residualApparentEnergy, 0,
+               (sizeof(*residualApparentEnergy) * NUM_CT));
+  residualImport = 0.0f;
+  residualExport = 0.0f;
+  (void)memset(cycleRing, 0, sizeof(cycleRing));
+  (void)memset(cycleTracker, 0, sizeof(cycleTracker));
+  cycleIdxWr    = 0;
+  cycleIdxRd    = 0;
+  reportWindow  = 0;
+  thdState      = THD_IDLE;
+  t_ZClast      = 0;
+  freqDetCycles = 0;
+  freqDetected  = 0;
+  (void)memset(eventStateV, 0, sizeof(eventStateV));
+  (void)memset(ocTripped, 0, sizeof(ocTripped));
+  (void)memset(stepState, 0, sizeof(stepState));
+  (void)memset(sensorCycles, 0, sizeof(sensorCycles));
+  (void)memset(sensorFault, 0, sizeof(sensorFault));
+  (void)memset(vMeanSet, 0, sizeof(vMeanSet));
+  divertIMeanSet = false;
 }
 
 RAMFUNC void ecmFilterSample(SampleSet_t *pDst) {
@@ -564,9 +1482,12 @@ RAMFUNC ECM_STATUS_t ecmInjectSample(void) {
   static uint32_t    t_RepLast = 0;
   uint32_t           t_start   = 0;
   bool               zcFlag    = false;
+  bool               cycleDone = false;
 
   static size_t idxInject = 0;
 
+  CycleAccumulator_t *pCycle = &cycleRing[cycleIdxWr & (CYCLE_DEPTH - 1u)];
+
   if (0 != ecmCfg.timeMicros) {
     t_start = (*ecmCfg.timeMicros)();
   }
@@ -574,29 +1495,51 @@ RAMFUNC ECM_STATUS_t ecmInjectSample(void) {
   ecmFilterSample(&sampleBuffer[idxInject]);
   accumCollecting->numSamples++;
 
+  /* Without zero-crossings a cycle never completes, so restart it before the
+   * accumulators can overflow. */
+  if (pCycle->numSamples >= CYCLE_SMP_MAX) {
+    diag.cycleLong++;
+    cycleClear(t_start);
+  }
+  pCycle->numSamples++;
+
   for (size_t idxV = 0; idxV < NUM_V; idxV++) {
     if (channelActive[idxV]) {
-      int32_t V = sampleBuffer[idxInject].smpV[idxV];
-      accumCollecting->processV[idxV].sumV_sqr += ssqr64(V);
-      accumCollecting->processV[idxV].sumV_deltas += V;
+      int32_t         V      = sampleBuffer[idxInject].smpV[idxV];
+      VAccumulator_t *pAccum = &accumCollecting->processV[idxV];
+      pAccum->sumV_sqr += ssqr64(V);
+      pAccum->sumV_deltas += V;
+      if (V > pAccum->smpMax) {
+        pAccum->smpMax = (q15_t)V;
+      } else if (V < pAccum->smpMin) {
+        pAccum->smpMin = (q15_t)V;
+      }
+      pCycle->sumV_sqr[idxV] += (uint32_t)(V * V);
+      pCycle->sumV_deltas[idxV] += V;
+    }
+  }
+
+  /* Capture just over a single nominal cycle of voltage samples for harmonic
+   * analysis, starting at a zero crossing. */
+  if (THD_CAPTURING == thdState) {
+    for (size_t idxV = 0; idxV < NUM_V; idxV++) {
+      thdCapture[idxV][thdLen] = sampleBuffer[idxInject].smpV[idxV];
+    }
+    if (++thdLen >= thdTarget) {
+      thdWindow = accumCollecting->window;
+      thdState  = THD_READY;
     }
   }
 
-  /* 3-phase L-L values. Conventionally, line crossings go 1->2->3, so capture
-   * correct differences. */
-  if (threePhase) {
-    int32_t v1   = sampleBuffer[idxInject].smpV[0];
-    int32_t v2   = sampleBuffer[idxInject].smpV[1];
-    int32_t v3   = sampleBuffer[idxInject].smpV[2];
-    int32_t v1v2 = v1 - v2;
-    int32_t v2v3 = v2 - v3;
-    int32_t v3v1 = v3 - v1;
-    accumCollecting->processV[3].sumV_sqr += ssqr64(v1v2);
-    accumCollecting->processV[3].sumV_deltas += v1v2;
-    accumCollecting->processV[4].sumV_sqr += ssqr64(v2v3);
-    accumCollecting->processV[4].sumV_deltas += v2v3;
-    accumCollecting->processV[5].sumV_sqr += ssqr64(v3v1);
-    accumCollecting->processV[5].sumV_deltas += v3v1;
+  /* L-L values for each active pair. Conventionally, line crossings go
+   * 1->2->3, so capture correct differences. */
+  for (size_t i = 0; i < 3u; i++) {
+    if (llActive[i]) {
+      int32_t vLL = sampleBuffer[idxInject].smpV[i] -
+                    sampleBuffer[idxInject].smpV[(i + 1u) % 3u];
+      accumCollecting->processV[i + NUM_V].sumV_sqr += ssqr64(vLL);
+      accumCollecting->processV[i + NUM_V].sumV_deltas += vLL;
+    }
   }
 
   for (size_t idxCT = 0; idxCT < NUM_CT; idxCT++) {
@@ -616,10 +1559,26 @@ RAMFUNC ECM_STATUS_t ecmInjectSample(void) {
       int32_t thisV = sampleBuffer[thisVidx].smpV[v1];
       int32_t lastV = sampleBuffer[lastVidx].smpV[v1];
 
-      accumCollecting->processCT[idxCT].sumPA[0] += smul64(thisCT, lastV);
-      accumCollecting->processCT[idxCT].sumPB[0] += smul64(thisCT, thisV);
       accumCollecting->processCT[idxCT].sumI_sqr += ssqr64(thisCT);
       accumCollecting->processCT[idxCT].sumI_deltas += thisCT;
+      if (thisCT > accumCollecting->processCT[idxCT].smpMax) {
+        accumCollecting->processCT[idxCT].smpMax = (q15_t)thisCT;
+      } else if (thisCT < accumCollecting->processCT[idxCT].smpMin) {
+        accumCollecting->processCT[idxCT].smpMin = (q15_t)thisCT;
+      }
+
+      pCycle->sumI_sqr[idxCT] += (uint32_t)(thisCT * thisCT);
+      pCycle->sumI_deltas[idxCT] += thisCT;
+
+      /* Apparent only channels skip the V × I product */
+      if (apparentOnly[idxCT]) {
+        continue;
+      }
+
+      accumCollecting->processCT[idxCT].sumPA[0] += smul64(thisCT, lastV);
+      accumCollecting->processCT[idxCT].sumPB[0] += smul64(thisCT, thisV);
+      pCycle->sumPA[idxCT] += thisCT * lastV;
+      pCycle->sumPB[idxCT] += thisCT * thisV;
 
       /* L-L load */
       if (v1 != v2) {
@@ -627,6 +1586,9 @@ RAMFUNC ECM_STATUS_t ecmInjectSample(void) {
         lastV = sampleBuffer[lastVidx].smpV[v2];
         accumCollecting->processCT[idxCT].sumPA[1] += smul64(thisCT, lastV);
         accumCollecting->processCT[idxCT].sumPB[1] += smul64(thisCT, thisV);
+
+        pCycle->sumPA[idxCT] -= thisCT * lastV;
+        pCycle->sumPB[idxCT] -= thisCT * thisV;
       }
     }
   }
@@ -635,14 +1597,54 @@ RAMFUNC ECM_STATUS_t ecmInjectSample(void) {
    * zero-crossing, swap buffers and pend event.
    */
   uint32_t timeNow_us = (ecmCfg.timeMicros != 0) ? (*ecmCfg.timeMicros)() : 0;
-  if (zeroCrossingSW(sampleBuffer[idxInject].smpV[0], timeNow_us)) {
+  const ZeroCross_t zc =
+      channelActive[0]
+          ? zeroCrossingSW(sampleBuffer[idxInject].smpV[0], timeNow_us)
+          : ZC_NONE;
+
+  /* Keep the voltage and diversion sums of the first half-cycle */
+  if ((ZC_NEG == zc) && (0 == pCycle->numSamplesH)) {
+    for (size_t idxV = 0; idxV < NUM_V; idxV++) {
+      pCycle->sumV_sqrH[idxV]    = pCycle->sumV_sqr[idxV];
+      pCycle->sumV_deltasH[idxV] = pCycle->sumV_deltas[idxV];
+    }
+    const uint32_t chDivert = ecmCfg.divertCfg.ch;
+    if (chDivert < NUM_CT) {
+      pCycle->sumPA_H      = pCycle->sumPA[chDivert];
+      pCycle->sumPB_H      = pCycle->sumPB[chDivert];
+      pCycle->sumI_deltasH = pCycle->sumI_deltas[chDivert];
+    }
+    pCycle->numSamplesH = pCycle->numSamples;
+    pCycle->tHalf_us    = timeNow_us;
+  }
+
+  if (ZC_POS == zc) {
 
     zcFlag   = true;
     t_ZClast = timeNow_us;
 
-    if (0 == discardCycles) {
+    if (ecmCfg.freqAuto && !freqLocked) {
+      cycleClear(timeNow_us);
+      cycleDone = freqDetect(timeNow_us);
+    } else if (0 == discardCycles) {
+      static uint32_t thdCycles = 0;
+
       accumCollecting->cycles++;
+      cycleDone = cyclePush(timeNow_us);
+
+      if ((THD_IDLE == thdState) && (++thdCycles >= THD_CYCLES)) {
+        const uint32_t setPeriod_us =
+            ecmCfg.samplePeriod * OVERSAMPLING_RATIO * VCT_TOTAL;
+
+        thdCycles = 0;
+        thdLen    = 0;
+        thdTarget = (1000000u / (setPeriod_us * ecmCfg.mainsFreq)) + 2u;
+        if (thdTarget <= THD_SMP_MAX) {
+          thdState = THD_CAPTURING;
+        }
+      }
     } else {
+      cycleClear(timeNow_us);
       discardCycles--;
       if (0 == discardCycles) {
         accumSwapClear();
@@ -651,8 +1653,9 @@ RAMFUNC ECM_STATUS_t ecmInjectSample(void) {
     }
   }
 
-  /* If no zero-crossing has been detected in 100 ms, fall back to assumed
-   * Vrms (or time-based reporting if assumedVrms not configured) */
+  /* If no zero-crossing has been detected in 100 ms, or V1 is disabled, fall
+   * back to assumed Vrms (or time-based reporting if assumedVrms not
+   * configured) */
   if ((*ecmCfg.timeMicrosDelta)(t_ZClast) > 100000u) {
     useAssumedV = true;
     /* Force discard phase to complete if stuck waiting for valid crossings */
@@ -704,8 +1707,12 @@ RAMFUNC ECM_STATUS_t ecmInjectSample(void) {
   /* Advance injection point, masking for overflow */
   idxInject = (idxInject + 1u) & (PROC_DEPTH - 1u);
 
-  return reportReady ? ECM_REPORT_COMPLETE
-                     : (pend1s ? ECM_PEND_1S : ECM_CYCLE_ONGOING);
+  if (reportReady) {
+    return ECM_REPORT_COMPLETE;
+  } else if (pend1s) {
+    return ECM_PEND_1S;
+  }
+  return cycleDone ? ECM_CYCLE_COMPLETE : ECM_CYCLE_ONGOING;
 }
 
 ECMPerformance_t *ecmPerformance(void) {
@@ -715,23 +1722,447 @@ ECMPerformance_t *ecmPerformance(void) {
   return perfIdle;
 }
 
+const ECMDiagnostics_t *ecmDiagnostics(void) { return &diag; }
+
+void ecmDiagnosticsReset(void) { (void)memset(&diag, 0, sizeof(diag)); }
+
+const ECMDemand_t *ecmDemand(void) { return &demand; }
+
+void ecmDemandReset(void) {
+  (void)memset(&demand, 0, sizeof(demand));
+  (void)memset(demandJ, 0, sizeof(demandJ));
+}
+
+/*! @brief Calculate the power at a single frequency with the Goertzel
+ *         algorithm.
+ *  @param [in] pSmp : pointer to the samples
+ *  @param [in] n : number of samples
+ *  @param [in] w : frequency in radians per sample
+ *  @return the unscaled power at the frequency
+ */
+static float goertzelPower(const float *pSmp, uint32_t n, float w) {
+  const float coeff = qfp_fmul(2.0f, qfp_fcos(w));
+
+  float s1 = 0.0f;
+  float s2 = 0.0f;
+  for (size_t i = 0; i < n; i++) {
+    float s0 = qfp_fsub(qfp_fadd(pSmp[i], qfp_fmul(coeff, s1)), s2);
+    s2       = s1;
+    s1       = s0;
+  }
+
+  return qfp_fsub(qfp_fadd(qfp_fmul(s1, s1), qfp_fmul(s2, s2)),
+                  qfp_fmul(coeff, qfp_fmul(s1, s2)));
+}
+
+/*! @brief Accumulate the fundamental and harmonic power of a captured cycle.
+ *         The capture is resampled to exactly one cycle at the nominal mains
+ *         frequency, so that each harmonic falls on a single frequency bin.
+ *         Harmonics at or above the Nyquist frequency are excluded.
+ */
+static void thdProcess(void) {
+  CycleTracker_t *pTrack = &cycleTracker[thdWindow & 1u];
+  float           cycle[THD_RESAMPLE];
+
+  const uint32_t setPeriod_us =
+      ecmCfg.samplePeriod * OVERSAMPLING_RATIO * VCT_TOTAL;
+
+  uint32_t harmMax = 500000u / (setPeriod_us * ecmCfg.mainsFreq);
+  if (harmMax > THD_HARM_MAX) {
+    harmMax = THD_HARM_MAX;
+  }
+
+  /* Nominal cycle period in samples, and the step between resampled points */
+  const float period =
+      qfp_fdiv(1000000.0f, qfp_uint2float(setPeriod_us * ecmCfg.mainsFreq));
+  const float step  = qfp_fdiv(period, (float)THD_RESAMPLE);
+  const float wFund = TWO_PI / (float)THD_RESAMPLE;
+
+  for (size_t idxV = 0; idxV < NUM_V; idxV++) {
+    if (channelActive[idxV]) {
+      /* Linear interpolation between captured samples */
+      for (size_t i = 0; i < THD_RESAMPLE; i++) {
+        const float   t    = qfp_fmul(step, qfp_uint2float(i));
+        const int32_t idx  = qfp_float2int_z(t);
+        const float   frac = qfp_fsub(t, qfp_int2float(idx));
+        const float   s0   = qfp_int2float(thdCapture[idxV][idx]);
+        const float   s1   = qfp_int2float(thdCapture[idxV][idx + 1]);
+        cycle[i]           = qfp_fadd(s0, qfp_fmul(frac, qfp_fsub(s1, s0)));
+      }
+
+      float harm = 0.0f;
+      for (uint32_t h = 2; h <= harmMax; h++) {
+        harm = qfp_fadd(harm, goertzelPower(cycle, THD_RESAMPLE,
+                                            qfp_fmul(wFund, qfp_uint2float(h))));
+      }
+      pTrack->thdHarm[idxV] = qfp_fadd(pTrack->thdHarm[idxV], harm);
+      pTrack->thdFund[idxV] = qfp_fadd(
+          pTrack->thdFund[idxV], goertzelPower(cycle, THD_RESAMPLE, wFund));
+    }
+  }
+}
+
+ECM_STATUS_t ecmProcessCycle(void) {
+  bool      processed     = false;
+  CalcRMS_t rms;
+  float     vCycle[NUM_V] = {0};
+
+  if (0 != freqDetected) {
+    freqLock();
+  }
+
+  while (cycleIdxRd != cycleIdxWr) {
+    const CycleAccumulator_t *pCycle =
+        &cycleRing[cycleIdxRd & (CYCLE_DEPTH - 1u)];
+    CycleTracker_t *pTrack     = &cycleTracker[pCycle->window & 1u];
+    const bool      firstCycle = (0 == pTrack->numCycles);
+
+    const uint32_t numSamples = pCycle->numSamples;
+    rms.numSamples            = numSamples;
+
+    for (size_t idxV = 0; idxV < NUM_V; idxV++) {
+      if (channelActive[idxV]) {
+        rms.cal    = ecmCfg.vCfg[idxV].voltageCal;
+        rms.sDelta = pCycle->sumV_deltas[idxV];
+        rms.sSqr   = pCycle->sumV_sqr[idxV];
+
+        const float voltage = calcRMS(&rms);
+        vCycle[idxV]        = voltage;
+        eventCheckV(idxV, voltage, pCycle->tStart_us);
+
+        /* The extremes are of each half-cycle, so a dip lasting a single
+         * half-cycle is not averaged with the other half. */
+        const float meanCycle = qfp_fdiv(qfp_int2float(rms.sDelta),
+                                         qfp_uint2float(numSamples));
+        if (vMeanSet[idxV]) {
+          vMean[idxV] = qfp_fadd(
+              vMean[idxV], qfp_fdiv(qfp_fsub(meanCycle, vMean[idxV]),
+                                    qfp_uint2float(V_MEAN_CYCLES)));
+        } else {
+          vMean[idxV]    = meanCycle;
+          vMeanSet[idxV] = true;
+        }
+
+        float          vHalf[2] = {voltage, voltage};
+        const uint32_t nH       = pCycle->numSamplesH;
+        if ((nH > 0) && (nH < numSamples)) {
+          vHalf[0] = halfCycleRMS(rms.cal, pCycle->sumV_sqrH[idxV],
+                                  pCycle->sumV_deltasH[idxV], nH, numSamples,
+                                  vMean[idxV]);
+          vHalf[1] = halfCycleRMS(
+              rms.cal, (pCycle->sumV_sqr[idxV] - pCycle->sumV_sqrH[idxV]),
+              (pCycle->sumV_deltas[idxV] - pCycle->sumV_deltasH[idxV]),
+              (numSamples - nH), numSamples, vMean[idxV]);
+        }
+
+        for (size_t h = 0; h < 2u; h++) {
+          if ((firstCycle && (0 == h)) || (vHalf[h] < pTrack->rmsVMin[idxV])) {
+            pTrack->rmsVMin[idxV] = vHalf[h];
+          }
+          if ((firstCycle && (0 == h)) || (vHalf[h] > pTrack->rmsVMax[idxV])) {
+            pTrack->rmsVMax[idxV] = vHalf[h];
+          }
+        }
+      }
+    }
+
+    for (size_t idxCT = 0; idxCT < NUM_CT; idxCT++) {
+      if (channelActive[idxCT + NUM_V]) {
+        const size_t idxV1 = ecmCfg.ctCfg[idxCT].vChan1;
+        const size_t idxV2 = ecmCfg.ctCfg[idxCT].vChan2;
+
+        int32_t sumV_deltas = pCycle->sumV_deltas[idxV1];
+        if (idxV1 != idxV2) {
+          sumV_deltas -= pCycle->sumV_deltas[idxV2];
+        }
+
+        float power = qfp_fadd(
+            qfp_fmul(qfp_int2float(pCycle->sumPA[idxCT]),
+                     ecmCfg.ctCfg[idxCT].phaseX[0]),
+            qfp_fmul(qfp_int2float(pCycle->sumPB[idxCT]),
+                     ecmCfg.ctCfg[idxCT].phaseY[0]));
+        power = qfp_fdiv(power, qfp_uint2float(numSamples));
+
+        const float offset =
+            qfp_fmul(qfp_int2float(pCycle->sumI_deltas[idxCT]),
+                     qfp_int2float(sumV_deltas));
+        power = qfp_fsub(power, qfp_fdiv(offset, qfp_uint642float(
+                                                     usqr64(numSamples))));
+        power = qfp_fmul(power, qfp_fmul(ecmCfg.ctCfg[idxCT].ctCal,
+                                         ecmCfg.vCfg[idxV1].voltageCal));
+
+        rms.cal    = ecmCfg.ctCfg[idxCT].ctCal;
+        rms.sDelta = pCycle->sumI_deltas[idxCT];
+        rms.sSqr   = pCycle->sumI_sqr[idxCT];
+
+        const float current = calcRMS(&rms);
+        eventCheckI(idxCT, current, pCycle->tStart_us);
+
+        /* Stuck: variance under 1 count², with the mean away from the
+         * midpoint. Both sides are scaled by the number of samples squared. */
+        const int32_t  sumI    = pCycle->sumI_deltas[idxCT];
+        const uint64_t sumSqrN = (uint64_t)numSamples * pCycle->sumI_sqr[idxCT];
+        const int64_t  varN2   = (int64_t)sumSqrN - ((int64_t)sumI * sumI);
+        const int32_t  offsetN = (int32_t)(STUCK_OFFSET * numSamples);
+        const bool     stuck   = (varN2 < (int64_t)usqr64(numSamples)) &&
+                             ((sumI >= offsetN) || (sumI <= -offsetN));
+        sensorCheck(idxCT, current, stuck, pCycle->tStart_us);
+
+        if (apparentOnly[idxCT]) {
+          power = qfp_fmul(qfp_fmul(current, vCycle[idxV1]),
+                           ecmCfg.ctCfg[idxCT].assumedPF);
+        }
+
+        if (!isFinite_(power)) {
+          power = 0.0f;
+          diag.nonFinite++;
+        }
+
+        eventCheckStep(idxCT, power, pCycle->tStart_us);
+
+        if ((ECM_DIVERT_DISABLED != ecmCfg.divertCfg.mode) &&
+            (idxCT == ecmCfg.divertCfg.ch)) {
+          divertCycle(pCycle, idxCT, power);
+        }
+
+        if (firstCycle || (power < pTrack->powerMin[idxCT])) {
+          pTrack->powerMin[idxCT] = power;
+        }
+        if (firstCycle || (power > pTrack->powerMax[idxCT])) {
+          pTrack->powerMax[idxCT] = power;
+        }
+      }
+    }
+
+    freqTrack(pCycle->tStart_us);
+    pTrack->numCycles++;
+    cycleIdxRd = (cycleIdxRd + 1u) & CYCLE_FMASK;
+    processed  = true;
+  }
+
+  if (THD_READY == thdState) {
+    thdProcess();
+    thdState = THD_IDLE;
+  }
+
+  return processed ? ECM_CYCLE_COMPLETE : ECM_CYCLE_ONGOING;
+}
+
+/*! @brief Sum the CT power and current onto their voltage channels */
+static void phaseTotals(void) {
+  (void)memset(datasetProc.phasePower, 0, sizeof(datasetProc.phasePower));
+  (void)memset(datasetProc.phaseApparent, 0,
+               sizeof(datasetProc.phaseApparent));
+  (void)memset(datasetProc.phaseI, 0, sizeof(datasetProc.phaseI));
+
+  for (size_t idxCT = 0; idxCT < NUM_CT; idxCT++) {
+    if (!channelActive[idxCT + NUM_V]) {
+      continue;
+    }
+
+    const DataCT_t *pCT   = &datasetProc.CT[idxCT];
+    const size_t    idxV1 = ecmCfg.ctCfg[idxCT].vChan1;
+
+    if (idxV1 == ecmCfg.ctCfg[idxCT].vChan2) {
+      datasetProc.phasePower[idxV1] += pCT->realPower;
+      datasetProc.phaseApparent[idxV1] += pCT->apparentPower;
+      datasetProc.phaseI[idxV1] =
+          qfp_fadd(datasetProc.phaseI[idxV1], pCT->rmsI);
+    }
+  }
+}
+
+/*! @brief Add the latest report to a running calibration. When enough
+ *         reports have been averaged the reference is checked and the
+ *         calibration values scaled to match it.
+ */
+static void calUpdate(void) {
+  CalSession_t   *pCal  = &calSession;
+  const size_t    ch    = pCal->result.idxCt;
+  const size_t    idxV  = ecmCfg.ctCfg[ch].vChan1;
+  const DataCT_t *pCT   = &datasetProc.CT[ch];
+  const float     power = qfp_int2float(pCT->realPower);
+
+  if (useAssumedV || apparentOnly[ch] || !channelActive[idxV] ||
+      !(datasetProc.rmsV[idxV] > 0.0f) || !(power > 0.0f)) {
+    pCal->result.status = ECM_CAL_NO_SIGNAL;
+    return;
+  }
+
+  if ((0 == pCal->count) || (power < pCal->powerMin)) {
+    pCal->powerMin = power;
+  }
+  if ((0 == pCal->count) || (power > pCal->powerMax)) {
+    pCal->powerMax = power;
+  }
+  pCal->sumV  = qfp_fadd(pCal->sumV, datasetProc.rmsV[idxV]);
+  pCal->sumP  = qfp_fadd(pCal->sumP, power);
+  pCal->sumPF = qfp_fadd(pCal->sumPF, pCT->pf);
+  if (++pCal->count < pCal->reports) {
+    return;
+  }
+
+  const float n      = qfp_uint2float(pCal->count);
+  const float meanV  = qfp_fdiv(pCal->sumV, n);
+  const float meanP  = qfp_fdiv(pCal->sumP, n);
+  const float spread = qfp_fdiv(
+      qfp_fmul(qfp_fsub(pCal->powerMax, pCal->powerMin), 100.0f), meanP);
+
+  if (qfp_fdiv(pCal->sumPF, n) < CAL_PF_MIN) {
+    pCal->result.status = ECM_CAL_PF;
+  } else if (spread > CAL_STABLE_PCT) {
+    pCal->result.status = ECM_CAL_UNSTABLE;
+  } else {
+    /* Correct the voltage, then the current for the remaining power error */
+    const float scaleV = qfp_fdiv(pCal->refVrms, meanV);
+    const float scaleI = qfp_fdiv(pCal->refPower, qfp_fmul(meanP, scaleV));
+
+    pCal->result.voltageCalRaw =
+        qfp_fmul(ecmCfg.vCfg[idxV].voltageCalRaw, scaleV);
+    pCal->result.ctCalRaw = qfp_fmul(ecmCfg.ctCfg[ch].ctCalRaw, scaleI);
+    pCal->result.status   = ECM_CAL_DONE;
+  }
+}
+
+bool ecmCalStart(const size_t ch, const float refVrms, const float refPower,
+                 const uint32_t reports) {
+  if ((ch >= NUM_CT) || (0 == reports) || !(refVrms > 0.0f) ||
+      !(refPower > 0.0f) ||
+      (ecmCfg.ctCfg[ch].vChan1 != ecmCfg.ctCfg[ch].vChan2)) {
+    return false;
+  }
+
+  (void)memset(&calSession, 0, sizeof(calSession));
+  calSession.result.status = ECM_CAL_RUNNING;
+  calSession.result.idxCt  = ch;
+  calSession.refVrms       = refVrms;
+  calSession.refPower      = refPower;
+  calSession.reports       = reports;
+  return true;
+}
+
+void ecmCalResult(ECMCalResult_t *pDst) { *pDst = calSession.result; }
+
+bool ecmCalTake(ECMCalResult_t *pDst) {
+  if (ECM_CAL_DONE != calSession.result.status) {
+    return false;
+  }
+
+  *pDst                    = calSession.result;
+  calSession.result.status = ECM_CAL_IDLE;
+  return true;
+}
+
+/*! @brief Accumulate the net power of the totalised CTs as imported or
+ *         exported energy, depending on its sign.
+ *  @param [in] power : net real power, positive when importing
+ *  @param [in] time : report time (s)
+ */
+static void netEnergy(float power, float time) {
+  const bool isImport = (power >= 0.0f);
+  float     *pResidual = isImport ? &residualImport : &residualExport;
+  int32_t   *pWh = isImport ? &datasetProc.importWh : &datasetProc.exportWh;
+
+  float energy = qfp_fmul(isImport ? power : qfp_fsub(0.0f, power), time);
+  energy       = qfp_fadd(energy, *pResidual);
+  int32_t wh   = qfp_float2int_z(qfp_fdiv(energy, 3600.0f));
+
+  *pWh += wh;
+  *pResidual = qfp_fsub(energy, qfp_int2float(wh * 3600));
+}
+
+/*! @brief Accumulate the report energy into the open demand window. When the
+ *         window is full, close it and update the peaks.
+ *  @param [in] totalPower : net real power of the totalised CTs (W)
+ *  @param [in] time : report time (s)
+ */
+static void demandUpdate(float totalPower, float time) {
+  if (0 == ecmCfg.demandTime_s) {
+    return;
+  }
+
+  ECMDemandCh_t *pCh[NUM_CT + 1];
+  pCh[0]     = &demand.total;
+  demandJ[0] = qfp_fadd(demandJ[0], qfp_fmul(totalPower, time));
+  for (size_t i = 0; i < NUM_CT; i++) {
+    const float energy = qfp_fmul(datasetProc.CT[i].intervalWh, 3600.0f);
+    pCh[i + 1]         = &demand.CT[i];
+    demandJ[i + 1]     = qfp_fadd(demandJ[i + 1], energy);
+  }
+  demand.windowTime = qfp_fadd(demand.windowTime, time);
+
+  const bool closed =
+      (demand.windowTime >= qfp_uint2float(ecmCfg.demandTime_s));
+  for (size_t i = 0; i < (NUM_CT + 1); i++) {
+    pCh[i]->average = qfp_fdiv(demandJ[i], demand.windowTime);
+    if (closed) {
+      const int32_t avg = qfp_float2int_z(qfp_fadd(pCh[i]->average, 0.5f));
+      if ((0 == pCh[i]->peakSeq) || (avg > pCh[i]->peak)) {
+        pCh[i]->peak    = avg;
+        pCh[i]->peakSeq = datasetProc.reportSeq;
+      }
+      demandJ[i] = 0.0f;
+    }
+  }
+  if (closed) {
+    demand.windowTime = 0.0f;
+  }
+}
+
 RAMFUNC ECMDataset_t *ecmProcessSet(void) {
   uint32_t  t_start = 0;
   CalcRMS_t rms;
 
   t_start = (*ecmCfg.timeMicros)();
 
+  /* Consume any cycles still pending so that the extremes cover the whole
+   * report window. */
+  (void)ecmProcessCycle();
+  CycleTracker_t *pTrack = &cycleTracker[accumProcessing->window & 1u];
+  const bool      noCycles = (0 == pTrack->numCycles);
+
   /* Reused constants */
   const uint32_t numSamples    = accumProcessing->numSamples;
   const uint64_t numSamplesSqr = usqr64(numSamples);
   rms.numSamples               = numSamples;
 
+  const float deadband_rad =
+      qfp_fmul(ecmCfg.phaseDeadband, (TWO_PI / 360.0f));
+  const float tanDeadband =
+      qfp_fdiv(qfp_fsin(deadband_rad), qfp_fcos(deadband_rad));
+
   /* Choose if using the assumed time, or cycle locked real time */
   const uint32_t t_dividend =
       useAssumedV ? ecmCfg.reportTime_us : accumProcessing->tDelta_us;
   const float timeTotal = qfp_fdiv(qfp_uint2float(t_dividend), 1000000.0f);
   datasetProc.wallTime  = timeTotal;
 
+  /* Bookkeeping for downstream gap detection; not cleared with the energy */
+  datasetProc.reportSeq++;
+  datasetProc.cycleCount = accumProcessing->cycles;
+  datasetProc.sampleSets = numSamples;
+
+  /* Without zero-crossings the average restarts, so a dropout is flagged */
+  if (useAssumedV) {
+    freqTrackReset();
+  }
+
+  /* Queue the change to or from the assumed voltage */
+  if (useAssumedV != vLostReported) {
+    const EventState_t vEvt = {.active    = true,
+                               .type      = useAssumedV ? ECM_EVT_V_LOST
+                                                        : ECM_EVT_V_RESTORED,
+                               .tStart_us = t_start};
+    eventPush(&vEvt, 0);
+    vLostReported = useAssumedV;
+  }
+  datasetProc.frequency =
+      (0 == freqCount)
+          ? 0.0f
+          : qfp_fdiv(qfp_uint2float(freqCount * 1000000u),
+                     qfp_uint2float(freqSum_us));
+  datasetProc.freqValid = (freqCount >= freqAvgWindow());
+
   for (size_t idxV = 0; idxV < NUM_V; idxV++) {
     if (channelActive[idxV]) {
       rms.cal    = ecmCfg.vCfg[idxV].voltageCal;
@@ -746,14 +2177,34 @@ RAMFUNC ECMDataset_t *ecmProcessSet(void) {
         voltage = 0.0f;
       }
 
-      datasetProc.rmsV[idxV] = voltage;
+      datasetProc.rmsV[idxV]    = voltage;
+      datasetProc.rmsVMin[idxV] = noCycles ? voltage : pTrack->rmsVMin[idxV];
+      datasetProc.rmsVMax[idxV] = noCycles ? voltage : pTrack->rmsVMax[idxV];
+
+      float thd = 0.0f;
+      if (pTrack->thdFund[idxV] > 0.0f) {
+        thd = qfp_fdiv(pTrack->thdHarm[idxV], pTrack->thdFund[idxV]);
+        thd = qfp_fmul(100.0f, qfp_fsqrt(thd));
+      }
+      datasetProc.thdV[idxV] = thd;
+
+      datasetProc.crestV[idxV] =
+          useAssumedV
+              ? 0.0f
+              : crestFactor(&rms, voltage,
+                            accumProcessing->processV[idxV].smpMax,
+                            accumProcessing->processV[idxV].smpMin);
     } else {
-      datasetProc.rmsV[idxV] = 0.0f;
+      datasetProc.rmsV[idxV]    = 0.0f;
+      datasetProc.rmsVMin[idxV] = 0.0f;
+      datasetProc.rmsVMax[idxV] = 0.0f;
+      datasetProc.thdV[idxV]    = 0.0f;
+      datasetProc.crestV[idxV]  = 0.0f;
     }
   }
 
-  if (threePhase) {
-    for (size_t i = 0; i < 3; i++) {
+  for (size_t i = 0; i < 3u; i++) {
+    if (llActive[i]) {
       rms.cal    = ecmCfg.vCfg[i].voltageCal;
       rms.sDelta = accumProcessing->processV[i + NUM_V].sumV_deltas;
       rms.sSqr   = accumProcessing->processV[i + NUM_V].sumV_sqr;
@@ -766,9 +2217,12 @@ RAMFUNC ECMDataset_t *ecmProcessSet(void) {
       }
 
       datasetProc.rmsV[i + NUM_V] = voltage;
+    } else {
+      datasetProc.rmsV[i + NUM_V] = 0.0f;
     }
   }
 
+  float totalNow = 0.0f;
   for (size_t idxCT = 0; idxCT < NUM_CT; idxCT++) {
     if (channelActive[idxCT + NUM_V]) {
       int32_t idxV1 = ecmCfg.ctCfg[idxCT].vChan1;
@@ -779,6 +2233,10 @@ RAMFUNC ECMDataset_t *ecmProcessSet(void) {
       rms.sDelta = accumProcessing->processCT[idxCT].sumI_deltas;
       rms.sSqr   = accumProcessing->processCT[idxCT].sumI_sqr;
       datasetProc.CT[idxCT].rmsI = calcRMS(&rms);
+      datasetProc.CT[idxCT].crest =
+          crestFactor(&rms, datasetProc.CT[idxCT].rmsI,
+                      accumProcessing->processCT[idxCT].smpMax,
+                      accumProcessing->processCT[idxCT].smpMin);
 
       // Power and energy
       float sumEnergy = qfp_fadd(
@@ -787,12 +2245,29 @@ RAMFUNC ECMDataset_t *ecmProcessSet(void) {
           (qfp_fmul(qfp_int642float(accumProcessing->processCT[idxCT].sumPB[0]),
                     ecmCfg.ctCfg[idxCT].phaseY[0])));
 
+      float sumReactive = qfp_fadd(
+          (qfp_fmul(qfp_int642float(accumProcessing->processCT[idxCT].sumPA[0]),
+                    ecmCfg.ctCfg[idxCT].quadX[0])),
+          (qfp_fmul(qfp_int642float(accumProcessing->processCT[idxCT].sumPB[0]),
+                    ecmCfg.ctCfg[idxCT].quadY[0])));
+
       int32_t vi_offset =
           rms.sDelta * accumProcessing->processV[idxV1].sumV_deltas;
 
+      float reactiveNow = 0.0f;
+      if (!useAssumedV) {
+        reactiveNow = reactivePower(
+            sumReactive, vi_offset, numSamples,
+            qfp_fadd(ecmCfg.ctCfg[idxCT].quadX[0],
+                     ecmCfg.ctCfg[idxCT].quadY[0]),
+            qfp_fmul(rms.cal, ecmCfg.vCfg[idxV1].voltageCal));
+      }
+
       float powerNow;
       if (useAssumedV) {
-        powerNow = qfp_fmul(datasetProc.CT[idxCT].rmsI, ecmCfg.assumedVrms);
+        powerNow = qfp_fmul(qfp_fmul(datasetProc.CT[idxCT].rmsI,
+                                     ecmCfg.assumedVrms),
+                            ecmCfg.assumedPF);
       } else {
         powerNow = qfp_fdiv(sumEnergy, qfp_uint2float(numSamples));
         powerNow =
@@ -818,6 +2293,22 @@ RAMFUNC ECMDataset_t *ecmProcessSet(void) {
                                          qfp_uint642float(numSamplesSqr)));
         powerNow2 = qfp_fmul(powerNow2,
                              qfp_fmul(rms.cal, ecmCfg.vCfg[idxV2].voltageCal));
+
+        if (!useAssumedV) {
+          sumReactive = qfp_fadd(
+              (qfp_fmul(
+                  qfp_int642float(accumProcessing->processCT[idxCT].sumPA[1]),
+                  ecmCfg.ctCfg[idxCT].quadX[1])),
+              (qfp_fmul(
+                  qfp_int642float(accumProcessing->processCT[idxCT].sumPB[1]),
+                  ecmCfg.ctCfg[idxCT].quadY[1])));
+          reactiveNow = qfp_fsub(
+              reactiveNow,
+              reactivePower(sumReactive, vi_offset, numSamples,
+                            qfp_fadd(ecmCfg.ctCfg[idxCT].quadX[1],
+                                     ecmCfg.ctCfg[idxCT].quadY[1]),
+                            qfp_fmul(rms.cal, ecmCfg.vCfg[idxV2].voltageCal)));
+        }
         powerNow  = qfp_fsub(powerNow, powerNow2);
       }
 
@@ -834,41 +2325,127 @@ RAMFUNC ECMDataset_t *ecmProcessSet(void) {
               /* V1-V2 */
               rmsV = datasetProc.rmsV[3];
             } else {
-              /* V1-V3 */
-              rmsV = datasetProc.rmsV[4];
+              /* V1-V3, same magnitude as V3-V1 */
+              rmsV = datasetProc.rmsV[5];
             }
           } else {
             /* V2-V3 */
-            rmsV = datasetProc.rmsV[5];
+            rmsV = datasetProc.rmsV[4];
           }
         }
       }
 
-      float VA   = qfp_fmul(datasetProc.CT[idxCT].rmsI, rmsV);
+      float VA = qfp_fmul(datasetProc.CT[idxCT].rmsI, rmsV);
+
+      if (apparentOnly[idxCT] && !useAssumedV) {
+        powerNow    = qfp_fmul(VA, ecmCfg.ctCfg[idxCT].assumedPF);
+        reactiveNow = 0.0f;
+      }
+
+      /* A non-finite value would poison the energy residuals, so the channel
+       * is reported as zero and no energy is added for this report. */
+      if (!isFinite_(VA) || !isFinite_(powerNow) || !isFinite_(reactiveNow)) {
+        datasetProc.CT[idxCT].rmsI  = 0.0f;
+        datasetProc.CT[idxCT].crest = 0.0f;
+        VA                          = 0.0f;
+        powerNow                    = 0.0f;
+        reactiveNow                 = 0.0f;
+        diag.nonFinite++;
+      }
+
       float pf   = qfp_fdiv(powerNow, VA);
       bool  pf_b = ((pf > 1.05f) || (pf < -1.05f) || (pf != pf));
 
       datasetProc.CT[idxCT].pf = pf_b ? 0.0f : pf;
+      if (useAssumedV) {
+        datasetProc.CT[idxCT].pf = ecmCfg.assumedPF;
+        datasetProc.estimatedCh |= (1u << idxCT);
+      } else if (apparentOnly[idxCT]) {
+        datasetProc.CT[idxCT].pf = ecmCfg.ctCfg[idxCT].assumedPF;
+        datasetProc.estimatedCh |= (1u << idxCT);
+      } else {
+        datasetProc.estimatedCh &= ~(1u << idxCT);
+      }
 
       // Energy and power, rounding to nearest integer
       datasetProc.CT[idxCT].realPower =
           qfp_float2int_z(qfp_fadd(powerNow, 0.5f));
       datasetProc.CT[idxCT].apparentPower = qfp_float2int_z(qfp_fadd(VA, 0.5f));
+      datasetProc.CT[idxCT].reactivePower =
+          qfp_float2int_z(qfp_fadd(reactiveNow, 0.5f));
+
+      if (powerNow >= 0.0f) {
+        datasetProc.CT[idxCT].quadrant =
+            (reactiveNow >= 0.0f) ? ECM_QUAD_I : ECM_QUAD_IV;
+      } else {
+        datasetProc.CT[idxCT].quadrant =
+            (reactiveNow >= 0.0f) ? ECM_QUAD_II : ECM_QUAD_III;
+      }
+
+      /* In phase if |Q| / |P| is within tan(dead band) */
+      const float absP =
+          (powerNow < 0.0f) ? qfp_fsub(0.0f, powerNow) : powerNow;
+      const float absQ =
+          (reactiveNow < 0.0f) ? qfp_fsub(0.0f, reactiveNow) : reactiveNow;
+      if (useAssumedV || apparentOnly[idxCT] || !(VA >= 1.0f)) {
+        datasetProc.CT[idxCT].phase = ECM_PHASE_UNKNOWN;
+      } else if (absQ <= qfp_fmul(absP, tanDeadband)) {
+        datasetProc.CT[idxCT].phase = ECM_PHASE_IN;
+      } else {
+        datasetProc.CT[idxCT].phase =
+            (reactiveNow > 0.0f) ? ECM_PHASE_LAG : ECM_PHASE_LEAD;
+      }
+
+      if ((0 == ecmCfg.totalMask) || (ecmCfg.totalMask & (1u << idxCT))) {
+        totalNow = qfp_fadd(totalNow, powerNow);
+      }
+
+      const float powerMin = noCycles ? powerNow : pTrack->powerMin[idxCT];
+      const float powerMax = noCycles ? powerNow : pTrack->powerMax[idxCT];
+      datasetProc.CT[idxCT].realPowerMin =
+          qfp_float2int_z(qfp_fadd(powerMin, 0.5f));
+      datasetProc.CT[idxCT].realPowerMax =
+          qfp_float2int_z(qfp_fadd(powerMax, 0.5f));
 
       // REVISIT : Consider double precision here, some truncation observed
       float energyNow = qfp_fmul(powerNow, timeTotal);
+      datasetProc.CT[idxCT].intervalWh = qfp_fdiv(energyNow, 3600.0f);
       energyNow       = qfp_fadd(energyNow, residualEnergy[idxCT]);
       int32_t whNow   = qfp_float2int_z(qfp_fdiv(energyNow, 3600.0f));
 
       datasetProc.CT[idxCT].wattHour += whNow;
       residualEnergy[idxCT] = qfp_fsub(energyNow, qfp_int2float(whNow * 3600));
+      if (whNow > 0) {
+        whPulses[idxCT] += (uint32_t)whNow;
+      }
+
+      // Apparent energy, accumulated in the same manner as real energy
+      float vaEnergyNow = qfp_fmul(VA, timeTotal);
+      vaEnergyNow       = qfp_fadd(vaEnergyNow, residualApparentEnergy[idxCT]);
+      int32_t vahNow    = qfp_float2int_z(qfp_fdiv(vaEnergyNow, 3600.0f));
+
+      datasetProc.CT[idxCT].vaHour += vahNow;
+      residualApparentEnergy[idxCT] =
+          qfp_fsub(vaEnergyNow, qfp_int2float(vahNow * 3600));
 
     } else {
       /* Zero all values otherwise */
       (void)memset(&datasetProc.CT[idxCT], 0, sizeof(*datasetProc.CT));
+      datasetProc.estimatedCh &= ~(1u << idxCT);
     }
   }
 
+  phaseTotals();
+  datasetProc.totalPower = qfp_float2int_z(qfp_fadd(totalNow, 0.5f));
+  netEnergy(totalNow, timeTotal);
+  demandUpdate(totalNow, timeTotal);
+
+  if (ECM_CAL_RUNNING == calSession.result.status) {
+    calUpdate();
+  }
+
+  (void)memset(pTrack, 0, sizeof(*pTrack));
+
   perfActive->numCycles++;
   perfActive->microsCycles += (*ecmCfg.timeMicrosDelta)(t_start);
 
@@ -73,9 +73,12 @@ typedef struct VCfg_ {
 typedef struct CTCfgUnpacked_ {
   float    phaseX[2];
   float    phaseY[2];
+  float    quadX[2]; /* Interpolation for the voltage shifted by 90° */
+  float    quadY[2];
   float    phCal;
   float    ctCal;
   float    ctCalRaw;
+  float    assumedPF; /* Apparent only if > 0, real power is S × assumedPF */
   bool     active;
   uint8_t  vChan1;
   uint8_t  vChan2;
@@ -84,37 +87,149 @@ typedef struct CTCfgUnpacked_ {
   uint32_t idxInterpolateV;
 } CTCfg_t;
 
+typedef enum ECM_EVENT_ {
+  ECM_EVT_SAG,          /* RMS voltage below the sag threshold */
+  ECM_EVT_SWELL,        /* RMS voltage above the swell threshold */
+  ECM_EVT_OVERCURRENT,  /* RMS current above a CT's limit */
+  ECM_EVT_DIVERT_ON,    /* Diversion output switched on */
+  ECM_EVT_DIVERT_OFF,   /* Diversion output switched off */
+  ECM_EVT_LOAD_STEP,    /* Step change in a CT's real power */
+  ECM_EVT_SENSOR_LOW,   /* CT current below its expected minimum */
+  ECM_EVT_SENSOR_STUCK, /* CT samples constant away from the midpoint */
+  ECM_EVT_SENSOR_OK,    /* CT recovered from a sensor fault */
+  ECM_EVT_V_LOST,       /* No mains voltage, using the assumed voltage */
+  ECM_EVT_V_RESTORED    /* Mains voltage measured again */
+} ECM_EVENT_t;
+
+typedef struct ECMEvent_ {
+  ECM_EVENT_t type;
+  uint32_t    ch;        /* Logical V or CT channel index */
+  uint32_t    tStart_us; /* Time at the start of the first cycle */
+  uint32_t    cycles;    /* Duration in mains cycles */
+  float       value;     /* Extreme, trip current, export, or step (W) */
+  float       power;     /* Real power after a load step (W) */
+} ECMEvent_t;
+
+typedef struct EventCfg_ {
+  float    vNominal;           /* Nominal RMS voltage, 0 to disable sag/swell */
+  float    sagPct;             /* Sag threshold, % of nominal */
+  float    swellPct;           /* Swell threshold, % of nominal */
+  uint32_t minCycles;          /* Minimum cycles for a sag or swell event */
+  float    iLimit[NUM_CT];     /* Overcurrent trip (A RMS), 0 to disable */
+  float    stepPower;          /* Load step (W) for an event, 0 to disable */
+  uint32_t stepCycles;         /* Cycles averaged for each step comparison */
+  float    iMin[NUM_CT];       /* Expected minimum current (A RMS) */
+  uint32_t iMinCycles[NUM_CT]; /* Sensor fault cycles, 0 to disable */
+} EventCfg_t;
+
+typedef enum ECM_DIVERT_MODE_ {
+  ECM_DIVERT_DISABLED,  /* No diversion */
+  ECM_DIVERT_THRESHOLD, /* Switch on export thresholds with hysteresis */
+  ECM_DIVERT_BUCKET     /* Switch on the exported energy held in a bucket */
+} ECM_DIVERT_MODE_t;
+
+/* Surplus power diversion, evaluated on each mains half-cycle. Power is
+ * imported when positive, so export is the negated real power of the CT. In
+ * bucket mode each half-cycle's exported energy is added to a bucket limited
+ * to [0, bucketJ]. The output switches on when the bucket is full and off when
+ * it is empty, so the load is driven in bursts that match the surplus.
+ */
+typedef struct DivertCfg_ {
+  ECM_DIVERT_MODE_t mode;
+  uint32_t          ch;         /* CT on the grid connection, logical index */
+  float             onExport;   /* Export (W) to switch on */
+  float             offExport;  /* Export (W) to switch off, below onExport */
+  uint32_t          holdHalves; /* Minimum half-cycles between changes */
+  float             bucketJ;    /* Bucket capacity (J), bucket mode only */
+} DivertCfg_t;
+
 typedef struct ECMCfg_ {
   uint32_t (*timeMicros)(void);          /* Time in microseconds now */
   uint32_t (*timeMicrosDelta)(uint32_t); /* Time delta in microseconds */
 
   uint32_t reportCycles;  /* Number of cycles before reporting */
-  uint32_t mainsFreq;     /* Mains frequency */
+  uint32_t mainsFreq;     /* Nominal mains frequency, 50 or 60 Hz */
+  bool     freqAuto;      /* Detect the nominal frequency at startup */
   uint32_t samplePeriod;  /* Sampling period for each sample */
   uint32_t reportTime_us; /* Report time in microseconds */
   float    assumedVrms;   /* Assume RMS voltage if not found */
+  float    assumedPF;     /* Assume power factor if no voltage */
+  float    phaseDeadband; /* Angle (°) either side of 0 that is in phase */
+  uint32_t settleCycles;  /* Cycles discarded after a flush, 0 for default */
+  uint32_t totalMask;     /* CTs in totalPower (logical bits), 0 for all */
+  uint32_t freqAvgCycles; /* Cycles in the frequency average, 0 for default */
+  uint32_t demandTime_s;  /* Demand window (s), 0 to disable demand */
 
   uint8_t mapCTLog[NUM_CT]; /* Map of CT to microcontroller pins */
 
   GainOffset_t correction; /* Gain and offset correction */
 
-  CTCfg_t ctCfg[NUM_CT]; /* CT Configuration */
-  VCfg_t  vCfg[NUM_V];   /* Voltage configuration */
+  CTCfg_t     ctCfg[NUM_CT]; /* CT Configuration */
+  VCfg_t      vCfg[NUM_V];   /* Voltage configuration */
+  EventCfg_t  evtCfg;        /* Power quality event configuration */
+  DivertCfg_t divertCfg;     /* Surplus diversion configuration */
 } ECMCfg_t;
 
+/* Power quadrant, from the signs of real (P) and reactive (Q) power. Q is
+ * positive when the current lags the voltage (inductive).
+ */
+typedef enum ECM_QUADRANT_ {
+  ECM_QUAD_I,   /* P >= 0, Q >= 0 : import, lagging */
+  ECM_QUAD_II,  /* P < 0, Q >= 0 */
+  ECM_QUAD_III, /* P < 0, Q < 0 */
+  ECM_QUAD_IV   /* P >= 0, Q < 0 : import, leading */
+} ECM_QUADRANT_t;
+
+/* Phase of the current relative to the voltage. Antiphase, for example a
+ * reversed CT, is also in phase.
+ */
+typedef enum ECM_PHASE_ {
+  ECM_PHASE_UNKNOWN, /* No current, or no voltage reference */
+  ECM_PHASE_IN,      /* Within the dead band */
+  ECM_PHASE_LEAD,    /* Current leads the voltage (Q < 0) */
+  ECM_PHASE_LAG      /* Current lags the voltage (Q > 0) */
+} ECM_PHASE_t;
+
 typedef struct DataCT_ {
-  float   rmsI;
-  float   pf;
-  int32_t realPower;
-  int32_t apparentPower;
-  int32_t wattHour;
+  float          rmsI;
+  float          pf;           /* P / S; the sign follows real power */
+  int32_t        realPower;
+  int32_t        realPowerMin; /* Minimum single cycle power in the report */
+  int32_t        realPowerMax; /* Maximum single cycle power in the report */
+  int32_t        apparentPower;
+  int32_t        reactivePower; /* VAr, 0 when using the assumed voltage */
+  int32_t        wattHour;
+  float          intervalWh; /* Real energy in this report (Wh) */
+  int32_t        vaHour;     /* Apparent energy, not kept through a reset */
+  float          crest;      /* Current crest factor, 0 if no current */
+  ECM_QUADRANT_t quadrant;
+  ECM_PHASE_t    phase;
 } DataCT_t;
 
 typedef struct ECMDataset_ {
   float    wallTime;
-  uint32_t activeCh;
+  float    frequency; /* Mean mains frequency (Hz), 0 if not measured */
+  bool     freqValid; /* Frequency averaged over a full, plausible window */
+  uint32_t reportSeq;  /* Report sequence number, counts from startup */
+  uint32_t cycleCount; /* Mains cycles in the report, 0 without voltage */
+  uint32_t sampleSets; /* Sample sets accumulated in the report */
+  uint32_t activeCh;        /* Active channel mask, V in the low bits */
+  uint32_t estimatedCh;     /* CT mask with power estimated from assumed PF */
   float    rmsV[NUM_V * 2]; /* For L-L */
+  float    rmsVMin[NUM_V];  /* Minimum half-cycle RMS voltage */
+  float    rmsVMax[NUM_V];  /* Maximum half-cycle RMS voltage */
+  float    thdV[NUM_V];     /* Voltage THD (%), harmonics 2-9 */
+  float    crestV[NUM_V];   /* Voltage crest factor, 0 if no voltage */
   DataCT_t CT[NUM_CT];
+
+  /* Sums over the active CTs referenced to each voltage channel. L-L CTs are
+   * only included in totalPower. Currents are summed as RMS values. */
+  int32_t phasePower[NUM_V];    /* Real power (W) */
+  int32_t phaseApparent[NUM_V]; /* Apparent power (VA) */
+  float   phaseI[NUM_V];        /* RMS current (A) */
+  int32_t totalPower;           /* Net real power of the totalised CTs (W) */
+  int32_t importWh; /* Net imported energy, not retained through a reset */
+  int32_t exportWh; /* Net exported energy, not retained through a reset */
 } ECMDataset_t;
 
 typedef struct ECMPerformance_ {
@@ -126,6 +241,46 @@ typedef struct ECMPerformance_ {
   uint32_t microsDatasets;
 } ECMPerformance_t;
 
+/* Fault counters, counting from startup or the last reset */
+typedef struct ECMDiagnostics_ {
+  uint32_t cycleOverrun; /* Completed cycles dropped, cycle queue full */
+  uint32_t cycleLong;    /* Cycles restarted without a zero-crossing */
+  uint32_t zcRejected;   /* Zero-crossings rejected for their period */
+  uint32_t eventDropped; /* Events dropped, event queue full */
+  uint32_t nonFinite;    /* Non-finite values replaced with 0 */
+} ECMDiagnostics_t;
+
+/* Average power over fixed demand windows. A window closes on the first
+ * report at or past the window time. */
+typedef struct ECMDemandCh_ {
+  float    average; /* Average so far over the open window (W) */
+  int32_t  peak;    /* Highest average of a closed window (W) */
+  uint32_t peakSeq; /* Report sequence that closed the peak, 0 if none */
+} ECMDemandCh_t;
+
+typedef struct ECMDemand_ {
+  ECMDemandCh_t total;      /* Net power of the totalised CTs */
+  ECMDemandCh_t CT[NUM_CT]; /* Each CT */
+  float         windowTime; /* Time in the open window (s) */
+} ECMDemand_t;
+
+typedef enum ECM_CAL_STATUS_ {
+  ECM_CAL_IDLE,      /* No calibration started */
+  ECM_CAL_RUNNING,   /* Averaging reports */
+  ECM_CAL_DONE,      /* Proposed values are ready */
+  ECM_CAL_NO_SIGNAL, /* No measured voltage or current on the channel */
+  ECM_CAL_PF,        /* Power factor too far from 1 for a resistive load */
+  ECM_CAL_UNSTABLE   /* Power varied too much between reports */
+} ECM_CAL_STATUS_t;
+
+/* Calibration of a CT and its voltage channel against a resistive reference */
+typedef struct ECMCalResult_ {
+  ECM_CAL_STATUS_t status;
+  uint32_t         idxCt;         /* CT, logical index */
+  float            voltageCalRaw; /* Proposed voltage calibration */
+  float            ctCalRaw;      /* Proposed CT calibration */
+} ECMCalResult_t;
+
 typedef struct AutoPhaseRes_ {
   uint32_t idxCt;
   float    phase;
@@ -136,14 +291,30 @@ typedef struct AutoPhaseRes_ {
  * Function prototypes
  *****************************************************************************/
 
-/*! @brief Clear accumulated energy in dataset */
+/*! @brief Clear accumulated real, apparent, and net energy in dataset */
 void ecmClearEnergy(void);
 
-/*! @brief Clear accumulated energy for a single channel
+/*! @brief Clear accumulated real and apparent energy for a single channel
  *  @param [in] idx : channel index (0 to NUM_CT-1)
  */
 void ecmClearEnergyChannel(const size_t idx);
 
+/*! @brief Set the accumulated real energy for a single channel, for example
+ *         to restore a total saved elsewhere. Any residual energy is cleared.
+ *  @param [in] idx : channel index (0 to NUM_CT-1)
+ *  @param [in] wattHour : accumulated energy in Wh
+ */
+void ecmSetEnergyChannel(const size_t idx, const int32_t wattHour);
+
+/*! @brief Take the whole Wh imported on a channel since the last call, for
+ *         example to flash an LED per Wh. Exported energy does not pulse;
+ *         import after export pulses once the accumulated energy rises past
+ *         each whole Wh again.
+ *  @param [in] idx : channel index (0 to NUM_CT-1)
+ *  @return number of Wh pulses
+ */
+uint32_t ecmWhPulsesTake(const size_t idx);
+
 /*! @brief Get the pointer to the configuration struct
  *  @return pointer to Emon CM configuration struct
  */
@@ -159,11 +330,77 @@ void ecmConfigChannel(const size_t ch);
  */
 void ecmConfigInit(void);
 
+/*! @brief Apply changed channel, calibration, and mains frequency settings
+ *         while running, and flush the data. Unlike ecmConfigInit, the energy
+ *         accumulators are kept.
+ *  @param [in] mainsFreq : 50 or 60 Hz, or 0 to detect, see ecmConfigMainsFreq
+ */
+void ecmConfigReload(const uint32_t mainsFreq);
+
+/*! @brief Set the overcurrent limit for a CT. An ECM_EVT_OVERCURRENT event
+ *         is queued on the first cycle above the limit. The channel must fall
+ *         below the release threshold, a few percent under the limit, before
+ *         it can trip again.
+ *  @param [in] ch : CT channel, logical index
+ *  @param [in] limit : RMS current limit in A, 0 to disable
+ */
+void ecmConfigCurrentLimit(const size_t ch, const float limit);
+
+/*! @brief Set the sensor check for a CT. A fault is a cycle below the
+ *         expected minimum current, for a circuit that is always on, or with
+ *         its samples stuck at a constant value away from the midpoint. After
+ *         a number of consecutive fault cycles an ECM_EVT_SENSOR_LOW or
+ *         ECM_EVT_SENSOR_STUCK event is queued, and ECM_EVT_SENSOR_OK on the
+ *         first good cycle after that.
+ *  @param [in] ch : CT channel, logical index
+ *  @param [in] iMin : expected minimum RMS current in A, 0 for stuck only
+ *  @param [in] cycles : consecutive fault cycles for an event, 0 to disable
+ */
+void ecmConfigSensorCheck(const size_t ch, const float iMin,
+                          const uint32_t cycles);
+
+/*! @brief Set a CT to apparent power only. The V × I product is not
+ *         accumulated; real power is estimated as S × PF, where S uses the
+ *         RMS voltage of the CT's voltage channel, and the channel is flagged
+ *         in estimatedCh.
+ *  @param [in] ch : CT channel, logical index
+ *  @param [in] pf : assumed power factor (0, 1], or 0 to measure real power
+ *  @return true if the power factor is valid, false otherwise
+ */
+bool ecmConfigApparentOnly(const size_t ch, const float pf);
+
+/*! @brief Set the CTs that are added into the net total power and energy.
+ *         CTs on sub-circuits of another CT should be left out, so that they
+ *         are not counted twice.
+ *  @param [in] mask : bit n set to include CT n (logical index), 0 for all
+ */
+void ecmConfigTotalMask(const uint32_t mask);
+
+/*! @brief Set the nominal mains frequency. The CT phase interpolation and
+ *         cycles between reports are recalculated, and the data flushed.
+ *  @param [in] mainsFreq : 50 or 60 Hz, or 0 to detect the frequency from the
+ *                          first second of zero-crossings and lock to it
+ */
+void ecmConfigMainsFreq(const uint32_t mainsFreq);
+
 /*! @brief Set cycles between reports
  *  @param [in] reportCycles : cycles between reports
  */
 void ecmConfigReportCycles(uint32_t reportCycles);
 
+/*! @brief Get the state of the surplus diversion output. Each change is
+ *         also queued as an ECM_EVT_DIVERT_ON/OFF event.
+ *  @return true if diverting, false otherwise or if diversion is disabled
+ */
+bool ecmDivertActive(void);
+
+/*! @brief Get the oldest pending power quality event. Events are detected
+ *         from each mains cycle in ecmProcessCycle, independent of reports.
+ *  @param [out] pDst : pointer to the event destination
+ *  @return true if an event was copied, false if there are none pending
+ */
+bool ecmEventGet(ECMEvent_t *pDst);
+
 /*! @brief Returns a pointer to the ADC data buffer
  *  @return pointer to the active ADC data buffer.
  */
@@ -174,6 +411,12 @@ volatile RawSampleSetPacked_t *ecmDataBuffer(void);
  */
 void ecmDataBufferSwap(void);
 
+/*! @brief Select the data sampling buffer to handle. ADC will be filling the
+ *         other.
+ *  @param [in] idx : index of the completed buffer, 0 or 1
+ */
+void ecmDataBufferSelect(size_t idx);
+
 /*! @brief Unpack and optionally low pass filter the raw sample
  *         The struct from the DMA has no partition into V/CT channels, so
  *         alter this function to move data from the implementation specific
@@ -185,6 +428,12 @@ void ecmFilterSample(SampleSet_t *pDst) RAMFUNC;
 /*! @brief Flush all data and reset the equilibration cycle count */
 void ecmFlush(void);
 
+/*! @brief Check if the settling cycles after a flush, and any frequency
+ *         detection, have completed. No energy is accumulated before then.
+ *  @return true if settled, false otherwise
+ */
+bool ecmSettled(void);
+
 /*! @brief Injects a raw sample from the ADC into the accumulators. */
 ECM_STATUS_t ecmInjectSample(void) RAMFUNC;
 
@@ -193,13 +442,65 @@ ECM_STATUS_t ecmInjectSample(void) RAMFUNC;
  */
 ECMPerformance_t *ecmPerformance(void);
 
+/*! @brief Gets the fault counters
+ *  @return pointer to the fault counters
+ */
+const ECMDiagnostics_t *ecmDiagnostics(void);
+
+/*! @brief Clear all the fault counters */
+void ecmDiagnosticsReset(void);
+
+/*! @brief Gets the demand averages and peaks, updated with each report
+ *  @return pointer to the demand state
+ */
+const ECMDemand_t *ecmDemand(void);
+
+/*! @brief Clear the demand peaks and restart the open window, for example at
+ *         the start of a day or month.
+ */
+void ecmDemandReset(void);
+
+/*! @brief Start calibrating a CT and its voltage channel against a known
+ *         resistive reference. The readings are averaged over a number of
+ *         reports and the proposed raw calibration values are made available
+ *         through ecmCalResult. Nothing is changed; the caller applies the
+ *         values taken with ecmCalTake.
+ *  @param [in] ch : CT channel, logical index, not line-to-line
+ *  @param [in] refVrms : reference RMS voltage
+ *  @param [in] refPower : reference real power (W)
+ *  @param [in] reports : number of reports to average
+ *  @return true if the calibration was started, false otherwise
+ */
+bool ecmCalStart(const size_t ch, const float refVrms, const float refPower,
+                 const uint32_t reports);
+
+/*! @brief Get the state of the calibration and any proposed values
+ *  @param [out] pDst : calibration result
+ */
+void ecmCalResult(ECMCalResult_t *pDst);
+
+/*! @brief Take the proposed values of a completed calibration, and end it
+ *  @param [out] pDst : calibration result
+ *  @return true if there were values to take, false otherwise
+ */
+bool ecmCalTake(ECMCalResult_t *pDst);
+
 /*! @brief Calibrate a CT sensor's lead against the input voltage
  *  @param [in] pDst : autophase structure
  */
 void ecmPhaseCalibrate(AutoPhaseRes_t *pDst);
 
-/*! @brief Processes a whole cycle */
-ECM_STATUS_t ecmProcessCycle(void) RAMFUNC;
+/*! @brief Process all completed mains cycles, tracking the minimum and
+ *         maximum values within the report. Call when ecmInjectSample returns
+ *         ECM_CYCLE_COMPLETE; any pending cycles are also processed by
+ *         ecmProcessSet. If there is no voltage, there are no cycles and the
+ *         extremes are equal to the report's mean values. A mains frequency
+ *         found by automatic detection is applied here, outside the
+ *         interrupt; ecmInjectSample returns ECM_CYCLE_COMPLETE when it is.
+ *  @return ECM_CYCLE_COMPLETE if any cycles were processed,
+ *          ECM_CYCLE_ONGOING otherwise
+ */
+ECM_STATUS_t ecmProcessCycle(void);
 
 /*! @brief Processes a whole data set
  *  @param [out] pData : pointer to the processed data structure
@@ -0,0 +1,58 @@
+#include <string.h>
+
+#include "frame.h"
+
+#ifndef HOSTED
+#include "driver_DMAC.h"
+#else
+#include "emonCM_test.h"
+#endif /* HOSTED */
+
+size_t frameEncode(const void *pPayload, uint8_t n, uint8_t node,
+                   uint8_t *pDst, size_t m) {
+  const size_t w = (size_t)n + FRAME_OVERHEAD;
+
+  if (w > m) {
+    return 0;
+  }
+
+  pDst[0] = FRAME_SOF;
+  pDst[1] = FRAME_VERSION;
+  pDst[2] = node;
+  pDst[3] = n;
+  memcpy(&pDst[FRAME_HDR_W], pPayload, n);
+
+  const uint16_t crc         = calcCRC16_ccitt(pDst, (FRAME_HDR_W + n));
+  pDst[FRAME_HDR_W + n]      = (uint8_t)crc;
+  pDst[FRAME_HDR_W + n + 1u] = (uint8_t)(crc >> 8);
+
+  return w;
+}
+
+FrameStatus_t frameDecode(const uint8_t *pSrc, size_t n, FrameInfo_t *pInfo) {
+  if (n < FRAME_OVERHEAD) {
+    return FRAME_SHORT;
+  }
+  if (FRAME_SOF != pSrc[0]) {
+    return FRAME_BAD_SOF;
+  }
+  if (FRAME_VERSION != pSrc[1]) {
+    return FRAME_BAD_VERSION;
+  }
+
+  const size_t nPayload = pSrc[3];
+  if (n < (nPayload + FRAME_OVERHEAD)) {
+    return FRAME_SHORT;
+  }
+
+  const uint16_t crc = (uint16_t)(pSrc[FRAME_HDR_W + nPayload] |
+                                  (pSrc[FRAME_HDR_W + nPayload + 1u] << 8));
+  if (crc != calcCRC16_ccitt(pSrc, (FRAME_HDR_W + nPayload))) {
+    return FRAME_BAD_CRC;
+  }
+
+  pInfo->node     = pSrc[2];
+  pInfo->n        = (uint8_t)nPayload;
+  pInfo->pPayload = &pSrc[FRAME_HDR_W];
+  return FRAME_OK;
+}
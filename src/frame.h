@@ -0,0 +1,50 @@
+#pragma once
+
+#include <stddef.h>
+#include <stdint.h>
+
+/* Binary report frame:
+ *   [0]     FRAME_SOF
+ *   [1]     FRAME_VERSION
+ *   [2]     node ID
+ *   [3]     payload length, n
+ *   [4..]   payload (a packed structure, e.g. PackedDataCT_t)
+ *   [4 + n] CRC16-CCITT over bytes [0, 4 + n), little endian
+ */
+#define FRAME_SOF      0xA5u
+#define FRAME_VERSION  1u
+#define FRAME_HDR_W    4u
+#define FRAME_OVERHEAD (FRAME_HDR_W + 2u)
+
+typedef enum FrameStatus_ {
+  FRAME_OK,
+  FRAME_SHORT,       /* Fewer bytes than the frame claims */
+  FRAME_BAD_SOF,     /* First byte is not FRAME_SOF */
+  FRAME_BAD_VERSION, /* Unknown format version */
+  FRAME_BAD_CRC      /* Corrupted in transit */
+} FrameStatus_t;
+
+typedef struct FrameInfo_ {
+  uint8_t        node;     /* Node ID from the header */
+  uint8_t        n;        /* Payload length */
+  const uint8_t *pPayload; /* Payload, within the source buffer */
+} FrameInfo_t;
+
+/*! @brief Wrap a payload in a binary frame
+ *  @param [in] pPayload : pointer to the payload
+ *  @param [in] n : payload length
+ *  @param [in] node : node ID
+ *  @param [out] pDst : destination buffer
+ *  @param [in] m : width of the destination buffer
+ *  @return frame length (n + FRAME_OVERHEAD), or 0 if it does not fit in m
+ */
+size_t frameEncode(const void *pPayload, uint8_t n, uint8_t node,
+                   uint8_t *pDst, size_t m);
+
+/*! @brief Check a binary frame and locate its payload
+ *  @param [in] pSrc : pointer to the start of the frame
+ *  @param [in] n : number of bytes available from pSrc
+ *  @param [out] pInfo : header fields and payload, only valid if FRAME_OK
+ *  @return FRAME_OK if the frame is complete and the CRC matches
+ */
+FrameStatus_t frameDecode(const uint8_t *pSrc, size_t n, FrameInfo_t *pInfo);
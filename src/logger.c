@@ -0,0 +1,66 @@
+#include <string.h>
+
+#include "logger.h"
+#include "util.h"
+#include "wallClock.h"
+
+static const char *const levelTag[]  = {"ERR:", "WRN:", "INF:", "DBG:"};
+static const char *const levelName[] = {"error", "warn", "info", "debug"};
+
+static LogOpt_t   logOpt       = {0};
+static LogLevel_t logThreshold = LOG_LEVEL_DEF;
+
+void logInit(const LogOpt_t *pOpt) {
+  logOpt       = *pOpt;
+  logThreshold = LOG_LEVEL_DEF;
+}
+
+bool logEnabled(const LogLevel_t level) {
+  return (0 != logOpt.puts) && (level <= logThreshold);
+}
+
+LogLevel_t logLevelGet(void) { return logThreshold; }
+
+const char *logLevelName(const LogLevel_t level) {
+  return (level <= LOG_DEBUG) ? levelName[level] : "";
+}
+
+bool logLevelParse(const char *s, LogLevel_t *pLevel) {
+  for (size_t i = 0; i <= LOG_DEBUG; i++) {
+    if ((0 == strcmp(s, levelName[i])) ||
+        (('0' + (char)i == s[0]) && (0 == s[1]))) {
+      *pLevel = (LogLevel_t)i;
+      return true;
+    }
+  }
+  return false;
+}
+
+void logLevelSet(const LogLevel_t level) {
+  logThreshold = (level <= LOG_DEBUG) ? level : LOG_DEBUG;
+}
+
+void logPuts(const LogLevel_t level, const char *s) {
+  char     tBuf[WALL_CLOCK_ISO_W];
+  uint32_t epoch = 0;
+
+  if (!logEnabled(level)) {
+    return;
+  }
+
+  if (logOpt.epoch) {
+    epoch = logOpt.epoch();
+  }
+
+  logOpt.puts(levelTag[level]);
+  if (epoch) {
+    (void)wallClockIso(tBuf, epoch);
+    logOpt.puts(tBuf);
+    logOpt.puts(":");
+  } else if (logOpt.millis) {
+    (void)utilUtoa(tBuf, logOpt.millis(), ITOA_BASE10);
+    logOpt.puts(tBuf);
+    logOpt.puts(":");
+  }
+  logOpt.puts(s);
+}
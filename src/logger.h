@@ -0,0 +1,72 @@
+#pragma once
+
+#include <stdbool.h>
+#include <stdint.h>
+
+/* Levelled status messages. Each line is sent as "<LVL>:<ms>:<message>",
+ * where LVL is ERR, WRN, INF, or DBG. Once the wall clock is known, the time
+ * is sent as ISO-8601 UTC instead of ms. Messages above the threshold are
+ * dropped before anything is formatted; check logEnabled before building a
+ * message that takes any work.
+ */
+
+typedef enum LogLevel_ {
+  LOG_ERROR, /* Something has failed and data may be lost */
+  LOG_WARN,  /* Unexpected, but handled */
+  LOG_INFO,  /* Normal operation */
+  LOG_DEBUG  /* Detail for development */
+} LogLevel_t;
+
+#define LOG_LEVEL_DEF LOG_WARN
+
+typedef struct LogOpt_ {
+  void (*puts)(const char *s); /* Output a null terminated string */
+  uint32_t (*millis)(void);    /* Time in milliseconds now */
+  uint32_t (*epoch)(void);     /* Unix time now, 0 if not known. May be 0 */
+} LogOpt_t;
+
+/*! @brief Set the output and time source. Until this is called, nothing is
+ *         sent. The threshold is set to LOG_LEVEL_DEF.
+ *  @param [in] pOpt : output and time functions
+ */
+void logInit(const LogOpt_t *pOpt);
+
+/*! @brief Indicate if a message at this level would be sent
+ *  @param [in] level : message level
+ *  @return true if level is at or below the threshold
+ */
+bool logEnabled(const LogLevel_t level);
+
+/*! @brief Get the current threshold
+ *  @return threshold level
+ */
+LogLevel_t logLevelGet(void);
+
+/*! @brief Name of a level, e.g. "info"
+ *  @param [in] level : level
+ *  @return name of the level
+ */
+const char *logLevelName(const LogLevel_t level);
+
+/*! @brief Parse a level as a number (0-3) or name (error, warn, info, debug)
+ *  @param [in] s : null terminated string
+ *  @param [out] pLevel : level, only valid if true is returned
+ *  @return true if s names a level
+ */
+bool logLevelParse(const char *s, LogLevel_t *pLevel);
+
+/*! @brief Set the threshold. Messages above it are dropped.
+ *  @param [in] level : new threshold
+ */
+void logLevelSet(const LogLevel_t level);
+
+/*! @brief Send a message if its level is at or below the threshold
+ *  @param [in] level : message level
+ *  @param [in] s : null terminated message, including any line ending
+ */
+void logPuts(const LogLevel_t level, const char *s);
+
+#define LOG_ERR(s) logPuts(LOG_ERROR, (s))
+#define LOG_WRN(s) logPuts(LOG_WARN, (s))
+#define LOG_INF(s) logPuts(LOG_INFO, (s))
+#define LOG_DBG(s) logPuts(LOG_DEBUG, (s))
@@ -0,0 +1,251 @@
+#include "modbus.h"
+
+#define FN_READ_HOLDING  0x03u
+#define FN_READ_INPUT    0x04u
+#define FN_WRITE_SINGLE  0x06u
+#define FN_WRITE_MULTI   0x10u
+#define READ_QTY_MAX     125u
+#define WRITE_QTY_MAX    123u
+#define ADDR_BROADCAST   0u
+#define GAP_FIXED_US     1750u     /* Fixed gap above 19200 baud */
+#define GAP_CHAR_BITS_US 38500000u /* 3.5 chars * 11 bits * 1E6 us */
+
+static size_t   excResponse(uint8_t *pDst, uint8_t addr, uint8_t fn,
+                            ModbusExc_t exc);
+static uint16_t getU16(const uint8_t *pSrc);
+static size_t   handleFrame(ModbusSlave_t *pSlave, uint8_t *pDst);
+static size_t   putCRC(uint8_t *pDst, size_t n);
+static void     putU16(uint8_t *pDst, uint16_t val);
+static size_t   readRegs(const ModbusSlave_t *pSlave, uint8_t *pDst, size_t n);
+static size_t   writeMulti(const ModbusSlave_t *pSlave, uint8_t *pDst,
+                           size_t n);
+static size_t   writeSingle(const ModbusSlave_t *pSlave, uint8_t *pDst,
+                            size_t n);
+
+static size_t excResponse(uint8_t *pDst, uint8_t addr, uint8_t fn,
+                          ModbusExc_t exc) {
+  pDst[0] = addr;
+  pDst[1] = fn | 0x80u;
+  pDst[2] = (uint8_t)exc;
+  return putCRC(pDst, 3u);
+}
+
+static uint16_t getU16(const uint8_t *pSrc) {
+  return (uint16_t)((pSrc[0] << 8) | pSrc[1]);
+}
+
+static size_t handleFrame(ModbusSlave_t *pSlave, uint8_t *pDst) {
+  const size_t  n    = pSlave->n;
+  const uint8_t addr = pSlave->buf[0];
+  size_t        nOut = 0;
+
+  /* Smallest frame is address, function, and CRC */
+  if (n < 4u) {
+    return 0;
+  }
+
+  if (0 != modbusCRC16(pSlave->buf, n)) {
+    pSlave->stats.crcErrors++;
+    return 0;
+  }
+
+  if ((addr != pSlave->addr) && (ADDR_BROADCAST != addr)) {
+    return 0;
+  }
+
+  /* Lengths passed on do not include the CRC */
+  switch (pSlave->buf[1]) {
+  case FN_READ_HOLDING:
+  case FN_READ_INPUT:
+    nOut = readRegs(pSlave, pDst, (n - 2u));
+    break;
+  case FN_WRITE_SINGLE:
+    nOut = writeSingle(pSlave, pDst, (n - 2u));
+    break;
+  case FN_WRITE_MULTI:
+    nOut = writeMulti(pSlave, pDst, (n - 2u));
+    break;
+  default:
+    nOut = excResponse(pDst, pSlave->addr, pSlave->buf[1],
+                       MODBUS_EXC_ILLEGAL_FUNCTION);
+  }
+
+  /* Broadcasts are acted on but never answered */
+  if (ADDR_BROADCAST == addr) {
+    return 0;
+  }
+  pSlave->stats.frames++;
+  return nOut;
+}
+
+static size_t putCRC(uint8_t *pDst, size_t n) {
+  const uint16_t crc = modbusCRC16(pDst, n);
+  pDst[n]            = (uint8_t)crc;
+  pDst[n + 1u]       = (uint8_t)(crc >> 8);
+  return n + 2u;
+}
+
+static void putU16(uint8_t *pDst, uint16_t val) {
+  pDst[0] = (uint8_t)(val >> 8);
+  pDst[1] = (uint8_t)val;
+}
+
+/* 0x03 and 0x04: addr fn start(2) qty(2) */
+static size_t readRegs(const ModbusSlave_t *pSlave, uint8_t *pDst, size_t n) {
+  const uint8_t  fn    = pSlave->buf[1];
+  const uint16_t start = getU16(&pSlave->buf[2]);
+  const uint16_t qty   = getU16(&pSlave->buf[4]);
+  ModbusExc_t (*read)(uint16_t, uint16_t *) =
+      (FN_READ_INPUT == fn) ? pSlave->pMap->readInput
+                            : pSlave->pMap->readHolding;
+
+  if (6u != n) {
+    return excResponse(pDst, pSlave->addr, fn, MODBUS_EXC_ILLEGAL_VALUE);
+  }
+  if ((0 == qty) || (qty > READ_QTY_MAX)) {
+    return excResponse(pDst, pSlave->addr, fn, MODBUS_EXC_ILLEGAL_VALUE);
+  }
+  if (((uint32_t)start + qty) > 0x10000u) {
+    return excResponse(pDst, pSlave->addr, fn, MODBUS_EXC_ILLEGAL_ADDRESS);
+  }
+
+  pDst[0] = pSlave->addr;
+  pDst[1] = fn;
+  pDst[2] = (uint8_t)(qty * 2u);
+  for (uint16_t i = 0; i < qty; i++) {
+    uint16_t          val = 0;
+    const ModbusExc_t exc = read((uint16_t)(start + i), &val);
+    if (MODBUS_EXC_NONE != exc) {
+      return excResponse(pDst, pSlave->addr, fn, exc);
+    }
+    putU16(&pDst[3u + (2u * i)], val);
+  }
+  return putCRC(pDst, (3u + (2u * qty)));
+}
+
+/* 0x10: addr fn start(2) qty(2) count values(2 * qty) */
+static size_t writeMulti(const ModbusSlave_t *pSlave, uint8_t *pDst,
+                         size_t n) {
+  const uint16_t start = getU16(&pSlave->buf[2]);
+  const uint16_t qty   = getU16(&pSlave->buf[4]);
+  const uint8_t  count = pSlave->buf[6];
+
+  if ((n < 7u) || (0 == qty) || (qty > WRITE_QTY_MAX) ||
+      (count != (qty * 2u)) || (n != (7u + count))) {
+    return excResponse(pDst, pSlave->addr, FN_WRITE_MULTI,
+                       MODBUS_EXC_ILLEGAL_VALUE);
+  }
+  if (((uint32_t)start + qty) > 0x10000u) {
+    return excResponse(pDst, pSlave->addr, FN_WRITE_MULTI,
+                       MODBUS_EXC_ILLEGAL_ADDRESS);
+  }
+
+  for (uint16_t i = 0; i < qty; i++) {
+    const ModbusExc_t exc = pSlave->pMap->writeHolding(
+        (uint16_t)(start + i), getU16(&pSlave->buf[7u + (2u * i)]));
+    if (MODBUS_EXC_NONE != exc) {
+      return excResponse(pDst, pSlave->addr, FN_WRITE_MULTI, exc);
+    }
+  }
+
+  /* Echo the address, start, and quantity */
+  for (size_t i = 0; i < 6u; i++) {
+    pDst[i] = pSlave->buf[i];
+  }
+  return putCRC(pDst, 6u);
+}
+
+/* 0x06: addr fn reg(2) value(2) */
+static size_t writeSingle(const ModbusSlave_t *pSlave, uint8_t *pDst,
+                          size_t n) {
+  if (6u != n) {
+    return excResponse(pDst, pSlave->addr, FN_WRITE_SINGLE,
+                       MODBUS_EXC_ILLEGAL_VALUE);
+  }
+
+  const ModbusExc_t exc = pSlave->pMap->writeHolding(
+      getU16(&pSlave->buf[2]), getU16(&pSlave->buf[4]));
+  if (MODBUS_EXC_NONE != exc) {
+    return excResponse(pDst, pSlave->addr, FN_WRITE_SINGLE, exc);
+  }
+
+  /* The response echoes the request */
+  for (size_t i = 0; i < 6u; i++) {
+    pDst[i] = pSlave->buf[i];
+  }
+  return putCRC(pDst, 6u);
+}
+
+uint16_t modbusCRC16(const void *pSrc, size_t n) {
+  const uint8_t *pData = (const uint8_t *)pSrc;
+  uint16_t       crc   = 0xFFFFu;
+
+  while (n--) {
+    crc ^= *pData++;
+    for (size_t b = 0; b < 8u; b++) {
+      crc = (crc & 1u) ? (uint16_t)((crc >> 1) ^ 0xA001u) : (crc >> 1);
+    }
+  }
+  return crc;
+}
+
+void modbusInit(ModbusSlave_t *pSlave, const ModbusMap_t *pMap, uint8_t addr,
+                uint32_t baud) {
+  pSlave->pMap    = pMap;
+  pSlave->addr    = addr;
+  pSlave->gap_us  = (baud > 19200u) ? GAP_FIXED_US : (GAP_CHAR_BITS_US / baud);
+  pSlave->tLast   = 0;
+  pSlave->n       = 0;
+  pSlave->ready   = false;
+  pSlave->overrun = false;
+
+  pSlave->stats.frames    = 0;
+  pSlave->stats.crcErrors = 0;
+  pSlave->stats.overruns  = 0;
+}
+
+void modbusRxByte(ModbusSlave_t *pSlave, uint8_t c, uint32_t t_us) {
+  if (pSlave->ready) {
+    pSlave->stats.overruns++;
+    return;
+  }
+
+  /* A gap inside a frame means the rest was lost; start again */
+  if (pSlave->n && ((t_us - pSlave->tLast) >= pSlave->gap_us)) {
+    pSlave->n       = 0;
+    pSlave->overrun = false;
+  }
+  pSlave->tLast = t_us;
+
+  if (pSlave->n < MODBUS_FRAME_MAX) {
+    pSlave->buf[pSlave->n++] = c;
+  } else {
+    pSlave->overrun = true;
+    pSlave->stats.overruns++;
+  }
+}
+
+bool modbusFrameEnd(ModbusSlave_t *pSlave, uint32_t t_us) {
+  if (!pSlave->ready && pSlave->n &&
+      ((t_us - pSlave->tLast) >= pSlave->gap_us)) {
+    pSlave->ready = true;
+  }
+  return pSlave->ready;
+}
+
+size_t modbusReply(ModbusSlave_t *pSlave, uint8_t *pDst, size_t m) {
+  size_t nOut = 0;
+
+  if (!pSlave->ready) {
+    return 0;
+  }
+
+  if (!pSlave->overrun && (m >= MODBUS_FRAME_MAX)) {
+    nOut = handleFrame(pSlave, pDst);
+  }
+
+  pSlave->n       = 0;
+  pSlave->overrun = false;
+  pSlave->ready   = false;
+  return nOut;
+}
@@ -0,0 +1,89 @@
+#pragma once
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+/* Modbus RTU slave. Bytes are added one at a time as they are received, and a
+ * frame ends after 3.5 character times of silence. The register map is
+ * supplied by the application as callbacks.
+ */
+
+#define MODBUS_FRAME_MAX 256u /* RTU ADU limit, including address and CRC */
+
+typedef enum ModbusExc_ {
+  MODBUS_EXC_NONE             = 0x00u,
+  MODBUS_EXC_ILLEGAL_FUNCTION = 0x01u,
+  MODBUS_EXC_ILLEGAL_ADDRESS  = 0x02u,
+  MODBUS_EXC_ILLEGAL_VALUE    = 0x03u,
+  MODBUS_EXC_DEVICE_FAILURE   = 0x04u,
+  MODBUS_EXC_BUSY             = 0x06u
+} ModbusExc_t;
+
+typedef struct ModbusMap_ {
+  /* Read one input register (function 0x04) */
+  ModbusExc_t (*readInput)(uint16_t addr, uint16_t *pVal);
+  /* Read one holding register (function 0x03) */
+  ModbusExc_t (*readHolding)(uint16_t addr, uint16_t *pVal);
+  /* Write one holding register (functions 0x06 and 0x10) */
+  ModbusExc_t (*writeHolding)(uint16_t addr, uint16_t val);
+} ModbusMap_t;
+
+typedef struct ModbusStats_ {
+  uint32_t frames;    /* Frames addressed to this slave and answered */
+  uint32_t crcErrors; /* Frames dropped for a bad CRC */
+  uint32_t overruns;  /* Bytes dropped; frame too long or not yet handled */
+} ModbusStats_t;
+
+typedef struct ModbusSlave_ {
+  const ModbusMap_t *pMap;
+  uint8_t            addr;   /* Slave address, 1-247 */
+  uint32_t           gap_us; /* Silence that ends a frame */
+  uint32_t           tLast;  /* Time of the last byte (us) */
+  volatile size_t    n;      /* Bytes in the current frame */
+  volatile bool      ready;  /* Frame complete, waiting for modbusReply */
+  volatile bool      overrun;
+  uint8_t            buf[MODBUS_FRAME_MAX];
+  ModbusStats_t      stats;
+} ModbusSlave_t;
+
+/*! @brief Calculate the Modbus CRC16 (0xA001 reflected, initial 0xFFFF)
+ *  @param [in] pSrc : pointer to data
+ *  @param [in] n : number of bytes
+ *  @return CRC16 value; sent low byte first
+ */
+uint16_t modbusCRC16(const void *pSrc, size_t n);
+
+/*! @brief Initialise a slave
+ *  @param [out] pSlave : pointer to the slave
+ *  @param [in] pMap : register map callbacks
+ *  @param [in] addr : slave address, 1-247
+ *  @param [in] baud : line baud rate, to find the inter-frame gap
+ */
+void modbusInit(ModbusSlave_t *pSlave, const ModbusMap_t *pMap, uint8_t addr,
+                uint32_t baud);
+
+/*! @brief Add a received byte. Safe to call from the UART interrupt.
+ *  @param [in] pSlave : pointer to the slave
+ *  @param [in] c : received byte
+ *  @param [in] t_us : time the byte was received (us)
+ */
+void modbusRxByte(ModbusSlave_t *pSlave, uint8_t c, uint32_t t_us);
+
+/*! @brief Check if the frame being received has ended. Once true, further
+ *         bytes are dropped until modbusReply is called. Call with
+ *         modbusRxByte masked (e.g. interrupts disabled).
+ *  @param [in] pSlave : pointer to the slave
+ *  @param [in] t_us : current time (us)
+ *  @return true if a frame is ready for modbusReply
+ */
+bool modbusFrameEnd(ModbusSlave_t *pSlave, uint32_t t_us);
+
+/*! @brief Handle a ready frame and build the response. Frames for another
+ *         slave, broadcasts, and frames with a bad CRC have no response.
+ *  @param [in] pSlave : pointer to the slave
+ *  @param [out] pDst : response buffer
+ *  @param [in] m : width of pDst, at least MODBUS_FRAME_MAX
+ *  @return number of bytes to send, 0 if no response
+ */
+size_t modbusReply(ModbusSlave_t *pSlave, uint8_t *pDst, size_t m);
@@ -0,0 +1,153 @@
+#include "emon32_samd.h"
+
+#include "driver_SERCOM.h"
+#include "driver_TIME.h"
+
+#include "configuration.h"
+#include "emon_CM.h"
+#include "modbusMap.h"
+
+#include "qfplib-m0-full.h"
+
+static ModbusExc_t readHolding(uint16_t addr, uint16_t *pVal);
+static ModbusExc_t readInput(uint16_t addr, uint16_t *pVal);
+static uint16_t    scale100(const float val);
+static ModbusExc_t writeHolding(uint16_t addr, uint16_t val);
+
+static const ModbusMap_t      map      = {readInput, readHolding, writeHolding};
+static ModbusSlave_t          slave    = {0};
+static bool                   enabled  = false;
+static const Emon32Dataset_t *pDataset = 0;
+static uint8_t                txBuffer[MODBUS_FRAME_MAX];
+
+static ModbusExc_t readHolding(uint16_t addr, uint16_t *pVal) {
+  const ECMCfg_t *pCfg = ecmConfigGet();
+
+  if (addr < NUM_V) {
+    *pVal = scale100(pCfg->vCfg[addr].voltageCalRaw);
+  } else if (addr < VCT_TOTAL) {
+    *pVal = scale100(pCfg->ctCfg[addr - NUM_V].ctCalRaw);
+  } else if (MODBUS_HREG_CONTROL == addr) {
+    *pVal = 0;
+  } else {
+    return MODBUS_EXC_ILLEGAL_ADDRESS;
+  }
+  return MODBUS_EXC_NONE;
+}
+
+static ModbusExc_t readInput(uint16_t addr, uint16_t *pVal) {
+  if (addr >= MODBUS_IREG_NUM) {
+    return MODBUS_EXC_ILLEGAL_ADDRESS;
+  }
+
+  /* Diagnostics are available before the first report */
+  switch (addr) {
+  case MODBUS_IREG_FRAMES:
+    *pVal = (uint16_t)slave.stats.frames;
+    return MODBUS_EXC_NONE;
+  case MODBUS_IREG_CRC_ERR:
+    *pVal = (uint16_t)slave.stats.crcErrors;
+    return MODBUS_EXC_NONE;
+  case MODBUS_IREG_OVERRUN:
+    *pVal = (uint16_t)slave.stats.overruns;
+    return MODBUS_EXC_NONE;
+  case MODBUS_IREG_TX_DROP:
+    *pVal = (uint16_t)uartTxDropped();
+    return MODBUS_EXC_NONE;
+  }
+
+  if ((0 == pDataset) || (0 == pDataset->pECM)) {
+    return MODBUS_EXC_BUSY;
+  }
+
+  const ECMDataset_t *pECM = pDataset->pECM;
+
+  if (addr < MODBUS_IREG_FREQ) {
+    *pVal = scale100(pECM->rmsV[addr]);
+  } else if (MODBUS_IREG_FREQ == addr) {
+    *pVal = scale100(pECM->frequency);
+  } else if (addr < MODBUS_IREG_ENERGY) {
+    int32_t p = pECM->CT[addr - MODBUS_IREG_POWER].realPower;
+    if (p > INT16_MAX) {
+      p = INT16_MAX;
+    } else if (p < INT16_MIN) {
+      p = INT16_MIN;
+    }
+    *pVal = (uint16_t)(int16_t)p;
+  } else if (addr < MODBUS_IREG_MSG) {
+    const uint16_t idx = addr - MODBUS_IREG_ENERGY;
+    const uint32_t wh  = (uint32_t)pECM->CT[idx / 2u].wattHour;
+    *pVal              = (idx & 1u) ? (uint16_t)wh : (uint16_t)(wh >> 16);
+  } else {
+    const uint32_t msg = pDataset->msgNum;
+    *pVal = (MODBUS_IREG_MSG == addr) ? (uint16_t)(msg >> 16) : (uint16_t)msg;
+  }
+  return MODBUS_EXC_NONE;
+}
+
+/*! @brief Convert a non-negative value to an unsigned register in 0.01 units,
+ *         saturating at UINT16_MAX.
+ */
+static uint16_t scale100(const float val) {
+  const int32_t scaled = qfp_float2int_z(qfp_fmul(val, 100.0f));
+  if (scaled < 0) {
+    return 0;
+  }
+  return (scaled > UINT16_MAX) ? UINT16_MAX : (uint16_t)scaled;
+}
+
+static ModbusExc_t writeHolding(uint16_t addr, uint16_t val) {
+  if (addr < VCT_TOTAL) {
+    const float cal = qfp_fdiv(qfp_uint2float(val), 100.0f);
+    return configSetCalibration(addr, cal) ? MODBUS_EXC_NONE
+                                           : MODBUS_EXC_ILLEGAL_VALUE;
+  }
+
+  if (MODBUS_HREG_CONTROL == addr) {
+    if (MODBUS_CTRL_STORE == val) {
+      emon32EventSet(EVT_STORE_ACCUM);
+    } else if (MODBUS_CTRL_TRIGGER == val) {
+      emon32EventSet(EVT_ECM_TRIG);
+    } else {
+      return MODBUS_EXC_ILLEGAL_VALUE;
+    }
+    return MODBUS_EXC_NONE;
+  }
+
+  return MODBUS_EXC_ILLEGAL_ADDRESS;
+}
+
+void modbusMapInit(uint8_t addr, uint32_t baud) {
+  if ((0 == addr) || (addr > 247u)) {
+    return;
+  }
+  modbusInit(&slave, &map, addr, baud);
+  enabled = true;
+}
+
+bool modbusMapEnabled(void) { return enabled; }
+
+void modbusMapProcess(void) {
+  bool ready;
+
+  if (!enabled) {
+    return;
+  }
+
+  __disable_irq();
+  ready = modbusFrameEnd(&slave, timerMicros());
+  __enable_irq();
+
+  if (ready) {
+    const size_t n = modbusReply(&slave, txBuffer, sizeof(txBuffer));
+    if (n) {
+      (void)uartWrite(SERCOM_UART, txBuffer, n);
+    }
+  }
+}
+
+void modbusMapRxByte(uint8_t c) { modbusRxByte(&slave, c, timerMicros()); }
+
+const ModbusStats_t *modbusMapStats(void) { return &slave.stats; }
+
+void modbusMapUpdate(const Emon32Dataset_t *pData) { pDataset = pData; }
@@ -0,0 +1,72 @@
+#pragma once
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#include "emon32.h"
+#include "modbus.h"
+
+/* Modbus RTU register map, served on the hardware UART. See docs/configuration.md.
+ *
+ * Input registers (0x04):
+ *   0-2    : V1-V3 RMS voltage x100
+ *   3      : mains frequency x100, 0 if not measured
+ *   4-15   : CT1-12 real power (W), signed, saturated to 16 bits
+ *   16-39  : CT1-12 energy (Wh), signed 32 bit, high word first
+ *   40-41  : message number, high word first
+ *   42-45  : frames answered, CRC errors, overruns, UART Tx dropped
+ *
+ * Holding registers (0x03, 0x06, 0x10):
+ *   0-2    : V1-V3 calibration x100
+ *   3-14   : CT1-12 calibration x100
+ *   15     : control; write 1 to store accumulators, 2 to trigger a report
+ */
+
+#define MODBUS_IREG_V       0u
+#define MODBUS_IREG_FREQ    3u
+#define MODBUS_IREG_POWER   4u
+#define MODBUS_IREG_ENERGY  16u
+#define MODBUS_IREG_MSG     40u
+#define MODBUS_IREG_FRAMES  42u
+#define MODBUS_IREG_CRC_ERR 43u
+#define MODBUS_IREG_OVERRUN 44u
+#define MODBUS_IREG_TX_DROP 45u
+#define MODBUS_IREG_NUM     46u
+
+#define MODBUS_HREG_CAL     0u
+#define MODBUS_HREG_CONTROL 15u
+#define MODBUS_HREG_NUM     16u
+
+#define MODBUS_CTRL_STORE   1u
+#define MODBUS_CTRL_TRIGGER 2u
+
+/*! @brief Start the Modbus slave on the UART
+ *  @param [in] addr : slave address, 1-247. 0 leaves Modbus disabled.
+ *  @param [in] baud : UART baud rate
+ */
+void modbusMapInit(uint8_t addr, uint32_t baud);
+
+/*! @brief Indicate if the UART is used for Modbus
+ *  @return true if Modbus is enabled
+ */
+bool modbusMapEnabled(void);
+
+/*! @brief Handle a completed request and queue the response on the UART.
+ *         Call every 1 ms from the main loop.
+ */
+void modbusMapProcess(void);
+
+/*! @brief Add a byte received on the UART. Call from the UART interrupt.
+ *  @param [in] c : received byte
+ */
+void modbusMapRxByte(uint8_t c);
+
+/*! @brief Statistics for the slave
+ *  @return pointer to the statistics
+ */
+const ModbusStats_t *modbusMapStats(void);
+
+/*! @brief Set the dataset served in the input registers, after each report
+ *  @param [in] pData : pointer to the latest dataset
+ */
+void modbusMapUpdate(const Emon32Dataset_t *pData);
@@ -0,0 +1,46 @@
+#include "outQueue.h"
+
+void outQueueInit(OutQueue_t *pQ, uint8_t *pBuf, size_t depth,
+                  size_t (*write)(const void *pSrc, size_t n)) {
+  ringbufInit(&pQ->ring, pBuf, depth);
+  pQ->write = write;
+}
+
+void outQueueClear(OutQueue_t *pQ) {
+  ringbufSkip(&pQ->ring, ringbufCount(&pQ->ring));
+}
+
+size_t outQueueCount(const OutQueue_t *pQ) { return ringbufCount(&pQ->ring); }
+
+bool outQueuePut(OutQueue_t *pQ, const void *pSrc, size_t n) {
+  if (n > ringbufFree(&pQ->ring)) {
+    return false;
+  }
+  (void)ringbufWrite(&pQ->ring, pSrc, n);
+  return true;
+}
+
+size_t outQueueService(OutQueue_t *pQ, size_t budget) {
+  size_t sent = 0;
+
+  /* A wrapped ring is offered as two runs */
+  while (sent < budget) {
+    const uint8_t *pData;
+    size_t         n = ringbufPeek(&pQ->ring, &pData);
+
+    if (0 == n) {
+      break;
+    }
+    if (n > (budget - sent)) {
+      n = budget - sent;
+    }
+
+    const size_t taken = pQ->write(pData, n);
+    ringbufSkip(&pQ->ring, taken);
+    sent += taken;
+    if (taken < n) {
+      break;
+    }
+  }
+  return sent;
+}
@@ -0,0 +1,55 @@
+#pragma once
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#include "ringbuf.h"
+
+/* Output queued whole and sent a little at a time. Each message (a report,
+ * a status line) is added whole or not at all, so messages are never
+ * interleaved. outQueueService moves at most a budget of bytes to the sink,
+ * so a long report does not hold up other work; the rest is left for the next
+ * call.
+ */
+
+typedef struct OutQueue_ {
+  RingBuf_t ring;
+  size_t (*write)(const void *pSrc, size_t n); /* Sink, returns bytes taken */
+} OutQueue_t;
+
+/*! @brief Initialise (or empty) a queue
+ *  @param [out] pQ : pointer to the queue
+ *  @param [in] pBuf : backing storage, depth bytes
+ *  @param [in] depth : size of pBuf, must be a power of 2
+ *  @param [in] write : sink; it may take fewer bytes than offered, e.g. when
+ *                      its own buffer is full
+ */
+void outQueueInit(OutQueue_t *pQ, uint8_t *pBuf, size_t depth,
+                  size_t (*write)(const void *pSrc, size_t n));
+
+/*! @brief Discard anything waiting
+ *  @param [out] pQ : pointer to the queue
+ */
+void outQueueClear(OutQueue_t *pQ);
+
+/*! @brief Number of bytes waiting to be sent
+ *  @param [in] pQ : pointer to the queue
+ */
+size_t outQueueCount(const OutQueue_t *pQ);
+
+/*! @brief Add a message to the queue, only if all of it fits
+ *  @param [out] pQ : pointer to the queue
+ *  @param [in] pSrc : message
+ *  @param [in] n : number of bytes at pSrc
+ *  @return true if added, false if there was not room; nothing is added
+ */
+bool outQueuePut(OutQueue_t *pQ, const void *pSrc, size_t n);
+
+/*! @brief Send waiting bytes to the sink, stopping at the budget or when the
+ *         sink takes less than it was offered
+ *  @param [out] pQ : pointer to the queue
+ *  @param [in] budget : most bytes to send
+ *  @return number of bytes sent
+ */
+size_t outQueueService(OutQueue_t *pQ, size_t budget);
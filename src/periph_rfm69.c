@@ -3,6 +3,9 @@
 #include <string.h>
 
 #include "board_def.h"
+#include "driver_EIC.h"
+#include "driver_PORT.h"
+#include "driver_SAMD.h"
 #include "driver_SERCOM.h"
 #include "driver_TIME.h"
 #include "emon32.h"
@@ -22,6 +25,7 @@ typedef struct RFMRx_ {
 } RFMRx_t;
 
 static bool      rfmAckRecv(uint16_t fromId);
+static void      rfmDio0(uint8_t line);
 static void      rfmFreqToBand(const RFM_Freq_t freq, uint8_t *band);
 static void      rfmPacketHandler(void); /* LPL: interruptHandler */
 static uint8_t   rfmReadReg(const uint8_t addr);
@@ -33,6 +37,7 @@ static RFMSend_t rfmSendWithRetry(uint8_t n, const uint8_t retries,
                                   uint8_t *pRetryCount);
 static bool      rfmSetMode(RFMMode_t mode);
 static bool      rfmTxAvailable(void); /* LPL: canSend */
+static bool      rfmWaitDio0(const uint32_t tStart, const uint32_t timeout);
 static void      rfmWriteReg(const uint8_t addr, const uint8_t data);
 static uint8_t   spiRx(void);
 static void      spiTx(const uint8_t b);
@@ -43,7 +48,7 @@ static uint8_t       rfmBuffer[64] = {0};
 static int8_t        rfmMode       = 0;
 static RFMRx_t       rfmRx         = {0};
 static uint8_t       rxData[64]    = {0};
-static volatile bool rxRdy         = false;
+static volatile bool dio0          = false;
 static const Pin_t   rst           = {GRP_RFM_INTF, PIN_RFM_RST};
 static const Pin_t   sel           = {GRP_SERCOM_SPI, PIN_SPI_RFM_SS};
 
@@ -54,6 +59,14 @@ static bool rfmAckRecv(uint16_t fromId) {
   return false;
 }
 
+/*! @brief Interrupt callback for a rising edge on DIO0; PACKETSENT in Tx and
+ *         PAYLOADREADY in Rx.
+ */
+static void rfmDio0(uint8_t line) {
+  (void)line;
+  dio0 = true;
+}
+
 static void rfmFreqToBand(const RFM_Freq_t freq, uint8_t *band) {
   /* Default to 433.92 MHz */
   band[2] = RFM_FRFMSB_433;
@@ -147,6 +160,23 @@ static bool rfmTxAvailable(void) {
   return canSend;
 }
 
+/*! @brief Sleep until DIO0 rises, or the timeout expires. The millisecond
+ *         tick wakes the core, so the timeout is always checked.
+ *  @param [in] tStart : time the wait started
+ *  @param [in] timeout : timeout in ms
+ *  @return true if DIO0 rose, false if timed out
+ */
+static bool rfmWaitDio0(const uint32_t tStart, const uint32_t timeout) {
+  while (!dio0) {
+    if (timerMillisDelta(tStart) > timeout) {
+      return false;
+    }
+    samdSleepIdle();
+  }
+  dio0 = false;
+  return true;
+}
+
 static void rfmWriteReg(const uint8_t addr, const uint8_t data) {
   spiSelect(sel);
   /* Datasheet 5.2.1, Figure 24: "wnr is 1 for write" */
@@ -219,6 +249,7 @@ static void rfmRxBegin(void) {
                                     RFM_PACKET2_RXRESTART));
   }
 
+  dio0 = false;
   rfmWriteReg(REG_DIOMAPPING1, RFM_DIOMAPPING1_DIO0_01); // "PAYLOADREADY" in Rx
   (void)rfmSetMode(RFM69_MODE_RX);
 }
@@ -275,22 +306,27 @@ static RFMSend_t rfmSendWithRetry(uint8_t n, const uint8_t retries,
     spiDeSelect(sel);
 
     /* Enter Tx mode, no need to wait for the mode to be ready as the Tx will
-     * empty the FIFO when ready. */
+     * empty the FIFO when ready. DIO0 rises when the packet has been sent. */
     tStart = timerMillis();
+    dio0   = false;
+    rfmWriteReg(REG_DIOMAPPING1, RFM_DIOMAPPING1_DIO0_00); // "PACKETSENT" in Tx
     (void)rfmSetMode(RFM69_MODE_TX);
-    while (0 == (rfmReadReg(REG_IRQFLAGS2) & RFM_IRQFLAGS2_PACKETSENT)) {
-      if (timerMillisDelta(tStart > 25u)) {
-        return RFM_FUNCTIONAL_FAILURE;
-      }
+    if (!rfmWaitDio0(tStart, 25u)) {
+      return RFM_FUNCTIONAL_FAILURE;
     }
     (void)rfmSetMode(RFM69_MODE_STANDBY);
 
-    /* Listen for the acknowledgement */
+    /* Listen for the acknowledgement; only read a packet when DIO0 indicates
+     * one has been received. */
     const uint32_t tSent = timerMillis();
-    while (timerMillisDelta(tSent) < RFM_TIMEOUT) {
+    rfmRxBegin();
+    while (rfmWaitDio0(tSent, RFM_TIMEOUT)) {
       if (rfmAckRecv(5)) {
         return RFM_SUCCESS;
       }
+      if (RFM69_MODE_RX != rfmMode) {
+        rfmRxBegin();
+      }
     }
   }
   return RFM_TIMED_OUT;
@@ -339,8 +375,6 @@ static bool rfmSetMode(RFMMode_t mode) {
 
 uint8_t *rfmGetBuffer(void) { return rfmBuffer; }
 
-void rfmInterrupt(void) { rxRdy = true; }
-
 bool rfmInit(const RFMOpt_t *pOpt) {
 
   /* Immediately return if the interfaces are being externally controlled. */
@@ -426,6 +460,11 @@ bool rfmInit(const RFMOpt_t *pOpt) {
     }
   }
 
+  /* Packet sent and received are signalled on DIO0 */
+  portPinMux(GRP_RFM_INTF, PIN_RFM_IRQ, PMUX_RFM_IRQ);
+  eicCallbackLine(EXTINT_RFM_IRQ, &rfmDio0);
+  eicLineEnable(EXTINT_RFM_IRQ, EIC_SENSE_RISE);
+
   initDone = true;
   return true;
 }
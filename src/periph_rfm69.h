@@ -34,9 +34,6 @@ uint8_t *rfmGetBuffer(void);
  */
 bool rfmInit(const RFMOpt_t *pOpt);
 
-/*! @brief The interrupt handler for RFM69 receive */
-void rfmInterrupt(void);
-
 /*! @brief Send data through the RFM69
  *  @param [in] n : number of bytes to be sent
  *  @param [in] retries : number of retry attempts
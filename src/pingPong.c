@@ -0,0 +1,41 @@
+#include <string.h>
+
+#include "pingPong.h"
+
+void pingPongInit(PingPong_t *pPP) {
+  pPP->fill  = 0;
+  pPP->ready = PING_PONG_NONE;
+  pPP->proc  = PING_PONG_NONE;
+  (void)memset(&pPP->stats, 0, sizeof(pPP->stats));
+}
+
+void pingPongComplete(PingPong_t *pPP) {
+  const uint8_t done = pPP->fill;
+
+  pPP->fill = done ^ 1u;
+
+  /* The DMA has moved into a buffer that is still wanted */
+  if ((pPP->ready == pPP->fill) || (pPP->proc == pPP->fill)) {
+    pPP->stats.overruns++;
+  }
+  pPP->ready = done;
+}
+
+bool pingPongTake(PingPong_t *pPP, uint8_t *pIdx) {
+  if (PING_PONG_NONE == pPP->ready) {
+    return false;
+  }
+  *pIdx      = pPP->ready;
+  pPP->proc  = pPP->ready;
+  pPP->ready = PING_PONG_NONE;
+  pPP->stats.sets++;
+  return true;
+}
+
+void pingPongRelease(PingPong_t *pPP) { pPP->proc = PING_PONG_NONE; }
+
+void pingPongResync(PingPong_t *pPP) {
+  pPP->fill  = 0;
+  pPP->ready = PING_PONG_NONE;
+  pPP->stats.resyncs++;
+}
@@ -0,0 +1,60 @@
+#pragma once
+
+#include <stdbool.h>
+#include <stdint.h>
+
+/* Hand-off of the ADC's two sample set buffers (ping-pong) from the DMA to the
+ * processing. The DMA fills one buffer while the other is processed:
+ *   - Each completed set is taken once, in the order it was captured.
+ *   - If the DMA completes a set while the one before is still waiting or
+ *     being processed, the DMA is now writing into that older buffer. This is
+ *     an overrun; a waiting set is dropped, a set being processed is counted.
+ *   - After a transfer error or a lost conversion, samples are no longer
+ *     attributed to the right channel. The driver restarts the DMA and the ADC
+ *     scan from the start of buffer 0, and anything completed is discarded.
+ * The bookkeeping is kept here, away from the registers, so it can be tested;
+ * the ADC driver reports completions and restarts.
+ */
+
+#define PING_PONG_NONE 0xFFu
+
+typedef struct PingPongStats_ {
+  uint32_t sets;     /* Completed sets taken for processing */
+  uint32_t overruns; /* Sets overwritten before processing finished */
+  uint32_t resyncs;  /* Restarts to realign the channels */
+} PingPongStats_t;
+
+typedef struct PingPong_ {
+  uint8_t         fill;  /* Buffer the DMA is filling */
+  uint8_t         ready; /* Completed buffer waiting, or PING_PONG_NONE */
+  uint8_t         proc;  /* Buffer being processed, or PING_PONG_NONE */
+  PingPongStats_t stats;
+} PingPong_t;
+
+/*! @brief Initialise the state; the DMA starts on buffer 0
+ *  @param [out] pPP : pointer to the state
+ */
+void pingPongInit(PingPong_t *pPP);
+
+/*! @brief The DMA has completed the buffer it was filling, and moved on to
+ *         the other
+ *  @param [out] pPP : pointer to the state
+ */
+void pingPongComplete(PingPong_t *pPP);
+
+/*! @brief Take the oldest completed set for processing
+ *  @param [out] pPP : pointer to the state
+ *  @param [out] pIdx : index of the buffer to process
+ *  @return true if there was a completed set, false otherwise
+ */
+bool pingPongTake(PingPong_t *pPP, uint8_t *pIdx);
+
+/*! @brief Processing of the taken set has finished
+ *  @param [out] pPP : pointer to the state
+ */
+void pingPongRelease(PingPong_t *pPP);
+
+/*! @brief The DMA has been restarted at buffer 0; discard completed sets
+ *  @param [out] pPP : pointer to the state
+ */
+void pingPongResync(PingPong_t *pPP);
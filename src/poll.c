@@ -0,0 +1,180 @@
+#include <string.h>
+
+#include "poll.h"
+
+#define POLL_HDR_W 3u /* Response command, sequence number, and status */
+
+static size_t       encodeFrame(const uint8_t *pPayload, uint8_t n,
+                                uint8_t node, uint8_t *pDst, size_t m);
+static PollStatus_t handleCmd(PollSlave_t *pSlave, const FrameInfo_t *pInfo,
+                              uint8_t *pData, size_t *pN);
+
+/*! @brief Wrap a payload in a frame, and COBS encode it with the delimiter
+ *  @return number of bytes, 0 if it does not fit in m
+ */
+static size_t encodeFrame(const uint8_t *pPayload, const uint8_t n,
+                          const uint8_t node, uint8_t *pDst, const size_t m) {
+  uint8_t frame[POLL_FRAME_MAX];
+
+  const size_t nFrame = frameEncode(pPayload, n, node, frame, sizeof(frame));
+  if ((0 == nFrame) || (0 == m)) {
+    return 0;
+  }
+
+  const size_t w = cobsEncode(frame, nFrame, pDst, (m - 1u));
+  if (0 == w) {
+    return 0;
+  }
+  pDst[w] = COBS_DELIM;
+  return w + 1u;
+}
+
+/*! @brief Carry out a request addressed to this node
+ *  @param [in] pSlave : pointer to the slave
+ *  @param [in] pInfo : request frame, with at least the command and sequence
+ *  @param [out] pData : response data
+ *  @param [out] pN : length of the response data
+ *  @return status for the response
+ */
+static PollStatus_t handleCmd(PollSlave_t *pSlave, const FrameInfo_t *pInfo,
+                              uint8_t *pData, size_t *pN) {
+  const uint8_t *pArg = pInfo->pPayload + 2u;
+  const size_t   nArg = pInfo->n - 2u;
+  const size_t   m    = POLL_PAYLOAD_MAX - POLL_HDR_W;
+  const uint8_t  seq  = pInfo->pPayload[1];
+  uint16_t       val;
+  PollStatus_t   status;
+
+  *pN = 0;
+
+  switch (pInfo->pPayload[0]) {
+  case POLL_CMD_DATA:
+    if (1u != nArg) {
+      return POLL_ERR_ARG;
+    }
+    return pSlave->pMap->readData(pArg[0], pData, m, pN);
+
+  case POLL_CMD_DIAG:
+    return pSlave->pMap->readDiag(pData, m, pN);
+
+  case POLL_CMD_CAL_RD:
+    if (1u != nArg) {
+      return POLL_ERR_ARG;
+    }
+    status = pSlave->pMap->readCal(pArg[0], &val);
+    if (POLL_OK == status) {
+      pData[0] = (uint8_t)val;
+      pData[1] = (uint8_t)(val >> 8);
+      *pN      = 2u;
+    }
+    return status;
+
+  case POLL_CMD_CAL_WR:
+    if (3u != nArg) {
+      return POLL_ERR_ARG;
+    }
+    /* A retry of the last write was applied; the response was lost */
+    if (pSlave->wrValid && (seq == pSlave->wrSeq)) {
+      pSlave->stats.repeats++;
+      return POLL_OK;
+    }
+    val    = (uint16_t)(pArg[1] | (pArg[2] << 8));
+    status = pSlave->pMap->writeCal(pArg[0], val);
+    if (POLL_OK == status) {
+      pSlave->wrValid = true;
+      pSlave->wrSeq   = seq;
+    }
+    return status;
+  }
+
+  return POLL_ERR_CMD;
+}
+
+void pollInit(PollSlave_t *pSlave, const PollMap_t *pMap, uint8_t node) {
+  memset(pSlave, 0, sizeof(*pSlave));
+  pSlave->pMap = pMap;
+  pSlave->node = node;
+  cobsDecoderInit(&pSlave->dec, pSlave->rx, sizeof(pSlave->rx));
+}
+
+void pollRxByte(PollSlave_t *pSlave, uint8_t c) {
+  const CobsRx_t rx = cobsDecodeByte(&pSlave->dec, c);
+
+  if (COBS_RX_ERROR == rx) {
+    pSlave->stats.badFrames++;
+  } else if (COBS_RX_FRAME == rx) {
+    if (pSlave->ready) {
+      pSlave->stats.overruns++;
+      return;
+    }
+    memcpy(pSlave->req, pSlave->rx, pSlave->dec.n);
+    pSlave->n     = pSlave->dec.n;
+    pSlave->ready = true;
+  }
+}
+
+size_t pollReply(PollSlave_t *pSlave, uint8_t *pDst, size_t m) {
+  FrameInfo_t  info;
+  uint8_t      rsp[POLL_PAYLOAD_MAX];
+  size_t       nData;
+  size_t       w = 0;
+  PollStatus_t status;
+
+  if (!pSlave->ready) {
+    return 0;
+  }
+
+  /* Other nodes' requests and responses are on the bus too; stay silent */
+  if ((FRAME_OK != frameDecode(pSlave->req, pSlave->n, &info)) ||
+      (info.n < 2u)) {
+    pSlave->stats.badFrames++;
+  } else if ((pSlave->node == info.node) && !(info.pPayload[0] & POLL_REPLY)) {
+    status = handleCmd(pSlave, &info, (rsp + POLL_HDR_W), &nData);
+    if (POLL_OK != status) {
+      nData = 0;
+    }
+    rsp[0] = info.pPayload[0] | POLL_REPLY;
+    rsp[1] = info.pPayload[1];
+    rsp[2] = (uint8_t)status;
+
+    w = encodeFrame(rsp, (uint8_t)(POLL_HDR_W + nData), pSlave->node, pDst,
+                    m);
+    if (w) {
+      pSlave->stats.frames++;
+    }
+  }
+
+  pSlave->ready = false;
+  return w;
+}
+
+size_t pollRequest(uint8_t node, PollCmd_t cmd, uint8_t seq, const void *pArg,
+                   uint8_t nArg, uint8_t *pDst, size_t m) {
+  uint8_t req[POLL_PAYLOAD_MAX];
+
+  if (nArg > (POLL_PAYLOAD_MAX - 2u)) {
+    return 0;
+  }
+
+  req[0] = (uint8_t)cmd;
+  req[1] = seq;
+  if (nArg) {
+    memcpy((req + 2u), pArg, nArg);
+  }
+  return encodeFrame(req, (uint8_t)(2u + nArg), node, pDst, m);
+}
+
+PollCheck_t pollReplyCheck(const uint8_t *pSrc, size_t n, uint8_t node,
+                           PollCmd_t cmd, uint8_t seq, FrameInfo_t *pInfo) {
+  if ((FRAME_OK != frameDecode(pSrc, n, pInfo)) || (pInfo->n < POLL_HDR_W)) {
+    return POLL_CHECK_FRAME;
+  }
+  if ((node != pInfo->node) || !(pInfo->pPayload[0] & POLL_REPLY)) {
+    return POLL_CHECK_NODE;
+  }
+  if (((cmd | POLL_REPLY) != pInfo->pPayload[0]) ||
+      (seq != pInfo->pPayload[1])) {
+    return POLL_CHECK_SEQ;
+  }
+  return POLL_CHECK_OK;
+}
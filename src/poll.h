@@ -0,0 +1,135 @@
+#pragma once
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#include "cobs.h"
+#include "frame.h"
+
+/* Polled request/response protocol for nodes sharing a bus, e.g. RS485. The
+ * master sends a request to one node, and only that node responds. Requests
+ * and responses are binary frames (frame.h), COBS encoded and followed by the
+ * delimiter (cobs.h). The frame's node ID is the addressed node, and the
+ * payload is:
+ *   request  : [cmd] [seq] [arguments]
+ *   response : [cmd | POLL_REPLY] [seq] [status] [data, if POLL_OK]
+ * The sequence number is chosen by the master and echoed in the response, so
+ * a late response to an earlier request is not mistaken for the current one.
+ *
+ * Commands (multi-byte values are little endian):
+ *   POLL_CMD_DATA   [range]      : latest report, packed as for RF; range is
+ *                                  0 CT1-6, 1 temperature/pulse, 2 CT7-12
+ *   POLL_CMD_DIAG                : diagnostic counters
+ *   POLL_CMD_CAL_RD [ch]         : calibration x100 (u16); V1-3, then CT1-12
+ *   POLL_CMD_CAL_WR [ch] [u16]   : set calibration x100. A write repeating the
+ *                                  previous write's sequence number is a retry,
+ *                                  and is answered without writing again.
+ */
+
+#define POLL_REPLY       0x80u /* Set in the command of a response */
+#define POLL_PAYLOAD_MAX 64u
+#define POLL_FRAME_MAX   (POLL_PAYLOAD_MAX + FRAME_OVERHEAD)
+
+/* Width of an encoded frame, including the delimiter */
+#define POLL_TX_MAX (COBS_ENC_MAX(POLL_FRAME_MAX) + 1u)
+
+typedef enum PollCmd_ {
+  POLL_CMD_DATA   = 0x01u,
+  POLL_CMD_DIAG   = 0x02u,
+  POLL_CMD_CAL_RD = 0x03u,
+  POLL_CMD_CAL_WR = 0x04u
+} PollCmd_t;
+
+typedef enum PollStatus_ {
+  POLL_OK,
+  POLL_ERR_CMD,  /* Unknown command */
+  POLL_ERR_ARG,  /* Argument missing or out of range */
+  POLL_ERR_BUSY  /* No data yet */
+} PollStatus_t;
+
+typedef enum PollCheck_ {
+  POLL_CHECK_OK,
+  POLL_CHECK_FRAME, /* Not a valid frame, e.g. a bad CRC */
+  POLL_CHECK_NODE,  /* Not a response from the addressed node */
+  POLL_CHECK_SEQ    /* Response to another command or sequence number */
+} PollCheck_t;
+
+typedef struct PollMap_ {
+  /* Copy the latest data for a range, setting the length in pN */
+  PollStatus_t (*readData)(uint8_t range, uint8_t *pDst, size_t m, size_t *pN);
+  /* Copy the diagnostic counters, setting the length in pN */
+  PollStatus_t (*readDiag)(uint8_t *pDst, size_t m, size_t *pN);
+  /* Read one calibration value x100 */
+  PollStatus_t (*readCal)(uint8_t ch, uint16_t *pVal);
+  /* Write one calibration value x100 */
+  PollStatus_t (*writeCal)(uint8_t ch, uint16_t val);
+} PollMap_t;
+
+typedef struct PollStats_ {
+  uint32_t frames;    /* Requests to this node answered */
+  uint32_t badFrames; /* Frames dropped for bad framing or CRC */
+  uint32_t overruns;  /* Frames dropped while a request was waiting */
+  uint32_t repeats;   /* Writes retried with the same sequence number */
+} PollStats_t;
+
+typedef struct PollSlave_ {
+  const PollMap_t *pMap;
+  uint8_t          node;    /* Node ID, 1-254 */
+  bool             wrValid; /* wrSeq holds the last write's sequence number */
+  uint8_t          wrSeq;
+  volatile bool    ready; /* Request in req, waiting for pollReply */
+  size_t           n;     /* Length of the request */
+  uint8_t          req[POLL_FRAME_MAX];
+  uint8_t          rx[POLL_FRAME_MAX];
+  CobsDecoder_t    dec;
+  PollStats_t      stats;
+} PollSlave_t;
+
+/*! @brief Initialise a slave
+ *  @param [out] pSlave : pointer to the slave
+ *  @param [in] pMap : command callbacks
+ *  @param [in] node : node ID, 1-254
+ */
+void pollInit(PollSlave_t *pSlave, const PollMap_t *pMap, uint8_t node);
+
+/*! @brief Add a received byte. Safe to call from the UART interrupt. A frame
+ *         that completes while a request is waiting is dropped.
+ *  @param [in] pSlave : pointer to the slave
+ *  @param [in] c : received byte
+ */
+void pollRxByte(PollSlave_t *pSlave, uint8_t c);
+
+/*! @brief Handle a waiting request and build the encoded response. Frames for
+ *         another node, responses, and bad frames have no response.
+ *  @param [in] pSlave : pointer to the slave
+ *  @param [out] pDst : response buffer
+ *  @param [in] m : width of pDst, at least POLL_TX_MAX
+ *  @return number of bytes to send, 0 if no response
+ */
+size_t pollReply(PollSlave_t *pSlave, uint8_t *pDst, size_t m);
+
+/*! @brief Build an encoded request, as a master
+ *  @param [in] node : node ID to address
+ *  @param [in] cmd : command
+ *  @param [in] seq : sequence number
+ *  @param [in] pArg : arguments, may be 0 if nArg is 0
+ *  @param [in] nArg : number of argument bytes
+ *  @param [out] pDst : destination buffer
+ *  @param [in] m : width of pDst
+ *  @return number of bytes to send, 0 if it does not fit
+ */
+size_t pollRequest(uint8_t node, PollCmd_t cmd, uint8_t seq, const void *pArg,
+                   uint8_t nArg, uint8_t *pDst, size_t m);
+
+/*! @brief Check a decoded frame is the response to a request, as a master
+ *  @param [in] pSrc : decoded frame (without COBS)
+ *  @param [in] n : frame length
+ *  @param [in] node : node ID that was addressed
+ *  @param [in] cmd : command that was sent
+ *  @param [in] seq : sequence number that was sent
+ *  @param [out] pInfo : frame; the payload starts with the response header
+ *  @return POLL_CHECK_OK if the response matches the request
+ */
+PollCheck_t pollReplyCheck(const uint8_t *pSrc, size_t n, uint8_t node,
+                           PollCmd_t cmd, uint8_t seq, FrameInfo_t *pInfo);
@@ -0,0 +1,139 @@
+#include <string.h>
+
+#include "emon32_samd.h"
+
+#include "driver_SERCOM.h"
+
+#include "configuration.h"
+#include "dataPack.h"
+#include "emon_CM.h"
+#include "pollMap.h"
+#include "util.h"
+
+#include "qfplib-m0-full.h"
+
+static PollStatus_t readCal(uint8_t ch, uint16_t *pVal);
+static PollStatus_t readData(uint8_t range, uint8_t *pDst, size_t m,
+                             size_t *pN);
+static PollStatus_t readDiag(uint8_t *pDst, size_t m, size_t *pN);
+static PollStatus_t writeCal(uint8_t ch, uint16_t val);
+
+static const PollMap_t map = {readData, readDiag, readCal, writeCal};
+
+static PollSlave_t            slave    = {0};
+static bool                   enabled  = false;
+static const Emon32Dataset_t *pDataset = 0;
+static uint8_t                txBuffer[POLL_TX_MAX];
+
+static PollStatus_t readCal(uint8_t ch, uint16_t *pVal) {
+  const ECMCfg_t *pCfg = ecmConfigGet();
+  float           cal;
+
+  if (ch < NUM_V) {
+    cal = pCfg->vCfg[ch].voltageCalRaw;
+  } else if (ch < VCT_TOTAL) {
+    cal = pCfg->ctCfg[ch - NUM_V].ctCalRaw;
+  } else {
+    return POLL_ERR_ARG;
+  }
+
+  const int32_t scaled = qfp_float2int_z(qfp_fmul(cal, 100.0f));
+  if (scaled < 0) {
+    *pVal = 0;
+  } else {
+    *pVal = (scaled > UINT16_MAX) ? UINT16_MAX : (uint16_t)scaled;
+  }
+  return POLL_OK;
+}
+
+static PollStatus_t readData(uint8_t range, uint8_t *pDst, size_t m,
+                             size_t *pN) {
+  if (range > PACKED_CT7_12) {
+    return POLL_ERR_ARG;
+  }
+  if ((0 == pDataset) || (0 == pDataset->pECM)) {
+    return POLL_ERR_BUSY;
+  }
+  if (m < sizeof(PackedDataCT_t)) {
+    return POLL_ERR_ARG;
+  }
+  *pN = dataPackPacked(pDataset, pDst, (PackedRange_t)range);
+  return POLL_OK;
+}
+
+static PollStatus_t readDiag(uint8_t *pDst, size_t m, size_t *pN) {
+  const ECMDiagnostics_t *pDiag = ecmDiagnostics();
+  UARTStats_t             uartStat;
+  PollDiag_t              diag;
+
+  if (m < sizeof(diag)) {
+    return POLL_ERR_ARG;
+  }
+
+  uartStats(&uartStat);
+  const uint32_t rxErr = uartStat.rxFrame + uartStat.rxParity +
+                         uartStat.rxOverrun;
+
+  diag.cycleOverrun = pDiag->cycleOverrun;
+  diag.cycleLong    = pDiag->cycleLong;
+  diag.zcRejected   = pDiag->zcRejected;
+  diag.eventDropped = pDiag->eventDropped;
+  diag.nonFinite    = pDiag->nonFinite;
+  diag.truncated    = dataPackTruncated();
+  diag.uartTxDrop   = uartStat.dropped;
+  diag.uartRxErr    = rxErr;
+  diag.frames       = slave.stats.frames;
+  diag.badFrames    = slave.stats.badFrames;
+
+  memcpy(pDst, &diag, sizeof(diag));
+  *pN = sizeof(diag);
+  return POLL_OK;
+}
+
+static PollStatus_t writeCal(uint8_t ch, uint16_t val) {
+  if (ch >= VCT_TOTAL) {
+    return POLL_ERR_ARG;
+  }
+  const float cal = qfp_fdiv(qfp_uint2float(val), 100.0f);
+  return configSetCalibration(ch, cal) ? POLL_OK : POLL_ERR_ARG;
+}
+
+void pollMapInit(uint8_t node) {
+  if (0 == node) {
+    return;
+  }
+
+  /* Spread the serial numbers over 1-254 so that boards on one bus are
+   * likely, but not certain, to differ. Check with the 'l' listing. */
+  if (POLL_NODE_SERIAL == node) {
+    uint32_t id[4];
+    for (size_t i = 0; i < 4u; i++) {
+      id[i] = getUniqueID(i);
+    }
+    node = (uint8_t)(1u + (utilCRC8(id, sizeof(id)) % 254u));
+  }
+
+  pollInit(&slave, &map, node);
+  enabled = true;
+}
+
+bool pollMapEnabled(void) { return enabled; }
+
+uint8_t pollMapNode(void) { return enabled ? slave.node : 0; }
+
+void pollMapProcess(void) {
+  if (!enabled) {
+    return;
+  }
+
+  const size_t n = pollReply(&slave, txBuffer, sizeof(txBuffer));
+  if (n) {
+    (void)uartWrite(SERCOM_UART, txBuffer, n);
+  }
+}
+
+void pollMapRxByte(uint8_t c) { pollRxByte(&slave, c); }
+
+const PollStats_t *pollMapStats(void) { return &slave.stats; }
+
+void pollMapUpdate(const Emon32Dataset_t *pData) { pDataset = pData; }
@@ -0,0 +1,62 @@
+#pragma once
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#include "emon32.h"
+#include "poll.h"
+
+/* Polled protocol (poll.h) served on the hardware UART. See
+ * docs/configuration.md. The POLL_CMD_DIAG response is PollDiag_t.
+ */
+
+#define POLL_NODE_SERIAL 255u /* Node ID derived from the serial number */
+
+typedef struct __attribute__((__packed__)) PollDiag_ {
+  uint32_t cycleOverrun;
+  uint32_t cycleLong;
+  uint32_t zcRejected;
+  uint32_t eventDropped;
+  uint32_t nonFinite;
+  uint32_t truncated;  /* Text reports truncated */
+  uint32_t uartTxDrop; /* UART bytes dropped, Tx buffer full */
+  uint32_t uartRxErr;  /* UART framing, parity, and overrun errors */
+  uint32_t frames;     /* Requests answered */
+  uint32_t badFrames;  /* Frames dropped for bad framing or CRC */
+} PollDiag_t;
+
+/*! @brief Start the polled protocol on the UART
+ *  @param [in] node : node ID, 1-254, or POLL_NODE_SERIAL. 0 leaves the
+ *                     protocol disabled.
+ */
+void pollMapInit(uint8_t node);
+
+/*! @brief Indicate if the UART is used for the polled protocol
+ *  @return true if enabled
+ */
+bool pollMapEnabled(void);
+
+/*! @brief Node ID in use, after any derivation from the serial number
+ *  @return node ID, 0 if disabled
+ */
+uint8_t pollMapNode(void);
+
+/*! @brief Handle a received request and queue the response on the UART.
+ *         Call every 1 ms from the main loop.
+ */
+void pollMapProcess(void);
+
+/*! @brief Add a byte received on the UART. Call from the UART interrupt.
+ *  @param [in] c : received byte
+ */
+void pollMapRxByte(uint8_t c);
+
+/*! @brief Statistics for the slave
+ *  @return pointer to the statistics
+ */
+const PollStats_t *pollMapStats(void);
+
+/*! @brief Set the dataset served by POLL_CMD_DATA, after each report
+ *  @param [in] pData : pointer to the latest dataset
+ */
+void pollMapUpdate(const Emon32Dataset_t *pData);
@@ -1,18 +1,54 @@
 #include <stddef.h>
 
 #include "board_def.h"
+#include "debounce.h"
+#include "driver_EIC.h"
 #include "driver_PORT.h"
 #include "driver_TIME.h"
 #include "emon32.h"
 #include "emon32_samd.h"
 #include "pulse.h"
 
-typedef enum PulseLvl_ { PULSE_LVL_LOW, PULSE_LVL_HIGH } PulseLvl_t;
+static volatile uint32_t pulseCount[NUM_OPA];
+static PulseCfg_t        pulseCfg[NUM_OPA];
+static Debounce_t        pulseDb[NUM_OPA];
 
-static uint8_t    pinValue[NUM_OPA];
-static uint32_t   pulseCount[NUM_OPA];
-static PulseCfg_t pulseCfg[NUM_OPA];
-static PulseLvl_t pulseLvlLast[NUM_OPA];
+static void pulseCountEdge(const size_t index);
+static void pulseEdge(uint8_t line);
+
+/*! @brief Count a debounced transition if it is on a configured edge. Called
+ *         with interrupts disabled, or from the interrupt.
+ */
+static void pulseCountEdge(const size_t index) {
+  const bool rising = pulseDb[index].level;
+
+  switch (pulseCfg[index].edge) {
+  case PULSE_EDGE_RISING:
+    if (rising) {
+      pulseCount[index]++;
+    }
+    break;
+  case PULSE_EDGE_FALLING:
+    if (!rising) {
+      pulseCount[index]++;
+    }
+    break;
+  case PULSE_EDGE_BOTH:
+    pulseCount[index]++;
+  }
+}
+
+/*! @brief Interrupt callback for an edge on an EXTINT line */
+static void pulseEdge(uint8_t line) {
+  for (size_t i = 0; i < NUM_OPA; i++) {
+    if (pulseCfg[i].active && (line == pulseCfg[i].extint)) {
+      const bool level = portPinValue(pulseCfg[i].grp, pulseCfg[i].pin);
+      if (debounceEdge(&pulseDb[i], level, timerMillis())) {
+        pulseCountEdge(i);
+      }
+    }
+  }
+}
 
 PulseCfg_t *pulseGetCfg(const size_t index) {
   /* If no pulse counters attached or index out of range, return 0 */
@@ -26,7 +62,18 @@ PulseCfg_t *pulseGetCfg(const size_t index) {
 void pulseInit(const size_t index) {
   const uint8_t opaPUs[] = {PIN_OPA1_PU, PIN_OPA2_PU};
 
-  const uint8_t pin = pulseCfg[index].pin;
+  const uint8_t pin    = pulseCfg[index].pin;
+  const uint8_t extint = pulseCfg[index].extint;
+
+  /* Release the line; the pin may be used for OneWire */
+  if (EXTINT_NONE != extint) {
+    eicLineDisable(extint);
+    portPinMuxClear(GRP_OPA, pin);
+  }
+
+  if (!pulseCfg[index].active) {
+    return;
+  }
 
   /* Enable pull up if configured and allow a delay to charge RC */
   if (pulseCfg[index].puEn) {
@@ -41,54 +88,44 @@ void pulseInit(const size_t index) {
     portPinCfg(GRP_OPA, pin, PORT_PINCFG_PULLEN, PIN_CFG_SET);
     portPinDrv(GRP_OPA, pin, PIN_DRV_CLR);
   }
-  pinValue[index] = (uint8_t)portPinValue(GRP_OPA, pin);
 
   /* Use the first read value as the current state */
-  pulseLvlLast[index] = (PulseLvl_t)pinValue[index];
+  __disable_irq();
+  debounceInit(&pulseDb[index], pulseCfg[index].periods,
+               portPinValue(GRP_OPA, pin), timerMillis());
+  __enable_irq();
+
+  if (EXTINT_NONE != extint) {
+    eicCallbackLine(extint, &pulseEdge);
+    portPinMux(GRP_OPA, pin, PORT_PMUX_PMUXE_A_Val);
+    eicLineEnable(extint, EIC_SENSE_BOTH);
+  }
 }
 
 void pulseSetCount(const size_t index, const uint32_t value) {
+  __disable_irq();
   pulseCount[index] = value;
+  __enable_irq();
 }
 
+/* A word read is a single access, so no edge is lost by reading */
 uint32_t pulseGetCount(const size_t index) { return pulseCount[index]; }
 
 void pulseUpdate(void) {
-  uint32_t   mask;
-  PulseLvl_t level;
+  const uint32_t t = timerMillis();
 
   for (size_t i = 0; i < NUM_OPA; i++) {
     if (pulseCfg[i].active) {
-      mask  = (1 << pulseCfg[i].periods) - 1u;
-      level = pulseLvlLast[i];
-
-      pinValue[i] <<= 1;
-      pinValue[i] += (uint8_t)portPinValue(pulseCfg[i].grp, pulseCfg[i].pin);
-
-      if (0 == (pinValue[i] & mask)) {
-        level = PULSE_LVL_LOW;
-      } else if (mask == (pinValue[i] & mask)) {
-        level = PULSE_LVL_HIGH;
+      /* The interrupt changes the same state */
+      __disable_irq();
+      if (EXTINT_NONE == pulseCfg[i].extint) {
+        debounceSample(&pulseDb[i],
+                       portPinValue(pulseCfg[i].grp, pulseCfg[i].pin), t);
       }
-
-      switch (pulseCfg[i].edge) {
-      case PULSE_EDGE_RISING:
-        if ((PULSE_LVL_LOW == pulseLvlLast[i]) && (PULSE_LVL_HIGH == level)) {
-          pulseCount[i]++;
-        }
-        break;
-      case PULSE_EDGE_FALLING:
-        if ((PULSE_LVL_HIGH == pulseLvlLast[i]) && (PULSE_LVL_LOW == level)) {
-          pulseCount[i]++;
-        }
-        break;
-      case PULSE_EDGE_BOTH:
-        if (pulseLvlLast[i] != level) {
-          pulseCount[i]++;
-        }
+      if (debounceUpdate(&pulseDb[i], t)) {
+        pulseCountEdge(i);
       }
-
-      pulseLvlLast[i] = level;
+      __enable_irq();
     }
   }
 }
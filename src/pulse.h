@@ -13,7 +13,8 @@ typedef struct PulseCfg_ {
   PulseEdge_t edge;    /* Edge or edges to detect */
   uint8_t     grp;     /* GPIO group */
   uint8_t     pin;     /* GPIO pin */
-  uint32_t    periods; /* Blank period */
+  uint8_t     extint;  /* EXTINT line, or EXTINT_NONE to poll the pin */
+  uint32_t    periods; /* Debounce window (ms) */
   bool        active;  /* Channel active  */
   bool        puEn;    /* Pull up enabled */
 } PulseCfg_t;
@@ -24,12 +25,15 @@ typedef struct PulseCfg_ {
  */
 PulseCfg_t *pulseGetCfg(const size_t index);
 
-/*! Initialise a configured pulse counter
+/*! Initialise a configured pulse counter. Edges are taken by interrupt if
+ *  the channel has an EXTINT line. An inactive channel's line is released.
  *  @param [in] index : pulse counter index
  */
 void pulseInit(const size_t index);
 
-/*! @brief Update the pulse counter(s) */
+/*! @brief Update the pulse counter(s): sample polled inputs, and take changes
+ *         that settled inside the debounce window. Call every 1 ms.
+ */
 void pulseUpdate(void);
 
 /*! @brief Sets the pulse count value
@@ -38,7 +42,9 @@ void pulseUpdate(void);
  */
 void pulseSetCount(const size_t index, const uint32_t value);
 
-/*! @brief Get the current pulse count value
+/*! @brief Get the current pulse count value. The count is cumulative across
+ *         reports and resets, and wraps from UINT32_MAX to 0; consumers should
+ *         take the difference between readings with unsigned arithmetic.
  *  @return current pulse value
  */
 uint32_t pulseGetCount(const size_t index);
@@ -0,0 +1,76 @@
+#include "ringbuf.h"
+
+/* Indices run over [0, 2 * depth) */
+static size_t idxNext(const RingBuf_t *pRing, const size_t idx) {
+  return (idx + 1u) & ((pRing->depth << 1) - 1u);
+}
+
+void ringbufInit(RingBuf_t *pRing, uint8_t *pBuf, size_t depth) {
+  pRing->pBuf  = pBuf;
+  pRing->depth = depth;
+  pRing->idxWr = 0;
+  pRing->idxRd = 0;
+  pRing->peak  = 0;
+}
+
+size_t ringbufCount(const RingBuf_t *pRing) {
+  return (pRing->idxWr - pRing->idxRd) & ((pRing->depth << 1) - 1u);
+}
+
+size_t ringbufFree(const RingBuf_t *pRing) {
+  return pRing->depth - ringbufCount(pRing);
+}
+
+bool ringbufGet(RingBuf_t *pRing, uint8_t *pC) {
+  const size_t idxRd = pRing->idxRd;
+
+  if (idxRd == pRing->idxWr) {
+    return false;
+  }
+
+  *pC          = pRing->pBuf[idxRd & (pRing->depth - 1u)];
+  pRing->idxRd = idxNext(pRing, idxRd);
+  return true;
+}
+
+size_t ringbufPeak(const RingBuf_t *pRing) { return pRing->peak; }
+
+size_t ringbufPeek(const RingBuf_t *pRing, const uint8_t **ppData) {
+  const size_t pos    = pRing->idxRd & (pRing->depth - 1u);
+  const size_t count  = ringbufCount(pRing);
+  const size_t linear = pRing->depth - pos;
+
+  *ppData = &pRing->pBuf[pos];
+  return (count < linear) ? count : linear;
+}
+
+void ringbufSkip(RingBuf_t *pRing, size_t n) {
+  pRing->idxRd = (pRing->idxRd + n) & ((pRing->depth << 1) - 1u);
+}
+
+bool ringbufPut(RingBuf_t *pRing, uint8_t c) {
+  const size_t idxWr = pRing->idxWr;
+
+  if (0 == ringbufFree(pRing)) {
+    return false;
+  }
+
+  pRing->pBuf[idxWr & (pRing->depth - 1u)] = c;
+  pRing->idxWr                             = idxNext(pRing, idxWr);
+
+  const size_t count = ringbufCount(pRing);
+  if (count > pRing->peak) {
+    pRing->peak = count;
+  }
+  return true;
+}
+
+size_t ringbufWrite(RingBuf_t *pRing, const void *pSrc, size_t n) {
+  const uint8_t *pData = (const uint8_t *)pSrc;
+  size_t         added = 0;
+
+  while ((added < n) && ringbufPut(pRing, pData[added])) {
+    added++;
+  }
+  return added;
+}
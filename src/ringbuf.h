@@ -0,0 +1,79 @@
+#pragma once
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+/*! @brief Single producer, single consumer byte ring. The depth must be a
+ *         power of 2. Indices run over twice the depth so that a full ring
+ *         can be told apart from an empty one.
+ */
+typedef struct RingBuf_ {
+  uint8_t        *pBuf;
+  size_t          depth;
+  volatile size_t idxWr;
+  volatile size_t idxRd;
+  size_t          peak; /* Most bytes waiting at once, set by the producer */
+} RingBuf_t;
+
+/*! @brief Initialise (or empty) a ring
+ *  @param [out] pRing : pointer to the ring
+ *  @param [in] pBuf : backing storage, depth bytes
+ *  @param [in] depth : size of pBuf, must be a power of 2
+ */
+void ringbufInit(RingBuf_t *pRing, uint8_t *pBuf, size_t depth);
+
+/*! @brief Number of bytes waiting in the ring
+ *  @param [in] pRing : pointer to the ring
+ */
+size_t ringbufCount(const RingBuf_t *pRing);
+
+/*! @brief Number of bytes that can be added before the ring is full
+ *  @param [in] pRing : pointer to the ring
+ */
+size_t ringbufFree(const RingBuf_t *pRing);
+
+/*! @brief Take the oldest byte from the ring
+ *  @param [in] pRing : pointer to the ring
+ *  @param [out] pC : the byte, only valid if true is returned
+ *  @return true if a byte was taken, false if the ring was empty
+ */
+bool ringbufGet(RingBuf_t *pRing, uint8_t *pC);
+
+/*! @brief Most bytes that have been waiting in the ring at once since it was
+ *         initialised. Reaching the depth means a writer may have been
+ *         refused.
+ *  @param [in] pRing : pointer to the ring
+ */
+size_t ringbufPeak(const RingBuf_t *pRing);
+
+/*! @brief Find the run of waiting bytes that is contiguous in memory, starting
+ *         at the oldest. This lets a consumer (e.g. DMA) read straight from
+ *         the buffer; the bytes stay in the ring until ringbufSkip.
+ *  @param [in] pRing : pointer to the ring
+ *  @param [out] ppData : start of the run, only valid if non-zero is returned
+ *  @return number of bytes in the run, 0 if the ring is empty
+ */
+size_t ringbufPeek(const RingBuf_t *pRing, const uint8_t **ppData);
+
+/*! @brief Release bytes previously found with ringbufPeek
+ *  @param [in] pRing : pointer to the ring
+ *  @param [in] n : number of bytes to release, no more than ringbufPeek gave
+ */
+void ringbufSkip(RingBuf_t *pRing, size_t n);
+
+/*! @brief Add a byte to the ring
+ *  @param [in] pRing : pointer to the ring
+ *  @param [in] c : byte to add
+ *  @return true if added, false if the ring was full
+ */
+bool ringbufPut(RingBuf_t *pRing, uint8_t c);
+
+/*! @brief Add as many bytes as will fit. The rest are not added; the caller
+ *         decides what to do with them.
+ *  @param [in] pRing : pointer to the ring
+ *  @param [in] pSrc : bytes to add
+ *  @param [in] n : number of bytes at pSrc
+ *  @return number of bytes added
+ */
+size_t ringbufWrite(RingBuf_t *pRing, const void *pSrc, size_t n);
@@ -0,0 +1,54 @@
+#include "rs485.h"
+
+void rs485Init(Rs485_t *pBus, const uint32_t guard_us, void (*de)(bool on)) {
+  pBus->state    = RS485_RX;
+  pBus->guard_us = guard_us;
+  pBus->tRx_us   = 0;
+  pBus->de       = de;
+  pBus->de(false);
+}
+
+void rs485RxByte(Rs485_t *pBus, const uint32_t t_us) { pBus->tRx_us = t_us; }
+
+bool rs485TxStart(Rs485_t *pBus, const uint32_t t_us, uint32_t *pWait_us) {
+  *pWait_us = 0;
+
+  switch (pBus->state) {
+  case RS485_TX:
+    return true;
+  case RS485_DRAIN:
+    /* Queued again before TXC; DE is still asserted */
+    pBus->state = RS485_TX;
+    return true;
+  case RS485_RX:
+  case RS485_GUARD: {
+    const uint32_t quiet = t_us - pBus->tRx_us;
+    if (quiet < pBus->guard_us) {
+      pBus->state = RS485_GUARD;
+      *pWait_us   = pBus->guard_us - quiet;
+      return false;
+    }
+    pBus->de(true);
+    pBus->state = RS485_TX;
+    return true;
+  }
+  }
+  return false;
+}
+
+bool rs485TxEmpty(Rs485_t *pBus) {
+  if (RS485_TX != pBus->state) {
+    return false;
+  }
+  pBus->state = RS485_DRAIN;
+  return true;
+}
+
+bool rs485TxComplete(Rs485_t *pBus) {
+  if (RS485_DRAIN != pBus->state) {
+    return false;
+  }
+  pBus->de(false);
+  pBus->state = RS485_RX;
+  return true;
+}
@@ -0,0 +1,64 @@
+#pragma once
+
+#include <stdbool.h>
+#include <stdint.h>
+
+/* Half duplex RS485 driver enable (DE, with /RE tied to it). The transceiver
+ * drives the bus only while sending:
+ *   - DE is asserted when there is something to send, once the bus has been
+ *     quiet for the turnaround guard since the last received byte.
+ *   - When nothing is left to queue, DE is held until transmit complete
+ *     (TXC), i.e. after the last stop bit. Releasing on data register empty
+ *     (DRE) would clip the final byte.
+ * The state changes are kept here, away from the registers, so they can be
+ * tested; the UART driver supplies the time and the pin.
+ */
+
+typedef enum Rs485State_ {
+  RS485_RX,    /* DE released, receiving */
+  RS485_GUARD, /* Waiting for the turnaround guard before asserting DE */
+  RS485_TX,    /* DE asserted, sending */
+  RS485_DRAIN  /* Nothing left to queue, DE held until TXC */
+} Rs485State_t;
+
+typedef struct Rs485_ {
+  Rs485State_t state;
+  uint32_t     guard_us; /* Bus quiet time before asserting DE */
+  uint32_t     tRx_us;   /* Time of the last received byte */
+  void (*de)(bool on);   /* Drive the DE/RE pin */
+} Rs485_t;
+
+/*! @brief Initialise the state, and release DE
+ *  @param [out] pBus : pointer to the state
+ *  @param [in] guard_us : turnaround guard in microseconds
+ *  @param [in] de : drives the DE/RE pin; true asserts DE
+ */
+void rs485Init(Rs485_t *pBus, uint32_t guard_us, void (*de)(bool on));
+
+/*! @brief Note a received byte, which restarts the turnaround guard
+ *  @param [out] pBus : pointer to the state
+ *  @param [in] t_us : time now in microseconds
+ */
+void rs485RxByte(Rs485_t *pBus, uint32_t t_us);
+
+/*! @brief There is something to send. DE is asserted if the guard has passed.
+ *  @param [out] pBus : pointer to the state
+ *  @param [in] t_us : time now in microseconds
+ *  @param [out] pWait_us : if false is returned, the time until the guard
+ *                          has passed; call again then
+ *  @return true if sending can start, false if waiting for the guard
+ */
+bool rs485TxStart(Rs485_t *pBus, uint32_t t_us, uint32_t *pWait_us);
+
+/*! @brief Everything has been handed to the UART
+ *  @param [out] pBus : pointer to the state
+ *  @return true if DE is now held for TXC; enable the TXC interrupt
+ */
+bool rs485TxEmpty(Rs485_t *pBus);
+
+/*! @brief Transmit complete (TXC) after the last stop bit. DE is released
+ *         unless more has been queued since rs485TxEmpty.
+ *  @param [out] pBus : pointer to the state
+ *  @return true if DE was released
+ */
+bool rs485TxComplete(Rs485_t *pBus);
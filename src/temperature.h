@@ -4,6 +4,13 @@
 
 #define TEMP_CONVERSION_T 750 /* Minimum temperature sample time */
 
+/* Reported values (Q4 fixed point) when no valid temperature is available.
+ * These follow the OEM convention of out of range values >= 300°C.
+ */
+#define TEMP_Q4_ABSENT       4800 /* 300°C : no sensor in this slot */
+#define TEMP_Q4_OUT_OF_RANGE 4832 /* 302°C : sensor reading out of range */
+#define TEMP_Q4_FAILED       4864 /* 304°C : sensor read failed */
+
 typedef enum TEMP_INTF_ {
   TEMP_INTF_NONE,
   TEMP_INTF_ONEWIRE,
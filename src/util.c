@@ -1,9 +1,22 @@
 #include <stdbool.h>
 #include <stdio.h>
+#include <string.h>
 
 #include "util.h"
 
+#ifndef HOSTED
 #include "qfplib-m0-full.h"
+#else
+#include "emonCM_test.h"
+#endif /* HOSTED */
+
+/* Scale for each number of decimal places in utilFtoaDp */
+static const float ftoaScale[FTOA_DP_MAX + 1u] = {1.0f, 10.0f, 100.0f,
+                                                  1000.0f};
+
+/* Largest magnitude that fits in uint32 when scaled by ftoaScale */
+static const float ftoaScaledMax[FTOA_DP_MAX + 1u] = {
+    4294967295.0f, 429496729.0f, 42949672.0f, 4294967.0f};
 
 static bool isnumeric(const char c);
 
@@ -65,6 +78,37 @@ size_t utilUtoa(char *pBuf, uint32_t val, const ITOA_BASE_t base) {
   return len + 1u;
 }
 
+size_t utilLineCheck(char *pLine, size_t n, const size_t m,
+                     const LineCheck_t check) {
+  const char hex[] = "0123456789ABCDEF";
+  uint8_t    sum;
+
+  if ((LINE_CHECK_NONE == check) || (m < 6u)) {
+    return n;
+  }
+
+  /* Strip the line ending, and leave room for "*XX\r\n" and the null */
+  if ((n > 0) && ('\n' == pLine[n - 1u])) {
+    n--;
+  }
+  if ((n > 0) && ('\r' == pLine[n - 1u])) {
+    n--;
+  }
+  if (n > (m - 6u)) {
+    n = m - 6u;
+  }
+
+  sum = (LINE_CHECK_CRC8 == check) ? utilCRC8(pLine, n) : utilXor8(pLine, n);
+
+  pLine[n++] = '*';
+  pLine[n++] = hex[sum >> 4];
+  pLine[n++] = hex[sum & 0xFu];
+  pLine[n++] = '\r';
+  pLine[n++] = '\n';
+  pLine[n]   = '\0';
+  return n;
+}
+
 size_t utilItoa(char *pBuf, int32_t val, const ITOA_BASE_t base) {
   if ((ITOA_BASE10 == base) && (val < 0)) {
     *pBuf = '-';
@@ -110,6 +154,43 @@ ConvUint_t utilAtoui(const char *pBuf, ITOA_BASE_t base) {
   return conv;
 }
 
+bool utilAtouiList(const char *pBuf, uint32_t *pDst, const size_t n,
+                   size_t *pNum) {
+  size_t num = 0;
+
+  *pNum = 0;
+  if (' ' == *pBuf) {
+    pBuf++;
+  }
+
+  while (*pBuf) {
+    uint32_t result = 0;
+    size_t   digits = 0;
+
+    while (isnumeric(*pBuf)) {
+      result = result * 10 + (uint32_t)(*pBuf - '0');
+      pBuf++;
+      digits++;
+    }
+    if ((0 == digits) || (num >= n)) {
+      return false;
+    }
+    pDst[num++] = result;
+
+    if (' ' == *pBuf) {
+      pBuf++;
+      if (0 == *pBuf) {
+        return false;
+      }
+    } else if (*pBuf) {
+      return false;
+    }
+  }
+
+  *pNum = num;
+  return true;
+}
+
 ConvInt_t utilAtoi(const char *pBuf, ITOA_BASE_t base) {
   bool isNegative = ('-' == *pBuf);
   if (isNegative) {
@@ -122,55 +203,125 @@ ConvInt_t utilAtoi(const char *pBuf, ITOA_BASE_t base) {
   return conv;
 }
 
+bool utilBaudReg(uint32_t baud, uint32_t fRef, uint16_t *pReg) {
+  /* Above f_ref / 16 the register would be negative */
+  if ((0 == baud) || ((uint64_t)baud * 16u > fRef)) {
+    return false;
+  }
+
+  /* 65536 * 16 = 2^20; round to the nearest register value */
+  const uint32_t scaled =
+      (uint32_t)((((uint64_t)baud << 20) + (fRef / 2u)) / fRef);
+  if (0 == scaled) {
+    return false;
+  }
+  const uint16_t reg = (uint16_t)(65536u - scaled);
+
+  /* Very low rates can not be resolved, so check the achieved rate */
+  const uint32_t achieved = (uint32_t)(((uint64_t)fRef * scaled) >> 20);
+  const uint32_t err =
+      (achieved > baud) ? (achieved - baud) : (baud - achieved);
+  if ((err * 100u) > baud) {
+    return false;
+  }
+
+  *pReg = reg;
+  return true;
+}
+
+uint8_t utilCRC8(const void *pSrc, size_t n) {
+  const uint8_t *pData = (const uint8_t *)pSrc;
+  uint8_t        crc   = 0;
+
+  while (n--) {
+    crc ^= *pData++;
+    for (size_t b = 0; b < 8u; b++) {
+      crc = (crc & 0x80u) ? (uint8_t)((crc << 1) ^ 0x07u)
+                          : (uint8_t)(crc << 1);
+    }
+  }
+  return crc;
+}
+
 bool utilCharPrintable(const char c) {
   /* Allow any printable character plus \r and \n */
   return (((c >= 32) && (c <= 126)) || ('\r' == c) || ('\n' == c));
 }
 
-size_t utilFtoa(char *pBuf, float val) {
-  char     buf[16]; /* Enough for -2147483648.99 + null */
-  char    *p = &buf[15];
-  uint32_t units;
-  uint32_t decimals;
-  bool     neg = false;
+size_t utilFtoa(char *pBuf, float val) { return utilFtoaDp(pBuf, val, 2u); }
+
+size_t utilFtoaDp(char *pBuf, float val, uint32_t dp) {
+  char        buf[16]; /* Enough for -4294967295.000 + null */
+  char       *p = &buf[15];
+  const char *pSrc;
+  uint32_t    units;
+  uint32_t    bits;
+  bool        neg = false;
 
   *p = '\0';
 
-  if (val < 0.0f) {
-    neg = true;
-    val = qfp_fmul(val, -1.0f);
+  if (dp > FTOA_DP_MAX) {
+    dp = FTOA_DP_MAX;
   }
 
-  /* Extract integer and fractional parts */
-  units    = (uint32_t)qfp_float2int_z(val);
-  decimals = (uint32_t)qfp_float2int_z(qfp_fmul(val, 100.0f)) - (units * 100);
+  /* Non-finite values have all the exponent bits set */
+  (void)memcpy(&bits, &val, sizeof(bits));
+  if (0x7F800000u == (bits & 0x7F800000u)) {
+    if (bits & 0x007FFFFFu) {
+      pSrc = "nan";
+    } else {
+      pSrc = (bits >> 31) ? "-inf" : "inf";
+    }
+  } else {
+    if (val < 0.0f) {
+      neg = true;
+      val = qfp_fmul(val, -1.0f);
+    }
 
-  /* Write decimals (always 2 digits) using fast division */
-  uint32_t q = fastDiv10(decimals);
-  *--p       = (char)('0' + (decimals - q * 10));
-  *--p       = (char)('0' + q);
-  *--p       = '.';
+    if (val < ftoaScaledMax[dp]) {
+      /* Round half away from zero in the last place, so any carry propagates
+       * into the integer part (e.g. 9.996 -> 10.00) */
+      units = qfp_float2uint(qfp_fadd(qfp_fmul(val, ftoaScale[dp]), 0.5f));
 
-  /* Write integer part */
-  if (units == 0) {
-    *--p = '0';
-  } else {
-    while (units != 0) {
-      q     = fastDiv10(units);
-      *--p  = (char)('0' + (units - q * 10));
-      units = q;
+      /* Write decimals using fast division */
+      for (uint32_t i = 0; i < dp; i++) {
+        const uint32_t q = fastDiv10(units);
+        *--p             = (char)('0' + (units - q * 10));
+        units            = q;
+      }
+    } else {
+      /* No fractional part is representable; clamp to the uint32 range */
+      units = (val < 4294967295.0f) ? qfp_float2uint(val) : UINT32_MAX;
+      for (uint32_t i = 0; i < dp; i++) {
+        *--p = '0';
+      }
+    }
+    if (dp) {
+      *--p = '.';
+    }
+
+    /* Write integer part */
+    if (units == 0) {
+      *--p = '0';
+    } else {
+      while (units != 0) {
+        const uint32_t q = fastDiv10(units);
+        *--p             = (char)('0' + (units - q * 10));
+        units            = q;
+      }
     }
-  }
 
-  if (neg) {
-    *--p = '-';
+    if (neg) {
+      *--p = '-';
+    }
+    pSrc = p;
   }
 
   /* Copy to output buffer */
   char    *dst = pBuf;
   uint32_t len = 0;
-  while (*p) {
-    *dst++ = *p++;
+  while (*pSrc) {
+    *dst++ = *pSrc++;
     len++;
   }
   *dst = '\0';
@@ -224,3 +375,13 @@ ConvFloat_t utilAtof(const char *pBuf) {
   conv.valid = true;
   return conv;
 }
+
+uint8_t utilXor8(const void *pSrc, size_t n) {
+  const uint8_t *pData = (const uint8_t *)pSrc;
+  uint8_t        sum   = 0;
+
+  while (n--) {
+    sum ^= *pData++;
+  }
+  return sum;
+}
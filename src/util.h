@@ -6,6 +6,17 @@
 
 typedef enum ITOA_BASE_ { ITOA_BASE10, ITOA_BASE16 } ITOA_BASE_t;
 
+/* Checksum appended to text output lines. The values are stored in the
+ * configuration. */
+typedef enum LineCheck_ {
+  LINE_CHECK_NONE, /* No checksum */
+  LINE_CHECK_XOR,  /* NMEA style XOR of the payload */
+  LINE_CHECK_CRC8  /* CRC-8, polynomial 0x07, initial value 0 */
+} LineCheck_t;
+
+#define LINE_CHECK_W 3u /* Width of the "*XX" added by utilLineCheck */
+#define FTOA_DP_MAX  3u /* Most decimal places from utilFtoaDp */
+
 typedef struct ConvFloat_ {
   bool  valid; /* true if the value in val is valid */
   float val;   /* converted float value */
@@ -49,6 +60,37 @@ ConvInt_t utilAtoi(const char *pBuf, ITOA_BASE_t base);
  */
 ConvUint_t utilAtoui(const char *pBuf, ITOA_BASE_t base);
 
+/*! @brief Convert a list of unsigned decimal values separated by single
+ *         spaces, such as the values of "jp 2 0 0 3". A leading space is
+ *         skipped. Every value must have a digit, so a doubled or trailing
+ *         space is not valid.
+ *  @param [in] pBuf : pointer to the null terminated list
+ *  @param [out] pDst : converted values
+ *  @param [in] n : most values in the list
+ *  @param [out] pNum : number of values converted, 0 for an empty list
+ *  @return true if the list is valid, false if not or longer than n
+ */
+bool utilAtouiList(const char *pBuf, uint32_t *pDst, const size_t n,
+                   size_t *pNum);
+
+/*! @brief Calculate the SERCOM USART BAUD register for asynchronous
+ *         arithmetic mode with 16x oversampling:
+ *           BAUD = 65536 * (1 - 16 * f_baud / f_ref)
+ *  @param [in] baud : requested baud rate
+ *  @param [in] fRef : SERCOM reference clock (Hz)
+ *  @param [out] pReg : register value, only valid if true is returned
+ *  @return true if the achieved rate is within 1 % of baud, false otherwise
+ */
+bool utilBaudReg(uint32_t baud, uint32_t fRef, uint16_t *pReg);
+
+/*! @brief Calculate the CRC-8 (polynomial 0x07, initial value 0, not
+ *         reflected) of a buffer
+ *  @param [in] pSrc : pointer to data
+ *  @param [in] n : number of bytes
+ *  @return CRC-8 value
+ */
+uint8_t utilCRC8(const void *pSrc, size_t n);
+
 /*! @brief Indicate if a character is printable
  *  @param [in] c : character to check
  *  @return true if printable, false otherwise
@@ -56,13 +98,46 @@ ConvUint_t utilAtoui(const char *pBuf, ITOA_BASE_t base);
 bool utilCharPrintable(const char c);
 
 /*! @brief Convert float to null terminated base 10 string, with 2 dp.
- *         precision.
- *  @param [in] pBuf : pointer to string buffer, at least 11 characters
+ *         precision, rounded half away from zero. Non-finite values are
+ *         written as "nan", "inf", or "-inf", and magnitudes beyond the
+ *         uint32 range are clamped to 4294967295.00.
+ *  @param [in] pBuf : pointer to string buffer, at least 15 characters
  *  @param [in] val : value to convert
  *  @return the number of characters (including NULL).
  */
 size_t utilFtoa(char *pBuf, float val);
 
+/*! @brief As utilFtoa, with 0 to FTOA_DP_MAX decimal places. With 0 there is
+ *         no decimal point. Where the value is too large for all the places
+ *         to be represented, they are written as 0.
+ *  @param [in] pBuf : pointer to string buffer, at least 16 characters
+ *  @param [in] val : value to convert
+ *  @param [in] dp : decimal places, limited to FTOA_DP_MAX
+ *  @return the number of characters (including NULL).
+ */
+size_t utilFtoaDp(char *pBuf, float val, uint32_t dp);
+
+/*! @brief Append a checksum to a text line as "*XX\r\n", where XX is the
+ *         checksum of the payload in upper case hex. The payload is the line
+ *         up to any trailing "\r\n". If there is not room for the checksum,
+ *         the payload is shortened first, so the checksum always covers what
+ *         is sent. The line is null terminated.
+ *  @param [in,out] pLine : line to check, n characters
+ *  @param [in] n : number of characters in the line
+ *  @param [in] m : width of pLine, at least 6
+ *  @param [in] check : checksum to use; the line is unchanged for none
+ *  @return number of characters in the line, excluding the null
+ */
+size_t utilLineCheck(char *pLine, size_t n, const size_t m,
+                     const LineCheck_t check);
+
+/*! @brief Calculate the XOR of all bytes in a buffer
+ *  @param [in] pSrc : pointer to data
+ *  @param [in] n : number of bytes
+ *  @return XOR of the bytes
+ */
+uint8_t utilXor8(const void *pSrc, size_t n);
+
 /*! @brief Convert signed integer to null terminated string.
  *  @param [in] pBuf : pointer to string buffer, at least 12 characters
  *  @param [in] val : value to convert
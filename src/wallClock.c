@@ -0,0 +1,77 @@
+#include "wallClock.h"
+
+static uint32_t (*timeMillis)(void) = 0;
+static uint32_t baseEpoch           = 0; /* Unix time at baseMillis */
+static uint32_t baseMillis          = 0;
+
+/*! @brief Write v as n decimal digits, with leading zeros */
+static char *putDigits(char *pBuf, uint32_t v, size_t n) {
+  for (size_t i = n; i > 0; i--) {
+    pBuf[i - 1u] = (char)('0' + (v % 10u));
+    v /= 10u;
+  }
+  return pBuf + n;
+}
+
+void wallClockInit(uint32_t (*millis)(void)) {
+  timeMillis = millis;
+  baseEpoch  = 0;
+  baseMillis = 0;
+}
+
+size_t wallClockIso(char *pBuf, uint32_t epoch) {
+  /* Civil date from days since 1970-01-01, counting in 400 year eras from
+   * 0000-03-01 so that the leap day falls at the end of the year. See
+   * H. Hinnant, "chrono-Compatible Low-Level Date Algorithms".
+   */
+  const uint32_t secs = epoch % 86400u;
+  const uint32_t z    = (epoch / 86400u) + 719468u;
+  const uint32_t era  = z / 146097u;
+  const uint32_t doe  = z - (era * 146097u);
+  const uint32_t yoe =
+      (doe - (doe / 1460u) + (doe / 36524u) - (doe / 146096u)) / 365u;
+  const uint32_t doy   = doe - ((365u * yoe) + (yoe / 4u) - (yoe / 100u));
+  const uint32_t mp    = ((5u * doy) + 2u) / 153u;
+  const uint32_t day   = doy - (((153u * mp) + 2u) / 5u) + 1u;
+  const uint32_t month = (mp < 10u) ? (mp + 3u) : (mp - 9u);
+  const uint32_t year  = yoe + (era * 400u) + ((month <= 2u) ? 1u : 0u);
+
+  char *p = pBuf;
+  p       = putDigits(p, year, 4u);
+  *p++    = '-';
+  p       = putDigits(p, month, 2u);
+  *p++    = '-';
+  p       = putDigits(p, day, 2u);
+  *p++    = 'T';
+  p       = putDigits(p, secs / 3600u, 2u);
+  *p++    = ':';
+  p       = putDigits(p, (secs / 60u) % 60u, 2u);
+  *p++    = ':';
+  p       = putDigits(p, secs % 60u, 2u);
+  *p++    = 'Z';
+  *p      = 0;
+  return (size_t)(p - pBuf);
+}
+
+uint32_t wallClockNow(void) {
+  if (!wallClockValid()) {
+    return 0;
+  }
+
+  /* Move the base on by whole seconds, so the millisecond difference stays
+   * well inside its range however long the clock runs. */
+  const uint32_t secs = (timeMillis() - baseMillis) / 1000u;
+  baseEpoch += secs;
+  baseMillis += secs * 1000u;
+  return baseEpoch;
+}
+
+void wallClockSet(uint32_t epoch) {
+  if (0 == timeMillis) {
+    return;
+  }
+  baseMillis = timeMillis();
+  baseEpoch  = epoch;
+}
+
+bool wallClockValid(void) { return (0 != timeMillis) && (0 != baseEpoch); }
@@ -0,0 +1,40 @@
+#pragma once
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+/* Wall-clock time, kept as a Unix time set over the command interface plus
+ * the milliseconds elapsed since. Until it is set, output keeps its relative
+ * (since startup) time.
+ */
+
+#define WALL_CLOCK_ISO_W 21u /* "YYYY-MM-DDTHH:MM:SSZ" and null */
+
+/*! @brief Set the millisecond time source. The clock is unset.
+ *  @param [in] millis : time in milliseconds now
+ */
+void wallClockInit(uint32_t (*millis)(void));
+
+/*! @brief Format a Unix time as ISO-8601 UTC, e.g. "2025-01-31T12:00:00Z"
+ *  @param [out] pBuf : destination, at least WALL_CLOCK_ISO_W wide
+ *  @param [in] epoch : Unix time (s)
+ *  @return number of characters, excluding the null
+ */
+size_t wallClockIso(char *pBuf, uint32_t epoch);
+
+/*! @brief The current Unix time. Must be called at least every 49 days (the
+ *         millisecond time wraps); each report does so.
+ *  @return Unix time (s), 0 if the clock has not been set
+ */
+uint32_t wallClockNow(void);
+
+/*! @brief Set the clock. Times already sent are not changed.
+ *  @param [in] epoch : Unix time (s) now, non-zero
+ */
+void wallClockSet(uint32_t epoch);
+
+/*! @brief Indicate if the clock has been set
+ *  @return true if set
+ */
+bool wallClockValid(void);
@@ -0,0 +1,41 @@
+#include "wlRing.h"
+
+#ifndef HOSTED
+#include "driver_DMAC.h"
+#else
+#include "emonCM_test.h"
+#endif /* HOSTED */
+
+/*! @brief Read one record and check its CRC
+ *  @return true if the CRC matches
+ */
+static bool wlRingReadRecord(const WLRing_t *pRing, const uint32_t idx,
+                             void *pDst) {
+  const uint32_t addr = pRing->base + (idx * pRing->size);
+  WLHeader_t     header;
+
+  if (!pRing->read(addr, &header, sizeof(header))) {
+    return false;
+  }
+  if (!pRing->read((addr + sizeof(header)), pDst, pRing->dataLen)) {
+    return false;
+  }
+  return (header.crc16_ccitt == calcCRC16_ccitt(pDst, pRing->dataLen));
+}
+
+bool wlRingReadNewest(const WLRing_t *pRing, const uint32_t idxNewest,
+                      void *pDst, uint32_t *pIdx) {
+  uint32_t idx = idxNewest;
+
+  for (size_t n = 0; n < pRing->count; n++) {
+    /* Retry once; I2C reads can occasionally be corrupted */
+    if (wlRingReadRecord(pRing, idx, pDst) ||
+        wlRingReadRecord(pRing, idx, pDst)) {
+      *pIdx = idx;
+      return true;
+    }
+    idx = (0 == idx) ? (uint32_t)(pRing->count - 1u) : (idx - 1u);
+  }
+  *pIdx = idxNewest;
+  return false;
+}
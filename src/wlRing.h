@@ -0,0 +1,41 @@
+#pragma once
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+/* Records in the wear levelled ring of the EEPROM. Each record is a header,
+ * then the data. Every record written on one pass of the ring has the same
+ * valid byte, which changes on the next pass, so the newest record is the one
+ * before the first change. A write cut short (e.g. by a power loss) leaves a
+ * record whose CRC does not match; the records before it are still intact.
+ * The read is kept here, away from the I2C, so it can be tested.
+ */
+
+typedef struct __attribute__((__packed__)) WLHeader_ {
+  uint8_t  valid;
+  uint8_t  res0;
+  uint16_t crc16_ccitt; /* CRC16-CCITT of the data */
+} WLHeader_t;
+
+_Static_assert(sizeof(WLHeader_t) == 4,
+               "EEPROM wear limit header was not 4 bytes.");
+
+typedef struct WLRing_ {
+  uint32_t base;    /* Address of the first record */
+  size_t   count;   /* Number of records in the ring */
+  size_t   size;    /* Bytes per record, including the header */
+  size_t   dataLen; /* Bytes of data in each record */
+  bool (*read)(uint32_t addr, void *pDst, size_t n);
+} WLRing_t;
+
+/*! @brief Read the newest record with a good CRC. Starting from the newest,
+ *         each record is read twice before moving to the one before it.
+ *  @param [in] pRing : pointer to the ring
+ *  @param [in] idxNewest : index of the newest record
+ *  @param [out] pDst : data from the record, dataLen bytes
+ *  @param [out] pIdx : index of the record that was read
+ *  @return true if a record was good, false if none were
+ */
+bool wlRingReadNewest(const WLRing_t *pRing, uint32_t idxNewest, void *pDst,
+                      uint32_t *pIdx);
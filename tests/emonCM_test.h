@@ -1,6 +1,7 @@
 #pragma once
 
 #include <math.h>
+#include <stddef.h>
 #include <stdint.h>
 
 /* Redefine the QFPLIB functions to use native floats */
@@ -30,9 +31,26 @@ int qfp_float2int_z(float a) { return (int)a; }
 
 int qfp_float2int(float a) { return (int)a; }
 
+unsigned int qfp_float2uint(float a) { return (unsigned int)a; }
+
 /* Reference C implementations for asm_math functions */
 uint64_t usqr64(uint32_t x) { return (uint64_t)x * x; }
 
 uint64_t ssqr64(int32_t x) { return (uint64_t)((int64_t)x * x); }
 
 int64_t smul64(int32_t a, int32_t b) { return (int64_t)a * b; }
+
+/* Software CRC16-CCITT (0x1021, initial 0xFFFF), as the DMAC computes it */
+uint16_t calcCRC16_ccitt(const void *pSrc, size_t n) {
+  const uint8_t *pData = (const uint8_t *)pSrc;
+  uint16_t       crc   = 0xFFFF;
+
+  while (n--) {
+    crc ^= (uint16_t)(*pData++ << 8);
+    for (int b = 0; b < 8; b++) {
+      crc = (crc & 0x8000) ? (uint16_t)((crc << 1) ^ 0x1021)
+                           : (uint16_t)(crc << 1);
+    }
+  }
+  return crc;
+}
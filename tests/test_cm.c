@@ -25,6 +25,12 @@ typedef struct wave_ {
   double phi;    /* Phase (rad) */
   double s;      /* Scale (0 < s <= 1.0) */
   int    offset; /* Constant offset, clamped if outside range */
+  int    tStep;    /* Time (us) to change to sStep, ignored if 0 */
+  int    tStepEnd; /* Time (us) to return to s, ignored if 0 */
+  double sStep;    /* Scale after tStep */
+  int    hN;     /* Harmonic number, ignored if 0 */
+  double hS;     /* Harmonic scale, relative to the fundamental */
+  double pulse;  /* Zero the wave where |sin| is below this, ignored if 0 */
 } wave_t;
 
 typedef struct noise_ {
@@ -95,6 +101,174 @@ ECMDataset_t   *dataset;
 volatile RawSampleSetPacked_t *volatile smpRaw[2];
 wave_t wave[VCT_TOTAL];
 
+/* Called after each processed cycle when set, to model a load that follows
+ * the firmware's outputs.
+ */
+static void (*cycleHook)(void) = NULL;
+
+/* Called after each report when set */
+static void (*reportHook)(void) = NULL;
+
+/* Leave completed cycles queued until the report, to overrun the queue */
+static bool cycleSkip = false;
+
+/* Diverted load model: the grid CT sees the surplus as export, plus the load
+ * while the diversion output is on.
+ */
+static wave_t *pDivertWave;
+static double  divertSurplus;
+static double  divertLoad;
+static int     divertOnCycles;
+static int     divertAllCycles;
+static bool    divertLast;
+static int     divertChanges;
+static bool    divertEvtOk;
+
+/* First processed cycle after a flush, and if the engine was settled then */
+static int64_t settleFirst_us;
+static bool    settleFirstOk;
+
+static void settleModel(void) {
+  if (settleFirst_us < 0) {
+    settleFirst_us = tick;
+    settleFirstOk  = ecmSettled();
+  }
+}
+
+/* Sum of the interval energy of CT1 over the reports */
+static double intervalSum;
+
+static void intervalModel(void) { intervalSum += dataset->CT[0].intervalWh; }
+
+/* Report bookkeeping: sequence number, cycles, and sample sets */
+static uint32_t countSeq[4];
+static uint32_t countCycles[4];
+static uint32_t countSets[4];
+static int      countReportNum;
+
+/* Load profile stepped at each report, with the reported powers and times */
+static const double demandProfile[9] = {1.0, 1.0, 1.0, 3.5, 3.5,
+                                        1.0, 0.5, 0.5, 0.5};
+static double       demandP[9];
+static double       demandTotal[9];
+static double       demandTime[9];
+static uint32_t     demandSeq[9];
+static int          demandReportNum;
+static wave_t      *pDemandWave;
+
+static void demandModel(void) {
+  if (demandReportNum < 9) {
+    demandP[demandReportNum]     = dataset->CT[0].realPower;
+    demandTotal[demandReportNum] = dataset->totalPower;
+    demandTime[demandReportNum]  = dataset->wallTime;
+    demandSeq[demandReportNum]   = dataset->reportSeq;
+    demandReportNum++;
+  }
+  if (demandReportNum < 9) {
+    currentToWave(demandProfile[demandReportNum], 5, 0, pDemandWave);
+  }
+}
+
+/* Current ramped linearly on a CT each cycle */
+static wave_t *pRampWave;
+static double  rampS0;
+static double  rampPerCycle;
+static int     rampCycles;
+
+static void rampModel(void) {
+  rampCycles++;
+  pRampWave->s = rampS0 * (1.0 + (rampPerCycle * rampCycles));
+}
+
+/* CT fault for the first cycles of a run, then a good 1 A signal */
+static wave_t *pSensorWave;
+static double  sensorGoodS;
+static int     sensorCycles;
+
+static void sensorModel(void) {
+  if (150 == ++sensorCycles) {
+    pSensorWave->s      = sensorGoodS;
+    pSensorWave->offset = 0;
+  }
+}
+
+static void countModel(void) {
+  if (countReportNum < 4) {
+    countSeq[countReportNum]    = dataset->reportSeq;
+    countCycles[countReportNum] = dataset->cycleCount;
+    countSets[countReportNum]   = dataset->sampleSets;
+    countReportNum++;
+  }
+}
+
+/* Change the report period part way through a report: shorten it below the
+ * cycles already collected in the first report, and lengthen it in the third.
+ */
+static int periodCycle;
+
+static void periodModel(void) {
+  periodCycle++;
+  if ((0 == countReportNum) && (60 == periodCycle)) {
+    ecmConfigReportCycles(25);
+  } else if ((2 == countReportNum) && (10 == periodCycle)) {
+    ecmConfigReportCycles(50);
+  }
+}
+
+static void periodReportModel(void) {
+  countModel();
+  periodCycle = 0;
+}
+
+/* Reported frequency and validity, and the jitter on the voltage frequency */
+static float  freqReports[32];
+static bool   freqValidReports[32];
+static int    freqReportNum;
+static double freqJitter;
+
+static void freqModel(void) {
+  if (freqReportNum < 32) {
+    freqReports[freqReportNum]      = dataset->frequency;
+    freqValidReports[freqReportNum] = dataset->freqValid;
+    freqReportNum++;
+  }
+}
+
+/* Change the voltage frequency each cycle, keeping the phase continuous */
+static void freqJitterModel(void) {
+  const double u = (2.0 * random() / RAND_MAX) - 1.0;
+  const double f = MAINS_FREQ + (freqJitter * u);
+  for (int i = 0; i < NUM_V; i++) {
+    const double omega = 2 * M_PI * f;
+    wave[i].phi += (wave[i].omega - omega) * tick / 1000000.0;
+    wave[i].omega = omega;
+  }
+}
+
+static void divertModel(void) {
+  const bool   on  = ecmDivertActive();
+  const double net = (on ? divertLoad : 0.0) - divertSurplus;
+  ECMEvent_t   evt;
+
+  currentToWave(fabs(net), 5, (net < 0.0) ? 180.0 : 0.0, pDivertWave);
+  divertOnCycles += on ? 1 : 0;
+  divertAllCycles++;
+
+  /* Each change of the output must be queued as a single event */
+  if (on != divertLast) {
+    const bool evtGot = ecmEventGet(&evt);
+    if (!evtGot ||
+        (evt.type != (on ? ECM_EVT_DIVERT_ON : ECM_EVT_DIVERT_OFF))) {
+      divertEvtOk = false;
+    }
+    divertChanges++;
+    divertLast = on;
+  }
+  if (ecmEventGet(&evt)) {
+    divertEvtOk = false;
+  }
+}
+
 static uint32_t timeMicros(void) { return tick; }
 
 static uint32_t timeMicrosDelta(uint32_t tickPrev) {
@@ -152,8 +326,19 @@ static void dynamicRun(int reports, int prtReport, noise_t *noise, bool noVAC) {
     smpIdx = !smpIdx;
     ecmDataBufferSwap();
 
-    if (ECM_REPORT_COMPLETE == ecmInjectSample()) {
+    status = ecmInjectSample();
+    if ((ECM_CYCLE_COMPLETE == status) && cycleSkip) {
+      /* Cycles are left in the queue */
+    } else if (ECM_CYCLE_COMPLETE == status) {
+      (void)ecmProcessCycle();
+      if (cycleHook) {
+        cycleHook();
+      }
+    } else if (ECM_REPORT_COMPLETE == status) {
       dataset = ecmProcessSet();
+      if (reportHook) {
+        reportHook();
+      }
       if (prtReport > -1) {
         printReport(reportNum, tick, dataset, prtReport);
       }
@@ -222,6 +407,8 @@ int main(int argc, char *argv[]) {
   pEcmCfg->reportTime_us =
       (1000000 / pEcmCfg->mainsFreq) * pEcmCfg->reportCycles;
   pEcmCfg->assumedVrms     = 240;
+  pEcmCfg->assumedPF       = 1.0f;
+  pEcmCfg->phaseDeadband   = 5.0f;
   pEcmCfg->samplePeriod    = 13;
   pEcmCfg->timeMicros      = &timeMicros;
   pEcmCfg->timeMicrosDelta = &timeMicrosDelta;
@@ -372,6 +559,27 @@ int main(int argc, char *argv[]) {
   if (!checkDataset(dataset, 1.0f)) {
     return 1;
   }
+
+  /* Losing the voltage queues one event and flags the CTs as estimated, and
+   * both are reversed once the voltage returns. */
+  {
+    ECMEvent_t     evtLost     = {0};
+    ECMEvent_t     evtRestored = {0};
+    const uint32_t ctMask      = (1u << NUM_CT) - 1u;
+    const bool     lost        = ecmEventGet(&evtLost) &&
+                          (ECM_EVT_V_LOST == evtLost.type) &&
+                          ((dataset->estimatedCh & ctMask) == ctMask);
+    tick = 0;
+    dynamicRun(1, -1, &noise, false);
+    const bool restored = ecmEventGet(&evtRestored) &&
+                          (ECM_EVT_V_RESTORED == evtRestored.type) &&
+                          (0 == dataset->estimatedCh);
+    if (!lost || !restored || ecmEventGet(&evtRestored)) {
+      printf("\nLost: %d (%d) Restored: %d (%d) Estimated: 0x%x\n", lost,
+             evtLost.type, restored, evtRestored.type, dataset->estimatedCh);
+      return 1;
+    }
+  }
   printf("Done!\n");
 
   printf("    - 600 s report period ... ");
@@ -396,6 +604,1313 @@ int main(int argc, char *argv[]) {
   checkDataset(dataset, 1.0f);
   printf("Done!\n");
 
+  /* Step the load from 1.5 A to 3 A half way through a 2 s report. The
+   * maximum must follow the step, while the mean lies between the levels. */
+  printf("    - Load step min/max ...   ");
+  pEcmCfg->reportCycles = 100;
+  for (int i = NUM_V; i < VCT_TOTAL; i++) {
+    currentToWave(1.5, 5, 0, &wave[i]);
+    wave[i].tStep = 1000000;
+    wave[i].sStep = wave[i].s * 2.0;
+  }
+  tick = 0;
+  dynamicRun(1, -1, &noise, false);
+  {
+    const DataCT_t *pCT = &dataset->CT[0];
+    const int stepTol = pCT->realPowerMin / 10;
+    if ((pCT->realPowerMax < (2 * pCT->realPowerMin - stepTol)) ||
+        (pCT->realPowerMax > (2 * pCT->realPowerMin + stepTol)) ||
+        (pCT->realPower <= pCT->realPowerMin) ||
+        (pCT->realPower >= pCT->realPowerMax)) {
+      printf("\nP min: %d mean: %d max: %d\n", pCT->realPowerMin,
+             pCT->realPower, pCT->realPowerMax);
+      return 1;
+    }
+    if ((dataset->rmsVMin[0] > dataset->rmsV[0]) ||
+        (dataset->rmsVMax[0] < dataset->rmsV[0]) ||
+        ((dataset->rmsVMax[0] - dataset->rmsVMin[0]) > 5.0f)) {
+      printf("\nVrms min: %.2f mean: %.2f max: %.2f\n", dataset->rmsVMin[0],
+             dataset->rmsV[0], dataset->rmsVMax[0]);
+      return 1;
+    }
+  }
+  for (int i = NUM_V; i < VCT_TOTAL; i++) {
+    wave[i].tStep = 0;
+  }
+  printf("Done!\n");
+
+  /* A dip to 70 % for a single positive half-cycle. The full cycle's RMS
+   * voltage is 86 %, but the minimum must be that of the half-cycle. */
+  printf("    - Half-cycle dip ...      ");
+  wave[0].tStep    = 1000000;
+  wave[0].tStepEnd = 1010000;
+  wave[0].sStep    = wave[0].s * 0.7;
+  tick             = 0;
+  dynamicRun(1, -1, &noise, false);
+  if ((dataset->rmsVMin[0] > (VRMS_GOLD * 0.75f)) ||
+      (dataset->rmsVMin[0] < (VRMS_GOLD * 0.65f)) ||
+      (dataset->rmsVMax[0] > (VRMS_GOLD + 5.0f))) {
+    printf("\nVrms min: %.2f mean: %.2f max: %.2f\n", dataset->rmsVMin[0],
+           dataset->rmsV[0], dataset->rmsVMax[0]);
+    return 1;
+  }
+  wave[0].tStep    = 0;
+  wave[0].tStepEnd = 0;
+  printf("Done!\n");
+
+  /* With PF = 0.5, apparent energy accumulates at twice the real energy */
+  printf("    - PF 0.5 VAh/Wh ...       ");
+  fflush(stdout);
+  pEcmCfg->reportCycles = (unsigned int)(REPORT_TIME * MAINS_FREQ);
+  for (int i = NUM_V; i < VCT_TOTAL; i++) {
+    currentToWave(3.5, 5, 60.0, &wave[i]);
+  }
+  ecmClearEnergy();
+  tick = 0;
+  dynamicRun(4, -1, &noise, false);
+  if (!checkDataset(dataset, 0.5f) ||
+      (abs(dataset->CT[0].vaHour - (2 * dataset->CT[0].wattHour)) > 2)) {
+    printf("\nWh: %d VAh: %d\n", dataset->CT[0].wattHour,
+           dataset->CT[0].vaHour);
+    return 1;
+  }
+  printf("Done!\n");
+
+  printf("    - THD, clean sine ...     ");
+  fflush(stdout);
+  tick = 0;
+  dynamicRun(1, -1, &noise, false);
+  if (dataset->thdV[0] > 1.0f) {
+    printf("\nTHD: %.2f %%\n", dataset->thdV[0]);
+    return 1;
+  }
+  printf("Done!\n");
+
+  printf("    - THD, 5%% 3rd harm ...   ");
+  fflush(stdout);
+  wave[0].hN = 3;
+  wave[0].hS = 0.05;
+  tick       = 0;
+  dynamicRun(1, -1, &noise, false);
+  wave[0].hN = 0;
+  if ((dataset->thdV[0] < 4.5f) || (dataset->thdV[0] > 5.5f)) {
+    printf("\nTHD: %.2f %%\n", dataset->thdV[0]);
+    return 1;
+  }
+  printf("Done!\n");
+
+  /* A 3 cycle sag to 60 %, aligned to the mains cycles, must generate a
+   * single sag event. */
+  printf("    - Voltage sag event ...   ");
+  fflush(stdout);
+  pEcmCfg->reportCycles     = 100;
+  pEcmCfg->evtCfg.vNominal  = VRMS_GOLD;
+  pEcmCfg->evtCfg.sagPct    = 90.0f;
+  pEcmCfg->evtCfg.swellPct  = 110.0f;
+  pEcmCfg->evtCfg.minCycles = 1;
+  wave[0].tStep             = 1000000;
+  wave[0].tStepEnd          = 1060000;
+  wave[0].sStep             = wave[0].s * 0.6;
+  tick                      = 0;
+  dynamicRun(1, -1, &noise, false);
+  wave[0].tStep = 0;
+  {
+    ECMEvent_t evt;
+    int        numEvt = 0;
+    while (ecmEventGet(&evt)) {
+      numEvt++;
+      if ((ECM_EVT_SAG != evt.type) || (0 != evt.ch) || (3 != evt.cycles) ||
+          (evt.value < (0.6f * VRMS_GOLD - 5.0f)) ||
+          (evt.value > (0.6f * VRMS_GOLD + 5.0f))) {
+        printf("\nEvent: %d ch: %u cycles: %u value: %.2f\n", evt.type,
+               evt.ch, evt.cycles, evt.value);
+        return 1;
+      }
+    }
+    if (1 != numEvt) {
+      printf("\nEvents: %d\n", numEvt);
+      return 1;
+    }
+  }
+  pEcmCfg->evtCfg.vNominal = 0.0f;
+  printf("Done!\n");
+
+  /* Overcurrent with a limit equivalent to 2 A, scaled from a steady 1.5 A
+   * reading. CT1 trips once when stepped to 2.5 A. CT2 starts tripped, and
+   * dropping to 1.95 A is within the hysteresis so it is held. CT3 drops to
+   * 1 A, releasing, and trips again on returning to 2.5 A.
+   */
+  printf("    - Overcurrent events ...  ");
+  fflush(stdout);
+  for (int i = NUM_V; i < VCT_TOTAL; i++) {
+    currentToWave(1.5, 5, 0, &wave[i]);
+  }
+  tick = 0;
+  dynamicRun(1, -1, &noise, false);
+  {
+    const float iLimit = dataset->CT[0].rmsI * (2.0f / 1.5f);
+    for (int i = 0; i < 3; i++) {
+      wave_t *pW = &wave[NUM_V + pEcmCfg->mapCTLog[i]];
+      ecmConfigCurrentLimit(i, iLimit);
+      currentToWave((0 == i) ? 1.5 : 2.5, 5, 0, pW);
+      pW->tStep    = 500000;
+      pW->tStepEnd = 800000;
+    }
+    wave[NUM_V + pEcmCfg->mapCTLog[0]].sStep =
+        wave[NUM_V + pEcmCfg->mapCTLog[0]].s * (2.5 / 1.5);
+    wave[NUM_V + pEcmCfg->mapCTLog[1]].sStep =
+        wave[NUM_V + pEcmCfg->mapCTLog[1]].s * (1.95 / 2.5);
+    wave[NUM_V + pEcmCfg->mapCTLog[2]].sStep =
+        wave[NUM_V + pEcmCfg->mapCTLog[2]].s * (1.0 / 2.5);
+    tick = 0;
+    dynamicRun(1, -1, &noise, false);
+
+    const int  expTrips[3] = {1, 1, 2};
+    int        numTrips[3] = {0};
+    ECMEvent_t evt;
+    while (ecmEventGet(&evt)) {
+      if ((ECM_EVT_OVERCURRENT != evt.type) || (evt.ch > 2) ||
+          (evt.value < iLimit) || (evt.value > (iLimit * 1.3f))) {
+        printf("\nEvent: %d ch: %u value: %.2f\n", evt.type, evt.ch,
+               evt.value);
+        return 1;
+      }
+      numTrips[evt.ch]++;
+    }
+    for (int i = 0; i < 3; i++) {
+      if (expTrips[i] != numTrips[i]) {
+        printf("\nCT%d trips: %d\n", (i + 1), numTrips[i]);
+        return 1;
+      }
+      ecmConfigCurrentLimit(i, 0.0f);
+      wave[NUM_V + pEcmCfg->mapCTLog[i]].tStep = 0;
+    }
+  }
+  printf("Done!\n");
+
+  /* Detect 60 Hz mains from the first second of zero-crossings, and measure
+   * the same power as at 50 Hz. */
+  printf("    - 60 Hz auto detect ...   ");
+  fflush(stdout);
+  for (int i = NUM_V; i < VCT_TOTAL; i++) {
+    currentToWave(3.5, 5, 0, &wave[i]);
+  }
+  tick = 0;
+  dynamicRun(1, -1, &noise, false);
+  {
+    const int power50 = dataset->CT[0].realPower;
+
+    ecmConfigMainsFreq(0);
+    for (int i = 0; i < VCT_TOTAL; i++) {
+      wave[i].omega = 2 * M_PI * 60.0;
+    }
+    tick = 0;
+    dynamicRun(2, -1, &noise, false);
+    if ((60 != pEcmCfg->mainsFreq) || !checkDataset(dataset, 1.0f) ||
+        (abs(dataset->CT[0].realPower - power50) > (power50 / 100))) {
+      printf("\nf: %u P50: %d P60: %d\n", pEcmCfg->mainsFreq, power50,
+             dataset->CT[0].realPower);
+      return 1;
+    }
+  }
+  for (int i = 0; i < VCT_TOTAL; i++) {
+    wave[i].omega = 2 * M_PI * MAINS_FREQ;
+  }
+  ecmConfigMainsFreq(50);
+  printf("Done!\n");
+
+  /* With V1 disabled, power is estimated from the assumed voltage and PF.
+   * Enabling V1 again must return to measured power. */
+  printf("    - Assumed V and PF ...    ");
+  fflush(stdout);
+  pEcmCfg->assumedPF       = 0.9f;
+  pEcmCfg->vCfg[0].vActive = false;
+  ecmConfigChannel(0);
+  tick = 0;
+  dynamicRun(2, -1, &noise, false);
+  {
+    const DataCT_t *pCT = &dataset->CT[0];
+    const float     pEst = pCT->rmsI * VRMS_GOLD * 0.9f;
+    if (!(dataset->estimatedCh & 1u) || (pCT->pf != 0.9f) ||
+        (fabsf(pCT->realPower - pEst) > 1.0f)) {
+      printf("\nEstimated: 0x%x PF: %.2f P: %d\n", dataset->estimatedCh,
+             pCT->pf, pCT->realPower);
+      return 1;
+    }
+  }
+  pEcmCfg->vCfg[0].vActive = true;
+  ecmConfigChannel(0);
+  tick = 0;
+  dynamicRun(2, -1, &noise, false);
+  if ((0 != dataset->estimatedCh) || !checkDataset(dataset, 1.0f)) {
+    printf("\nEstimated: 0x%x\n", dataset->estimatedCh);
+    return 1;
+  }
+  pEcmCfg->assumedPF = 1.0f;
+  printf("Done!\n");
+
+  /* A sine has a crest factor of sqrt(2); keeping only the peaks of each half
+   * cycle, like a rectifier load, must give a much higher value. */
+  printf("    - Crest factor ...        ");
+  fflush(stdout);
+  tick = 0;
+  dynamicRun(1, -1, &noise, false);
+  if ((fabsf(dataset->crestV[0] - 1.414f) > 0.03f) ||
+      (fabsf(dataset->CT[0].crest - 1.414f) > 0.03f)) {
+    printf("\nCrest V: %.3f I: %.3f\n", dataset->crestV[0],
+           dataset->CT[0].crest);
+    return 1;
+  }
+  wave[NUM_V + pEcmCfg->mapCTLog[0]].pulse = 0.99;
+  tick                                      = 0;
+  dynamicRun(1, -1, &noise, false);
+  wave[NUM_V + pEcmCfg->mapCTLog[0]].pulse = 0.0;
+  if (dataset->CT[0].crest < 2.5f) {
+    printf("\nCrest I: %.3f\n", dataset->CT[0].crest);
+    return 1;
+  }
+  printf("Done!\n");
+
+  /* Clearing a single channel's energy must leave the others untouched */
+  printf("    - Clear channel energy .. ");
+  fflush(stdout);
+  ecmClearEnergy();
+  tick = 0;
+  dynamicRun(4, -1, &noise, false);
+  {
+    const int32_t wh0  = dataset->CT[0].wattHour;
+    const int32_t vah0 = dataset->CT[0].vaHour;
+    ecmClearEnergyChannel(1);
+    if ((0 == wh0) || (wh0 != dataset->CT[0].wattHour) ||
+        (vah0 != dataset->CT[0].vaHour) || (0 != dataset->CT[1].wattHour) ||
+        (0 != dataset->CT[1].vaHour)) {
+      printf("\nCT1 Wh: %d VAh: %d CT2 Wh: %d VAh: %d\n",
+             dataset->CT[0].wattHour, dataset->CT[0].vaHour,
+             dataset->CT[1].wattHour, dataset->CT[1].vaHour);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  /* Restoring a saved total must continue accumulating from it, matching a
+   * channel that was never cleared. */
+  printf("    - Restore energy ...      ");
+  fflush(stdout);
+  {
+    const int32_t whSaved = dataset->CT[2].wattHour;
+    ecmClearEnergyChannel(2);
+    ecmSetEnergyChannel(2, whSaved);
+    tick = 0;
+    dynamicRun(4, -1, &noise, false);
+    if ((dataset->CT[2].wattHour <= whSaved) ||
+        (abs(dataset->CT[2].wattHour - dataset->CT[3].wattHour) > 1)) {
+      printf("\nSaved: %d Restored: %d Reference: %d\n", whSaved,
+             dataset->CT[2].wattHour, dataset->CT[3].wattHour);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  /* Loading a stored configuration (sl/sd) while running reapplies the
+   * channels and frequency, but must not reset the accumulated energy. */
+  printf("    - Reload keeps energy ... ");
+  fflush(stdout);
+  {
+    int32_t whLoaded[NUM_CT];
+    for (int i = 0; i < NUM_CT; i++) {
+      whLoaded[i] = dataset->CT[i].wattHour;
+    }
+    ecmConfigReload(50);
+    tick = 0;
+    dynamicRun(1, -1, &noise, false);
+    for (int i = 0; i < NUM_CT; i++) {
+      if (dataset->CT[i].wattHour < whLoaded[i]) {
+        printf("\nCT%d Wh: %d Loaded: %d\n", (i + 1),
+               dataset->CT[i].wattHour, whLoaded[i]);
+        return 1;
+      }
+    }
+  }
+  printf("Done!\n");
+
+  /* Reactive power and quadrant with the current shifted by ±30° and
+   * reversed. A positive phase here leads the voltage. */
+  printf("    - Power quadrants ...     ");
+  fflush(stdout);
+  {
+    const double         phase[4] = {-30.0, -150.0, 150.0, 30.0};
+    const ECM_QUADRANT_t quad[4]  = {ECM_QUAD_I, ECM_QUAD_II, ECM_QUAD_III,
+                                     ECM_QUAD_IV};
+    for (int q = 0; q < 4; q++) {
+      for (int i = NUM_V; i < VCT_TOTAL; i++) {
+        currentToWave(3.5, 5, phase[q], &wave[i]);
+      }
+      tick = 0;
+      dynamicRun(1, -1, &noise, false);
+      const DataCT_t *pCT  = &dataset->CT[0];
+      const float     qExp = 0.5f * pCT->apparentPower;
+      if ((quad[q] != pCT->quadrant) ||
+          (fabsf(abs(pCT->reactivePower) - qExp) > (0.02f * qExp))) {
+        printf("\nPhase: %.0f Quadrant: %d P: %d Q: %d S: %d\n", phase[q],
+               pCT->quadrant, pCT->realPower, pCT->reactivePower,
+               pCT->apparentPower);
+        return 1;
+      }
+    }
+  }
+  printf("Done!\n");
+
+  /* Lead and lag with ±30° shifted current; 3° is within the dead band */
+  printf("    - Lead/lag ...            ");
+  fflush(stdout);
+  {
+    const double      phase[3] = {30.0, -30.0, 3.0};
+    const ECM_PHASE_t rel[3]   = {ECM_PHASE_LEAD, ECM_PHASE_LAG, ECM_PHASE_IN};
+    for (int p = 0; p < 3; p++) {
+      for (int i = NUM_V; i < VCT_TOTAL; i++) {
+        currentToWave(3.5, 5, phase[p], &wave[i]);
+      }
+      tick = 0;
+      dynamicRun(1, -1, &noise, false);
+      if (rel[p] != dataset->CT[0].phase) {
+        printf("\nPhase: %.0f Relation: %d\n", phase[p],
+               dataset->CT[0].phase);
+        return 1;
+      }
+    }
+  }
+  printf("Done!\n");
+
+  /* Threshold diversion on CT1 with levels scaled from a 1 A export. A 5
+   * cycle burst above the on threshold must hold the output on for the hold
+   * time; the band between thresholds must not change the output. */
+  printf("    - Threshold diversion ... ");
+  fflush(stdout);
+  {
+    ECMEvent_t evt;
+    wave_t    *pW = &wave[NUM_V + pEcmCfg->mapCTLog[0]];
+
+    while (ecmEventGet(&evt)) {
+    }
+    currentToWave(1.0, 5, 180.0, pW);
+    pW->tStep = 0;
+    tick      = 0;
+    dynamicRun(1, -1, &noise, false);
+    const float p1 = -dataset->CT[0].realPower;
+
+    pEcmCfg->divertCfg.mode       = ECM_DIVERT_THRESHOLD;
+    pEcmCfg->divertCfg.ch         = 0;
+    pEcmCfg->divertCfg.onExport   = 1.75f * p1;
+    pEcmCfg->divertCfg.offExport  = 1.25f * p1;
+    pEcmCfg->divertCfg.holdHalves = 20;
+
+    /* 1 A, with 2 A from 0.5 s to 0.6 s */
+    pW->tStep    = 500000;
+    pW->tStepEnd = 600000;
+    pW->sStep    = pW->s * 2.0;
+    tick         = 0;
+    dynamicRun(1, -1, &noise, false);
+    ECMEvent_t evtOn  = {0};
+    ECMEvent_t evtOff = {0};
+    if (!ecmEventGet(&evtOn) || !ecmEventGet(&evtOff) ||
+        ecmEventGet(&evt) || (ECM_EVT_DIVERT_ON != evtOn.type) ||
+        (ECM_EVT_DIVERT_OFF != evtOff.type) ||
+        ((evtOff.tStart_us - evtOn.tStart_us) < 200000u) ||
+        ((evtOff.tStart_us - evtOn.tStart_us) > 220000u)) {
+      printf("\nOn: %d @ %u Off: %d @ %u\n", evtOn.type, evtOn.tStart_us,
+             evtOff.type, evtOff.tStart_us);
+      return 1;
+    }
+
+    /* 1.5 A is between the thresholds, so must stay off and then on */
+    currentToWave(1.5, 5, 180.0, pW);
+    pW->tStep = 0;
+    tick      = 0;
+    dynamicRun(1, -1, &noise, false);
+    const bool bandOff = !ecmDivertActive() && !ecmEventGet(&evt);
+
+    pW->tStep    = 500000;
+    pW->tStepEnd = 1000000;
+    pW->sStep    = pW->s * (2.0 / 1.5);
+    tick         = 0;
+    dynamicRun(1, -1, &noise, false);
+    if (!bandOff || !ecmDivertActive() || !ecmEventGet(&evt) ||
+        (ECM_EVT_DIVERT_ON != evt.type) || ecmEventGet(&evt)) {
+      printf("\nBand off: %d Active: %d\n", bandOff, ecmDivertActive());
+      return 1;
+    }
+
+    /* A single half-cycle above the on threshold must switch on, though
+     * the cycle's power is below it */
+    currentToWave(0.5, 5, 180.0, pW);
+    pW->tStep = 0;
+    tick      = 0;
+    dynamicRun(1, -1, &noise, false);
+    while (ecmEventGet(&evt)) {
+    }
+
+    pW->tStep    = 500000;
+    pW->tStepEnd = 510000;
+    pW->sStep    = pW->s * 5.0;
+    tick         = 0;
+    dynamicRun(1, -1, &noise, false);
+    if (!ecmEventGet(&evt) || (ECM_EVT_DIVERT_ON != evt.type) ||
+        (evt.tStart_us < 490000u) || (evt.tStart_us > 520000u)) {
+      printf("\nHalf-cycle: %d @ %u\n", evt.type, evt.tStart_us);
+      return 1;
+    }
+    while (ecmEventGet(&evt)) {
+    }
+    pW->tStep               = 0;
+    pEcmCfg->divertCfg.mode = ECM_DIVERT_DISABLED;
+  }
+  printf("Done!\n");
+
+  /* Energy bucket diversion with a 3 A load. Over a long run the load must be
+   * on for the fraction of time given by surplus / load, and each burst must
+   * be queued as events.
+   */
+  printf("    - Bucket diversion ... ");
+  fflush(stdout);
+  {
+    const double surplus[] = {0.6, 1.5, 2.4};
+
+    pDivertWave = &wave[NUM_V + pEcmCfg->mapCTLog[0]];
+    currentToWave(1.0, 5, 180.0, pDivertWave);
+    pDivertWave->tStep = 0;
+    tick               = 0;
+    dynamicRun(1, -1, &noise, false);
+    const float p1 = -dataset->CT[0].realPower;
+
+    pEcmCfg->divertCfg.mode       = ECM_DIVERT_BUCKET;
+    pEcmCfg->divertCfg.ch         = 0;
+    pEcmCfg->divertCfg.holdHalves = 4;
+    pEcmCfg->divertCfg.bucketJ    = p1 * 0.5f;
+    divertLoad                    = 3.0;
+    divertLast                    = ecmDivertActive();
+    cycleHook                     = divertModel;
+
+    for (size_t i = 0; i < (sizeof(surplus) / sizeof(*surplus)); i++) {
+      divertSurplus   = surplus[i];
+      divertOnCycles  = 0;
+      divertAllCycles = 0;
+      divertChanges   = 0;
+      divertEvtOk     = true;
+      tick            = 0;
+      dynamicRun(4, -1, &noise, false);
+
+      const double duty = (double)divertOnCycles / divertAllCycles;
+      if ((fabs(duty - (surplus[i] / divertLoad)) > 0.03) || !divertEvtOk ||
+          (divertChanges < 2)) {
+        printf("\nSurplus: %.1f A Duty Gold: %.2f Test: %.2f Events: %d\n",
+               surplus[i], surplus[i] / divertLoad, duty, divertEvtOk);
+        return 1;
+      }
+    }
+    cycleHook               = NULL;
+    pEcmCfg->divertCfg.mode = ECM_DIVERT_DISABLED;
+  }
+  printf("Done!\n");
+
+  /* Settling: no cycle may be processed before the configured number of
+   * cycles after a flush, and the first report must be within spec.
+   */
+  printf("    - Settling ... ");
+  fflush(stdout);
+  {
+    currentToWave(3.5, 5, 0, &wave[NUM_V + pEcmCfg->mapCTLog[0]]);
+    pEcmCfg->settleCycles = 20;
+    ecmFlush();
+    const bool settledEarly = ecmSettled();
+
+    settleFirst_us = -1;
+    cycleHook      = settleModel;
+    tick           = 0;
+    dynamicRun(1, -1, &noise, false);
+    cycleHook = NULL;
+
+    if (settledEarly || !settleFirstOk || (settleFirst_us < 400000) ||
+        (settleFirst_us > 460000) || !checkDataset(dataset, 0.0f)) {
+      printf("\nSettled early: %d First cycle: %ld us\n", settledEarly,
+             (long)settleFirst_us);
+      return 1;
+    }
+    pEcmCfg->settleCycles = 0;
+  }
+  printf("Done!\n");
+
+  /* A non-finite calibration on CT1 must be reported as zero and counted,
+   * and must not poison the energy accumulated once it is corrected.
+   */
+  printf("    - Non-finite values ... ");
+  fflush(stdout);
+  {
+    CTCfg_t *pCT = &pEcmCfg->ctCfg[0];
+
+    tick = 0;
+    dynamicRun(1, -1, &noise, false);
+    const int32_t  powerGood = dataset->CT[0].realPower;
+    const float    ctCal     = pCT->ctCal;
+    const uint32_t nfStart   = ecmDiagnostics()->nonFinite;
+
+    pCT->ctCal = NAN;
+    tick       = 0;
+    dynamicRun(1, -1, &noise, false);
+    const bool    zeroed = (0 == dataset->CT[0].realPower) &&
+                           (0.0f == dataset->CT[0].rmsI);
+    const int32_t whNaN  = dataset->CT[0].wattHour;
+
+    pCT->ctCal = ctCal;
+    tick       = 0;
+    dynamicRun(1, -1, &noise, false);
+    const int32_t whDelta = dataset->CT[0].wattHour - whNaN;
+    const float   whGold  = powerGood * dataset->wallTime / 3600.0f;
+
+    if (!zeroed || (ecmDiagnostics()->nonFinite <= nfStart) ||
+        (abs(dataset->CT[0].realPower - powerGood) > 1) ||
+        (fabsf(whDelta - whGold) > 1.0f)) {
+      printf("\nZeroed: %d Count: %u Power: %d Gold: %d Wh: %d\n", zeroed,
+             ecmDiagnostics()->nonFinite - nfStart, dataset->CT[0].realPower,
+             powerGood, whDelta);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  /* Drive each fault path in turn and check that its counter moves, then
+   * clear the counters.
+   */
+  printf("    - Diagnostics ... ");
+  fflush(stdout);
+  {
+    const ECMDiagnostics_t *pDiag = ecmDiagnostics();
+    ECMEvent_t              evt;
+
+    ecmDiagnosticsReset();
+
+    /* Cycles are only consumed by the report, so the queue overruns */
+    cycleSkip = true;
+    tick      = 0;
+    dynamicRun(1, -1, &noise, false);
+    cycleSkip = false;
+    const uint32_t overrun = pDiag->cycleOverrun;
+
+    /* With no voltage, cycles are restarted at the sample limit */
+    tick = 0;
+    dynamicRun(1, -1, &noise, true);
+    const uint32_t cycleLong = pDiag->cycleLong;
+
+    /* At 100 Hz every other crossing is too soon after the last */
+    for (int i = 0; i < NUM_V; i++) {
+      wave[i].omega = 2 * M_PI * 100.0;
+    }
+    tick = 0;
+    dynamicRun(1, -1, &noise, false);
+    for (int i = 0; i < NUM_V; i++) {
+      wave[i].omega = 2 * M_PI * MAINS_FREQ;
+    }
+    const uint32_t zcRejected = pDiag->zcRejected;
+
+    /* An overcurrent trip on every CT overfills the event queue */
+    while (ecmEventGet(&evt)) {
+    }
+    for (int i = 0; i < NUM_CT; i++) {
+      pEcmCfg->evtCfg.iLimit[i] = 0.01f;
+    }
+    tick = 0;
+    dynamicRun(1, -1, &noise, false);
+    for (int i = 0; i < NUM_CT; i++) {
+      pEcmCfg->evtCfg.iLimit[i] = 0.0f;
+    }
+    while (ecmEventGet(&evt)) {
+    }
+    const uint32_t eventDropped = pDiag->eventDropped;
+
+    ecmDiagnosticsReset();
+    if ((0 == overrun) || (0 == cycleLong) || (0 == zcRejected) ||
+        (eventDropped != (NUM_CT - 8)) || (0 != pDiag->cycleOverrun) ||
+        (0 != pDiag->cycleLong) || (0 != pDiag->zcRejected) ||
+        (0 != pDiag->eventDropped) || (0 != pDiag->nonFinite)) {
+      printf("\nOverrun: %u Long: %u ZC: %u Dropped: %u\n", overrun,
+             cycleLong, zcRejected, eventDropped);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  /* Apparent only: with the PF set to the measured value, the estimate must
+   * match the fully measured channel. Invalid PFs are rejected. */
+  printf("    - Apparent only ... ");
+  fflush(stdout);
+  {
+    currentToWave(3.5, 5, 30.0, &wave[NUM_V + pEcmCfg->mapCTLog[0]]);
+    tick = 0;
+    dynamicRun(1, -1, &noise, false);
+    const DataCT_t measured = dataset->CT[0];
+
+    const bool rejected =
+        !ecmConfigApparentOnly(0, 1.5f) && !ecmConfigApparentOnly(0, -0.1f);
+    ecmConfigApparentOnly(0, measured.pf);
+    tick = 0;
+    dynamicRun(1, -1, &noise, false);
+    const DataCT_t estimated = dataset->CT[0];
+    const bool     flagged   = dataset->estimatedCh & 1u;
+    ecmConfigApparentOnly(0, 0.0f);
+
+    if (!rejected || !flagged ||
+        (abs(estimated.realPower - measured.realPower) >
+         (measured.realPower / 100)) ||
+        (abs(estimated.apparentPower - measured.apparentPower) > 1) ||
+        (fabsf(estimated.pf - measured.pf) > 0.001f)) {
+      printf("\nRejected: %d Flagged: %d P: %d Gold: %d S: %d Gold: %d\n",
+             rejected, flagged, estimated.realPower, measured.realPower,
+             estimated.apparentPower, measured.apparentPower);
+      return 1;
+    }
+    currentToWave(3.5, 5, 0, &wave[NUM_V + pEcmCfg->mapCTLog[0]]);
+  }
+  printf("Done!\n");
+
+  /* Three phase: CT1-6 are two per phase with the current in phase with the
+   * voltage, CT7 is on V1-V2, and the rest stay on V1. */
+  printf("    - Phase totals ... ");
+  fflush(stdout);
+  {
+    for (int i = 1; i < NUM_V; i++) {
+      pEcmCfg->vCfg[i].vActive = true;
+      ecmConfigChannel(i);
+    }
+    for (int i = 0; i < 6; i++) {
+      pEcmCfg->ctCfg[i].vChan1 = i / 2;
+      pEcmCfg->ctCfg[i].vChan2 = i / 2;
+      ecmConfigChannel(i + NUM_V);
+      currentToWave(1.0 + (0.5 * i), 5, 120.0 * (i / 2),
+                    &wave[NUM_V + pEcmCfg->mapCTLog[i]]);
+    }
+    pEcmCfg->ctCfg[6].vChan2 = 1;
+    ecmConfigChannel(6 + NUM_V);
+    currentToWave(2.0, 5, -30.0, &wave[NUM_V + pEcmCfg->mapCTLog[6]]);
+
+    tick = 0;
+    dynamicRun(1, -1, &noise, false);
+
+    int32_t pGold[NUM_V] = {0};
+    int32_t sGold[NUM_V] = {0};
+    float   iGold[NUM_V] = {0};
+    int32_t totalGold    = 0;
+    bool    inPhase      = true;
+    for (int i = 0; i < NUM_CT; i++) {
+      const DataCT_t *pCT = &dataset->CT[i];
+      const int       v   = pEcmCfg->ctCfg[i].vChan1;
+
+      totalGold += pCT->realPower;
+      if (v == pEcmCfg->ctCfg[i].vChan2) {
+        pGold[v] += pCT->realPower;
+        sGold[v] += pCT->apparentPower;
+        iGold[v] += pCT->rmsI;
+      }
+      inPhase = inPhase && (pCT->pf > 0.99f);
+    }
+
+    bool sumsOk = inPhase && (abs(totalGold - dataset->totalPower) <= NUM_CT);
+    for (int v = 0; v < NUM_V; v++) {
+      sumsOk = sumsOk && (pGold[v] == dataset->phasePower[v]) &&
+               (sGold[v] == dataset->phaseApparent[v]) &&
+               (fabsf(iGold[v] - dataset->phaseI[v]) < 0.01f);
+    }
+    /* CT3 and CT4 on V2 carry 2.0 A and 2.5 A, and CT5 and CT6 on V3 carry
+     * 3.0 A and 3.5 A, so the power scales with the summed current. */
+    const float ratio =
+        (float)dataset->phasePower[2] / (float)dataset->phasePower[1];
+    if (!sumsOk || (fabsf(ratio - (6.5f / 4.5f)) > 0.02f)) {
+      for (int v = 0; v < NUM_V; v++) {
+        printf("\nV%d P: %d Gold: %d", (v + 1), dataset->phasePower[v],
+               pGold[v]);
+      }
+      printf("\nIn phase: %d Total: %d Gold: %d Ratio: %.3f\n", inPhase,
+             dataset->totalPower, totalGold, ratio);
+      return 1;
+    }
+
+    for (int i = 1; i < NUM_V; i++) {
+      pEcmCfg->vCfg[i].vActive = false;
+      ecmConfigChannel(i);
+    }
+    for (int i = 0; i < 7; i++) {
+      pEcmCfg->ctCfg[i].vChan1 = 0;
+      pEcmCfg->ctCfg[i].vChan2 = 0;
+      ecmConfigChannel(i + NUM_V);
+      currentToWave(3.5, 5, 0, &wave[NUM_V + pEcmCfg->mapCTLog[i]]);
+    }
+  }
+  printf("Done!\n");
+
+  /* Net total: CT1 is the grid connection importing, CT2 a sub-circuit of
+   * CT1, and CT3 a generator exporting. Only CT1 and CT3 are totalised. */
+  printf("    - Net total ... ");
+  fflush(stdout);
+  {
+    currentToWave(3.5, 5, 0, &wave[NUM_V + pEcmCfg->mapCTLog[0]]);
+    currentToWave(1.5, 5, 0, &wave[NUM_V + pEcmCfg->mapCTLog[1]]);
+    currentToWave(2.0, 5, 180.0, &wave[NUM_V + pEcmCfg->mapCTLog[2]]);
+
+    ecmConfigTotalMask((1u << 0) | (1u << 2));
+    const int32_t importStart = dataset->importWh;
+    const int32_t exportStart = dataset->exportWh;
+    tick                      = 0;
+    dynamicRun(1, -1, &noise, false);
+    const int32_t netGold =
+        dataset->CT[0].realPower + dataset->CT[2].realPower;
+    const float   whImport = netGold * dataset->wallTime / 3600.0f;
+    const int32_t net      = dataset->totalPower;
+    const int32_t importWh = dataset->importWh - importStart;
+
+    /* Only the generator: the total is exported */
+    ecmConfigTotalMask(1u << 2);
+    tick = 0;
+    dynamicRun(1, -1, &noise, false);
+    const float whExport =
+        -dataset->CT[2].realPower * dataset->wallTime / 3600.0f;
+    const int32_t exportWh = dataset->exportWh - exportStart;
+    const bool importHeld = (dataset->importWh - importStart) == importWh;
+    ecmConfigTotalMask(0);
+
+    if ((netGold <= 0) || (abs(net - netGold) > 2) ||
+        (fabsf(importWh - whImport) > 1.0f) ||
+        (fabsf(exportWh - whExport) > 1.0f) || !importHeld) {
+      printf("\nNet: %d Gold: %d Import: %d (%.1f) Export: %d (%.1f)\n", net,
+             netGold, importWh, whImport, exportWh, whExport);
+      return 1;
+    }
+    for (int i = 0; i < 3; i++) {
+      currentToWave(3.5, 5, 0, &wave[NUM_V + pEcmCfg->mapCTLog[i]]);
+    }
+  }
+  printf("Done!\n");
+
+  /* Split phase: V2 is 180° from V1. CT1 is a 240 V load across both legs,
+   * CT2 a 120 V load on V1, and CT3 a 120 V load on V2, all at unity PF. */
+  printf("    - Split phase ... ");
+  fflush(stdout);
+  {
+    pEcmCfg->vCfg[1].vActive = true;
+    wave[1].phi              = M_PI;
+    ecmConfigChannel(1);
+
+    const uint8_t vChans[3][2] = {{0, 1}, {0, 0}, {1, 1}};
+    const double  phases[3]    = {0.0, 0.0, 180.0};
+    for (int i = 0; i < 3; i++) {
+      pEcmCfg->ctCfg[i].vChan1 = vChans[i][0];
+      pEcmCfg->ctCfg[i].vChan2 = vChans[i][1];
+      ecmConfigChannel(i + NUM_V);
+      currentToWave(2.0, 5, phases[i], &wave[NUM_V + pEcmCfg->mapCTLog[i]]);
+    }
+
+    tick = 0;
+    dynamicRun(1, -1, &noise, false);
+    const DataCT_t *pCT = dataset->CT;
+
+    const bool vOk = fabsf(dataset->rmsV[3] - (2.0f * dataset->rmsV[0])) <
+                     (0.01f * dataset->rmsV[3]);
+    const bool pOk =
+        (abs(pCT[0].realPower - (2 * pCT[1].realPower)) <
+         (pCT[0].realPower / 100)) &&
+        (abs(pCT[2].realPower - pCT[1].realPower) < (pCT[1].realPower / 100));
+    const bool pfOk =
+        (pCT[0].pf > 0.99f) && (pCT[1].pf > 0.99f) && (pCT[2].pf > 0.99f);
+    if (!vOk || !pOk || !pfOk) {
+      printf("\nV1: %.1f V1-V2: %.1f\n", dataset->rmsV[0], dataset->rmsV[3]);
+      for (int i = 0; i < 3; i++) {
+        printf("CT%d P: %d PF: %.3f\n", (i + 1), pCT[i].realPower, pCT[i].pf);
+      }
+      return 1;
+    }
+
+    pEcmCfg->vCfg[1].vActive = false;
+    wave[1].phi              = M_PI * 120 / 180;
+    ecmConfigChannel(1);
+    for (int i = 0; i < 3; i++) {
+      pEcmCfg->ctCfg[i].vChan1 = 0;
+      pEcmCfg->ctCfg[i].vChan2 = 0;
+      ecmConfigChannel(i + NUM_V);
+      currentToWave(3.5, 5, 0, &wave[NUM_V + pEcmCfg->mapCTLog[i]]);
+    }
+  }
+  printf("Done!\n");
+
+  /* The interval energy of each report must sum to the change in the
+   * cumulative energy, both importing and exporting. */
+  printf("    - Interval energy ... ");
+  fflush(stdout);
+  {
+    const double phases[2] = {0.0, 180.0};
+
+    reportHook = intervalModel;
+    for (int i = 0; i < 2; i++) {
+      currentToWave(3.5, 5, phases[i], &wave[NUM_V + pEcmCfg->mapCTLog[0]]);
+      ecmClearEnergy();
+      intervalSum = 0.0;
+      tick        = 0;
+      dynamicRun(10, -1, &noise, false);
+
+      if (fabs(intervalSum - dataset->CT[0].wattHour) > 1.0) {
+        printf("\nInterval sum: %.2f Wh Cumulative: %d Wh\n", intervalSum,
+               dataset->CT[0].wattHour);
+        return 1;
+      }
+    }
+    reportHook = NULL;
+    currentToWave(3.5, 5, 0, &wave[NUM_V + pEcmCfg->mapCTLog[0]]);
+  }
+  printf("Done!\n");
+
+  /* Frequency: with jittered crossings, a 50 cycle average must vary much
+   * less between reports than a single cycle. The average is only valid once
+   * full, and restarts after a dropout of V1. */
+  printf("    - Frequency average ... ");
+  fflush(stdout);
+  {
+    const uint32_t avgCycles[2] = {1, 50};
+    double         variance[2]  = {0};
+    double         mean[2]      = {0};
+
+    ecmConfigReportCycles(25);
+    cycleHook  = freqJitterModel;
+    reportHook = freqModel;
+    freqJitter = 0.2;
+    for (int i = 0; i < 2; i++) {
+      pEcmCfg->freqAvgCycles = avgCycles[i];
+      freqReportNum          = 0;
+      tick                   = 0;
+      dynamicRun(24, -1, &noise, false);
+
+      /* Skip the reports while the average fills */
+      for (int r = 4; r < freqReportNum; r++) {
+        mean[i] += freqReports[r];
+      }
+      mean[i] /= (freqReportNum - 4);
+      for (int r = 4; r < freqReportNum; r++) {
+        variance[i] += pow(freqReports[r] - mean[i], 2);
+      }
+      variance[i] /= (freqReportNum - 4);
+    }
+    const bool startOk =
+        !freqValidReports[0] && freqValidReports[3] && freqValidReports[4];
+
+    /* V1 drops out from 2 s to 2.5 s */
+    freqJitter       = 0.0;
+    wave[0].tStep    = 2000000;
+    wave[0].tStepEnd = 2500000;
+    wave[0].sStep    = 0.0;
+    freqReportNum    = 0;
+    tick             = 0;
+    dynamicRun(12, -1, &noise, false);
+    wave[0].tStep = 0;
+    cycleHook     = NULL;
+    reportHook    = NULL;
+    ecmConfigReportCycles((unsigned int)(REPORT_TIME * MAINS_FREQ));
+    for (int i = 0; i < NUM_V; i++) {
+      wave[i].omega = 2 * M_PI * MAINS_FREQ;
+      wave[i].phi   = M_PI * 120 * i / 180;
+    }
+    pEcmCfg->freqAvgCycles = 0;
+
+    bool validBefore = false;
+    bool dropped     = false;
+    for (int r = 0; r < freqReportNum; r++) {
+      validBefore = validBefore || freqValidReports[r];
+      dropped     = dropped || (validBefore && !freqValidReports[r]);
+    }
+    const bool dropOk =
+        dropped && freqValidReports[freqReportNum - 1] &&
+        (fabsf(freqReports[freqReportNum - 1] - MAINS_FREQ) < 0.05f);
+
+    if ((fabs(mean[1] - MAINS_FREQ) > 0.05) ||
+        (variance[1] > (variance[0] / 10.0)) || !startOk || !dropOk) {
+      printf("\nMean: %.3f / %.3f Hz Var: %.5f / %.5f Start: %d Drop: %d\n",
+             mean[0], mean[1], variance[0], variance[1], startOk, dropOk);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  /* Calibration: CT1 and V1 are miscalibrated by -5% and +3%. Calibrating
+   * against the readings taken with the true values must recover them. A
+   * reactive load or a changing load must be rejected. */
+  printf("    - Calibration ... ");
+  fflush(stdout);
+  {
+    ECMCalResult_t res;
+    wave_t        *pW = &wave[NUM_V + pEcmCfg->mapCTLog[0]];
+
+    tick = 0;
+    dynamicRun(1, -1, &noise, false);
+    const float refV     = dataset->rmsV[0];
+    const float refP     = dataset->CT[0].realPower;
+    const float vCalTrue = pEcmCfg->vCfg[0].voltageCalRaw;
+    const float iCalTrue = pEcmCfg->ctCfg[0].ctCalRaw;
+
+    pEcmCfg->vCfg[0].voltageCalRaw = vCalTrue * 1.03f;
+    pEcmCfg->ctCfg[0].ctCalRaw     = iCalTrue * 0.95f;
+    ecmConfigChannel(0);
+    ecmConfigChannel(NUM_V);
+
+    const bool started = ecmCalStart(0, refV, refP, 3);
+    tick               = 0;
+    dynamicRun(3, -1, &noise, false);
+    ecmCalResult(&res);
+    const bool recovered =
+        (ECM_CAL_DONE == res.status) &&
+        (fabsf(res.voltageCalRaw - vCalTrue) < (0.005f * vCalTrue)) &&
+        (fabsf(res.ctCalRaw - iCalTrue) < (0.005f * iCalTrue));
+    const bool taken               = ecmCalTake(&res);
+    pEcmCfg->vCfg[0].voltageCalRaw = res.voltageCalRaw;
+    pEcmCfg->ctCfg[0].ctCalRaw     = res.ctCalRaw;
+    ecmConfigChannel(0);
+    ecmConfigChannel(NUM_V);
+    tick = 0;
+    dynamicRun(1, -1, &noise, false);
+    const bool matched = (fabsf(dataset->rmsV[0] - refV) < (0.005f * refV)) &&
+                         (fabsf(dataset->CT[0].realPower - refP) <
+                          (0.005f * refP));
+
+    /* 60° shift: not a resistive reference */
+    currentToWave(3.5, 5, 60.0, pW);
+    ecmCalStart(0, refV, refP, 2);
+    tick = 0;
+    dynamicRun(2, -1, &noise, false);
+    ecmCalResult(&res);
+    const bool pfRejected = (ECM_CAL_PF == res.status);
+
+    /* Load steps down by 10% halfway through the second report */
+    currentToWave(3.5, 5, 0, pW);
+    pW->tStep    = 15000000;
+    pW->tStepEnd = 0;
+    pW->sStep    = pW->s * 0.9;
+    ecmCalStart(0, refV, refP, 2);
+    tick = 0;
+    dynamicRun(2, -1, &noise, false);
+    pW->tStep = 0;
+    ecmCalResult(&res);
+    const bool stepRejected = (ECM_CAL_UNSTABLE == res.status);
+
+    if (!started || !recovered || !taken || !matched || !pfRejected ||
+        !stepRejected || ecmCalTake(&res) ||
+        ecmCalStart(0, refV, refP, 0)) {
+      printf("\nStart: %d Recovered: %d Taken: %d Matched: %d PF: %d "
+             "Step: %d\n",
+             started, recovered, taken, matched, pfRejected, stepRejected);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  /* Power against the analytical result: P = V.I.cos(phi) within 1% of S,
+   * S = V.I within 1%, and PF within 0.01, over phase shifts and currents. */
+  printf("    - Phase shift accuracy ... ");
+  fflush(stdout);
+  {
+    const double phis[5] = {0.0, 30.0, 45.0, 60.0, 90.0};
+    const double amps[3] = {0.5, 1.5, 3.5};
+    wave_t      *pW      = &wave[NUM_V + pEcmCfg->mapCTLog[0]];
+
+    for (int a = 0; a < 3; a++) {
+      for (int p = 0; p < 5; p++) {
+        currentToWave(amps[a], 5, phis[p], pW);
+        tick = 0;
+        dynamicRun(1, -1, &noise, false);
+
+        const DataCT_t *pCT    = &dataset->CT[0];
+        const double    cosPhi = cos(M_PI * phis[p] / 180.0);
+        const double    sGold  = dataset->rmsV[0] * pCT->rmsI;
+        const double    pGold  = sGold * cosPhi;
+
+        if ((fabs(pCT->realPower - pGold) > (0.01 * sGold)) ||
+            (fabs(pCT->apparentPower - sGold) > (0.01 * sGold)) ||
+            (fabs(pCT->pf - cosPhi) > 0.01)) {
+          printf("\n%.1f A %.0f deg: P: %d Gold: %.0f S: %d Gold: %.0f PF: "
+                 "%.3f Gold: %.3f\n",
+                 amps[a], phis[p], pCT->realPower, pGold, pCT->apparentPower,
+                 sGold, pCT->pf, cosPhi);
+          return 1;
+        }
+      }
+    }
+    currentToWave(3.5, 5, 0, pW);
+  }
+  printf("Done!\n");
+
+  /* Each report covers the configured cycles, with the sample sets given by
+   * the test's sample tick, and the sequence number is not reset with the
+   * energy. */
+  printf("    - Report counts ... ");
+  fflush(stdout);
+  {
+    countReportNum = 0;
+    reportHook     = countModel;
+    tick           = 0;
+    dynamicRun(2, -1, &noise, false);
+    ecmClearEnergy();
+    tick = 0;
+    dynamicRun(2, -1, &noise, false);
+    reportHook = NULL;
+
+    /* Two raw sets are downsampled to one */
+    const double setPeriod_us = 2.0 * (SMP_TICK) * VCT_TOTAL;
+    for (int i = 0; i < 4; i++) {
+      const double setsGold =
+          countCycles[i] * (1000000.0 / MAINS_FREQ) / setPeriod_us;
+      const bool seqOk = (0 == i) || (countSeq[i] == countSeq[i - 1] + 1);
+      if (!seqOk || (countCycles[i] != pEcmCfg->reportCycles) ||
+          (fabs(countSets[i] - setsGold) > 2.0)) {
+        printf("\nReport %d: Seq: %u Cycles: %u Sets: %u Gold: %.1f\n", i,
+               countSeq[i], countCycles[i], countSets[i], setsGold);
+        return 1;
+      }
+    }
+  }
+  printf("Done!\n");
+
+  /* The report period takes effect in the report being collected. Shortened
+   * below the cycles already collected, the report is sent on the next cycle.
+   */
+  printf("    - Report period change ... ");
+  fflush(stdout);
+  {
+    const uint32_t cyclesGold[4] = {61, 25, 50, 50};
+
+    ecmConfigReportCycles(100);
+    countReportNum = 0;
+    periodCycle    = 0;
+    cycleHook      = periodModel;
+    reportHook     = periodReportModel;
+    tick           = 0;
+    dynamicRun(4, -1, &noise, false);
+    cycleHook  = NULL;
+    reportHook = NULL;
+    ecmConfigReportCycles((unsigned int)(REPORT_TIME * MAINS_FREQ));
+
+    for (int i = 0; i < 4; i++) {
+      if (countCycles[i] != cyclesGold[i]) {
+        printf("\nReport %d: Cycles: %u Gold: %u\n", i, countCycles[i],
+               cyclesGold[i]);
+        return 1;
+      }
+    }
+  }
+  printf("Done!\n");
+
+  /* Wh pulses: importing, the pulses match the accumulated energy exactly;
+   * exporting gives no pulses, and import then pulses only once the energy
+   * rises past whole Wh again. */
+  printf("    - Wh pulses ... ");
+  fflush(stdout);
+  {
+    wave_t *pW = &wave[NUM_V + pEcmCfg->mapCTLog[0]];
+
+    ecmClearEnergy();
+    (void)ecmWhPulsesTake(0);
+    currentToWave(1.0, 5, 0, pW);
+    tick = 0;
+    dynamicRun(3, -1, &noise, false);
+    const int32_t  whImport     = dataset->CT[0].wattHour;
+    const uint32_t pulsesImport = ecmWhPulsesTake(0);
+
+    currentToWave(1.0, 5, 180.0, pW);
+    tick = 0;
+    dynamicRun(2, -1, &noise, false);
+    const int32_t  whExport     = dataset->CT[0].wattHour;
+    const uint32_t pulsesExport = ecmWhPulsesTake(0);
+
+    currentToWave(1.0, 5, 0, pW);
+    tick = 0;
+    dynamicRun(4, -1, &noise, false);
+    const int32_t  whRecover     = dataset->CT[0].wattHour;
+    const uint32_t pulsesRecover = ecmWhPulsesTake(0);
+
+    if ((whImport <= 0) || (pulsesImport != (uint32_t)whImport) ||
+        (0 != pulsesExport) ||
+        (pulsesRecover != (uint32_t)(whRecover - whExport)) ||
+        (0 != ecmWhPulsesTake(0)) || (0 != ecmWhPulsesTake(NUM_CT))) {
+      printf("\nImport: %d Wh %u pulses Export: %d Wh %u pulses Recover: %d "
+             "Wh %u pulses\n",
+             whImport, pulsesImport, whExport, pulsesExport, whRecover,
+             pulsesRecover);
+      return 1;
+    }
+    currentToWave(3.5, 5, 0, pW);
+  }
+  printf("Done!\n");
+
+  /* Demand: windows of three reports over a stepped load profile. The peak
+   * and the report closing it must match a reference computed from the
+   * reported powers, and a reset clears the peaks. */
+  printf("    - Peak demand ... ");
+  fflush(stdout);
+  {
+    pDemandWave = &wave[NUM_V + pEcmCfg->mapCTLog[0]];
+    currentToWave(demandProfile[0], 5, 0, pDemandWave);
+    pEcmCfg->demandTime_s = (uint32_t)(3 * REPORT_TIME);
+    ecmDemandReset();
+    demandReportNum = 0;
+    reportHook      = demandModel;
+    tick            = 0;
+    dynamicRun(9, -1, &noise, false);
+    reportHook = NULL;
+
+    double   peakGold[2]    = {0};
+    uint32_t peakSeqGold[2] = {0};
+    for (int w = 0; w < 3; w++) {
+      double energy[2] = {0};
+      double time      = 0.0;
+      for (int r = 3 * w; r < 3 * (w + 1); r++) {
+        energy[0] += demandP[r] * demandTime[r];
+        energy[1] += demandTotal[r] * demandTime[r];
+        time += demandTime[r];
+      }
+      for (int i = 0; i < 2; i++) {
+        if ((0 == w) || ((energy[i] / time) > peakGold[i])) {
+          peakGold[i]    = energy[i] / time;
+          peakSeqGold[i] = demandSeq[3 * w + 2];
+        }
+      }
+    }
+
+    const ECMDemand_t *pDemand = ecmDemand();
+    const bool ctOk =
+        (fabs(pDemand->CT[0].peak - peakGold[0]) < (0.01 * peakGold[0])) &&
+        (pDemand->CT[0].peakSeq == peakSeqGold[0]);
+    const bool totalOk =
+        (fabs(pDemand->total.peak - peakGold[1]) < (0.01 * peakGold[1])) &&
+        (pDemand->total.peakSeq == peakSeqGold[1]);
+    ecmDemandReset();
+    const bool resetOk = (0 == pDemand->CT[0].peakSeq) &&
+                         (0 == pDemand->total.peak) &&
+                         (0.0f == pDemand->windowTime);
+
+    if (!ctOk || !totalOk || !resetOk) {
+      printf("\nCT: %d W (%u) Gold: %.0f W (%u) Total: %d W (%u) Gold: %.0f "
+             "W (%u) Reset: %d\n",
+             pDemand->CT[0].peak, pDemand->CT[0].peakSeq, peakGold[0],
+             peakSeqGold[0], pDemand->total.peak, pDemand->total.peakSeq,
+             peakGold[1], peakSeqGold[1], resetOk);
+      return 1;
+    }
+    pEcmCfg->demandTime_s = 0;
+    currentToWave(3.5, 5, 0, pDemandWave);
+  }
+  printf("Done!\n");
+
+  /* Load steps with 1 s windows and a threshold equivalent to 0.5 A: a step
+   * from 1.5 A to 2.5 A gives exactly one event, a ramp over the same range
+   * across a report gives none. */
+  printf("    - Load step events ... ");
+  fflush(stdout);
+  {
+    wave_t    *pW = &wave[NUM_V + pEcmCfg->mapCTLog[0]];
+    ECMEvent_t evt;
+
+    for (int i = NUM_V; i < VCT_TOTAL; i++) {
+      currentToWave(1.5, 5, 0, &wave[i]);
+      wave[i].tStep = 0;
+    }
+    tick = 0;
+    dynamicRun(1, -1, &noise, false);
+    const double p1 = dataset->CT[0].realPower;
+    while (ecmEventGet(&evt)) {
+    }
+
+    pEcmCfg->evtCfg.stepPower  = (float)(p1 / 3.0);
+    pEcmCfg->evtCfg.stepCycles = 50;
+    pW->tStep                  = 3000000;
+    pW->tStepEnd               = 0;
+    pW->sStep                  = pW->s * (2.5 / 1.5);
+    tick                       = 0;
+    dynamicRun(1, -1, &noise, false);
+    pW->tStep = 0;
+
+    int   numSteps = 0;
+    float change   = 0.0f;
+    float power    = 0.0f;
+    while (ecmEventGet(&evt)) {
+      if (ECM_EVT_LOAD_STEP == evt.type) {
+        numSteps += (0 == evt.ch) ? 1 : 100;
+        change = evt.value;
+        power  = evt.power;
+      }
+    }
+    const bool stepOk = (1 == numSteps) &&
+                        (fabs(change - (p1 * 2.0 / 3.0)) < (0.05 * p1)) &&
+                        (fabs(power - (p1 * 5.0 / 3.0)) < (0.05 * p1));
+
+    currentToWave(1.5, 5, 0, pW);
+    pRampWave    = pW;
+    rampS0       = pW->s;
+    rampPerCycle = (1.0 / 1.5) / (REPORT_TIME * MAINS_FREQ);
+    rampCycles   = 0;
+    cycleHook    = rampModel;
+    tick         = 0;
+    dynamicRun(1, -1, &noise, false);
+    cycleHook = NULL;
+
+    int numRamp = 0;
+    while (ecmEventGet(&evt)) {
+      numRamp += (ECM_EVT_LOAD_STEP == evt.type) ? 1 : 0;
+    }
+
+    if (!stepOk || (0 != numRamp)) {
+      printf("\nSteps: %d Change: %.0f W Power: %.0f W P1: %.0f W Ramp: %d\n",
+             numSteps, change, power, p1, numRamp);
+      return 1;
+    }
+    pEcmCfg->evtCfg.stepPower = 0.0f;
+    currentToWave(3.5, 5, 0, pW);
+  }
+  printf("Done!\n");
+
+  /* Sensor check on CT1 with a 0.5 A minimum over 50 cycles: no events at
+   * 1 A, then a low current and a stuck offset each give one fault event and
+   * one recovery when the 1 A signal returns. */
+  printf("    - Sensor faults ... ");
+  fflush(stdout);
+  {
+    const ECM_EVENT_t faults[2] = {ECM_EVT_SENSOR_LOW, ECM_EVT_SENSOR_STUCK};
+    ECMEvent_t        evt;
+
+    pSensorWave = &wave[NUM_V + pEcmCfg->mapCTLog[0]];
+    while (ecmEventGet(&evt)) {
+    }
+    ecmConfigSensorCheck(0, 0.5f, 50);
+    currentToWave(1.0, 5, 0, pSensorWave);
+    sensorGoodS = pSensorWave->s;
+    tick        = 0;
+    dynamicRun(1, -1, &noise, false);
+    const bool quiet = !ecmEventGet(&evt);
+
+    cycleHook = sensorModel;
+    for (int i = 0; i < 2; i++) {
+      pSensorWave->s      = 0.0;
+      pSensorWave->offset = (0 == i) ? 0 : 500;
+      sensorCycles        = 0;
+      tick                = 0;
+      dynamicRun(1, -1, &noise, false);
+
+      ECMEvent_t evtOk = {0};
+      if (!quiet || !ecmEventGet(&evt) || !ecmEventGet(&evtOk) ||
+          ecmEventGet(&evtOk) || (faults[i] != evt.type) || (0 != evt.ch) ||
+          (evt.cycles != 50) || (ECM_EVT_SENSOR_OK != evtOk.type) ||
+          (evtOk.value < 0.9f)) {
+        printf("\nQuiet: %d Case: %d Fault: %d (%u cycles) Recovery: %d\n",
+               quiet, i, evt.type, evt.cycles, evtOk.type);
+        return 1;
+      }
+    }
+    cycleHook = NULL;
+    ecmConfigSensorCheck(0, 0.0f, 0);
+    currentToWave(3.5, 5, 0, pSensorWave);
+  }
+  printf("Done!\n");
+
   printf("\n  Finished!\n\n");
 
   return 0;
@@ -412,7 +1927,18 @@ static void currentToWave(double IRMS, int scaleCT, double phase, wave_t *w) {
 static q15_t generateWave(wave_t *w, int tMicros) {
   assert((w->s >= 0.0) && (w->s <= 1.0));
   q15_t  wave;
-  double a = sin(((w->omega * tMicros) / 1000000.0) + w->phi) * w->s;
+  bool   step = (0 != w->tStep) && (tMicros >= w->tStep) &&
+              ((0 == w->tStepEnd) || (tMicros < w->tStepEnd));
+  double s    = step ? w->sStep : w->s;
+  double theta = ((w->omega * tMicros) / 1000000.0) + w->phi;
+  double a     = sin(theta);
+  if ((0.0 != w->pulse) && (fabs(a) < w->pulse)) {
+    a = 0.0;
+  }
+  if (0 != w->hN) {
+    a += w->hS * sin(w->hN * theta);
+  }
+  a *= s;
   wave     = (q15_t)(a * MAX_A);
   wave += w->offset;
 
@@ -0,0 +1,145 @@
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "cmdSeq.h"
+
+static CmdSeq_t seq;
+static int      applied;
+
+/*! @brief Handle a line as the command interface does: carry out a new
+ *         command and record its result, or only reply to a duplicate
+ *  @return the reply line, or "" for an untagged line
+ */
+static const char *handle(const char *pLine, const CmdErr_t result) {
+  static char reply[CMD_SEQ_REPLY_W];
+  uint32_t    tag;
+  size_t      cmd;
+  CmdErr_t    err = result;
+
+  reply[0] = 0;
+  switch (cmdSeqParse(&seq, pLine, &tag, &cmd, &err)) {
+  case CMD_TAG_NONE:
+    applied++;
+    break;
+  case CMD_TAG_NEW:
+    applied++;
+    cmdSeqRecord(&seq, tag, result);
+    (void)cmdSeqReply(reply, tag, result);
+    break;
+  case CMD_TAG_DUP:
+    (void)cmdSeqReply(reply, tag, err);
+    break;
+  case CMD_TAG_BAD:
+    (void)cmdSeqReply(reply, 0, CMD_ERR_TAG);
+    break;
+  }
+  return reply;
+}
+
+int main(void) {
+
+  uint32_t tag;
+  size_t   cmd;
+  CmdErr_t err;
+
+  printf("---- emon32 command sequence test ----\n\n");
+
+  cmdSeqInit(&seq);
+
+  printf("  > Tag parsing ... ");
+  {
+    const char *bad[] = {"#", "#k1", "#12k1", "# 12 k1", "#1234567890 k1"};
+
+    if (CMD_TAG_NONE != cmdSeqParse(&seq, "k1 1 100.0", &tag, &cmd, &err)) {
+      printf("\n    Untagged line has a tag\n");
+      return 1;
+    }
+    if ((CMD_TAG_NEW != cmdSeqParse(&seq, "#17 k1 3.00", &tag, &cmd, &err)) ||
+        (17u != tag) || (4u != cmd)) {
+      printf("\n    Tag: %u Command: %zu\n", tag, cmd);
+      return 1;
+    }
+    if ((CMD_TAG_NEW != cmdSeqParse(&seq, "#999999999", &tag, &cmd, &err)) ||
+        (999999999u != tag) || (10u != cmd)) {
+      printf("\n    Longest tag: %u\n", tag);
+      return 1;
+    }
+    for (size_t i = 0; i < (sizeof(bad) / sizeof(bad[0])); i++) {
+      if (CMD_TAG_BAD != cmdSeqParse(&seq, bad[i], &tag, &cmd, &err)) {
+        printf("\n    Accepted: %s\n", bad[i]);
+        return 1;
+      }
+    }
+  }
+  printf("Done!\n");
+
+  printf("  > Replies ... ");
+  {
+    char buf[CMD_SEQ_REPLY_W];
+
+    if ((8u != cmdSeqReply(buf, 17u, CMD_OK)) ||
+        (0 != strcmp(buf, "#17 OK\r\n"))) {
+      printf("\n    OK: %s\n", buf);
+      return 1;
+    }
+    if ((25u != cmdSeqReply(buf, 999999999u, CMD_ERR_VALUE)) ||
+        (0 != strcmp(buf, "#999999999 ERR badvalue\r\n"))) {
+      printf("\n    Error: %s\n", buf);
+      return 1;
+    }
+    (void)cmdSeqReply(buf, 5u, CMD_ERR_TAG);
+    if (0 != strcmp(buf, "# ERR badtag\r\n")) {
+      printf("\n    Bad tag: %s\n", buf);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  /* A retransmission is answered with the first result and not applied */
+  printf("  > Duplicates ... ");
+  applied = 0;
+  if ((0 != strcmp(handle("#17 z", CMD_OK), "#17 OK\r\n")) ||
+      (0 != strcmp(handle("#18 k1 x", CMD_ERR_VALUE),
+                   "#18 ERR badvalue\r\n")) ||
+      (0 != strcmp(handle("#17 z", CMD_ERR_FAIL), "#17 OK\r\n")) ||
+      (0 != strcmp(handle("#18 k1 x", CMD_OK), "#18 ERR badvalue\r\n")) ||
+      (2 != applied)) {
+    printf("\n    Applied: %d\n", applied);
+    return 1;
+  }
+  printf("Done!\n");
+
+  /* New tags are applied in any order; untagged lines always are */
+  printf("  > Out of order tags ... ");
+  applied = 0;
+  if ((0 != strcmp(handle("#20 u", CMD_OK), "#20 OK\r\n")) ||
+      (0 != strcmp(handle("#19 w1", CMD_OK), "#19 OK\r\n")) ||
+      (0 != strcmp(handle("#21 w0", CMD_OK), "#21 OK\r\n")) ||
+      (0 != strcmp(handle("#19 w1", CMD_OK), "#19 OK\r\n")) ||
+      (0 != strcmp(handle("u", CMD_OK), "")) ||
+      (0 != strcmp(handle("u", CMD_OK), "")) || (5 != applied)) {
+    printf("\n    Applied: %d\n", applied);
+    return 1;
+  }
+  printf("Done!\n");
+
+  /* Only the last CMD_SEQ_HISTORY tags are remembered: 17 has dropped out */
+  printf("  > History ... ");
+  applied = 0;
+  if ((0 != strcmp(handle("#17 z", CMD_ERR_FAIL), "#17 ERR failed\r\n")) ||
+      (0 != strcmp(handle("#20 u", CMD_ERR_FAIL), "#20 OK\r\n")) ||
+      (1 != applied)) {
+    printf("\n    Applied: %d\n", applied);
+    return 1;
+  }
+  cmdSeqInit(&seq);
+  if ((0 != strcmp(handle("#20 u", CMD_OK), "#20 OK\r\n")) || (2 != applied)) {
+    printf("\n    Not cleared\n");
+    return 1;
+  }
+  printf("Done!\n");
+
+  printf("\n");
+  return 0;
+}
@@ -0,0 +1,188 @@
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "cobs.h"
+
+#define PAYLOAD_MAX 1024u
+
+static uint8_t payload[PAYLOAD_MAX];
+static uint8_t enc[COBS_ENC_MAX(PAYLOAD_MAX) + 1u];
+static uint8_t dec[PAYLOAD_MAX];
+
+/*! @brief Encode n bytes of payload, and check the frame has no zero, fits in
+ *         the worst case width, and decodes to the payload both as a whole and
+ *         a byte at a time.
+ *  @return 0 on success
+ */
+static int roundTrip(const size_t n) {
+  CobsDecoder_t decoder;
+  size_t        nDec = 0;
+  CobsRx_t      rx   = COBS_RX_BUSY;
+
+  const size_t w = cobsEncode(payload, n, enc, COBS_ENC_MAX(n));
+  if ((0 == w) || (w > COBS_ENC_MAX(n)) || memchr(enc, 0, w)) {
+    printf("\n    n: %zu Encoded: %zu\n", n, w);
+    return 1;
+  }
+
+  if ((COBS_OK != cobsDecode(enc, w, dec, sizeof(dec), &nDec)) ||
+      (n != nDec) || (0 != memcmp(payload, dec, n))) {
+    printf("\n    n: %zu Decoded: %zu\n", n, nDec);
+    return 1;
+  }
+
+  cobsDecoderInit(&decoder, dec, sizeof(dec));
+  enc[w] = COBS_DELIM;
+  for (size_t i = 0; i <= w; i++) {
+    rx = cobsDecodeByte(&decoder, enc[i]);
+    if ((i < w) && (COBS_RX_BUSY != rx)) {
+      break;
+    }
+  }
+  if ((COBS_RX_FRAME != rx) || (n != decoder.n) ||
+      (0 != memcmp(payload, dec, n))) {
+    printf("\n    n: %zu Incremental: %d %zu\n", n, rx, decoder.n);
+    return 1;
+  }
+  return 0;
+}
+
+int main(void) {
+
+  CobsDecoder_t decoder;
+  size_t        nDec;
+  size_t        w;
+
+  printf("---- emon32 COBS test ----\n\n");
+
+  /* Reference vectors */
+  printf("  > Known encodings ... ");
+  {
+    const uint8_t in0[]  = {0x00};
+    const uint8_t out0[] = {0x01, 0x01};
+    const uint8_t in1[]  = {0x11, 0x22, 0x00, 0x33};
+    const uint8_t out1[] = {0x03, 0x11, 0x22, 0x02, 0x33};
+    const uint8_t in2[]  = {0x11, 0x00, 0x00, 0x00};
+    const uint8_t out2[] = {0x02, 0x11, 0x01, 0x01, 0x01};
+
+    w = cobsEncode(in0, 0, enc, sizeof(enc));
+    if ((1u != w) || (0x01 != enc[0])) {
+      printf("\n    Empty: %zu\n", w);
+      return 1;
+    }
+    w = cobsEncode(in0, sizeof(in0), enc, sizeof(enc));
+    if ((sizeof(out0) != w) || memcmp(out0, enc, w)) {
+      printf("\n    Zero: %zu\n", w);
+      return 1;
+    }
+    w = cobsEncode(in1, sizeof(in1), enc, sizeof(enc));
+    if ((sizeof(out1) != w) || memcmp(out1, enc, w)) {
+      printf("\n    Mixed: %zu\n", w);
+      return 1;
+    }
+    w = cobsEncode(in2, sizeof(in2), enc, sizeof(enc));
+    if ((sizeof(out2) != w) || memcmp(out2, enc, w)) {
+      printf("\n    Trailing zeros: %zu\n", w);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  /* Payloads of zeros, no zeros, and random bytes, across the block size */
+  printf("  > Round trip ... ");
+  srand(1);
+  for (size_t n = 0; n < PAYLOAD_MAX; n++) {
+    memset(payload, 0, n);
+    if (roundTrip(n)) {
+      return 1;
+    }
+    memset(payload, 0xA5, n);
+    if (roundTrip(n)) {
+      return 1;
+    }
+    for (size_t i = 0; i < n; i++) {
+      payload[i] = (uint8_t)(((rand() % 4) == 0) ? 0 : rand());
+    }
+    if (roundTrip(n)) {
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  /* A full block has no implied zero, so the worst case is reached */
+  printf("  > Maximum length ... ");
+  memset(payload, 0x5A, PAYLOAD_MAX);
+  w = cobsEncode(payload, PAYLOAD_MAX, enc, COBS_ENC_MAX(PAYLOAD_MAX));
+  if ((COBS_ENC_MAX(PAYLOAD_MAX) != w) ||
+      (0 != cobsEncode(payload, PAYLOAD_MAX, enc, (w - 1u)))) {
+    printf("\n    Encoded: %zu\n", w);
+    return 1;
+  }
+  if (COBS_OVERFLOW != cobsDecode(enc, w, dec, (PAYLOAD_MAX - 1u), &nDec)) {
+    printf("\n    Overflow not detected\n");
+    return 1;
+  }
+  printf("Done!\n");
+
+  printf("  > Invalid frames ... ");
+  {
+    const uint8_t zero[] = {0x03, 0x11, 0x00};
+    const uint8_t part[] = {0x04, 0x11, 0x22};
+    const size_t  m      = sizeof(dec);
+    if ((COBS_ZERO != cobsDecode(zero, sizeof(zero), dec, m, &nDec)) ||
+        (COBS_SHORT != cobsDecode(part, sizeof(part), dec, m, &nDec))) {
+      printf("\n    Not rejected\n");
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  /* After a corrupted or overlong frame, the decoder recovers at the next
+   * delimiter. Empty frames are skipped. */
+  printf("  > Resynchronise ... ");
+  {
+    const uint8_t stream[] = {0x00, 0x05, 0x11, 0x00, 0x02, 0x41, 0x00,
+                              0x03, 0x11, 0x22, 0x22, 0x33, 0x00, 0x00,
+                              0x03, 0x6A, 0x31, 0x00};
+    const CobsRx_t expect[] = {
+        COBS_RX_BUSY,  COBS_RX_BUSY, COBS_RX_BUSY,  COBS_RX_ERROR,
+        COBS_RX_BUSY,  COBS_RX_BUSY, COBS_RX_FRAME, COBS_RX_BUSY,
+        COBS_RX_BUSY,  COBS_RX_BUSY, COBS_RX_BUSY,  COBS_RX_BUSY,
+        COBS_RX_ERROR, COBS_RX_BUSY, COBS_RX_BUSY,  COBS_RX_BUSY,
+        COBS_RX_BUSY,  COBS_RX_FRAME};
+
+    cobsDecoderInit(&decoder, dec, 4u);
+    for (size_t i = 0; i < sizeof(stream); i++) {
+      const CobsRx_t rx = cobsDecodeByte(&decoder, stream[i]);
+      if (expect[i] != rx) {
+        printf("\n    Byte: %zu Status: %d\n", i, rx);
+        return 1;
+      }
+      if ((6u == i) && ((1u != decoder.n) || (0x41 != dec[0]))) {
+        printf("\n    First frame: %zu\n", decoder.n);
+        return 1;
+      }
+    }
+    if ((2u != decoder.n) || memcmp("j1", dec, 2u)) {
+      printf("\n    Last frame: %zu\n", decoder.n);
+      return 1;
+    }
+
+    /* Longer than the buffer */
+    const uint8_t longFrame[] = {0x06, 0x31, 0x32, 0x33, 0x34, 0x35, 0x00};
+    CobsRx_t      rx          = COBS_RX_BUSY;
+    for (size_t i = 0; i < sizeof(longFrame); i++) {
+      rx = cobsDecodeByte(&decoder, longFrame[i]);
+    }
+    if (COBS_RX_ERROR != rx) {
+      printf("\n    Overlong frame accepted\n");
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  printf("\n");
+  return 0;
+}
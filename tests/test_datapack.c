@@ -0,0 +1,283 @@
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "dataPack.h"
+#include "temperature.h"
+#include "util.h"
+
+#define FULL_W 2048u
+
+/* Stubs for the modules dataPack.c uses but are not tested here */
+float tempAsFloat(const TEMP_INTF_t intf, const int16_t tFixed) {
+  (void)intf;
+  return (float)tFixed / 16.0f;
+}
+
+size_t frameEncode(const void *pPayload, uint8_t n, uint8_t node,
+                   uint8_t *pDst, size_t m) {
+  (void)pPayload;
+  (void)n;
+  (void)node;
+  (void)pDst;
+  (void)m;
+  return 0;
+}
+
+static ECMDataset_t    ecm;
+static Emon32Dataset_t dataset;
+static CHActive_t      chsActive;
+static char            full[FULL_W];
+static char            line[FULL_W];
+
+static const char     *markers[3] = {",!TRUNC:1", ",\"!TRUNC\":1", ",!TRUNC"};
+static const DataFmt_t fmts[3] = {DATA_FMT_KV, DATA_FMT_JSON, DATA_FMT_CSV};
+
+static void setDp(const uint8_t dp) {
+  for (size_t i = 0; i < DATA_FIELD_NUM; i++) {
+    chsActive.dp[i] = dp;
+  }
+}
+
+/*! @brief Check a line packed into m characters against the same line packed
+ *         without a limit. It must be null terminated and either the same as
+ *         the full line, or its leading whole fields followed by the marker.
+ *  @return 0 if the line is good, 1 if not truncated, -1 on failure
+ */
+static int checkLine(const DataFmt_t fmt, const size_t m, const size_t n) {
+  const size_t nFull = dataPackSerial(&dataset, full, FULL_W, fmt, &chsActive);
+  const char  *mark  = markers[fmt];
+  const char  *end   = (DATA_FMT_JSON == fmt) ? "}\r\n" : "\r\n";
+  const size_t markW = strlen(mark) + strlen(end);
+
+  if ((n >= m) || (n != strlen(line))) {
+    printf("\n    m: %zu n: %zu strlen: %zu\n", m, n, strlen(line));
+    return -1;
+  }
+  if ((n == nFull) && (0 == strcmp(line, full))) {
+    return 1;
+  }
+  if ((n < markW) || (0 != strncmp(line + n - markW, mark, strlen(mark))) ||
+      (0 != strcmp(line + n - strlen(end), end))) {
+    printf("\n    No marker (m: %zu): %s\n", m, line);
+    return -1;
+  }
+
+  /* The kept fields are a prefix of the full line ending at a field */
+  const size_t kept = n - markW;
+  if ((0 != strncmp(line, full, kept)) || (',' != full[kept])) {
+    printf("\n    Not whole fields (m: %zu): %s\n", m, line);
+    return -1;
+  }
+  return 0;
+}
+
+int main(void) {
+
+  uint32_t truncated;
+  size_t   n;
+
+  printf("---- emon32 data pack test ----\n\n");
+
+  /* Every channel, frequency, PF, pulse, and temperature field is present,
+   * with values as wide as they can be printed, at the most decimal places. */
+  for (size_t i = 0; i < NUM_V; i++) {
+    chsActive.V[i] = true;
+    ecm.rmsV[i]    = 42949672.95f;
+  }
+  for (size_t i = 0; i < NUM_CT; i++) {
+    chsActive.CT[i]         = true;
+    ecm.CT[i].realPower     = INT32_MIN + 1;
+    ecm.CT[i].wattHour      = INT32_MIN + 1;
+    ecm.CT[i].apparentPower = INT32_MAX;
+    ecm.CT[i].pf            = -1.0f;
+  }
+  for (size_t i = 0; i < NUM_OPA; i++) {
+    chsActive.pulse[i]  = true;
+    dataset.pulseCnt[i] = UINT32_MAX;
+  }
+  for (size_t i = 0; i < TEMP_MAX_ONEWIRE; i++) {
+    dataset.temp[i] = -880;
+  }
+  chsActive.time   = true;
+  chsActive.sel    = DATA_SEL_ALL;
+  ecm.activeCh     = UINT32_MAX;
+  ecm.frequency    = 50.0f;
+  ecm.freqValid    = true;
+  dataset.msgNum   = UINT32_MAX;
+  dataset.epoch    = UINT32_MAX;
+  dataset.pECM     = &ecm;
+  setDp(FTOA_DP_MAX);
+
+  printf("  > Oversized selection is marked ... ");
+  for (size_t f = 0; f < 3u; f++) {
+    truncated = dataPackTruncated();
+    n = dataPackSerial(&dataset, line, TX_BUFFER_W, fmts[f], &chsActive);
+    if ((0 != checkLine(fmts[f], TX_BUFFER_W, n)) ||
+        ((truncated + 1u) != dataPackTruncated())) {
+      printf("\n    Format: %zu Count: %u\n", f, dataPackTruncated());
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  /* Whatever the width and precision, the line is either whole or cleanly
+   * truncated */
+  printf("  > All widths ... ");
+  for (uint8_t dp = 0; dp <= FTOA_DP_MAX; dp++) {
+    setDp(dp);
+    for (size_t f = 0; f < 3u; f++) {
+      for (size_t m = 32u; m < FULL_W; m++) {
+        truncated = dataPackTruncated();
+        n         = dataPackSerial(&dataset, line, m, fmts[f], &chsActive);
+
+        const int res = checkLine(fmts[f], m, n);
+        if ((res < 0) || ((truncated + (0 == res)) != dataPackTruncated())) {
+          printf("\n    Format: %zu Width: %zu Places: %u\n", f, m, dp);
+          return 1;
+        }
+      }
+    }
+  }
+  printf("Done!\n");
+
+  /* A line that fits is not changed, and is not counted */
+  printf("  > Small selection is whole ... ");
+  memset(&chsActive, 0, sizeof(chsActive));
+  chsActive.V[0]  = true;
+  chsActive.CT[0] = true;
+  ecm.activeCh    = 0x9u;
+  ecm.rmsV[0]     = 240.0f;
+  for (size_t i = 0; i < TEMP_MAX_ONEWIRE; i++) {
+    dataset.temp[i] = TEMP_Q4_ABSENT;
+  }
+  truncated = dataPackTruncated();
+  n = dataPackSerial(&dataset, line, TX_BUFFER_W, DATA_FMT_JSON, &chsActive);
+  if ((1 != checkLine(DATA_FMT_JSON, TX_BUFFER_W, n)) ||
+      (truncated != dataPackTruncated())) {
+    printf("\n    Line: %s\n", line);
+    return 1;
+  }
+  printf("Done!\n");
+
+  /* With room left for it, the checksum does not cut the marker off */
+  printf("  > Marker with checksum ... ");
+  chsActive.CT[1] = true;
+  n = dataPackSerial(&dataset, line, (64u - LINE_CHECK_W), DATA_FMT_KV,
+                     &chsActive);
+  n = utilLineCheck(line, n, 64u, LINE_CHECK_XOR);
+  if ((n >= 64u) || (0 != strncmp(line + n - 14u, ",!TRUNC:1*", 10u))) {
+    printf("\n    Line: %s\n", line);
+    return 1;
+  }
+  printf("Done!\n");
+
+  /* Each class of field has its own number of decimal places */
+  printf("  > Decimal places ... ");
+  {
+    const char *gold =
+        "{\"MSG\":4294967295,\"TIME\":4294967295,\"V1\":240.13,\"F\":50.0,"
+        "\"P1\":-12,\"P2\":100,\"E1\":7.000,\"E2\":-3.000,\"PF1\":-0.988,"
+        "\"PF2\":0.500,\"t1\":21.1}\r\n";
+
+    chsActive.sel                 = DATA_SEL_ALL;
+    chsActive.dp[DATA_FIELD_V]    = 2u;
+    chsActive.dp[DATA_FIELD_P]    = 0;
+    chsActive.dp[DATA_FIELD_E]    = 3u;
+    chsActive.dp[DATA_FIELD_PF]   = 3u;
+    chsActive.dp[DATA_FIELD_F]    = 1u;
+    chsActive.dp[DATA_FIELD_TEMP] = 1u;
+    ecm.rmsV[0]                   = 240.125f;
+    ecm.frequency                 = 49.96f;
+    ecm.CT[0].realPower           = -12;
+    ecm.CT[1].realPower           = 100;
+    ecm.CT[0].wattHour            = 7;
+    ecm.CT[1].wattHour            = -3;
+    ecm.CT[0].pf                  = -0.9876f;
+    ecm.CT[1].pf                  = 0.5f;
+    dataset.temp[0]               = 338;
+
+    n = dataPackSerial(&dataset, line, TX_BUFFER_W, DATA_FMT_JSON, &chsActive);
+    if ((n != strlen(gold)) || (0 != strcmp(line, gold))) {
+      printf("\n    Line: %s", line);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  /* Energy, PF, and frequency are selected apart from the active channels.
+   * The header names the same columns as the line. */
+  printf("  > Field selection ... ");
+  {
+    const char *gold[3] = {
+        "{\"MSG\":4294967295,\"TIME\":4294967295,\"V1\":240.13,\"P1\":-12,"
+        "\"P2\":100,\"t1\":21.1}\r\n",
+        "{\"MSG\":4294967295,\"TIME\":4294967295,\"V1\":240.13,\"P1\":-12,"
+        "\"P2\":100,\"E1\":7.000,\"E2\":-3.000,\"t1\":21.1}\r\n",
+        "{\"MSG\":4294967295,\"TIME\":4294967295,\"V1\":240.13,\"F\":50.0,"
+        "\"P1\":-12,\"P2\":100,\"E1\":7.000,\"E2\":-3.000,\"PF1\":-0.988,"
+        "\"PF2\":0.500,\"t1\":21.1}\r\n"};
+    const char *goldHdr[3] = {
+        "MSG,TIME,V1,P1,P2,t1,t2,t3,t4,t5,t6,t7,t8\r\n",
+        "MSG,TIME,V1,P1,P2,E1,E2,t1,t2,t3,t4,t5,t6,t7,t8\r\n",
+        "MSG,TIME,V1,F,P1,P2,E1,E2,PF1,PF2,t1,t2,t3,t4,t5,t6,t7,t8\r\n"};
+    const uint8_t sel[3] = {0, DATA_SEL_DEF, DATA_SEL_ALL};
+
+    chsActive.time = true;
+    for (size_t s = 0; s < 3u; s++) {
+      chsActive.sel = sel[s];
+      n = dataPackSerial(&dataset, line, TX_BUFFER_W, DATA_FMT_JSON,
+                         &chsActive);
+      if ((n != strlen(gold[s])) || (0 != strcmp(line, gold[s]))) {
+        printf("\n    Selection: %u Line: %s", sel[s], line);
+        return 1;
+      }
+      n = dataPackSerialHeader(line, TX_BUFFER_W, &chsActive);
+      if ((n != strlen(goldHdr[s])) || (0 != strncmp(line, goldHdr[s], n))) {
+        printf("\n    Selection: %u Header: %.*s", sel[s], (int)n, line);
+        return 1;
+      }
+    }
+  }
+  printf("Done!\n");
+
+  /* Below 5 VA a PF is not meaningful, so it is left out (CSV: empty) rather
+   * than sent as a placeholder that would not parse as a number. A negative
+   * PF (exporting) is sent with its sign. CT1 is at 5 VA and CT2 just below.
+   * Key:Value sends every CT. */
+  printf("  > Power factor below 5 VA ... ");
+  {
+    const char *gold[3] = {
+        "MSG:4294967295,TIME:4294967295,V1:240.13,F:50.0,P1:-12,P2:100,P3:0,"
+        "P4:0,P5:0,P6:0,E1:7.000,E2:-3.000,E3:0.000,E4:0.000,E5:0.000,"
+        "E6:0.000,PF1:-0.988,pulse1:0,pulse2:0,pulse3:0,t1:21.1\r\n",
+        "{\"MSG\":4294967295,\"TIME\":4294967295,\"V1\":240.13,\"F\":50.0,"
+        "\"P1\":-12,\"P2\":100,\"E1\":7.000,\"E2\":-3.000,\"PF1\":-0.988,"
+        "\"t1\":21.1}\r\n",
+        "4294967295,4294967295,240.13,50.0,-12,100,7.000,-3.000,"
+        "-0.988,,21.1,,,,,,,\r\n"};
+
+    chsActive.sel           = DATA_SEL_ALL;
+    ecm.CT[0].apparentPower = 5;
+    ecm.CT[1].apparentPower = 4;
+    for (size_t i = 2; i < NUM_CT; i++) {
+      ecm.CT[i].realPower     = 0;
+      ecm.CT[i].wattHour      = 0;
+      ecm.CT[i].apparentPower = 0;
+    }
+    for (size_t i = 0; i < NUM_OPA; i++) {
+      dataset.pulseCnt[i] = 0;
+    }
+    for (size_t f = 0; f < 3u; f++) {
+      n = dataPackSerial(&dataset, line, TX_BUFFER_W, fmts[f], &chsActive);
+      if ((n != strlen(gold[f])) || (0 != strcmp(line, gold[f]))) {
+        printf("\n    Format: %zu Line: %s", f, line);
+        return 1;
+      }
+    }
+  }
+  printf("Done!\n");
+
+  printf("\n");
+  return 0;
+}
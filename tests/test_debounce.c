@@ -0,0 +1,178 @@
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+
+#include "debounce.h"
+
+#define WINDOW 20u
+
+typedef struct Edge_ {
+  uint32_t t;
+  bool     level;
+} Edge_t;
+
+static Debounce_t db;
+static unsigned   nRise;
+static unsigned   nFall;
+
+static void count(void) {
+  if (db.level) {
+    nRise++;
+  } else {
+    nFall++;
+  }
+}
+
+/*! @brief Feed edges as the interrupt does, with the 1 ms update between them
+ *         as the main loop does, until tEnd
+ */
+static void feed(const Edge_t *pEdges, const size_t n, const uint32_t tStart,
+                 const uint32_t tEnd) {
+  size_t idx = 0;
+
+  for (uint32_t t = tStart; t != tEnd; t++) {
+    while ((idx < n) && (pEdges[idx].t == t)) {
+      if (debounceEdge(&db, pEdges[idx].level, t)) {
+        count();
+      }
+      idx++;
+    }
+    if (debounceUpdate(&db, t)) {
+      count();
+    }
+  }
+}
+
+static void reset(const bool level, const uint32_t t) {
+  debounceInit(&db, WINDOW, level, t);
+  nRise = 0;
+  nFall = 0;
+}
+
+int main(void) {
+
+  printf("---- emon32 debounce test ----\n\n");
+
+  printf("  > Clean pulses ... ");
+  {
+    const Edge_t edges[] = {
+        {100u, true}, {150u, false}, {1000u, true}, {1050u, false}};
+    reset(false, 0);
+    feed(edges, 4u, 0, 2000u);
+    if ((2u != nRise) || (2u != nFall) || db.level) {
+      printf("\n    Rise: %u Fall: %u\n", nRise, nFall);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  /* The contact bounces on closing and opening */
+  printf("  > Bouncy pulses ... ");
+  {
+    const Edge_t edges[] = {{100u, true},  {101u, false}, {102u, true},
+                            {104u, false}, {105u, true},  {300u, false},
+                            {301u, true},  {303u, false}, {1000u, true},
+                            {1002u, false}, {1003u, true}};
+    reset(false, 0);
+    feed(edges, 11u, 0, 2000u);
+    if ((2u != nRise) || (1u != nFall) || !db.level) {
+      printf("\n    Rise: %u Fall: %u\n", nRise, nFall);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  /* The rising edge is counted as it arrives, not after the window */
+  printf("  > Counted on the first edge ... ");
+  {
+    const Edge_t edges[] = {{100u, true}, {101u, false}, {102u, true}};
+    reset(false, 0);
+    feed(edges, 3u, 0, 101u);
+    if (1u != nRise) {
+      printf("\n    Rise: %u\n", nRise);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  /* A pulse shorter than the window: the opening is taken once the window has
+   * passed, so the next pulse is still counted */
+  printf("  > Settles inside the window ... ");
+  {
+    const Edge_t edges[] = {{100u, true}, {105u, false}, {500u, true}};
+    reset(false, 0);
+    feed(edges, 2u, 0, 110u);
+    if ((1u != nRise) || (0 != nFall)) {
+      printf("\n    Fall taken early\n");
+      return 1;
+    }
+    feed(edges + 2u, 1u, 110u, 600u);
+    if ((2u != nRise) || (1u != nFall)) {
+      printf("\n    Rise: %u Fall: %u\n", nRise, nFall);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  /* Bounce that carries on past the window is not taken until the contact
+   * has been steady for the window */
+  printf("  > Long bounce ... ");
+  {
+    const Edge_t edges[] = {{100u, true},  {109u, false}, {118u, true},
+                            {127u, false}, {136u, true},  {145u, false},
+                            {154u, true}};
+    reset(false, 0);
+    feed(edges, 7u, 0, 500u);
+    if ((1u != nRise) || (0 != nFall) || !db.level) {
+      printf("\n    Rise: %u Fall: %u\n", nRise, nFall);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  /* A polled input is sampled each millisecond. A single high sample is a
+   * spike and is not counted; a level held for the window is counted once,
+   * the window after it was first seen. */
+  printf("  > Polled input ... ");
+  {
+    reset(false, 0);
+    for (uint32_t t = 0; t < 500u; t++) {
+      debounceSample(&db, (100u == t) || ((200u <= t) && (t < 250u)), t);
+      if (debounceUpdate(&db, t)) {
+        count();
+        if ((db.level ? 220u : 270u) != t) {
+          printf("\n    Taken at: %u\n", t);
+          return 1;
+        }
+      }
+      if ((t < 200u) && (0 != (nRise + nFall))) {
+        printf("\n    Spike counted\n");
+        return 1;
+      }
+    }
+    if ((1u != nRise) || (1u != nFall)) {
+      printf("\n    Rise: %u Fall: %u\n", nRise, nFall);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  /* The window is measured across the millisecond counter wrapping */
+  printf("  > Timer wrap ... ");
+  {
+    const Edge_t edges[] = {{UINT32_MAX - 4u, true},
+                            {UINT32_MAX - 2u, false},
+                            {5u, true},
+                            {25u, false}};
+    reset(false, UINT32_MAX - 100u);
+    feed(edges, 4u, UINT32_MAX - 100u, 100u);
+    if ((1u != nRise) || (1u != nFall)) {
+      printf("\n    Rise: %u Fall: %u\n", nRise, nFall);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  printf("\n");
+  return 0;
+}
@@ -0,0 +1,79 @@
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "frame.h"
+
+/* Software implementation, linked in with frame.c through emonCM_test.h */
+uint16_t calcCRC16_ccitt(const void *pSrc, size_t n);
+
+int main(void) {
+
+  const uint8_t payload[] = {0x01, 0x00, 0x00, 0x00, 0xA5, 0x00, 0xFF, 0x7F};
+  uint8_t       buf[32];
+  FrameInfo_t   info;
+  size_t        w;
+
+  printf("---- emon32 binary frame test ----\n\n");
+
+  /* CRC16-CCITT (0xFFFF initial) check value */
+  printf("  > CRC16 check value ... ");
+  {
+    const uint16_t crc = calcCRC16_ccitt("123456789", 9);
+    if (0x29B1 != crc) {
+      printf("\n    CRC: 0x%04X Expected: 0x29B1\n", crc);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  printf("  > Round trip ... ");
+  w = frameEncode(payload, sizeof(payload), 17, buf, sizeof(buf));
+  if ((sizeof(payload) + FRAME_OVERHEAD) != w) {
+    printf("\n    Length: %zu\n", w);
+    return 1;
+  }
+  if ((FRAME_OK != frameDecode(buf, w, &info)) || (17 != info.node) ||
+      (sizeof(payload) != info.n) ||
+      (0 != memcmp(payload, info.pPayload, sizeof(payload)))) {
+    printf("\n    Decode failed\n");
+    return 1;
+  }
+  printf("Done!\n");
+
+  printf("  > Frame too large for buffer ... ");
+  if (0 != frameEncode(payload, sizeof(payload), 17, buf, (w - 1u))) {
+    printf("\n    Encoded into a short buffer\n");
+    return 1;
+  }
+  printf("Done!\n");
+
+  /* Any single corrupted byte after the header checks is caught by the CRC */
+  printf("  > Corruption rejected ... ");
+  w = frameEncode(payload, sizeof(payload), 17, buf, sizeof(buf));
+  for (size_t i = 0; i < w; i++) {
+    const FrameStatus_t expect = (0 == i)   ? FRAME_BAD_SOF
+                                 : (1 == i) ? FRAME_BAD_VERSION
+                                 : (3 == i) ? FRAME_SHORT
+                                            : FRAME_BAD_CRC;
+    buf[i] ^= 0x10;
+    const FrameStatus_t status = frameDecode(buf, w, &info);
+    buf[i] ^= 0x10;
+    if (expect != status) {
+      printf("\n    Byte %zu: status %d Expected: %d\n", i, status, expect);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  printf("  > Truncated frame ... ");
+  if ((FRAME_SHORT != frameDecode(buf, (w - 1u), &info)) ||
+      (FRAME_SHORT != frameDecode(buf, 3u, &info))) {
+    printf("\n    Truncated frame accepted\n");
+    return 1;
+  }
+  printf("Done!\n");
+
+  printf("\n");
+  return 0;
+}
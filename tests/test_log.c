@@ -0,0 +1,97 @@
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "logger.h"
+
+static char     out[256];
+static uint32_t now = 0;
+static uint32_t epochNow = 0;
+
+static void     capturePuts(const char *s) { strcat(out, s); }
+static uint32_t captureMillis(void) { return now; }
+static uint32_t captureEpoch(void) { return epochNow; }
+
+int main(void) {
+
+  const LogOpt_t opt = {capturePuts, captureMillis};
+  LogLevel_t     level;
+
+  printf("---- emon32 log test ----\n\n");
+
+  /* Nothing is sent before the output is set */
+  printf("  > Silent before init ... ");
+  out[0] = 0;
+  LOG_ERR("lost\r\n");
+  if (out[0] || logEnabled(LOG_ERROR)) {
+    printf("\n    Output: %s\n", out);
+    return 1;
+  }
+  printf("Done!\n");
+
+  printf("  > Default threshold ... ");
+  logInit(&opt);
+  now = 1234u;
+  LOG_WRN("warn\r\n");
+  LOG_INF("info\r\n");
+  if ((LOG_LEVEL_DEF != logLevelGet()) ||
+      (0 != strcmp(out, "WRN:1234:warn\r\n"))) {
+    printf("\n    Output: %s\n", out);
+    return 1;
+  }
+  printf("Done!\n");
+
+  /* Lowering the threshold takes effect on the next message */
+  printf("  > Threshold change ... ");
+  out[0] = 0;
+  logLevelSet(LOG_ERROR);
+  LOG_WRN("warn\r\n");
+  LOG_ERR("err\r\n");
+  logLevelSet(LOG_DEBUG);
+  now = 5u;
+  LOG_DBG("dbg\r\n");
+  if (0 != strcmp(out, "ERR:1234:err\r\nDBG:5:dbg\r\n")) {
+    printf("\n    Output: %s\n", out);
+    return 1;
+  }
+  printf("Done!\n");
+
+  printf("  > Parse levels ... ");
+  const char *valid[] = {"0", "1", "2", "3", "error", "warn", "info", "debug"};
+  for (size_t i = 0; i < 8u; i++) {
+    if (!logLevelParse(valid[i], &level) || ((i % 4u) != level) ||
+        (0 != strcmp(logLevelName(level), valid[4u + (i % 4u)]))) {
+      printf("\n    Failed: %s\n", valid[i]);
+      return 1;
+    }
+  }
+  const char *invalid[] = {"", "4", "01", "warning", "Info"};
+  for (size_t i = 0; i < 5u; i++) {
+    if (logLevelParse(invalid[i], &level)) {
+      printf("\n    Accepted: %s\n", invalid[i]);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  /* With a wall clock, the time is ISO-8601 until the clock is unknown */
+  printf("  > Wall-clock prefix ... ");
+  {
+    const LogOpt_t optClock = {capturePuts, captureMillis, captureEpoch};
+    logInit(&optClock);
+    out[0]   = 0;
+    now      = 77u;
+    epochNow = 1700000000u;
+    LOG_WRN("a\r\n");
+    epochNow = 0;
+    LOG_WRN("b\r\n");
+    if (0 != strcmp(out, "WRN:2023-11-14T22:13:20Z:a\r\nWRN:77:b\r\n")) {
+      printf("\n    Output: %s\n", out);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  printf("\n---- Done! ----\n");
+  return 0;
+}
@@ -0,0 +1,326 @@
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "modbus.h"
+
+#define SLAVE_ADDR 17u
+#define BAUD       9600u
+#define CHAR_US    1146u /* 11 bits at 9600 baud */
+#define NUM_INPUT  8u
+#define NUM_HOLD   4u
+
+static uint16_t inputRegs[NUM_INPUT];
+static uint16_t holdingRegs[NUM_HOLD];
+static uint32_t tNow;
+
+static ModbusExc_t readInput(uint16_t addr, uint16_t *pVal) {
+  if (addr >= NUM_INPUT) {
+    return MODBUS_EXC_ILLEGAL_ADDRESS;
+  }
+  *pVal = inputRegs[addr];
+  return MODBUS_EXC_NONE;
+}
+
+static ModbusExc_t readHolding(uint16_t addr, uint16_t *pVal) {
+  if (addr >= NUM_HOLD) {
+    return MODBUS_EXC_ILLEGAL_ADDRESS;
+  }
+  *pVal = holdingRegs[addr];
+  return MODBUS_EXC_NONE;
+}
+
+static ModbusExc_t writeHolding(uint16_t addr, uint16_t val) {
+  if (addr >= NUM_HOLD) {
+    return MODBUS_EXC_ILLEGAL_ADDRESS;
+  }
+  if (val > 1000u) {
+    return MODBUS_EXC_ILLEGAL_VALUE;
+  }
+  holdingRegs[addr] = val;
+  return MODBUS_EXC_NONE;
+}
+
+static const ModbusMap_t map = {readInput, readHolding, writeHolding};
+
+/* Feed a frame one byte at a time, as the UART interrupt would, then wait for
+ * the inter-frame gap and build the response. */
+static size_t transact(ModbusSlave_t *pSlave, const uint8_t *pReq, size_t n,
+                       uint8_t *pResp) {
+  for (size_t i = 0; i < n; i++) {
+    modbusRxByte(pSlave, pReq[i], tNow);
+    tNow += CHAR_US;
+    if (modbusFrameEnd(pSlave, tNow)) {
+      printf("\n    Frame ended early at byte %zu\n", i);
+      return SIZE_MAX;
+    }
+  }
+  tNow += pSlave->gap_us;
+  if (!modbusFrameEnd(pSlave, tNow)) {
+    printf("\n    Frame did not end after the gap\n");
+    return SIZE_MAX;
+  }
+  return modbusReply(pSlave, pResp, MODBUS_FRAME_MAX);
+}
+
+/* Append the CRC to a request with its body already in place */
+static size_t withCRC(uint8_t *pReq, size_t n) {
+  const uint16_t crc = modbusCRC16(pReq, n);
+  pReq[n]            = (uint8_t)crc;
+  pReq[n + 1u]       = (uint8_t)(crc >> 8);
+  return n + 2u;
+}
+
+static int checkResp(const uint8_t *pResp, size_t n, const uint8_t *pExpect,
+                     size_t nExpect) {
+  if ((n != nExpect) || (0 != memcmp(pResp, pExpect, n))) {
+    printf("\n    Response (%zu):", n);
+    for (size_t i = 0; (SIZE_MAX != n) && (i < n); i++) {
+      printf(" %02X", pResp[i]);
+    }
+    printf("\n    Expected (%zu):", nExpect);
+    for (size_t i = 0; i < nExpect; i++) {
+      printf(" %02X", pExpect[i]);
+    }
+    printf("\n");
+    return 1;
+  }
+  return 0;
+}
+
+int main(void) {
+
+  ModbusSlave_t slave;
+  uint8_t       req[MODBUS_FRAME_MAX];
+  uint8_t       resp[MODBUS_FRAME_MAX];
+  size_t        n;
+
+  printf("---- emon32 Modbus RTU test ----\n\n");
+
+  for (size_t i = 0; i < NUM_INPUT; i++) {
+    inputRegs[i] = (uint16_t)(0x1100u + i);
+  }
+  modbusInit(&slave, &map, SLAVE_ADDR, BAUD);
+
+  printf("  > CRC16 check value ... ");
+  {
+    const uint16_t crc = modbusCRC16("123456789", 9);
+    if (0x4B37 != crc) {
+      printf("\n    CRC: 0x%04X Expected: 0x4B37\n", crc);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  printf("  > Inter-frame gap ... ");
+  {
+    ModbusSlave_t fast;
+    modbusInit(&fast, &map, SLAVE_ADDR, 115200u);
+    if ((4010u != slave.gap_us) || (1750u != fast.gap_us)) {
+      printf("\n    Gap: %u / %u us\n", slave.gap_us, fast.gap_us);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  printf("  > Read input registers (0x04) ... ");
+  {
+    const uint8_t rq[]     = {0x11, 0x04, 0x00, 0x02, 0x00, 0x03};
+    const uint8_t expect[] = {0x11, 0x04, 0x06, 0x11, 0x02,
+                              0x11, 0x03, 0x11, 0x04};
+    memcpy(req, rq, sizeof(rq));
+    n = transact(&slave, req, withCRC(req, sizeof(rq)), resp);
+    memcpy(req, expect, sizeof(expect));
+    if (checkResp(resp, n, req, withCRC(req, sizeof(expect)))) {
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  printf("  > Known frame CRC ... ");
+  {
+    /* 01 03 00 00 00 0A C5 CD is a widely published request */
+    const uint8_t rq[] = {0x01, 0x03, 0x00, 0x00, 0x00, 0x0A, 0xC5, 0xCD};
+    if (0 != modbusCRC16(rq, sizeof(rq))) {
+      printf("\n    CRC of a valid frame is not 0\n");
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  printf("  > Write single register (0x06) ... ");
+  {
+    const uint8_t rq[] = {0x11, 0x06, 0x00, 0x01, 0x01, 0x2C};
+    memcpy(req, rq, sizeof(rq));
+    const size_t w = withCRC(req, sizeof(rq));
+    n              = transact(&slave, req, w, resp);
+    if (checkResp(resp, n, req, w) || (300u != holdingRegs[1])) {
+      printf("    Holding 1: %u\n", holdingRegs[1]);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  printf("  > Write multiple registers (0x10) ... ");
+  {
+    const uint8_t rq[]     = {0x11, 0x10, 0x00, 0x02, 0x00, 0x02,
+                              0x04, 0x00, 0x0A, 0x01, 0x02};
+    const uint8_t expect[] = {0x11, 0x10, 0x00, 0x02, 0x00, 0x02};
+    memcpy(req, rq, sizeof(rq));
+    n = transact(&slave, req, withCRC(req, sizeof(rq)), resp);
+    memcpy(req, expect, sizeof(expect));
+    if (checkResp(resp, n, req, withCRC(req, sizeof(expect))) ||
+        (10u != holdingRegs[2]) || (258u != holdingRegs[3])) {
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  printf("  > Read holding registers (0x03) ... ");
+  {
+    const uint8_t rq[]     = {0x11, 0x03, 0x00, 0x01, 0x00, 0x03};
+    const uint8_t expect[] = {0x11, 0x03, 0x06, 0x01, 0x2C,
+                              0x00, 0x0A, 0x01, 0x02};
+    memcpy(req, rq, sizeof(rq));
+    n = transact(&slave, req, withCRC(req, sizeof(rq)), resp);
+    memcpy(req, expect, sizeof(expect));
+    if (checkResp(resp, n, req, withCRC(req, sizeof(expect)))) {
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  printf("  > Exceptions ... ");
+  {
+    const struct {
+      uint8_t rq[8];
+      size_t  n;
+      uint8_t fn;
+      uint8_t exc;
+    } cases[] = {
+        /* Unsupported function */
+        {{0x11, 0x05, 0x00, 0x01, 0xFF, 0x00}, 6, 0x05, 0x01},
+        /* Beyond the end of the map */
+        {{0x11, 0x04, 0x00, 0x06, 0x00, 0x03}, 6, 0x04, 0x02},
+        /* No registers, and too many */
+        {{0x11, 0x03, 0x00, 0x00, 0x00, 0x00}, 6, 0x03, 0x03},
+        {{0x11, 0x03, 0x00, 0x00, 0x00, 0x7E}, 6, 0x03, 0x03},
+        /* Value rejected by the map */
+        {{0x11, 0x06, 0x00, 0x00, 0x03, 0xE9}, 6, 0x06, 0x03},
+        /* Byte count does not match the quantity */
+        {{0x11, 0x10, 0x00, 0x00, 0x00, 0x01, 0x04}, 7, 0x10, 0x03},
+        /* Wrong length for a read */
+        {{0x11, 0x04, 0x00, 0x00, 0x00, 0x01, 0x00}, 7, 0x04, 0x03}};
+
+    for (size_t i = 0; i < (sizeof(cases) / sizeof(cases[0])); i++) {
+      uint8_t expect[8] = {SLAVE_ADDR, (uint8_t)(cases[i].fn | 0x80u),
+                           cases[i].exc};
+      memcpy(req, cases[i].rq, cases[i].n);
+      n = transact(&slave, req, withCRC(req, cases[i].n), resp);
+      if (checkResp(resp, n, expect, withCRC(expect, 3))) {
+        printf("    Case %zu\n", i);
+        return 1;
+      }
+    }
+  }
+  printf("Done!\n");
+
+  printf("  > CRC error ignored ... ");
+  {
+    const uint8_t  rq[]   = {0x11, 0x06, 0x00, 0x00, 0x00, 0x07};
+    const uint32_t crcErr = slave.stats.crcErrors;
+    memcpy(req, rq, sizeof(rq));
+    const size_t w = withCRC(req, sizeof(rq));
+    req[w - 1u] ^= 0x01;
+    n = transact(&slave, req, w, resp);
+    if ((0 != n) || (0 != holdingRegs[0]) ||
+        ((crcErr + 1u) != slave.stats.crcErrors)) {
+      printf("\n    Response %zu, holding 0: %u\n", n, holdingRegs[0]);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  printf("  > Other slave ignored ... ");
+  {
+    const uint8_t rq[] = {0x12, 0x06, 0x00, 0x00, 0x00, 0x07};
+    memcpy(req, rq, sizeof(rq));
+    n = transact(&slave, req, withCRC(req, sizeof(rq)), resp);
+    if ((0 != n) || (0 != holdingRegs[0])) {
+      printf("\n    Response %zu, holding 0: %u\n", n, holdingRegs[0]);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  printf("  > Broadcast write, no response ... ");
+  {
+    const uint8_t rq[] = {0x00, 0x06, 0x00, 0x00, 0x00, 0x07};
+    memcpy(req, rq, sizeof(rq));
+    n = transact(&slave, req, withCRC(req, sizeof(rq)), resp);
+    if ((0 != n) || (7u != holdingRegs[0])) {
+      printf("\n    Response %zu, holding 0: %u\n", n, holdingRegs[0]);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  /* A partial frame followed by silence is discarded, and the next request
+   * is handled normally. */
+  printf("  > Partial frame then gap ... ");
+  {
+    const uint8_t rq[]     = {0x11, 0x04, 0x00, 0x00, 0x00, 0x01};
+    const uint8_t expect[] = {0x11, 0x04, 0x02, 0x11, 0x00};
+    modbusRxByte(&slave, 0x11, tNow);
+    modbusRxByte(&slave, 0x04, (tNow + CHAR_US));
+    tNow += (2u * CHAR_US) + slave.gap_us;
+    memcpy(req, rq, sizeof(rq));
+    n = transact(&slave, req, withCRC(req, sizeof(rq)), resp);
+    memcpy(req, expect, sizeof(expect));
+    if (checkResp(resp, n, req, withCRC(req, sizeof(expect)))) {
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  printf("  > Bytes dropped while a frame is pending ... ");
+  {
+    const uint8_t  rq[]    = {0x11, 0x04, 0x00, 0x00, 0x00, 0x01};
+    const uint32_t overrun = slave.stats.overruns;
+    memcpy(req, rq, sizeof(rq));
+    const size_t w = withCRC(req, sizeof(rq));
+    for (size_t i = 0; i < w; i++) {
+      modbusRxByte(&slave, req[i], tNow);
+      tNow += CHAR_US;
+    }
+    tNow += slave.gap_us;
+    (void)modbusFrameEnd(&slave, tNow);
+    modbusRxByte(&slave, 0x55, tNow);
+    n = modbusReply(&slave, resp, sizeof(resp));
+    if ((7u != n) || ((overrun + 1u) != slave.stats.overruns)) {
+      printf("\n    Response %zu, overruns %u\n", n,
+             slave.stats.overruns - overrun);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  printf("  > Oversized frame dropped ... ");
+  {
+    for (size_t i = 0; i < (MODBUS_FRAME_MAX + 4u); i++) {
+      modbusRxByte(&slave, SLAVE_ADDR, tNow);
+      tNow += CHAR_US;
+    }
+    tNow += slave.gap_us;
+    (void)modbusFrameEnd(&slave, tNow);
+    n = modbusReply(&slave, resp, sizeof(resp));
+    if (0 != n) {
+      printf("\n    Response %zu\n", n);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  printf("\n");
+  return 0;
+}
@@ -0,0 +1,149 @@
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "outQueue.h"
+
+#define DEPTH  1024u
+#define BUDGET 64u
+
+static OutQueue_t q;
+static uint8_t    qBuf[DEPTH];
+static char       sent[4096];
+static size_t     nSent;
+static size_t     sinkRoom; /* Bytes the sink will take before it is full */
+
+/* As the USB CDC buffer: takes what fits, and is emptied every tick */
+static size_t sinkWrite(const void *pSrc, size_t n) {
+  if (n > sinkRoom) {
+    n = sinkRoom;
+  }
+  memcpy(sent + nSent, pSrc, n);
+  nSent += n;
+  sinkRoom -= n;
+  return n;
+}
+
+/*! @brief Build a report line of about n characters, as a 12 channel report
+ *         with diagnostics might be
+ */
+static size_t makeReport(char *pDst, const size_t n, const unsigned msg) {
+  size_t w = (size_t)sprintf(pDst, "MSG:%u", msg);
+  for (unsigned i = 1; w < (n - 20u); i++) {
+    w += (size_t)sprintf(pDst + w, ",P%u:%u,E%u:%u", i, (i * 37u), i, msg);
+  }
+  w += (size_t)sprintf(pDst + w, "\r\n");
+  return w;
+}
+
+/*! @brief Service the queue as the main loop does, until it is empty
+ *  @return number of invocations, 0 if any sent more than the budget
+ */
+static unsigned drain(const char *pStatus, const unsigned statusAt) {
+  unsigned calls = 0;
+
+  while (outQueueCount(&q)) {
+    if (BUDGET < outQueueService(&q, BUDGET)) {
+      return 0;
+    }
+    calls++;
+    /* A status line is queued whole in the middle of the report */
+    if (pStatus && (statusAt == calls)) {
+      if (!outQueuePut(&q, pStatus, strlen(pStatus))) {
+        return 0;
+      }
+    }
+    /* Every other call, the next tick empties the sink */
+    if (0 == (calls % 2u)) {
+      sinkRoom = BUDGET;
+    }
+  }
+  return calls;
+}
+
+int main(void) {
+
+  char     report[768];
+  char     gold[1024];
+  size_t   n;
+  unsigned calls;
+
+  printf("---- emon32 output queue test ----\n\n");
+
+  outQueueInit(&q, qBuf, DEPTH, &sinkWrite);
+
+  /* A large report is sent whole over several calls */
+  printf("  > Large report ... ");
+  n        = makeReport(report, 700u, 1u);
+  nSent    = 0;
+  sinkRoom = BUDGET;
+  if (!outQueuePut(&q, report, n)) {
+    printf("\n    Not queued\n");
+    return 1;
+  }
+  calls = drain(NULL, 0);
+  if ((calls < (n / BUDGET)) || (nSent != n) ||
+      (0 != memcmp(sent, report, n))) {
+    printf("\n    Calls: %u Sent: %zu of %zu\n", calls, nSent, n);
+    return 1;
+  }
+  printf("Done!\n");
+
+  /* The status line follows the report, never inside it, including when the
+   * ring wraps */
+  printf("  > Status line mid-report ... ");
+  for (unsigned msg = 2u; msg < 8u; msg++) {
+    const char *status = "WRN:1234:Report truncated.\r\n";
+
+    n = makeReport(report, 500u + (msg * 29u), msg);
+    (void)memcpy(gold, report, n);
+    (void)strcpy(gold + n, status);
+    nSent    = 0;
+    sinkRoom = BUDGET;
+    (void)outQueuePut(&q, report, n);
+
+    calls = drain(status, 3u);
+    if ((0 == calls) || (nSent != strlen(gold)) ||
+        (0 != memcmp(sent, gold, nSent))) {
+      printf("\n    Message: %u Sent: %zu\n", msg, nSent);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  /* Nothing is added unless all of it fits */
+  printf("  > Whole messages only ... ");
+  n = makeReport(report, 700u, 9u);
+  if (!outQueuePut(&q, report, n) || outQueuePut(&q, report, n) ||
+      (n != outQueueCount(&q))) {
+    printf("\n    Count: %zu\n", outQueueCount(&q));
+    return 1;
+  }
+  outQueueClear(&q);
+  if (0 != outQueueCount(&q)) {
+    printf("\n    Not cleared\n");
+    return 1;
+  }
+  printf("Done!\n");
+
+  /* A full sink stops the call short; the rest goes on the next */
+  printf("  > Full sink ... ");
+  nSent    = 0;
+  sinkRoom = 10u;
+  (void)outQueuePut(&q, "0123456789abcdef", 16u);
+  if ((10u != outQueueService(&q, BUDGET)) || (6u != outQueueCount(&q)) ||
+      (0 != outQueueService(&q, BUDGET))) {
+    printf("\n    Sent: %zu\n", nSent);
+    return 1;
+  }
+  sinkRoom = BUDGET;
+  if ((6u != outQueueService(&q, BUDGET)) ||
+      (0 != memcmp(sent, "0123456789abcdef", 16u))) {
+    printf("\n    Sent: %zu\n", nSent);
+    return 1;
+  }
+  printf("Done!\n");
+
+  printf("\n");
+  return 0;
+}
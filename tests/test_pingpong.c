@@ -0,0 +1,177 @@
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "pingPong.h"
+
+#define N_CH 15u
+
+/* Mock of the ADC scan and DMA: each beat writes the scanned channel into
+ * the buffer being filled, tagged with the set it belongs to */
+typedef struct Smp_ {
+  uint8_t  ch;
+  uint32_t set;
+} Smp_t;
+
+static PingPong_t pp;
+static Smp_t      buf[2][N_CH];
+static uint8_t    dmaFill; /* Buffer the mock DMA is writing */
+static size_t     dmaPos;
+static uint8_t    adcCh; /* Next channel in the ADC scan */
+static uint32_t   nSet;
+
+static void dmaBeat(void) {
+  buf[dmaFill][dmaPos].ch  = adcCh;
+  buf[dmaFill][dmaPos].set = nSet;
+  adcCh                    = (adcCh + 1u) % N_CH;
+  if (N_CH == ++dmaPos) {
+    dmaPos  = 0;
+    dmaFill = dmaFill ^ 1u;
+    nSet++;
+    pingPongComplete(&pp);
+  }
+}
+
+static void dmaSets(const unsigned n) {
+  for (unsigned i = 0; i < (n * N_CH); i++) {
+    dmaBeat();
+  }
+}
+
+/* As the driver: the transfer stops, the scan and DMA restart together */
+static void dmaResync(void) {
+  dmaFill = 0;
+  dmaPos  = 0;
+  adcCh   = 0;
+  pingPongResync(&pp);
+}
+
+/*! @brief Check a taken buffer is one whole set in channel order
+ *  @return the set number, or UINT32_MAX if it is not
+ */
+static uint32_t checkSet(const uint8_t idx) {
+  for (size_t i = 0; i < N_CH; i++) {
+    if ((buf[idx][i].ch != i) || (buf[idx][i].set != buf[idx][0].set)) {
+      return UINT32_MAX;
+    }
+  }
+  return buf[idx][0].set;
+}
+
+int main(void) {
+
+  uint8_t  idx;
+  uint32_t set;
+
+  printf("---- emon32 ping-pong hand-off test ----\n\n");
+
+  pingPongInit(&pp);
+
+  printf("  > Nothing before a set completes ... ");
+  for (size_t i = 0; i < (N_CH - 1u); i++) {
+    dmaBeat();
+  }
+  if (pingPongTake(&pp, &idx)) {
+    printf("\n    Took a set being filled\n");
+    return 1;
+  }
+  dmaBeat();
+  printf("Done!\n");
+
+  /* Processing keeps up: every set is taken once, in order */
+  printf("  > Alternating sets ... ");
+  for (uint32_t i = 0; i < 8u; i++) {
+    if (!pingPongTake(&pp, &idx) || (idx != (i & 1u)) ||
+        (i != checkSet(idx)) || (idx == dmaFill)) {
+      printf("\n    Set %u: buffer %u\n", i, idx);
+      return 1;
+    }
+    if (pingPongTake(&pp, &idx)) {
+      printf("\n    Set %u taken twice\n", i);
+      return 1;
+    }
+    pingPongRelease(&pp);
+    dmaSets(1);
+  }
+  if ((8u != pp.stats.sets) || (0 != pp.stats.overruns)) {
+    printf("\n    Sets: %u Overruns: %u\n", pp.stats.sets, pp.stats.overruns);
+    return 1;
+  }
+  printf("Done!\n");
+
+  /* Processing falls behind: the DMA completes the next set, and moves into
+   * the buffer being processed */
+  printf("  > Overrun while processing ... ");
+  (void)pingPongTake(&pp, &idx);
+  dmaSets(1);
+  if (1u != pp.stats.overruns) {
+    printf("\n    Overruns: %u\n", pp.stats.overruns);
+    return 1;
+  }
+  pingPongRelease(&pp);
+  /* The set completed meanwhile is whole, and still to be processed */
+  set = checkSet(dmaFill ^ 1u);
+  if (!pingPongTake(&pp, &idx) || (idx == dmaFill) ||
+      (set != checkSet(idx)) || (UINT32_MAX == set)) {
+    printf("\n    Buffer: %u\n", idx);
+    return 1;
+  }
+  pingPongRelease(&pp);
+  printf("Done!\n");
+
+  /* A set is never taken: the DMA overwrites it, and only the newest is
+   * offered */
+  printf("  > Overrun while waiting ... ");
+  dmaSets(3);
+  if ((3u != pp.stats.overruns) || !pingPongTake(&pp, &idx) ||
+      ((nSet - 1u) != checkSet(idx))) {
+    printf("\n    Overruns: %u Set: %u\n", pp.stats.overruns, checkSet(idx));
+    return 1;
+  }
+  pingPongRelease(&pp);
+  printf("Done!\n");
+
+  /* A transfer error part way through a set: the scan moves on while the DMA
+   * has stopped. Without the restart every later sample would be on the wrong
+   * channel. */
+  printf("  > Resync after a transfer error ... ");
+  dmaSets(1);
+  for (size_t i = 0; i < 4u; i++) {
+    dmaBeat();
+  }
+  adcCh = (adcCh + 3u) % N_CH;
+  dmaResync();
+  if (pingPongTake(&pp, &idx) || (1u != pp.stats.resyncs)) {
+    printf("\n    Completed set not discarded\n");
+    return 1;
+  }
+  for (uint32_t i = 0; i < 4u; i++) {
+    dmaSets(1);
+    if (!pingPongTake(&pp, &idx) || (idx != (i & 1u)) ||
+        (UINT32_MAX == checkSet(idx))) {
+      printf("\n    Set %u not aligned\n", i);
+      return 1;
+    }
+    pingPongRelease(&pp);
+  }
+  printf("Done!\n");
+
+  /* A restart while a set is being processed; the next set is in buffer 0 */
+  printf("  > Resync while processing ... ");
+  dmaSets(1);
+  (void)pingPongTake(&pp, &idx);
+  dmaResync();
+  pingPongRelease(&pp);
+  set = pp.stats.overruns;
+  dmaSets(1);
+  if ((set != pp.stats.overruns) || !pingPongTake(&pp, &idx) ||
+      (0 != idx) || (UINT32_MAX == checkSet(idx))) {
+    printf("\n    Overruns: %u Buffer: %u\n", pp.stats.overruns, idx);
+    return 1;
+  }
+  printf("Done!\n");
+
+  printf("\n");
+  return 0;
+}
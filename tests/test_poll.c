@@ -0,0 +1,260 @@
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "poll.h"
+
+#define NODE 12u
+
+static uint16_t    cal[4]    = {10000u, 9000u, 8000u, 7000u};
+static uint32_t    calWrites = 0;
+static PollSlave_t slave;
+static uint8_t     bus[POLL_TX_MAX];
+static uint8_t     frame[POLL_TX_MAX];
+
+static PollStatus_t readCal(uint8_t ch, uint16_t *pVal) {
+  if (ch >= 4u) {
+    return POLL_ERR_ARG;
+  }
+  *pVal = cal[ch];
+  return POLL_OK;
+}
+
+static PollStatus_t readData(uint8_t range, uint8_t *pDst, size_t m,
+                             size_t *pN) {
+  if (range > 2u) {
+    return POLL_ERR_ARG;
+  }
+  if (2u == range) {
+    return POLL_ERR_BUSY;
+  }
+  (void)m;
+  memset(pDst, (0xA0 + range), 40u);
+  *pN = 40u;
+  return POLL_OK;
+}
+
+static PollStatus_t readDiag(uint8_t *pDst, size_t m, size_t *pN) {
+  const uint32_t diag[2] = {1u, 2u};
+  (void)m;
+  memcpy(pDst, diag, sizeof(diag));
+  *pN = sizeof(diag);
+  return POLL_OK;
+}
+
+static PollStatus_t writeCal(uint8_t ch, uint16_t val) {
+  if (ch >= 4u) {
+    return POLL_ERR_ARG;
+  }
+  cal[ch] = val;
+  calWrites++;
+  return POLL_OK;
+}
+
+static const PollMap_t map = {readData, readDiag, readCal, writeCal};
+
+/*! @brief Send a request to the slave as a master would, and get the response
+ *  @return the number of bytes sent by the slave, 0 if it was silent
+ */
+static size_t transact(uint8_t node, PollCmd_t cmd, uint8_t seq,
+                       const void *pArg, uint8_t nArg) {
+  const size_t n = pollRequest(node, cmd, seq, pArg, nArg, bus, sizeof(bus));
+  for (size_t i = 0; i < n; i++) {
+    pollRxByte(&slave, bus[i]);
+  }
+  return pollReply(&slave, bus, sizeof(bus));
+}
+
+/*! @brief Decode a response from the bus and check it answers the request
+ *  @return the check result; pInfo holds the response if POLL_CHECK_OK
+ */
+static PollCheck_t check(size_t n, uint8_t node, PollCmd_t cmd, uint8_t seq,
+                         FrameInfo_t *pInfo) {
+  size_t nFrame;
+
+  if ((n < 2u) || (COBS_DELIM != bus[n - 1u]) ||
+      (COBS_OK != cobsDecode(bus, (n - 1u), frame, sizeof(frame), &nFrame))) {
+    return POLL_CHECK_FRAME;
+  }
+  return pollReplyCheck(frame, nFrame, node, cmd, seq, pInfo);
+}
+
+int main(void) {
+
+  FrameInfo_t info;
+  PollCheck_t res;
+  size_t      n;
+
+  printf("---- emon32 polled protocol test ----\n\n");
+
+  pollInit(&slave, &map, NODE);
+
+  printf("  > Addressed requests ... ");
+  {
+    const uint8_t range = 1u;
+    n                   = transact(NODE, POLL_CMD_DATA, 1u, &range, 1u);
+    res                 = check(n, NODE, POLL_CMD_DATA, 1u, &info);
+    if ((POLL_CHECK_OK != res) || (43u != info.n) ||
+        (POLL_OK != info.pPayload[2]) || (0xA1 != info.pPayload[3]) ||
+        (0xA1 != info.pPayload[42])) {
+      printf("\n    Data: %d %u\n", res, info.n);
+      return 1;
+    }
+
+    n   = transact(NODE, POLL_CMD_DIAG, 2u, 0, 0);
+    res = check(n, NODE, POLL_CMD_DIAG, 2u, &info);
+    if ((POLL_CHECK_OK != res) || (11u != info.n) || (1u != info.pPayload[3]) ||
+        (2u != info.pPayload[7])) {
+      printf("\n    Diagnostics: %d %u\n", res, info.n);
+      return 1;
+    }
+
+    const uint8_t ch = 3u;
+    n                = transact(NODE, POLL_CMD_CAL_RD, 3u, &ch, 1u);
+    res              = check(n, NODE, POLL_CMD_CAL_RD, 3u, &info);
+    if ((POLL_CHECK_OK != res) || (5u != info.n) ||
+        (7000u != (info.pPayload[3] | (info.pPayload[4] << 8)))) {
+      printf("\n    Calibration read: %d %u\n", res, info.n);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  printf("  > Errors ... ");
+  {
+    const uint8_t range = 2u;
+    const uint8_t ch    = 9u;
+
+    n   = transact(NODE, POLL_CMD_DATA, 4u, &range, 1u);
+    res = check(n, NODE, POLL_CMD_DATA, 4u, &info);
+    if ((POLL_CHECK_OK != res) || (3u != info.n) ||
+        (POLL_ERR_BUSY != info.pPayload[2])) {
+      printf("\n    Busy: %d\n", res);
+      return 1;
+    }
+    n   = transact(NODE, POLL_CMD_CAL_RD, 5u, &ch, 1u);
+    res = check(n, NODE, POLL_CMD_CAL_RD, 5u, &info);
+    if ((POLL_CHECK_OK != res) || (POLL_ERR_ARG != info.pPayload[2])) {
+      printf("\n    Argument: %d\n", res);
+      return 1;
+    }
+    n   = transact(NODE, POLL_CMD_DATA, 6u, 0, 0);
+    res = check(n, NODE, POLL_CMD_DATA, 6u, &info);
+    if ((POLL_CHECK_OK != res) || (POLL_ERR_ARG != info.pPayload[2])) {
+      printf("\n    Missing argument: %d\n", res);
+      return 1;
+    }
+    n   = transact(NODE, (PollCmd_t)0x7Fu, 7u, 0, 0);
+    res = check(n, NODE, (PollCmd_t)0x7Fu, 7u, &info);
+    if ((POLL_CHECK_OK != res) || (POLL_ERR_CMD != info.pPayload[2])) {
+      printf("\n    Command: %d\n", res);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  /* Requests for other nodes, and other nodes' responses, are not answered */
+  printf("  > Wrong node is silent ... ");
+  {
+    const uint32_t answered = slave.stats.frames;
+    const uint8_t  rsp[3]   = {(POLL_CMD_DIAG | POLL_REPLY), 8u, POLL_OK};
+
+    if ((0 != transact((NODE + 1u), POLL_CMD_DIAG, 8u, 0, 0)) ||
+        (0 != transact(0, POLL_CMD_DIAG, 8u, 0, 0))) {
+      printf("\n    Answered another node\n");
+      return 1;
+    }
+    if (0 != transact(NODE, (PollCmd_t)rsp[0], rsp[1], (rsp + 2u), 1u)) {
+      printf("\n    Answered a response\n");
+      return 1;
+    }
+    if (answered != slave.stats.frames) {
+      printf("\n    Frames: %u\n", slave.stats.frames);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  /* A corrupted request is dropped, and the next is still answered */
+  printf("  > Bad frames ... ");
+  {
+    const uint32_t bad = slave.stats.badFrames;
+
+    n = pollRequest(NODE, POLL_CMD_DIAG, 9u, 0, 0, bus, sizeof(bus));
+    bus[3] ^= 0x10u;
+    for (size_t i = 0; i < n; i++) {
+      pollRxByte(&slave, bus[i]);
+    }
+    if ((0 != pollReply(&slave, bus, sizeof(bus))) ||
+        ((bad + 1u) != slave.stats.badFrames)) {
+      printf("\n    Bad frame answered\n");
+      return 1;
+    }
+
+    /* Noise without a delimiter is discarded with the next frame */
+    pollRxByte(&slave, 0x55u);
+    pollRxByte(&slave, COBS_DELIM);
+    n = transact(NODE, POLL_CMD_DIAG, 10u, 0, 0);
+    if (POLL_CHECK_OK != check(n, NODE, POLL_CMD_DIAG, 10u, &info)) {
+      printf("\n    Not resynchronised\n");
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  /* A master matches responses by sequence number */
+  printf("  > Sequence mismatch ... ");
+  n   = transact(NODE, POLL_CMD_DIAG, 11u, 0, 0);
+  res = check(n, NODE, POLL_CMD_DIAG, 12u, &info);
+  if (POLL_CHECK_SEQ != res) {
+    printf("\n    Sequence: %d\n", res);
+    return 1;
+  }
+  n   = transact(NODE, POLL_CMD_DIAG, 13u, 0, 0);
+  res = check(n, NODE, POLL_CMD_DATA, 13u, &info);
+  if (POLL_CHECK_SEQ != res) {
+    printf("\n    Command: %d\n", res);
+    return 1;
+  }
+  n   = transact(NODE, POLL_CMD_DIAG, 14u, 0, 0);
+  res = check(n, (NODE + 1u), POLL_CMD_DIAG, 14u, &info);
+  if (POLL_CHECK_NODE != res) {
+    printf("\n    Node: %d\n", res);
+    return 1;
+  }
+  printf("Done!\n");
+
+  /* A retried write is acknowledged but not applied twice */
+  printf("  > Write retry ... ");
+  {
+    const uint8_t wr1[3] = {0u, 0x10u, 0x27u}; /* 10000 */
+    const uint8_t wr2[3] = {0u, 0x28u, 0x23u}; /* 9000 */
+
+    calWrites = 0;
+    n         = transact(NODE, POLL_CMD_CAL_WR, 20u, wr2, 3u);
+    res       = check(n, NODE, POLL_CMD_CAL_WR, 20u, &info);
+    if ((POLL_CHECK_OK != res) || (POLL_OK != info.pPayload[2]) ||
+        (9000u != cal[0]) || (1u != calWrites)) {
+      printf("\n    Write: %d %u\n", res, cal[0]);
+      return 1;
+    }
+    cal[0] = 1234u;
+    n      = transact(NODE, POLL_CMD_CAL_WR, 20u, wr2, 3u);
+    res    = check(n, NODE, POLL_CMD_CAL_WR, 20u, &info);
+    if ((POLL_CHECK_OK != res) || (POLL_OK != info.pPayload[2]) ||
+        (1234u != cal[0]) || (1u != slave.stats.repeats)) {
+      printf("\n    Retry applied: %u\n", cal[0]);
+      return 1;
+    }
+    n   = transact(NODE, POLL_CMD_CAL_WR, 21u, wr1, 3u);
+    res = check(n, NODE, POLL_CMD_CAL_WR, 21u, &info);
+    if ((POLL_CHECK_OK != res) || (10000u != cal[0]) || (2u != calWrites)) {
+      printf("\n    Next write: %u\n", cal[0]);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  printf("\n");
+  return 0;
+}
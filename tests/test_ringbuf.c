@@ -0,0 +1,145 @@
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "ringbuf.h"
+
+#define DEPTH 8u
+
+int main(void) {
+
+  RingBuf_t ring;
+  uint8_t   buf[DEPTH];
+  uint8_t   c;
+
+  printf("---- emon32 ring buffer test ----\n\n");
+
+  printf("  > Empty ring ... ");
+  ringbufInit(&ring, buf, DEPTH);
+  if ((0 != ringbufCount(&ring)) || (DEPTH != ringbufFree(&ring)) ||
+      ringbufGet(&ring, &c)) {
+    printf("\n    Count: %zu Free: %zu\n", ringbufCount(&ring),
+           ringbufFree(&ring));
+    return 1;
+  }
+  printf("Done!\n");
+
+  /* All of the depth is usable, and a full ring refuses further bytes */
+  printf("  > Fill to depth ... ");
+  for (size_t i = 0; i < DEPTH; i++) {
+    if (!ringbufPut(&ring, (uint8_t)i)) {
+      printf("\n    Put %zu refused\n", i);
+      return 1;
+    }
+  }
+  if ((DEPTH != ringbufCount(&ring)) || (0 != ringbufFree(&ring)) ||
+      ringbufPut(&ring, 0xFF) || (DEPTH != ringbufPeak(&ring))) {
+    printf("\n    Count: %zu Free: %zu\n", ringbufCount(&ring),
+           ringbufFree(&ring));
+    return 1;
+  }
+  printf("Done!\n");
+
+  printf("  > Drain in order ... ");
+  for (size_t i = 0; i < DEPTH; i++) {
+    if (!ringbufGet(&ring, &c) || (c != (uint8_t)i)) {
+      printf("\n    Get %zu: %u\n", i, c);
+      return 1;
+    }
+  }
+  if ((0 != ringbufCount(&ring)) || ringbufGet(&ring, &c)) {
+    printf("\n    Not empty: %zu\n", ringbufCount(&ring));
+    return 1;
+  }
+  printf("Done!\n");
+
+  /* The peak records the fullest the ring has been, not the current count */
+  printf("  > Peak ... ");
+  ringbufInit(&ring, buf, DEPTH);
+  (void)ringbufWrite(&ring, "abc", 3u);
+  (void)ringbufGet(&ring, &c);
+  (void)ringbufWrite(&ring, "d", 1u);
+  (void)ringbufGet(&ring, &c);
+  (void)ringbufGet(&ring, &c);
+  if ((3u != ringbufPeak(&ring)) || (1u != ringbufCount(&ring))) {
+    printf("\n    Peak: %zu Count: %zu\n", ringbufPeak(&ring),
+           ringbufCount(&ring));
+    return 1;
+  }
+  (void)ringbufGet(&ring, &c);
+  printf("Done!\n");
+
+  /* Interleave puts and gets so the indices wrap several times */
+  printf("  > Index wrap ... ");
+  {
+    uint8_t next = 0;
+    uint8_t want = 0;
+    for (size_t n = 0; n < (DEPTH * 5u); n++) {
+      for (size_t i = 0; i < 3u; i++) {
+        (void)ringbufPut(&ring, next++);
+      }
+      for (size_t i = 0; i < 3u; i++) {
+        if (!ringbufGet(&ring, &c) || (c != want++)) {
+          printf("\n    Pass %zu: got %u expected %u\n", n, c,
+                 (uint8_t)(want - 1u));
+          return 1;
+        }
+      }
+    }
+  }
+  printf("Done!\n");
+
+  /* A write longer than the free space is accepted up to the space */
+  printf("  > Partial write ... ");
+  {
+    char         out[DEPTH + 1u] = {0};
+    const size_t nFirst          = ringbufWrite(&ring, "abc", 3u);
+    const size_t nSecond         = ringbufWrite(&ring, "defghijkl", 9u);
+
+    for (size_t i = 0; ringbufGet(&ring, &c); i++) {
+      out[i] = (char)c;
+    }
+    if ((3u != nFirst) || (5u != nSecond) || (0 != strcmp(out, "abcdefgh")) ||
+        (DEPTH != ringbufPeak(&ring))) {
+      printf("\n    Accepted: %zu, %zu Result: %s\n", nFirst, nSecond, out);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  /* Peek gives the contiguous run up to the end of the buffer, so a wrapped
+   * ring is read in two parts. */
+  printf("  > Peek and skip ... ");
+  {
+    const uint8_t *pData;
+    char           out[DEPTH + 1u] = {0};
+    size_t         nOut            = 0;
+
+    ringbufInit(&ring, buf, DEPTH);
+    (void)ringbufWrite(&ring, "abcde", 5u);
+    for (size_t i = 0; i < 5u; i++) {
+      (void)ringbufGet(&ring, &c);
+    }
+    (void)ringbufWrite(&ring, "fghijk", 6u);
+
+    const size_t nFirst = ringbufPeek(&ring, &pData);
+    memcpy(&out[nOut], pData, nFirst);
+    nOut += nFirst;
+    ringbufSkip(&ring, nFirst);
+
+    const size_t nSecond = ringbufPeek(&ring, &pData);
+    memcpy(&out[nOut], pData, nSecond);
+    nOut += nSecond;
+    ringbufSkip(&ring, nSecond);
+
+    if ((3u != nFirst) || (3u != nSecond) || (0 != strcmp(out, "fghijk")) ||
+        (0 != ringbufPeek(&ring, &pData)) || (0 != ringbufCount(&ring))) {
+      printf("\n    Runs: %zu, %zu Result: %s\n", nFirst, nSecond, out);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  printf("\n");
+  return 0;
+}
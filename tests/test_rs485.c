@@ -0,0 +1,118 @@
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "rs485.h"
+
+#define GUARD_US 500u
+
+static Rs485_t bus;
+static bool    pinDE;
+static char    pinLog[32]; /* '1' for each assert, '0' for each release */
+static size_t  nPinLog;
+
+static void mockDE(bool on) {
+  pinDE = on;
+  if (nPinLog < (sizeof(pinLog) - 1u)) {
+    pinLog[nPinLog++] = on ? '1' : '0';
+    pinLog[nPinLog]   = 0;
+  }
+}
+
+static void resetLog(void) {
+  nPinLog   = 0;
+  pinLog[0] = 0;
+}
+
+int main(void) {
+
+  uint32_t wait;
+
+  printf("---- emon32 RS485 test ----\n\n");
+
+  printf("  > Initial state ... ");
+  rs485Init(&bus, GUARD_US, &mockDE);
+  if ((RS485_RX != bus.state) || pinDE || (0 != strcmp(pinLog, "0"))) {
+    printf("\n    State: %d Pin: %s\n", bus.state, pinLog);
+    return 1;
+  }
+  printf("Done!\n");
+
+  /* A request arrives, the reply is queued before the guard has passed */
+  printf("  > Turnaround guard ... ");
+  resetLog();
+  rs485RxByte(&bus, 10000u);
+  if (rs485TxStart(&bus, 10200u, &wait) || (RS485_GUARD != bus.state) ||
+      (300u != wait) || pinDE) {
+    printf("\n    Started inside the guard, wait: %u\n", wait);
+    return 1;
+  }
+  /* Another byte restarts the guard */
+  rs485RxByte(&bus, 10400u);
+  if (rs485TxStart(&bus, 10800u, &wait) || (100u != wait)) {
+    printf("\n    Guard not restarted, wait: %u\n", wait);
+    return 1;
+  }
+  if (!rs485TxStart(&bus, 10900u, &wait) || (RS485_TX != bus.state) ||
+      !pinDE || (0 != wait) || (0 != strcmp(pinLog, "1"))) {
+    printf("\n    Not started, state: %d\n", bus.state);
+    return 1;
+  }
+  printf("Done!\n");
+
+  /* DE is held through data register empty, and released on TXC */
+  printf("  > Release on TXC ... ");
+  if (!rs485TxStart(&bus, 11000u, &wait) || (0 != strcmp(pinLog, "1"))) {
+    printf("\n    More data changed the pin: %s\n", pinLog);
+    return 1;
+  }
+  if (rs485TxComplete(&bus) || !pinDE) {
+    printf("\n    Released while sending\n");
+    return 1;
+  }
+  if (!rs485TxEmpty(&bus) || (RS485_DRAIN != bus.state) || !pinDE) {
+    printf("\n    Released on empty\n");
+    return 1;
+  }
+  if (!rs485TxComplete(&bus) || (RS485_RX != bus.state) || pinDE ||
+      (0 != strcmp(pinLog, "10"))) {
+    printf("\n    Not released, pin: %s\n", pinLog);
+    return 1;
+  }
+  if (rs485TxEmpty(&bus) || rs485TxComplete(&bus) ||
+      (0 != strcmp(pinLog, "10"))) {
+    printf("\n    Spurious events changed the pin: %s\n", pinLog);
+    return 1;
+  }
+  printf("Done!\n");
+
+  /* Queued again between empty and TXC: DE stays asserted throughout */
+  printf("  > Queued while draining ... ");
+  resetLog();
+  if (!rs485TxStart(&bus, 20000u, &wait) || !rs485TxEmpty(&bus) ||
+      !rs485TxStart(&bus, 20100u, &wait) || (RS485_TX != bus.state) ||
+      rs485TxComplete(&bus) || !pinDE) {
+    printf("\n    State: %d Pin: %s\n", bus.state, pinLog);
+    return 1;
+  }
+  if (!rs485TxEmpty(&bus) || !rs485TxComplete(&bus) ||
+      (0 != strcmp(pinLog, "10"))) {
+    printf("\n    Pin: %s\n", pinLog);
+    return 1;
+  }
+  printf("Done!\n");
+
+  /* The guard is measured across the microsecond counter wrapping */
+  printf("  > Timer wrap ... ");
+  rs485RxByte(&bus, UINT32_MAX - 99u);
+  if (rs485TxStart(&bus, 200u, &wait) || (200u != wait) ||
+      !rs485TxStart(&bus, 400u, &wait)) {
+    printf("\n    Wait: %u\n", wait);
+    return 1;
+  }
+  printf("Done!\n");
+
+  printf("\n");
+  return 0;
+}
@@ -0,0 +1,253 @@
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "util.h"
+
+typedef struct BaudCase_ {
+  uint32_t baud;
+  bool     valid;
+  uint16_t reg;
+} BaudCase_t;
+
+typedef struct FtoaCase_ {
+  float       val;
+  const char *gold;
+} FtoaCase_t;
+
+typedef struct FtoaDpCase_ {
+  float       val;
+  uint32_t    dp;
+  const char *gold;
+} FtoaDpCase_t;
+
+/* Host side check of a line from utilLineCheck, as a logger would do it:
+ * find the final '*', recompute the checksum over everything before it, and
+ * compare with the hex digits. */
+static bool lineCheckValid(const char *pLine, const LineCheck_t check) {
+  const char *pStar = strrchr(pLine, '*');
+  unsigned    sent  = 0;
+  uint8_t     sum   = 0;
+
+  if (!pStar || (1 != sscanf(pStar + 1, "%2X", &sent)) ||
+      (0 != strcmp(pStar + 3, "\r\n"))) {
+    return false;
+  }
+
+  for (const char *p = pLine; p < pStar; p++) {
+    if (LINE_CHECK_XOR == check) {
+      sum ^= (uint8_t)*p;
+    } else {
+      sum ^= (uint8_t)*p;
+      for (int b = 0; b < 8; b++) {
+        sum = (sum & 0x80u) ? (uint8_t)((sum << 1) ^ 0x07u)
+                            : (uint8_t)(sum << 1);
+      }
+    }
+  }
+  return sum == sent;
+}
+
+int main(void) {
+
+  printf("---- emon32 utility test ----\n\n");
+
+  /* Rounded half away from zero at 2 dp, including the carry into the
+   * integer part. */
+  printf("  > Float to string rounding ... ");
+  {
+    const FtoaCase_t cases[] = {
+        {0.0f, "0.00"},           {-0.0f, "0.00"},
+        {0.004f, "0.00"},         {0.006f, "0.01"},
+        {0.999f, "1.00"},         {9.996f, "10.00"},
+        {99.995f, "100.00"},      {230.49999f, "230.50"},
+        {230.494f, "230.49"},     {1.25f, "1.25"},
+        {-0.999f, "-1.00"},       {-9.996f, "-10.00"},
+        {-230.494f, "-230.49"},   {-1.5f, "-1.50"},
+        {123456.0f, "123456.00"}, {4000000.0f, "4000000.00"}};
+    char buf[16];
+
+    for (size_t i = 0; i < (sizeof(cases) / sizeof(cases[0])); i++) {
+      const size_t len = utilFtoa(buf, cases[i].val);
+      if ((0 != strcmp(buf, cases[i].gold)) || (len != (strlen(buf) + 1u))) {
+        printf("\n    Value: %f Result: %s (%zu) Expected: %s\n",
+               (double)cases[i].val, buf, len, cases[i].gold);
+        return 1;
+      }
+    }
+  }
+  printf("Done!\n");
+
+  /* Each number of decimal places rounds in its last place, and above
+   * FTOA_DP_MAX is limited to it. */
+  printf("  > Float to string places ... ");
+  {
+    const FtoaDpCase_t cases[] = {{0.0f, 0u, "0"},
+                                  {239.5f, 0u, "240"},
+                                  {-239.5f, 0u, "-240"},
+                                  {239.96f, 1u, "240.0"},
+                                  {0.9995f, 3u, "1.000"},
+                                  {-0.9876f, 3u, "-0.988"},
+                                  {0.5f, 3u, "0.500"},
+                                  {1.0f, 9u, "1.000"},
+                                  {4294967040.0f, 0u, "4294967040"},
+                                  {5000000.0f, 3u, "5000000.000"},
+                                  {-1e12f, 3u, "-4294967295.000"}};
+    char               buf[16];
+
+    for (size_t i = 0; i < (sizeof(cases) / sizeof(cases[0])); i++) {
+      const size_t len = utilFtoaDp(buf, cases[i].val, cases[i].dp);
+      if ((0 != strcmp(buf, cases[i].gold)) || (len != (strlen(buf) + 1u))) {
+        printf("\n    Value: %f Result: %s (%zu) Expected: %s\n",
+               (double)cases[i].val, buf, len, cases[i].gold);
+        return 1;
+      }
+    }
+  }
+  printf("Done!\n");
+
+  /* Non-finite values are named, and magnitudes past the uint32 range are
+   * clamped rather than wrapping. */
+  printf("  > Float to string limits ... ");
+  {
+    const float      inf     = 1.0f / 0.0f;
+    const FtoaCase_t cases[] = {{0.0f / 0.0f, "nan"},
+                                {inf, "inf"},
+                                {-inf, "-inf"},
+                                {50000000.0f, "50000000.00"},
+                                {-50000000.0f, "-50000000.00"},
+                                {1e12f, "4294967295.00"},
+                                {-1e12f, "-4294967295.00"}};
+    char             buf[16];
+
+    for (size_t i = 0; i < (sizeof(cases) / sizeof(cases[0])); i++) {
+      const size_t len = utilFtoa(buf, cases[i].val);
+      if ((0 != strcmp(buf, cases[i].gold)) || (len != (strlen(buf) + 1u))) {
+        printf("\n    Value: %f Result: %s (%zu) Expected: %s\n",
+               (double)cases[i].val, buf, len, cases[i].gold);
+        return 1;
+      }
+    }
+  }
+  printf("Done!\n");
+
+  /* BAUD register at the 8 MHz SERCOM clock, from the datasheet formula.
+   * Rates above f_ref / 16, or too low to resolve, are rejected. */
+  printf("  > UART baud register ... ");
+  {
+    const BaudCase_t cases[] = {
+        {9600u, true, 64278u},   {19200u, true, 63019u},
+        {38400u, true, 60503u},  {57600u, true, 57986u},
+        {115200u, true, 50437u}, {230400u, true, 35337u},
+        {500000u, true, 0u},     {500001u, false, 0u},
+        {0u, false, 0u},         {3u, false, 0u},
+        {40u, false, 0u}};
+
+    for (size_t i = 0; i < (sizeof(cases) / sizeof(cases[0])); i++) {
+      uint16_t   reg   = 0;
+      const bool valid = utilBaudReg(cases[i].baud, 8000000u, &reg);
+      if ((valid != cases[i].valid) || (valid && (reg != cases[i].reg))) {
+        printf("\n    Baud: %u Result: %d %u Expected: %d %u\n",
+               cases[i].baud, valid, reg, cases[i].valid, cases[i].reg);
+        return 1;
+      }
+    }
+  }
+  printf("Done!\n");
+
+  /* Command values, such as "jp 2 0 0 3" or "jp2 0 0 3". An empty value
+   * must not read as 0 and shift the ones after it. */
+  printf("  > List of values ... ");
+  {
+    const struct {
+      const char *list;
+      bool        valid;
+      size_t      num;
+    } cases[] = {{"2 0 0 3", true, 4},  {" 2 0 0 3", true, 4},
+                 {"", true, 0},         {" ", true, 0},
+                 {"12", true, 1},       {"2  0", false, 0},
+                 {"2 0 ", false, 0},    {"  2", false, 0},
+                 {"2 x", false, 0},     {"1 2 3 4 5 6 7", false, 0}};
+    const uint32_t gold[4] = {2, 0, 0, 3};
+
+    for (size_t i = 0; i < (sizeof(cases) / sizeof(cases[0])); i++) {
+      uint32_t   vals[6] = {0};
+      size_t     num     = 99;
+      const bool valid   = utilAtouiList(cases[i].list, vals, 6, &num);
+      bool       ok = (valid == cases[i].valid) && (num == cases[i].num);
+      if (ok && (4 == num)) {
+        ok = (0 == memcmp(vals, gold, sizeof(gold)));
+      }
+      if (!ok) {
+        printf("\n    List: \"%s\" Result: %d %zu\n", cases[i].list, valid,
+               num);
+        return 1;
+      }
+    }
+  }
+  printf("Done!\n");
+
+  printf("  > Checksums ... ");
+  {
+    /* CRC-8/SMBUS check value, and a published NMEA sentence */
+    const char    nmea[] = "GPGLL,5300.97914,N,00259.98174,E,125926,A";
+    const uint8_t crc    = utilCRC8("123456789", 9);
+    const uint8_t xor    = utilXor8(nmea, strlen(nmea));
+    if ((0xF4 != crc) || (0x28 != xor)) {
+      printf("\n    CRC8: 0x%02X XOR: 0x%02X\n", crc, xor);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  printf("  > Line checksum ... ");
+  {
+    const LineCheck_t checks[] = {LINE_CHECK_XOR, LINE_CHECK_CRC8};
+    const char        line[]   = "MSG:1,V1:240.12,P1:100\r\n";
+    char              buf[64];
+
+    for (size_t i = 0; i < 2; i++) {
+      strcpy(buf, line);
+      const size_t n = utilLineCheck(buf, strlen(buf), sizeof(buf), checks[i]);
+      if ((n != (strlen(line) + 3u)) || (n != strlen(buf)) ||
+          (0 != strncmp(buf, line, (strlen(line) - 2u))) ||
+          !lineCheckValid(buf, checks[i])) {
+        printf("\n    Line: %s (%zu)\n", buf, n);
+        return 1;
+      }
+      buf[2] ^= 0x01;
+      if (lineCheckValid(buf, checks[i])) {
+        printf("\n    Corrupted line accepted: %s\n", buf);
+        return 1;
+      }
+    }
+
+    /* No checksum leaves the line alone */
+    strcpy(buf, line);
+    if (strlen(line) != utilLineCheck(buf, strlen(buf), sizeof(buf),
+                                      LINE_CHECK_NONE)) {
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  /* A full buffer, without a line ending, is shortened so the checksum fits
+   * and still covers exactly what is sent. */
+  printf("  > Line checksum after truncation ... ");
+  {
+    char buf[16];
+    memset(buf, 'A', sizeof(buf));
+    const size_t n =
+        utilLineCheck(buf, sizeof(buf), sizeof(buf), LINE_CHECK_CRC8);
+    if ((n != (sizeof(buf) - 1u)) || (n != strlen(buf)) ||
+        !lineCheckValid(buf, LINE_CHECK_CRC8)) {
+      printf("\n    Line: %s (%zu)\n", buf, n);
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  printf("\n");
+  return 0;
+}
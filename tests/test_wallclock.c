@@ -0,0 +1,84 @@
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "wallClock.h"
+
+static uint32_t now = 0;
+
+static uint32_t fakeMillis(void) { return now; }
+
+int main(void) {
+
+  char buf[WALL_CLOCK_ISO_W];
+
+  printf("---- emon32 wall clock test ----\n\n");
+
+  /* Including leap days, the end of a century, and the last 32 bit second */
+  printf("  > ISO-8601 ... ");
+  {
+    const uint32_t epochs[] = {0u,          951782400u, 951868799u,
+                               1709208000u, 1760616000u, UINT32_MAX};
+    const char    *gold[]   = {"1970-01-01T00:00:00Z", "2000-02-29T00:00:00Z",
+                               "2000-02-29T23:59:59Z", "2024-02-29T12:00:00Z",
+                               "2025-10-16T12:00:00Z", "2106-02-07T06:28:15Z"};
+    for (size_t i = 0; i < (sizeof(epochs) / sizeof(epochs[0])); i++) {
+      const size_t n = wallClockIso(buf, epochs[i]);
+      if ((20u != n) || (0 != strcmp(buf, gold[i]))) {
+        printf("\n    %u: %s Gold: %s\n", epochs[i], buf, gold[i]);
+        return 1;
+      }
+    }
+  }
+  printf("Done!\n");
+
+  printf("  > Unset ... ");
+  wallClockInit(fakeMillis);
+  now = 5000u;
+  if (wallClockValid() || (0 != wallClockNow())) {
+    printf("\n    Now: %u\n", wallClockNow());
+    return 1;
+  }
+  printf("Done!\n");
+
+  /* Whole seconds are counted from when it was set, not from startup */
+  printf("  > Set and advance ... ");
+  wallClockSet(1700000000u);
+  now = 6999u;
+  const uint32_t t0 = wallClockNow();
+  now               = 7000u;
+  const uint32_t t1 = wallClockNow();
+  if (!wallClockValid() || (1700000001u != t0) || (1700000002u != t1)) {
+    printf("\n    Now: %u, %u\n", t0, t1);
+    return 1;
+  }
+  printf("Done!\n");
+
+  /* The millisecond time wraps after ~49.7 days; read every day across it */
+  printf("  > Millisecond wrap ... ");
+  {
+    const uint32_t start = wallClockNow();
+    const uint32_t step  = 86400000u;
+    for (uint32_t d = 1u; d <= 100u; d++) {
+      now += step;
+      if (wallClockNow() != (start + (d * 86400u))) {
+        printf("\n    Day %u: %u\n", d, wallClockNow());
+        return 1;
+      }
+    }
+  }
+  printf("Done!\n");
+
+  /* Setting it again mid-run restarts from the new time */
+  printf("  > Set again ... ");
+  wallClockSet(1800000000u);
+  now += 1500u;
+  if (1800000001u != wallClockNow()) {
+    printf("\n    Now: %u\n", wallClockNow());
+    return 1;
+  }
+  printf("Done!\n");
+
+  printf("\n---- Done! ----\n");
+  return 0;
+}
@@ -0,0 +1,158 @@
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "wlRing.h"
+
+#define BASE     320u
+#define REC_SIZE 64u
+#define REC_CNT  11u
+#define DATA_LEN 60u
+
+/* Software implementation, linked in with wlRing.c through emonCM_test.h */
+uint16_t calcCRC16_ccitt(const void *pSrc, size_t n);
+
+static uint8_t eeprom[1024];
+static int     readFaults; /* Reads to corrupt before reading correctly */
+
+/* In memory EEPROM; a read fault flips a bit in what is returned */
+static bool mockRead(uint32_t addr, void *pDst, size_t n) {
+  if ((addr + n) > sizeof(eeprom)) {
+    return false;
+  }
+  (void)memcpy(pDst, &eeprom[addr], n);
+  if ((n > sizeof(WLHeader_t)) && (readFaults > 0)) {
+    readFaults--;
+    ((uint8_t *)pDst)[n / 2u] ^= 0x10u;
+  }
+  return true;
+}
+
+static const WLRing_t ring = {.base    = BASE,
+                              .count   = REC_CNT,
+                              .size    = REC_SIZE,
+                              .dataLen = DATA_LEN,
+                              .read    = &mockRead};
+
+/* Data in a record is its sequence number repeated */
+static void writeRecord(const uint32_t idx, const uint8_t valid,
+                        const uint8_t seq) {
+  uint8_t    data[DATA_LEN];
+  WLHeader_t header;
+
+  (void)memset(data, seq, DATA_LEN);
+  header.valid       = valid;
+  header.res0        = 0;
+  header.crc16_ccitt = calcCRC16_ccitt(data, DATA_LEN);
+  (void)memcpy(&eeprom[BASE + (idx * REC_SIZE)], &header, sizeof(header));
+  (void)memcpy(&eeprom[BASE + (idx * REC_SIZE) + sizeof(header)], data,
+               DATA_LEN);
+}
+
+/* Fill the ring as though n records had been written since it was cleared */
+static void fillRing(const unsigned n) {
+  (void)memset(eeprom, 0, sizeof(eeprom));
+  for (unsigned i = 0; i < n; i++) {
+    const uint8_t valid = (i < REC_CNT) ? 1u : 3u;
+    writeRecord(i % REC_CNT, valid, (uint8_t)(i + 1u));
+  }
+}
+
+static bool readSeq(const uint32_t idxNewest, uint8_t *pSeq, uint32_t *pIdx) {
+  uint8_t data[DATA_LEN];
+
+  if (!wlRingReadNewest(&ring, idxNewest, data, pIdx)) {
+    return false;
+  }
+  for (size_t i = 1; i < DATA_LEN; i++) {
+    if (data[i] != data[0]) {
+      return false;
+    }
+  }
+  *pSeq = data[0];
+  return true;
+}
+
+int main(void) {
+
+  uint8_t  seq;
+  uint32_t idx;
+
+  printf("---- emon32 wear levelled record test ----\n\n");
+
+  printf("  > Newest record ... ");
+  fillRing(5u);
+  if (!readSeq(4u, &seq, &idx) || (5u != seq) || (4u != idx)) {
+    printf("\n    Seq: %u Idx: %u\n", seq, idx);
+    return 1;
+  }
+  printf("Done!\n");
+
+  /* A single bad read is retried */
+  printf("  > Read retried ... ");
+  readFaults = 1;
+  if (!readSeq(4u, &seq, &idx) || (5u != seq) || (4u != idx)) {
+    printf("\n    Seq: %u Idx: %u\n", seq, idx);
+    return 1;
+  }
+  printf("Done!\n");
+
+  /* A write cut short damages the newest record; the one before wins */
+  printf("  > Damaged newest record ... ");
+  eeprom[BASE + (4u * REC_SIZE) + 10u] ^= 0xFFu;
+  if (!readSeq(4u, &seq, &idx) || (4u != seq) || (3u != idx)) {
+    printf("\n    Seq: %u Idx: %u\n", seq, idx);
+    return 1;
+  }
+  printf("Done!\n");
+
+  /* Going back past the start of the ring wraps to its end, after the ring
+   * has been round more than once */
+  printf("  > Skip back across the wrap ... ");
+  fillRing(REC_CNT + 2u);
+  eeprom[BASE + (1u * REC_SIZE) + 4u] ^= 0x01u;
+  eeprom[BASE + (0u * REC_SIZE) + 2u] ^= 0x01u;
+  if (!readSeq(1u, &seq, &idx) || (REC_CNT != seq) ||
+      ((REC_CNT - 1u) != idx)) {
+    printf("\n    Seq: %u Idx: %u\n", seq, idx);
+    return 1;
+  }
+  printf("Done!\n");
+
+  /* Nothing good at all: never written, or every record damaged */
+  printf("  > No good record ... ");
+  (void)memset(eeprom, 0, sizeof(eeprom));
+  if (readSeq(0, &seq, &idx) || (0 != idx)) {
+    printf("\n    Read from a blank ring\n");
+    return 1;
+  }
+  fillRing(REC_CNT);
+  for (uint32_t i = 0; i < REC_CNT; i++) {
+    eeprom[BASE + (i * REC_SIZE) + 8u] ^= 0x80u;
+  }
+  if (readSeq(REC_CNT - 1u, &seq, &idx)) {
+    printf("\n    Read a damaged record\n");
+    return 1;
+  }
+  printf("Done!\n");
+
+  /* A read past the end of the memory fails rather than reading rubbish */
+  printf("  > Failed read ... ");
+  {
+    const WLRing_t bad = {.base    = 1000u,
+                          .count   = 1u,
+                          .size    = REC_SIZE,
+                          .dataLen = DATA_LEN,
+                          .read    = &mockRead};
+    uint8_t        data[DATA_LEN];
+    if (wlRingReadNewest(&bad, 0, data, &idx)) {
+      printf("\n    Read succeeded\n");
+      return 1;
+    }
+  }
+  printf("Done!\n");
+
+  printf("\n");
+  return 0;
+}